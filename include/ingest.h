@@ -0,0 +1,48 @@
+/* C interface of the ingest crate, built with the `ffi` feature */
+#ifndef INGEST_H
+#define INGEST_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct IngestFfi IngestFfi;
+
+/* How far along the ingest is, all zeroes before it starts */
+typedef struct IngestProgress {
+    uint64_t files;
+    uint64_t bytes;
+    uint64_t total_bytes;
+    /* Bytes per second over the last few seconds */
+    double speed;
+} IngestProgress;
+
+/* Creates an ingestor with the default configuration, freed with ingest_free */
+IngestFfi *ingest_new(void);
+/* Frees an ingestor, which mustn't be running */
+void ingest_free(IngestFfi *ingest);
+
+/* Replaces the configuration with the JSON form of an IngestConfig. Returns 0, or -1 with the
+ * reason in ingest_last_error */
+int ingest_configure(const IngestFfi *ingest, const char *config);
+/* Ingests, blocking until it's done. Returns 0, or -1 with the reason in ingest_last_error */
+int ingest_run(const IngestFfi *ingest);
+/* Stops the running ingest. Callable from any thread */
+void ingest_cancel(const IngestFfi *ingest);
+/* Writes how far along the ingest is to progress. Callable from any thread */
+int ingest_progress(const IngestFfi *ingest, IngestProgress *progress);
+
+/* The report of the last ingest as JSON, NULL before the first one */
+char *ingest_report_json(const IngestFfi *ingest);
+/* Why the last call failed, NULL if it didn't */
+char *ingest_last_error(const IngestFfi *ingest);
+/* Frees a string returned by the other functions */
+void ingest_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,266 @@
+//! Stores the copies in a single tar or zip file, built with the `archive` feature, eg. to hand a
+//! shoot off on LTO or to a cloud service in one piece.
+//!
+//! The files are streamed into the archive with the structure they have at the target and
+//! hashed on the way. Once the ingest is over a manifest with their checksums, in the format of
+//! [`checksums`], is added at the root of the archive so it can be checked after extracting it.
+use crate::*;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+/// The formats of the archives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    /// Stored without compression, the media files don't compress
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Zip for a `.zip` path, tar for anything else
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("zip") => ArchiveFormat::Zip,
+            _ => ArchiveFormat::Tar,
+        }
+    }
+}
+
+enum Writer {
+    Tar(tar::Builder<File>),
+    Zip(Box<zip::ZipWriter<File>>),
+}
+
+#[derive(Default)]
+struct State {
+    /// Created with the first file
+    writer: Option<Writer>,
+    /// The key and hash of every file in the archive
+    manifest: Vec<(String, String)>,
+    /// The archive can't be appended to once a write failed halfway
+    broken: bool,
+    /// Nor once the manifest was added
+    closed: bool,
+}
+
+/// A tar or zip file the copies are streamed into, its clones append to the same file
+#[derive(Clone)]
+pub struct Archive {
+    path: PathBuf,
+    format: ArchiveFormat,
+    algo: HashAlgo,
+    state: Arc<Mutex<State>>,
+}
+
+impl Archive {
+    /// An archive at `path`, a zip when it ends with `.zip` and a tar otherwise
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Archive {
+            format: ArchiveFormat::from_path(&path),
+            path,
+            algo: HashAlgo::default(),
+            state: Arc::default(),
+        }
+    }
+
+    pub fn with_format(mut self, format: ArchiveFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// The algorithm of the embedded manifest, xxh128 by default
+    pub fn with_checksums(mut self, algo: HashAlgo) -> Self {
+        self.algo = algo;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn append(&self, path: &Path, key: &str) -> Result<()> {
+        let mut guard = self.state();
+        let state = &mut *guard;
+        if state.broken {
+            return Err(Error::custom_error(format!(
+                "{} is incomplete after a failed write",
+                self.path.display()
+            )));
+        }
+        if state.closed {
+            return Err(Error::custom_error(format!(
+                "{} was closed already",
+                self.path.display()
+            )));
+        }
+        // A sidecar shared by a raw and a jpeg comes twice, the archives can't hold it twice
+        if state.manifest.iter().any(|(stored, _)| stored == key) {
+            return Ok(());
+        }
+        let writer = match state.writer {
+            Some(ref mut writer) => writer,
+            None => state.writer.insert(self.create()?),
+        };
+        let metadata = std::fs::metadata(path)?;
+        let mut reader = HashingReader {
+            inner: File::open(path)?,
+            hasher: self.algo.hasher(),
+        };
+        let appended = match writer {
+            Writer::Tar(builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_metadata(&metadata);
+                builder.append_data(&mut header, key, &mut reader)
+            }
+            Writer::Zip(zip) => zip
+                .start_file(key, zip_options(&metadata))
+                .map_err(std::io::Error::other)
+                .and_then(|()| std::io::copy(&mut reader, zip).map(|_| ())),
+        };
+        if let Err(e) = appended {
+            state.broken = true;
+            return Err(e.into());
+        }
+        state
+            .manifest
+            .push((key.to_owned(), reader.hasher.finish()));
+        Ok(())
+    }
+
+    fn create(&self) -> Result<Writer> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = File::create(&self.path)?;
+        Ok(match self.format {
+            ArchiveFormat::Tar => Writer::Tar(tar::Builder::new(file)),
+            ArchiveFormat::Zip => Writer::Zip(Box::new(zip::ZipWriter::new(file))),
+        })
+    }
+
+    /// Adds the manifest and closes the archive, nothing is written when no file was stored
+    fn close(&self) -> Result<()> {
+        let mut state = self.state();
+        state.closed = true;
+        let Some(writer) = state.writer.take() else {
+            return Ok(());
+        };
+        let mut manifest = Vec::new();
+        for (key, hash) in state.manifest.drain(..) {
+            writeln!(manifest, "{}  {}", hash, key)?;
+        }
+        let name = checksums::manifest_name(self.algo);
+        let file = match writer {
+            Writer::Tar(mut builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(manifest.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(now());
+                builder.append_data(&mut header, name, manifest.as_slice())?;
+                builder.into_inner()?
+            }
+            Writer::Zip(mut zip) => {
+                let mut options = zip::write::SimpleFileOptions::default();
+                if let Some(time) = zip_time(std::time::SystemTime::now()) {
+                    options = options.last_modified_time(time);
+                }
+                zip.start_file(name, options).map_err(Error::custom_error)?;
+                zip.write_all(&manifest)?;
+                zip.finish().map_err(Error::custom_error)?
+            }
+        };
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Archive {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Archive")
+            .field("path", &self.path)
+            .field("format", &self.format)
+            .field("algo", &self.algo)
+            .finish()
+    }
+}
+
+impl Destination for Archive {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn store<'a>(&'a self, path: &'a Path, key: &'a str) -> StoreFuture<'a> {
+        let (archive, path, key) = (self.clone(), path.to_path_buf(), key.to_string());
+        Box::pin(destination::blocking(move || archive.append(&path, &key)))
+    }
+
+    fn finish(&self) -> StoreFuture<'_> {
+        let archive = self.clone();
+        Box::pin(destination::blocking(move || archive.close()))
+    }
+
+    fn capacity(&self) -> SpaceFuture<'_> {
+        destination::disk_space(&self.path, |path| fs2::total_space(path))
+    }
+
+    fn free(&self) -> SpaceFuture<'_> {
+        destination::disk_space(&self.path, |path| fs2::free_space(path))
+    }
+}
+
+struct HashingReader<R> {
+    inner: R,
+    hasher: Hasher,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+fn zip_options(metadata: &std::fs::Metadata) -> zip::write::SimpleFileOptions {
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(metadata.len() > u32::MAX as u64);
+    #[cfg(unix)]
+    let options = {
+        use std::os::unix::fs::PermissionsExt;
+        options.unix_permissions(metadata.permissions().mode())
+    };
+    match metadata.modified().ok().and_then(zip_time) {
+        Some(time) => options.last_modified_time(time),
+        None => options,
+    }
+}
+
+/// Zip can only hold the times from 1980 to 2107, in steps of two seconds
+fn zip_time(time: std::time::SystemTime) -> Option<zip::DateTime> {
+    let secs = time.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs() as i64;
+    let (year, month, day) = times::civil_from_days(secs.div_euclid(86400));
+    let secs = secs.rem_euclid(86400);
+    zip::DateTime::from_date_and_time(
+        u16::try_from(year).ok()?,
+        month as u8,
+        day as u8,
+        (secs / 3600) as u8,
+        (secs % 3600 / 60) as u8,
+        (secs % 60) as u8,
+    )
+    .ok()
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
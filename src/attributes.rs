@@ -0,0 +1,146 @@
+//! The attributes Windows flags files with besides hidden, which the walk tells apart instead of
+//! copying whatever isn't hidden, and the cloud placeholders.
+//!
+//! - OneDrive and Dropbox mark the files they keep online as offline or recalled on access on
+//!   Windows
+//! - iCloud Drive and the File Provider syncs mark them as dataless on macOS
+use crate::*;
+
+#[cfg(target_os = "macos")]
+const SF_DATALESS: u32 = 0x40000000;
+
+/// What's done with the placeholders of the files kept in the cloud by OneDrive, Dropbox or
+/// iCloud, whose contents aren't on the disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Placeholders {
+    /// Leave them out of the ingest with a [`Warning::Placeholder`]
+    #[default]
+    Skip,
+    /// Copy them, reading them downloads their contents
+    Hydrate,
+    /// Fail the ingest before copying anything
+    Fail,
+}
+
+/// The files and folders left out for their Windows attributes, on top of the hidden ones with
+/// [`Filter::ignore_hidden`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Attributes {
+    /// Skip the ones that belong to the system, eg. `desktop.ini`, hidden or not
+    pub skip_system: bool,
+    /// Skip every reparse point, not only the links which the walk never follows but also the
+    /// deduplicated or cloud files
+    pub skip_reparse_points: bool,
+    pub placeholders: Placeholders,
+}
+
+impl Default for Attributes {
+    fn default() -> Self {
+        Attributes {
+            skip_system: true,
+            skip_reparse_points: false,
+            placeholders: Placeholders::Skip,
+        }
+    }
+}
+
+impl Attributes {
+    /// Whether the file or folder at `path` is left out
+    #[cfg(windows)]
+    pub fn skips(&self, path: impl AsRef<Path>) -> bool {
+        use windows_sys::Win32::Storage::FileSystem::{
+            FILE_ATTRIBUTE_REPARSE_POINT, FILE_ATTRIBUTE_SYSTEM,
+        };
+        let Some(attributes) = windows(path.as_ref()) else {
+            return false;
+        };
+        let is = |attribute: u32| attributes & attribute != 0;
+        (self.skip_system && is(FILE_ATTRIBUTE_SYSTEM))
+            || (self.skip_reparse_points && is(FILE_ATTRIBUTE_REPARSE_POINT))
+    }
+
+    /// Whether the file or folder at `path` is left out
+    #[cfg(not(windows))]
+    pub fn skips(&self, _path: impl AsRef<Path>) -> bool {
+        false
+    }
+}
+
+/// Whether the file at `path` is a placeholder whose contents are only in the cloud
+pub(crate) fn is_placeholder(path: &Path) -> bool {
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::Storage::FileSystem::{
+            FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_OFFLINE, FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS,
+            FILE_ATTRIBUTE_RECALL_ON_OPEN,
+        };
+        windows(path).is_some_and(|attributes| {
+            attributes & FILE_ATTRIBUTE_DIRECTORY == 0
+                && attributes
+                    & (FILE_ATTRIBUTE_OFFLINE
+                        | FILE_ATTRIBUTE_RECALL_ON_OPEN
+                        | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS)
+                    != 0
+        })
+    }
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::macos::fs::MetadataExt;
+        std::fs::symlink_metadata(path)
+            .is_ok_and(|metadata| metadata.is_file() && metadata.st_flags() & SF_DATALESS != 0)
+    }
+    #[cfg(not(any(windows, target_os = "macos")))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Takes the cloud placeholders out of the walked `entries` as [`Attributes::placeholders`]
+    /// says, or fails on the first one
+    pub(crate) fn placeholders(
+        &mut self,
+        entries: &mut Vec<(&'ingest Path, vfs::Entry)>,
+    ) -> Result<()> {
+        let policy = self.filter.attributes.placeholders;
+        if policy == Placeholders::Hydrate || self.vfs.is_some() {
+            return Ok(());
+        }
+        let mut placeholders = Vec::new();
+        entries.retain(|(_, entry)| {
+            let placeholder = entry.is_file() && is_placeholder(entry.path());
+            if placeholder {
+                placeholders.push(entry.path().to_path_buf());
+            }
+            !placeholder
+        });
+        if let (Placeholders::Fail, Some(path)) = (policy, placeholders.first()) {
+            return Err(Error::custom_error(format!(
+                "{} and {} other files are only in the cloud, download them before ingesting",
+                path.display(),
+                placeholders.len() - 1
+            )));
+        }
+        for path in placeholders {
+            // Once across the passes
+            if !self.report.skipped.contains(&path) {
+                self.report.warn(Warning::Placeholder(path.clone()));
+                self.report.skipped.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The attributes of the file or folder at `path` itself, not of what it links to
+#[cfg(windows)]
+pub(crate) fn windows(path: &Path) -> Option<u32> {
+    use std::os::windows::fs::MetadataExt;
+    std::fs::symlink_metadata(path)
+        .map(|metadata| metadata.file_attributes())
+        .ok()
+}
@@ -0,0 +1,90 @@
+use crate::*;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+/// How much is read from each source and written to each destination
+const SAMPLE_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Measured speeds of the sources and destinations, in bytes per second
+#[derive(Debug, Clone, Default)]
+pub struct Benchmark {
+    /// Read speed of every source, 0 when there was nothing to read
+    pub sources: Vec<(PathBuf, f64)>,
+    pub target: f64,
+    pub backup: Option<f64>,
+    /// How long the ingest should take at these speeds, including the backup
+    pub estimate: Duration,
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Copies a few MB from each source to the target and the backup to estimate how long the
+    /// ingest takes. Reads may be served from the page cache if the files were read recently
+    pub fn benchmark(&self) -> Result<Benchmark> {
+        let target = write_speed(&self.target)?;
+        let backup = self.backup.as_deref().map(write_speed).transpose()?;
+
+        let mut benchmark = Benchmark {
+            target,
+            backup,
+            ..Default::default()
+        };
+        for (source, size) in self.source_sizes() {
+            let read = read_speed(&self.files_in(&source))?;
+            for write in std::iter::once(target).chain(backup) {
+                let speed = read.min(write);
+                if speed > 0.0 {
+                    benchmark.estimate += Duration::from_secs_f64(size as f64 / speed);
+                }
+            }
+            benchmark.sources.push((source, read));
+        }
+        Ok(benchmark)
+    }
+}
+
+/// Reads up to [`SAMPLE_SIZE`] bytes from the start of `files`
+fn read_speed(files: &[PathBuf]) -> Result<f64> {
+    let mut buffer = vec![0; DEFAULT_BUFFER_SIZE];
+    let mut read = 0;
+    let start = Instant::now();
+    for file in files.iter().filter(|file| file.is_file()) {
+        let mut reader = std::fs::File::open(file)?.take(SAMPLE_SIZE - read);
+        loop {
+            match reader.read(&mut buffer)? {
+                0 => break,
+                n => read += n as u64,
+            }
+        }
+        if read >= SAMPLE_SIZE {
+            break;
+        }
+    }
+    Ok(speed(read, start.elapsed()))
+}
+
+/// Writes and syncs [`SAMPLE_SIZE`] bytes in `dir`
+fn write_speed(dir: &Path) -> Result<f64> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!(".benchmark.{}", PART_EXTENSION));
+    let buffer = vec![0x5a; DEFAULT_BUFFER_SIZE];
+    let start = Instant::now();
+    let written = (|| -> Result<u64> {
+        let mut file = std::fs::File::create(&path)?;
+        let mut written = 0;
+        while written < SAMPLE_SIZE {
+            file.write_all(&buffer)?;
+            written += buffer.len() as u64;
+        }
+        file.sync_all()?;
+        Ok(written)
+    })();
+    std::fs::remove_file(&path).ok();
+    Ok(speed(written?, start.elapsed()))
+}
+
+fn speed(bytes: u64, elapsed: Duration) -> f64 {
+    match elapsed.as_secs_f64() {
+        secs if secs > 0.0 => bytes as f64 / secs,
+        _ => 0.0,
+    }
+}
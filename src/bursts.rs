@@ -0,0 +1,184 @@
+use crate::*;
+use std::time::{Duration, SystemTime};
+
+/// Runs of frames shot by the same camera with at most `gap` between two of them, eg. the 20 fps
+/// sequences of a sports shoot, listed in [`Report::bursts`].
+///
+/// The RAW and the JPEG of a frame count once. The camera is told apart by the serial or model in
+/// the EXIF with the `exif` feature, otherwise by the source the file is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BurstDetection {
+    pub gap: Duration,
+    /// Frames a run needs to be a burst
+    pub min_frames: usize,
+    /// Copy the frames of each burst into a `Burst-NNN` folder
+    pub folders: bool,
+}
+
+impl Default for BurstDetection {
+    fn default() -> Self {
+        BurstDetection {
+            gap: Duration::from_millis(250),
+            min_frames: 3,
+            folders: false,
+        }
+    }
+}
+
+/// Frames shot in a burst
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Burst {
+    /// Starting from 1 in the order of the capture times
+    pub number: usize,
+    pub camera: String,
+    pub frames: usize,
+    /// Every file of the frames, the RAWs along with their JPEGs
+    pub files: Vec<PathBuf>,
+}
+
+impl Burst {
+    /// The folder the burst is copied into, eg. `Burst-007`
+    pub fn folder(&self) -> String {
+        format!("Burst-{:03}", self.number)
+    }
+}
+
+/// The bursts of a pass and the burst of each of their files
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Bursts {
+    pub(crate) bursts: Vec<Burst>,
+    files: HashMap<PathBuf, usize>,
+}
+
+impl Bursts {
+    /// Splits the `shots`, files with their camera and capture time, into bursts
+    pub(crate) fn from_shots(
+        shots: impl IntoIterator<Item = (String, SystemTime, PathBuf)>,
+        detection: &BurstDetection,
+    ) -> Self {
+        // The files of a frame share their folder and stem
+        let mut frames: HashMap<(String, PathBuf), (SystemTime, Vec<PathBuf>)> = HashMap::new();
+        for (camera, time, path) in shots {
+            let frame = frames
+                .entry((camera, path.with_extension("")))
+                .or_insert((time, Vec::new()));
+            frame.0 = frame.0.min(time);
+            frame.1.push(path);
+        }
+        let mut frames: Vec<_> = frames
+            .into_iter()
+            .map(|((camera, _), (time, mut files))| {
+                files.sort();
+                (camera, time, files)
+            })
+            .collect();
+        frames.sort();
+
+        let mut bursts = Vec::new();
+        let mut run: Vec<Vec<PathBuf>> = Vec::new();
+        let mut last: Option<(&str, SystemTime)> = None;
+        for (camera, time, files) in &frames {
+            let continues = last.is_some_and(|(last_camera, last_time)| {
+                last_camera == camera
+                    && time.duration_since(last_time).unwrap_or_default() <= detection.gap
+            });
+            if !continues {
+                Self::close(&mut bursts, &mut run, last, detection);
+            }
+            run.push(files.clone());
+            last = Some((camera, *time));
+        }
+        Self::close(&mut bursts, &mut run, last, detection);
+
+        bursts.sort_by_key(|(time, _)| *time);
+        let bursts: Vec<Burst> = bursts
+            .into_iter()
+            .enumerate()
+            .map(|(i, (_, burst))| Burst {
+                number: i + 1,
+                ..burst
+            })
+            .collect();
+        let files = bursts
+            .iter()
+            .flat_map(|burst| burst.files.iter().map(|file| (file.clone(), burst.number)))
+            .collect();
+        Bursts { bursts, files }
+    }
+
+    /// Keeps the frames of `run` as a burst when there are enough of them
+    fn close(
+        bursts: &mut Vec<(SystemTime, Burst)>,
+        run: &mut Vec<Vec<PathBuf>>,
+        last: Option<(&str, SystemTime)>,
+        detection: &BurstDetection,
+    ) {
+        let run = std::mem::take(run);
+        let Some((camera, time)) = last else {
+            return;
+        };
+        if run.len() < detection.min_frames.max(2) {
+            return;
+        }
+        bursts.push((
+            time,
+            Burst {
+                number: 0,
+                camera: camera.to_owned(),
+                frames: run.len(),
+                files: run.into_iter().flatten().collect(),
+            },
+        ));
+    }
+
+    /// The folder of the burst `path` belongs to
+    pub(crate) fn folder(&self, path: &Path) -> Option<String> {
+        let number = self.files.get(path)?;
+        Some(self.bursts[number - 1].folder())
+    }
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Finds the bursts among the photos of `paths`
+    pub(crate) fn find_bursts<P: AsRef<Path>>(&self, paths: &[P]) -> Bursts {
+        let Some(detection) = &self.bursts else {
+            return Bursts::default();
+        };
+        let shots = paths.iter().map(AsRef::as_ref).filter_map(|path| {
+            let ext = path.extension()?.to_ascii_lowercase();
+            let ext = ext.to_str()?;
+            if !RAW_EXTENSIONS.contains(&ext) && !LOSSY_EXTENSIONS.contains(&ext) {
+                return None;
+            }
+            Some((
+                self.camera(path),
+                self.capture_time(path)?,
+                path.to_path_buf(),
+            ))
+        });
+        Bursts::from_shots(shots, detection)
+    }
+
+    /// Tells apart the camera `path` was shot with
+    fn camera(&self, path: &Path) -> String {
+        #[cfg(feature = "exif")]
+        if let Ok(meta) = crate::meta::read(path) {
+            let camera = match meta.serial {
+                Some(serial) => Some(serial),
+                None => meta
+                    .make
+                    .zip(meta.model)
+                    .map(|(make, model)| make + " " + &model),
+            };
+            if let Some(camera) = camera {
+                return camera;
+            }
+        }
+        match self.source_of(path) {
+            Some(source) => self.source_label(source).to_string_lossy().into_owned(),
+            None => String::new(),
+        }
+    }
+}
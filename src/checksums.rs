@@ -0,0 +1,99 @@
+//! Plain checksum manifests in the `sha256sum` / `md5sum` format, `<hash>  <relative path>` per
+//! line, so they can be checked with the standard tools as well as [`Ingestor::verify_manifest`].
+use crate::*;
+use std::io::Write;
+
+/// Returns the name of the manifest for `algo`, eg. `checksums.sha256`
+pub fn manifest_name(algo: HashAlgo) -> String {
+    format!("checksums.{}", algo.name())
+}
+
+/// Appends `files` to the manifest for `algo` at `root`, returning its path
+pub fn write_manifest(root: impl AsRef<Path>, algo: HashAlgo, files: &[HashedFile]) -> Result<PathBuf> {
+    write_manifest_in(&vfs::RealFs, root, algo, files)
+}
+
+pub(crate) fn write_manifest_in(
+    vfs: &dyn Vfs,
+    root: impl AsRef<Path>,
+    algo: HashAlgo,
+    files: &[HashedFile],
+) -> Result<PathBuf> {
+    let root = root.as_ref();
+    let path = root.join(manifest_name(algo));
+    let mut manifest = vfs.append(&path)?;
+    for file in hash::latest_by_path(files) {
+        if let Some(hash) = file.hash(algo) {
+            writeln!(manifest, "{}  {}", hash, relative(root, &file.path))?;
+        }
+    }
+    Ok(path)
+}
+
+/// A file whose content at the target doesn't match the manifest
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub path: PathBuf,
+    pub expected: String,
+    /// `None` if the file is missing
+    pub actual: Option<String>,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.actual {
+            Some(ref actual) => write!(
+                f,
+                "{}: expected {} got {}",
+                self.path.display(),
+                self.expected,
+                actual
+            ),
+            None => write!(f, "{}: missing", self.path.display()),
+        }
+    }
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Re-reads every file listed in the manifest at `path` and returns the ones that don't match.
+    /// The algorithm is taken from the manifest's extension
+    pub fn verify_manifest(path: impl AsRef<Path>) -> Result<Vec<Mismatch>> {
+        let path = path.as_ref();
+        let algo: HashAlgo = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .ok_or_else(|| Error::custom_error("Manifest has no extension"))?
+            .parse()?;
+        let root = path
+            .parent()
+            .ok_or_else(|| Error::custom_error("Manifest has no parent folder"))?;
+
+        let mut mismatches = Vec::new();
+        for line in std::fs::read_to_string(path)?.lines() {
+            let Some((expected, file)) = line.split_once(' ') else {
+                continue;
+            };
+            // sha256sum marks binary mode with a `*` in place of the second space
+            let file = file.trim_start_matches([' ', '*']);
+            let file = root.join(file);
+            let actual = algo.hash_file(&file).ok();
+            if actual.as_deref() != Some(expected) {
+                mismatches.push(Mismatch {
+                    path: file,
+                    expected: expected.to_owned(),
+                    actual,
+                });
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+fn relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,99 @@
+//! The video structures of AVCHD and XAVC cards, where the clips sit among index, playlist and
+//! thumbnail files that only the camera makes sense of.
+//!
+//! - AVCHD keeps its clips in `PRIVATE/AVCHD/BDMV/STREAM/*.MTS`
+//! - XAVC S keeps them in `M4ROOT/CLIP/*.MP4`, or `PRIVATE/M4ROOT/CLIP`, with a `C0001M01.XML`
+//!   of metadata next to each
+//! - XAVC and XDCAM keep them in `XDROOT/Clip/*.MXF`, with the same XML
+use crate::*;
+
+/// How the AVCHD and XAVC structures of video cards are ingested
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VideoCardPolicy {
+    /// Copy the whole structure as the other folders of the card
+    #[default]
+    Whole,
+    /// Only copy the clips
+    Essence,
+    /// Copy the clips along with their XML metadata, renamed with them
+    EssenceWithMetadata,
+}
+
+impl VideoCardPolicy {
+    /// Whether the file at `path` is ingested on its own, the files outside of the video
+    /// structures always are
+    pub fn keeps(&self, path: impl AsRef<Path>) -> bool {
+        *self == VideoCardPolicy::Whole || layout(path.as_ref()).is_none_or(|clip| clip)
+    }
+
+    pub fn copies_metadata(&self) -> bool {
+        *self == VideoCardPolicy::EssenceWithMetadata
+    }
+}
+
+/// `Some(true)` for a clip of a video structure, `Some(false)` for the rest of the structure and
+/// `None` outside of one
+fn layout(path: &Path) -> Option<bool> {
+    let folders: Vec<String> = path
+        .parent()?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_ascii_lowercase())
+        .collect();
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let parent = folders.last().map(String::as_str);
+    let within = |root: &str| folders.iter().any(|folder| folder == root);
+    if within("bdmv") {
+        Some(parent == Some("stream") && matches!(extension.as_str(), "mts" | "m2ts"))
+    } else if within("m4root") || within("xdroot") {
+        Some(parent == Some("clip") && matches!(extension.as_str(), "mp4" | "mxf"))
+    } else {
+        None
+    }
+}
+
+/// The XML metadata Sony cameras write along with `clip`, `C0001M01.XML` for `C0001.MP4`
+pub(crate) fn metadata_in(vfs: &dyn Vfs, clip: &Path) -> Option<PathBuf> {
+    if layout(clip) != Some(true) {
+        return None;
+    }
+    let stem = clip.file_stem()?.to_str()?;
+    vfs.read_dir(clip.parent()?)
+        .ok()?
+        .into_iter()
+        .map(vfs::Entry::into_path)
+        .find(|path| {
+            let Some(name) = path.file_stem().and_then(OsStr::to_str) else {
+                return false;
+            };
+            let suffix = name.get(stem.len()..).unwrap_or_default().as_bytes();
+            name.get(..stem.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(stem))
+                && suffix.len() == 3
+                && suffix[0].eq_ignore_ascii_case(&b'm')
+                && suffix[1..].iter().all(u8::is_ascii_digit)
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("xml"))
+        })
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// The XML metadata of the clip copied from `input` to `output` along with where it goes, when
+    /// the policy copies it
+    pub(crate) fn clip_metadata(&self, input: &Path, output: &Path) -> Option<(PathBuf, PathBuf)> {
+        if !self.filter.video_cards.copies_metadata() {
+            return None;
+        }
+        let metadata = metadata_in(self.vfs(), input)?;
+        // The suffix of the metadata follows the name of the clip, renamed or not
+        let name = metadata.file_name()?.to_str()?;
+        let suffix = name.get(input.file_stem()?.len()..)?;
+        let mut target = output.file_stem()?.to_os_string();
+        target.push(suffix);
+        Some((metadata.clone(), output.with_file_name(target)))
+    }
+}
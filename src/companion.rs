@@ -0,0 +1,113 @@
+use crate::*;
+
+/// How a [`Companion`] relates to the image it was found for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CompanionKind {
+    /// The same shot in another format, the JPEG or HEIF of a RAW or the RAW of a JPEG
+    Twin,
+    /// Edits and metadata, see [`SIDECAR_EXTENSIONS`]
+    Sidecar,
+    /// `.THM` thumbnail written by cameras next to their videos
+    Thumbnail,
+    /// `.LRV` or DJI `.LRF` low resolution copy of a video
+    Proxy,
+    /// `.WAV` voice memo recorded along with a photo
+    VoiceMemo,
+}
+
+impl CompanionKind {
+    /// The kind of companion a file with `extension` is, if any
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let extension = extension.to_ascii_lowercase();
+        Some(match extension.as_str() {
+            "thm" => CompanionKind::Thumbnail,
+            "lrv" | "lrf" => CompanionKind::Proxy,
+            "wav" => CompanionKind::VoiceMemo,
+            ext if SIDECAR_EXTENSIONS.contains(&ext) => CompanionKind::Sidecar,
+            ext if RAW_EXTENSIONS.contains(&ext) || LOSSY_EXTENSIONS.contains(&ext) => {
+                CompanionKind::Twin
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// A file that belongs with an image, found next to it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Companion {
+    pub path: PathBuf,
+    pub kind: CompanionKind,
+}
+
+/// Returns the files next to `path` that belong with it, sorted by path. They share its stem
+/// (`IMG_0001.JPG` for `IMG_0001.CR3`) or, but for the twins, append to its name
+/// (`IMG_0001.CR3.xmp`), or are the GoPro proxy of a clip (`GL010001.LRV` for `GX010001.MP4`).
+/// The names are matched case insensitively on every platform
+pub fn companions(path: impl AsRef<Path>) -> Vec<Companion> {
+    companions_in(&vfs::RealFs, path.as_ref())
+}
+
+pub(crate) fn companions_in(vfs: &dyn Vfs, path: &Path) -> Vec<Companion> {
+    let (Some(parent), Some(stem), Some(name)) =
+        (path.parent(), path.file_stem(), path.file_name())
+    else {
+        return Vec::new();
+    };
+    let Ok(dir) = vfs.read_dir(folder(parent)) else {
+        return Vec::new();
+    };
+    let mut companions: Vec<Companion> = dir
+        .into_iter()
+        .filter(|entry| entry.is_file() && entry.file_name() != name)
+        .filter_map(|entry| {
+            let path = entry.into_path();
+            let kind = CompanionKind::from_extension(path.extension()?.to_str()?)?;
+            let other = path.file_stem()?;
+            let matches = sidecar::same_family(other, stem)
+                || (kind != CompanionKind::Twin && other.eq_ignore_ascii_case(name));
+            matches.then_some(Companion { path, kind })
+        })
+        .collect();
+    companions.sort_by(|a, b| a.path.cmp(&b.path));
+    companions
+}
+
+/// Returns the file next to `path` with its stem and `extension` whatever the case of either,
+/// where `path.with_extension(extension)` only finds the exact name on most filesystems
+pub(crate) fn with_extension_in(vfs: &dyn Vfs, path: &Path, extension: &str) -> Option<PathBuf> {
+    let stem = path.file_stem()?;
+    vfs.read_dir(folder(path.parent()?))
+        .ok()?
+        .into_iter()
+        .map(vfs::Entry::into_path)
+        .find(|other| {
+            other
+                .file_stem()
+                .is_some_and(|s| s.eq_ignore_ascii_case(stem))
+                && other
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+        })
+}
+
+/// The names of the entries of `dir` in lowercase, to look many of them up whatever their case
+pub(crate) fn names_in(vfs: &dyn Vfs, dir: &Path) -> HashSet<String> {
+    vfs.read_dir(folder(dir))
+        .map(|entries| {
+            entries
+                .iter()
+                .map(|entry| entry.file_name().to_string_lossy().to_ascii_lowercase())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The parent of a bare file name is the current folder
+fn folder(parent: &Path) -> &Path {
+    match parent.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => parent,
+    }
+}
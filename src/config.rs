@@ -0,0 +1,426 @@
+//! An owned mirror of the [`IngestorBuilder`] that can be saved and loaded, eg. to keep named
+//! import presets like "Wedding card dump" or "Sports JPEG-only".
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Everything set on an [`IngestorBuilder`] except the progress counters, the cancel flag and the
+/// closures. Missing fields take the builder's defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IngestConfig {
+    /// Name of the preset
+    pub name: Option<String>,
+    pub sources: Vec<PathBuf>,
+    /// Sources by their label, see [`LABEL_TOKEN`]
+    pub labels: BTreeMap<String, PathBuf>,
+    pub target: Option<PathBuf>,
+    pub backup: Option<PathBuf>,
+    /// Copy to the backup in the same read of the cards as to the target
+    pub tee: Option<bool>,
+    pub structure: StructureConfig,
+    pub filter: FilterConfig,
+    /// Extensions of the sidecars copied along, the builder's default when unset
+    pub sidecars: Option<Vec<String>>,
+    /// How the application verifies the ingest, the ingest itself doesn't
+    pub verify: Option<VerifyMode>,
+    pub source_folders: Option<bool>,
+    pub routes: Option<Routes>,
+    pub session_gap: Option<Duration>,
+    pub bursts: Option<BurstDetection>,
+    /// Replaces [`JOB_TOKEN`] in the names of the copies
+    pub job_name: Option<String>,
+    pub order: Option<OrderBy>,
+    /// The files copied first, without the predicate
+    pub priority: Option<Priority>,
+    /// Seconds the camera clock of each source is ahead, see [`IngestorBuilder::with_clock_offset`]
+    pub clock_offsets: BTreeMap<PathBuf, i64>,
+    /// Offset from UTC in minutes of the camera clocks that don't record it
+    pub timezone: Option<i16>,
+    pub max_files_per_folder: Option<usize>,
+    pub collisions: Option<CollisionPolicy>,
+    pub collision_suffix: Option<CollisionSuffix>,
+    /// Leave out the files already in the target under any name
+    pub skip_if_hash_exists_in_target: Option<bool>,
+    pub windows_names: Option<bool>,
+    pub finder: Option<Finder>,
+    pub copy_jpg: Option<bool>,
+    pub pair_policy: Option<PairPolicy>,
+    pub copy_orphans: Option<bool>,
+    pub metadata: Option<MetadataPreset>,
+    pub generate_missing_xmp: Option<bool>,
+    /// GPX track and the offset of the camera clock from UTC in minutes
+    pub gpx: Option<(PathBuf, i16)>,
+    pub picks: Option<Picks>,
+    /// Command templates run after every copy, see [`CopyHook::Command`]
+    pub post_copy_commands: Vec<String>,
+    #[cfg(feature = "previews")]
+    pub previews: Option<preview::PreviewSize>,
+    pub timestamps: Option<Timestamps>,
+    pub preserve_metadata: Option<bool>,
+    pub atomic: Option<bool>,
+    pub durability: Option<Durability>,
+    pub no_cache: Option<bool>,
+    pub preallocate: Option<bool>,
+    pub retry: Option<Retry>,
+    pub reflink: Option<Reflink>,
+    pub on_error: Option<ErrorPolicy>,
+    pub quarantine: Option<Quarantine>,
+    pub mhl: Option<HashAlgo>,
+    pub checksums: Option<HashAlgo>,
+    #[cfg(feature = "validate")]
+    pub validate: Option<bool>,
+    pub buffer_size: Option<usize>,
+    /// Whether the target is a network share, detected when unset
+    pub network: Option<bool>,
+    pub reconnect_timeout: Option<Duration>,
+    pub max_throughput: Option<u64>,
+    pub reserved_space: Option<u64>,
+    pub depth: Option<usize>,
+    pub min_depth: Option<usize>,
+    pub depth_from: Option<DepthFrom>,
+    /// Relative to each source
+    pub included_subfolders: Option<Vec<PathBuf>>,
+    pub excluded_subfolders: Option<Vec<PathBuf>>,
+    /// Sources copied at the same time
+    pub concurrency: Option<usize>,
+}
+
+/// The owned form of [`Structure`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StructureConfig {
+    Rename(RenameConfig),
+    Preserve,
+    #[default]
+    Retain,
+}
+
+/// The owned form of [`Rename`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenameConfig {
+    pub name: Option<String>,
+    pub position: Position,
+    pub sequence: i32,
+    pub zeroes: u8,
+}
+
+/// The owned form of [`Filter`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterConfig {
+    /// Empty matches every extension
+    pub extensions: Vec<String>,
+    pub min_size: u64,
+    /// No limit when unset
+    pub max_size: Option<u64>,
+    pub ignore_hidden: bool,
+    pub video_cards: VideoCardPolicy,
+    pub attributes: Attributes,
+    pub max_files: Option<usize>,
+    pub every_nth: Option<usize>,
+    /// Match the files without an extension by their contents
+    pub sniff_contents: bool,
+}
+
+#[cfg(feature = "config")]
+impl IngestConfig {
+    /// Loads a TOML or JSON config, going by the extension. `~` and environment variables like
+    /// `$HOME` or `${CARD}` are expanded in the paths
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        let json = match path.extension().and_then(OsStr::to_str) {
+            Some(ext) => ext.eq_ignore_ascii_case("json"),
+            None => contents.trim_start().starts_with('{'),
+        };
+        let mut config: IngestConfig = match json {
+            true => serde_json::from_str(&contents).map_err(Error::custom_error)?,
+            false => toml::from_str(&contents).map_err(Error::custom_error)?,
+        };
+        config.expand()?;
+        Ok(config)
+    }
+
+    fn expand(&mut self) -> Result<()> {
+        for source in self.sources.iter_mut().chain(self.labels.values_mut()) {
+            *source = expand(source)?;
+        }
+        self.clock_offsets = std::mem::take(&mut self.clock_offsets)
+            .into_iter()
+            .map(|(source, seconds)| Ok((expand(&source)?, seconds)))
+            .collect::<Result<_>>()?;
+        for path in [&mut self.target, &mut self.backup].into_iter().flatten() {
+            *path = expand(path)?;
+        }
+        if let Some((ref mut gpx, _)) = self.gpx {
+            *gpx = expand(gpx)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces a leading `~` with the home folder and `$VAR` / `${VAR}` with the variable
+#[cfg(feature = "config")]
+fn expand(path: &Path) -> Result<PathBuf> {
+    let Some(path) = path.to_str() else {
+        return Ok(path.to_path_buf());
+    };
+    let var = |name: &str| {
+        std::env::var(name).map_err(|_| Error::custom_error(format!("${} isn't set", name)))
+    };
+    let mut expanded = String::new();
+    let mut rest = path;
+    if rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\") {
+        let home = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+        expanded.push_str(&var(home)?);
+        rest = &rest[1..];
+    }
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, next) = match after.strip_prefix('{') {
+            Some(braced) => {
+                let end = braced
+                    .find('}')
+                    .ok_or_else(|| Error::custom_error(format!("Unclosed ${{ in {}", path)))?;
+                (&braced[..end], &braced[end + 1..])
+            }
+            None => {
+                let end = after
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        match name {
+            "" => expanded.push('$'),
+            name => expanded.push_str(&var(name)?),
+        }
+        rest = next;
+    }
+    expanded.push_str(rest);
+    Ok(PathBuf::from(expanded))
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Filter::default().into()
+    }
+}
+
+impl From<Filter<'_>> for FilterConfig {
+    fn from(filter: Filter) -> Self {
+        FilterConfig {
+            extensions: filter.extensions.iter().map(|ext| ext.to_string()).collect(),
+            min_size: filter.min_size,
+            max_size: Some(filter.max_size).filter(|max| *max != u64::MAX),
+            ignore_hidden: filter.ignore_hidden,
+            video_cards: filter.video_cards,
+            attributes: filter.attributes,
+            max_files: filter.max_files,
+            every_nth: filter.every_nth,
+            sniff_contents: filter.sniff_contents,
+        }
+    }
+}
+
+impl<'ingest> IngestorBuilder<'ingest> {
+    /// A builder set up as `config` says, borrowing its paths and names
+    pub fn from_config(config: &'ingest IngestConfig) -> Self {
+        let structure = match config.structure {
+            StructureConfig::Rename(ref rename) => Structure::Rename(Rename {
+                name: rename.name.as_deref(),
+                position: rename.position,
+                sequence: rename.sequence,
+                zeroes: rename.zeroes,
+            }),
+            StructureConfig::Preserve => Structure::Preserve,
+            StructureConfig::Retain => Structure::Retain,
+        };
+        let filter = Filter {
+            extensions: Cow::Owned(config.filter.extensions.iter().map(String::as_str).collect()),
+            min_size: config.filter.min_size,
+            max_size: config.filter.max_size.unwrap_or(u64::MAX),
+            ignore_hidden: config.filter.ignore_hidden,
+            video_cards: config.filter.video_cards,
+            attributes: config.filter.attributes,
+            max_files: config.filter.max_files,
+            every_nth: config.filter.every_nth,
+            sniff_contents: config.filter.sniff_contents,
+        };
+        let mut builder = IngestorBuilder {
+            structure: Some(structure),
+            target: config.target.clone(),
+            backup: config.backup.clone(),
+            tee: config.tee,
+            filter: Some(filter),
+            sidecars: config.sidecars.as_ref().map(|extensions| SidecarPolicy {
+                extensions: Cow::Owned(extensions.iter().map(String::as_str).collect()),
+            }),
+            source_folders: config.source_folders,
+            routes: config.routes.clone(),
+            session_gap: config.session_gap,
+            bursts: config.bursts,
+            job_name: config.job_name.clone(),
+            order: config.order,
+            priority: config.priority.clone(),
+            timezone: config.timezone,
+            max_files_per_folder: config.max_files_per_folder,
+            collisions: config.collisions,
+            collision_suffix: config.collision_suffix.clone(),
+            skip_if_hash_exists_in_target: config.skip_if_hash_exists_in_target,
+            windows_names: config.windows_names,
+            finder: config.finder.clone(),
+            copy_jpg: config.copy_jpg,
+            pair_policy: config.pair_policy,
+            copy_orphans: config.copy_orphans,
+            metadata: config.metadata.clone(),
+            generate_missing_xmp: config.generate_missing_xmp,
+            gpx: config.gpx.as_ref().map(|(gpx, offset)| (gpx.as_path(), *offset)),
+            picks: config.picks.clone(),
+            #[cfg(feature = "previews")]
+            previews: config.previews,
+            timestamps: config.timestamps,
+            preserve_metadata: config.preserve_metadata,
+            atomic: config.atomic,
+            durability: config.durability,
+            no_cache: config.no_cache,
+            preallocate: config.preallocate,
+            retry: config.retry,
+            reflink: config.reflink,
+            on_error: config.on_error,
+            quarantine: config.quarantine,
+            mhl: config.mhl,
+            checksums: config.checksums,
+            #[cfg(feature = "validate")]
+            validate: config.validate,
+            buffer_size: config.buffer_size,
+            network: config.network,
+            reconnect_timeout: config.reconnect_timeout,
+            max_throughput: config.max_throughput,
+            reserved_space: config.reserved_space,
+            depth: config.depth,
+            min_depth: config.min_depth,
+            depth_from: config.depth_from,
+            included_subfolders: config.included_subfolders.as_ref().map(walk::subfolders),
+            excluded_subfolders: config.excluded_subfolders.as_ref().map(walk::subfolders),
+            concurrency: config.concurrency,
+            ..Default::default()
+        };
+        builder.with_source(&config.sources);
+        for (label, source) in &config.labels {
+            builder.with_labeled_source(label, source);
+        }
+        for (source, seconds) in &config.clock_offsets {
+            builder.with_clock_offset(source, *seconds);
+        }
+        for command in &config.post_copy_commands {
+            builder.with_post_copy_command(command);
+        }
+        builder
+    }
+
+    /// The saveable part of this builder, the closure hooks are left out
+    pub fn to_config(&self) -> IngestConfig {
+        let labels = self.labels.clone().unwrap_or_default();
+        let structure = match self.structure {
+            Some(Structure::Rename(rename)) => StructureConfig::Rename(RenameConfig {
+                name: rename.name.map(str::to_owned),
+                position: rename.position,
+                sequence: rename.sequence,
+                zeroes: rename.zeroes,
+            }),
+            Some(Structure::Preserve) => StructureConfig::Preserve,
+            Some(Structure::Retain) | None => StructureConfig::Retain,
+        };
+        IngestConfig {
+            name: None,
+            sources: self
+                .sources
+                .iter()
+                .flatten()
+                .filter(|source| !labels.contains_key(*source))
+                .map(|source| source.to_path_buf())
+                .collect(),
+            labels: labels
+                .iter()
+                .map(|(source, label)| (label.to_string(), source.to_path_buf()))
+                .collect(),
+            target: self.target.clone(),
+            backup: self.backup.clone(),
+            tee: self.tee,
+            structure,
+            filter: self.filter.clone().unwrap_or_default().into(),
+            sidecars: self.sidecars.as_ref().map(|sidecars| {
+                sidecars.extensions.iter().map(|ext| ext.to_string()).collect()
+            }),
+            verify: None,
+            source_folders: self.source_folders,
+            routes: self.routes.clone(),
+            session_gap: self.session_gap,
+            bursts: self.bursts,
+            job_name: self.job_name.clone(),
+            order: self.order,
+            priority: self.priority.clone(),
+            timezone: self.timezone,
+            clock_offsets: self
+                .clock_offsets
+                .iter()
+                .flatten()
+                .map(|(source, seconds)| (source.to_path_buf(), *seconds))
+                .collect(),
+            max_files_per_folder: self.max_files_per_folder,
+            collisions: self.collisions,
+            collision_suffix: self.collision_suffix.clone(),
+            skip_if_hash_exists_in_target: self.skip_if_hash_exists_in_target,
+            windows_names: self.windows_names,
+            finder: self.finder.clone(),
+            copy_jpg: self.copy_jpg,
+            pair_policy: self.pair_policy,
+            copy_orphans: self.copy_orphans,
+            metadata: self.metadata.clone(),
+            generate_missing_xmp: self.generate_missing_xmp,
+            gpx: self.gpx.map(|(gpx, offset)| (gpx.to_path_buf(), offset)),
+            picks: self.picks.clone(),
+            post_copy_commands: self
+                .copy_hooks
+                .iter()
+                .flatten()
+                .filter_map(|hook| match hook {
+                    CopyHook::Command(command) => Some(command.clone()),
+                    CopyHook::Closure(_) => None,
+                })
+                .collect(),
+            #[cfg(feature = "previews")]
+            previews: self.previews,
+            timestamps: self.timestamps,
+            preserve_metadata: self.preserve_metadata,
+            atomic: self.atomic,
+            durability: self.durability,
+            no_cache: self.no_cache,
+            preallocate: self.preallocate,
+            retry: self.retry,
+            reflink: self.reflink,
+            on_error: self.on_error,
+            quarantine: self.quarantine,
+            mhl: self.mhl,
+            checksums: self.checksums,
+            #[cfg(feature = "validate")]
+            validate: self.validate,
+            buffer_size: self.buffer_size,
+            network: self.network,
+            reconnect_timeout: self.reconnect_timeout,
+            max_throughput: self.max_throughput,
+            reserved_space: self.reserved_space,
+            depth: self.depth,
+            min_depth: self.min_depth,
+            depth_from: self.depth_from,
+            included_subfolders: self.included_subfolders.clone(),
+            excluded_subfolders: self.excluded_subfolders.clone(),
+            concurrency: self.concurrency,
+        }
+    }
+}
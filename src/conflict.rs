@@ -0,0 +1,132 @@
+use crate::*;
+use std::sync::Mutex;
+
+/// Something the ingestor asks about instead of deciding on its own
+#[derive(Debug, Clone)]
+pub enum Conflict {
+    /// `destination` is already taken at the target, by an earlier ingest or by another file
+    /// with the same name
+    Exists {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    /// The target, the backup or a destination doesn't have the space for the ingest, or later
+    /// for a file of it. [`Resolution::Abort`] stops it, [`Resolution::Skip`] leaves out the
+    /// files that don't fit in what's left above the reserved space and anything else copies them
+    /// anyway
+    InsufficientSpace { destination: PathBuf, needs: Needs },
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Conflict::Exists { destination, .. } => {
+                write!(f, "{} already exists", destination.display())
+            }
+            Conflict::InsufficientSpace { destination, needs } => write!(
+                f,
+                "{} needs {} more bytes of free space",
+                destination.display(),
+                needs.shortfall_bytes
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Replace what's at the destination
+    Overwrite,
+    /// Leave the file out, it's added to [`Report::skipped`]
+    Skip,
+    /// Copy next to it with a number, `DSC_0001-1.NEF`, like without a resolver
+    Rename,
+    /// Stop the ingest
+    Abort,
+}
+
+/// A [`Resolution`] and whether it answers the conflicts of the same kind that follow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub resolution: Resolution,
+    pub apply_to_all: bool,
+}
+
+impl From<Resolution> for Decision {
+    fn from(resolution: Resolution) -> Self {
+        Decision {
+            resolution,
+            apply_to_all: false,
+        }
+    }
+}
+
+/// Decides on the conflicts of an ingest, eg. by asking the user. Called from the task or
+/// thread doing the copies, which waits for the answer
+pub trait ConflictResolver: Send + Sync {
+    fn resolve(&self, conflict: &Conflict) -> Decision;
+}
+
+impl<F: Fn(&Conflict) -> Decision + Send + Sync> ConflictResolver for F {
+    fn resolve(&self, conflict: &Conflict) -> Decision {
+        self(conflict)
+    }
+}
+
+/// The resolver of an ingestor and the decisions it applied to all
+#[derive(Clone, Default)]
+pub struct Conflicts {
+    resolver: Option<Arc<dyn ConflictResolver>>,
+    all: Arc<Mutex<AppliedToAll>>,
+}
+
+#[derive(Debug, Default)]
+struct AppliedToAll {
+    exists: Option<Resolution>,
+    space: Option<Resolution>,
+}
+
+impl AppliedToAll {
+    fn of(&mut self, conflict: &Conflict) -> &mut Option<Resolution> {
+        match conflict {
+            Conflict::Exists { .. } => &mut self.exists,
+            Conflict::InsufficientSpace { .. } => &mut self.space,
+        }
+    }
+}
+
+impl std::fmt::Debug for Conflicts {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Conflicts")
+            .field("resolver", &self.resolver.is_some())
+            .finish()
+    }
+}
+
+impl Conflicts {
+    pub fn new(resolver: impl ConflictResolver + 'static) -> Self {
+        Conflicts {
+            resolver: Some(Arc::new(resolver)),
+            all: Default::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resolver.is_none()
+    }
+
+    /// How `conflict` is resolved, `None` without a resolver
+    pub(crate) fn resolve(&self, conflict: Conflict) -> Option<Resolution> {
+        let resolver = self.resolver.as_ref()?;
+        if let Some(resolution) = self.all.lock().ok().and_then(|mut all| *all.of(&conflict)) {
+            return Some(resolution);
+        }
+        let decision = resolver.resolve(&conflict);
+        if decision.apply_to_all {
+            if let Ok(mut all) = self.all.lock() {
+                *all.of(&conflict) = Some(decision.resolution);
+            }
+        }
+        Some(decision.resolution)
+    }
+}
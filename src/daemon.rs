@@ -0,0 +1,385 @@
+//! A background importer watching for camera cards, controlled over a local socket (a unix
+//! socket, or a named pipe on Windows) with one JSON object per line each way.
+//!
+//! The commands are `{"command": "cards"}`, `{"command": "ingests"}`,
+//! `{"command": "start", "card": "EOS_DIGITAL"}`, `{"command": "cancel", "card": "EOS_DIGITAL"}`
+//! and `{"command": "subscribe"}`, after which the connection receives the cards mounted and
+//! removed and the events of every ingest.
+use crate::devices::Card;
+use crate::*;
+use futures::future::BoxFuture;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::broadcast;
+
+/// How many messages a slow subscriber can lag behind before missing some
+const BACKLOG: usize = 1024;
+
+/// What [`IngestFactory::ingestor`] returns, a future so the ingest can set up destinations that
+/// connect somewhere first
+pub type IngestorFuture<'a> = BoxFuture<'a, std::result::Result<Ingestor<'a>, Error>>;
+
+/// Sets up the ingest of a card, the daemon adds its own event sink and runs it
+pub trait IngestFactory: Send + Sync + 'static {
+    fn ingestor<'a>(&'a self, card: &'a Card) -> IngestorFuture<'a>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    /// Lists the cards that are mounted
+    Cards,
+    /// Lists the running ingests and how far along they are
+    Ingests,
+    /// Ingests a card by its name or mount point
+    Start {
+        card: String,
+    },
+    Cancel {
+        card: String,
+    },
+    /// Streams the cards and the events of the ingests until the connection closes
+    Subscribe,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    Ok,
+    Error {
+        message: String,
+    },
+    Cards {
+        cards: Vec<Card>,
+    },
+    Ingests {
+        ingests: Vec<IngestStatus>,
+    },
+    Mounted {
+        card: Card,
+    },
+    Removed {
+        card: Card,
+    },
+    Event {
+        card: String,
+        event: Event,
+    },
+    /// An ingest is over, `error` is why it stopped early
+    Finished {
+        card: String,
+        files: usize,
+        failures: usize,
+        warnings: usize,
+        error: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct IngestStatus {
+    pub card: String,
+    pub files: usize,
+    pub bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// The card watcher and the control socket
+pub struct Daemon<F> {
+    factory: Arc<F>,
+    socket: PathBuf,
+    interval: Duration,
+    auto: bool,
+}
+
+impl<F: IngestFactory> Daemon<F> {
+    pub fn new(factory: F) -> Self {
+        Daemon {
+            factory: Arc::new(factory),
+            socket: default_socket(),
+            interval: Duration::from_secs(2),
+            auto: false,
+        }
+    }
+
+    /// Listens on `socket` instead of [`default_socket`]
+    pub fn with_socket(&mut self, socket: impl Into<PathBuf>) -> &mut Self {
+        self.socket = socket.into();
+        self
+    }
+
+    /// How often the mounted cards are looked for
+    pub fn with_interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Ingests every card as soon as it's mounted instead of waiting for a start command
+    pub fn auto_ingest(&mut self, auto: bool) -> &mut Self {
+        self.auto = auto;
+        self
+    }
+
+    /// Serves until the socket fails
+    pub async fn run(&self) -> Result<()> {
+        let shared = Arc::new(Shared {
+            factory: self.factory.clone(),
+            state: Mutex::new(State::default()),
+            messages: broadcast::channel(BACKLOG).0,
+        });
+        let watcher = tokio::spawn(watch(shared.clone(), self.interval, self.auto));
+        let served = listen(shared, &self.socket).await;
+        watcher.abort();
+        served
+    }
+}
+
+/// Where the daemon listens unless told otherwise
+pub fn default_socket() -> PathBuf {
+    #[cfg(windows)]
+    {
+        PathBuf::from(r"\\.\pipe\ingest")
+    }
+    #[cfg(not(windows))]
+    {
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir)
+            .join("ingest.sock")
+    }
+}
+
+struct Shared<F> {
+    factory: Arc<F>,
+    state: Mutex<State>,
+    messages: broadcast::Sender<Message>,
+}
+
+#[derive(Default)]
+struct State {
+    cards: Vec<Card>,
+    /// The handle is missing while the ingestor is being set up
+    ingests: HashMap<String, Option<IngestHandle>>,
+}
+
+impl<F: IngestFactory> Shared<F> {
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        // The state stays consistent even if a holder panicked
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn broadcast(&self, message: Message) {
+        // Nobody subscribed isn't an error
+        self.messages.send(message).ok();
+    }
+
+    fn handle(self: &Arc<Self>, command: Command) -> Message {
+        match command {
+            Command::Cards => Message::Cards {
+                cards: self.state().cards.clone(),
+            },
+            Command::Ingests => Message::Ingests {
+                ingests: self
+                    .state()
+                    .ingests
+                    .iter()
+                    .map(|(card, handle)| IngestStatus {
+                        card: card.clone(),
+                        files: handle.as_ref().map(IngestHandle::files).unwrap_or_default(),
+                        bytes: handle.as_ref().map(IngestHandle::bytes).unwrap_or_default(),
+                        total_bytes: handle
+                            .as_ref()
+                            .map(IngestHandle::total_bytes)
+                            .unwrap_or_default(),
+                    })
+                    .collect(),
+            },
+            Command::Start { card } => {
+                let found = self
+                    .state()
+                    .cards
+                    .iter()
+                    .find(|mounted| mounted.name == card || mounted.mount_point == Path::new(&card))
+                    .cloned();
+                match found {
+                    Some(card) => self.start(card),
+                    None => error(format!("No card {} is mounted", card)),
+                }
+            }
+            Command::Cancel { card } => {
+                let state = self.state();
+                match state.ingests.get(&card) {
+                    Some(Some(handle)) => {
+                        handle.cancel();
+                        Message::Ok
+                    }
+                    Some(None) => error(format!("The ingest of {} is still starting", card)),
+                    None => error(format!("{} isn't being ingested", card)),
+                }
+            }
+            // Answered by the connection
+            Command::Subscribe => Message::Ok,
+        }
+    }
+
+    /// Ingests `card` in the background
+    fn start(self: &Arc<Self>, card: Card) -> Message {
+        {
+            let mut state = self.state();
+            if state.ingests.contains_key(&card.name) {
+                return error(format!("{} is already being ingested", card.name));
+            }
+            state.ingests.insert(card.name.clone(), None);
+        }
+        let shared = self.clone();
+        tokio::spawn(async move {
+            let ingested = shared.ingest(&card).await;
+            shared.state().ingests.remove(&card.name);
+            let (files, failures, warnings, error) = match ingested {
+                Ok((files, report)) => (files, report.failures.len(), report.warnings.len(), None),
+                Err(e) => (0, 0, 0, Some(e.to_string())),
+            };
+            shared.broadcast(Message::Finished {
+                card: card.name,
+                files,
+                failures,
+                warnings,
+                error,
+            });
+        });
+        Message::Ok
+    }
+
+    async fn ingest(self: &Arc<Self>, card: &Card) -> Result<(usize, Report)> {
+        let mut ingestor = self.factory.ingestor(card).await?;
+        let (messages, name) = (self.messages.clone(), card.name.clone());
+        ingestor.events.push(move |event: &Event| {
+            messages
+                .send(Message::Event {
+                    card: name.clone(),
+                    event: event.clone(),
+                })
+                .ok();
+        });
+        let handle = ingestor.handle();
+        self.state()
+            .ingests
+            .insert(card.name.clone(), Some(handle.clone()));
+        ingestor.ingest().await?;
+        Ok((handle.files(), ingestor.report))
+    }
+}
+
+fn error(message: String) -> Message {
+    Message::Error { message }
+}
+
+/// Keeps the mounted cards up to date, ingesting the new ones with `auto`
+async fn watch<F: IngestFactory>(shared: Arc<Shared<F>>, interval: Duration, auto: bool) {
+    loop {
+        let cards = tokio::task::spawn_blocking(devices::cards)
+            .await
+            .unwrap_or_default();
+        let (mounted, removed) = {
+            let mut state = shared.state();
+            let mounted: Vec<Card> = cards
+                .iter()
+                .filter(|card| !state.cards.contains(card))
+                .cloned()
+                .collect();
+            let removed: Vec<Card> = state
+                .cards
+                .iter()
+                .filter(|card| !cards.contains(card))
+                .cloned()
+                .collect();
+            state.cards = cards;
+            (mounted, removed)
+        };
+        for card in removed {
+            shared.broadcast(Message::Removed { card });
+        }
+        for card in mounted {
+            shared.broadcast(Message::Mounted { card: card.clone() });
+            if auto {
+                shared.start(card);
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(unix)]
+async fn listen<F: IngestFactory>(shared: Arc<Shared<F>>, socket: &Path) -> Result<()> {
+    // Left behind by a daemon that didn't shut down cleanly
+    if tokio::net::UnixStream::connect(socket).await.is_err() {
+        std::fs::remove_file(socket).ok();
+    }
+    let listener = tokio::net::UnixListener::bind(socket)?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(serve(shared.clone(), stream));
+    }
+}
+
+#[cfg(windows)]
+async fn listen<F: IngestFactory>(shared: Arc<Shared<F>>, socket: &Path) -> Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(socket)?;
+    loop {
+        server.connect().await?;
+        // The next client connects to a new instance
+        let connected = std::mem::replace(&mut server, ServerOptions::new().create(socket)?);
+        tokio::spawn(serve(shared.clone(), connected));
+    }
+}
+
+/// Answers the commands of a connection, one per line
+async fn serve<F, S>(shared: Arc<Shared<F>>, stream: S)
+where
+    F: IngestFactory,
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (reply, subscribe) = match serde_json::from_str::<Command>(&line) {
+            Ok(command) => {
+                let subscribe = command == Command::Subscribe;
+                (shared.handle(command), subscribe)
+            }
+            Err(e) => (error(format!("Invalid command: {}", e)), false),
+        };
+        if send(&mut writer, &reply).await.is_err() {
+            return;
+        }
+        if subscribe {
+            let mut messages = shared.messages.subscribe();
+            loop {
+                match messages.recv().await {
+                    Ok(message) => {
+                        if send(&mut writer, &message).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        }
+    }
+}
+
+async fn send(writer: &mut (impl AsyncWrite + Unpin), message: &Message) -> Result<()> {
+    let mut line = serde_json::to_vec(message).map_err(Error::custom_error)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    writer.flush().await?;
+    Ok(())
+}
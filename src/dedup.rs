@@ -0,0 +1,183 @@
+//! The files of the target by content, so a card ingested again into an archive that already has
+//! its files, under whatever names, doesn't store them twice.
+//!
+//! Only the files of the target that have the size of a source file are hashed, once. The hashes
+//! are kept in [`INDEX_FILE`] at the target along with the modification time of the files, and
+//! taken again from there by the next ingests as long as the files didn't change
+use crate::*;
+use std::io::{BufRead, Write};
+use std::time::{Duration, SystemTime};
+
+/// The cache of the hashes of the files of the target, at its root
+pub(crate) const INDEX_FILE: &str = ".ingest-index";
+
+/// The algorithm the contents are compared with
+const ALGO: HashAlgo = HashAlgo::Xxh128;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HashIndex {
+    /// The files of the target by size
+    sizes: HashMap<u64, Vec<PathBuf>>,
+    /// The hashes known along with the modification time of the file they were taken at
+    hashes: HashMap<PathBuf, (u128, String)>,
+    /// Files were hashed since the cache was loaded
+    changed: bool,
+}
+
+impl HashIndex {
+    /// Lists the files of `target` and loads the cached hashes
+    fn build(vfs: &dyn Vfs, target: &Path) -> Self {
+        let mut index = HashIndex::default();
+        let entries = vfs::walk(vfs, target, usize::MAX, |entry| {
+            entry.path() == target || !is_bookkeeping(entry.path())
+        });
+        for entry in entries.filter(vfs::Entry::is_file) {
+            if let Ok(metadata) = vfs.metadata(entry.path()) {
+                index.insert(entry.into_path(), metadata.len);
+            }
+        }
+        let Ok(cache) = vfs.open(&target.join(INDEX_FILE)) else {
+            return index;
+        };
+        for line in std::io::BufReader::new(cache)
+            .lines()
+            .map_while(|line| line.ok())
+        {
+            let mut fields = line.splitn(3, ' ');
+            let (Some(hash), Some(modified), Some(path)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if let Ok(modified) = modified.parse() {
+                let path = target.join(path);
+                index.hashes.insert(path, (modified, hash.to_owned()));
+            }
+        }
+        index
+    }
+
+    pub(crate) fn insert(&mut self, path: PathBuf, size: u64) {
+        let files = self.sizes.entry(size).or_default();
+        if !files.contains(&path) {
+            files.push(path);
+        }
+    }
+
+    /// A file of the target with the contents of `input`
+    fn find(&mut self, vfs: &dyn Vfs, input: &Path) -> Result<Option<PathBuf>> {
+        let size = vfs.metadata(input)?.len;
+        // Every empty file would be the same
+        let Some(files) = self.sizes.get(&size).filter(|_| size > 0).cloned() else {
+            return Ok(None);
+        };
+        let hash = hash::hash_reader_with(vfs.open(input)?, &[ALGO])?.remove(0);
+        for file in files {
+            let Ok(metadata) = vfs.metadata(&file) else {
+                continue;
+            };
+            let modified = nanos(metadata.modified);
+            let known = self
+                .hashes
+                .get(&file)
+                .filter(|(at, _)| *at == modified)
+                .map(|(_, hash)| hash.clone());
+            let existing = match known {
+                Some(hash) => hash,
+                None => {
+                    let Ok(reader) = vfs.open(&file) else {
+                        continue;
+                    };
+                    let Ok(mut hashes) = hash::hash_reader_with(reader, &[ALGO]) else {
+                        continue;
+                    };
+                    let existing = hashes.remove(0);
+                    self.hashes
+                        .insert(file.clone(), (modified, existing.clone()));
+                    self.changed = true;
+                    existing
+                }
+            };
+            if existing == hash {
+                return Ok(Some(file));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Writes the hashes taken back to the cache at `target`
+    fn save(&mut self, vfs: &dyn Vfs, target: &Path) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        let mut cache = vfs.create(&target.join(INDEX_FILE))?;
+        for (path, (modified, hash)) in &self.hashes {
+            if let Ok(relative) = path.strip_prefix(target) {
+                writeln!(cache, "{} {} {}", hash, modified, relative.display())?;
+            }
+        }
+        cache.flush()?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+/// Whether `path` is written by the ingests next to the copies rather than one of them
+fn is_bookkeeping(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(OsStr::to_string_lossy)
+        .unwrap_or_default();
+    #[cfg(feature = "previews")]
+    if name == preview::PREVIEWS_FOLDER {
+        return true;
+    }
+    name.starts_with('.')
+        || name == mhl::MHL_FOLDER
+        || name == FAILURE_MANIFEST
+        || name.starts_with("checksums.")
+        || path
+            .extension()
+            .is_some_and(|ext| ext == PART_EXTENSION || ext == FAILED_EXTENSION)
+}
+
+pub(crate) fn nanos(time: Option<SystemTime>) -> u128 {
+    time.and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .unwrap_or(Duration::ZERO)
+        .as_nanos()
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// The copy already in the target of `input`, when it's left out for it
+    pub(crate) fn duplicate_in_target(&mut self, input: &Path) -> Result<Option<PathBuf>> {
+        if !self.skip_if_hash_exists_in_target {
+            return Ok(None);
+        }
+        let vfs = self.shared_vfs();
+        let index = self
+            .__index
+            .get_or_insert_with(|| HashIndex::build(vfs.as_ref(), &self.target));
+        index.find(vfs.as_ref(), input)
+    }
+
+    /// Adds the copy at `output` to the files of the target the next files are compared with
+    pub(crate) fn index(&mut self, output: &Path) {
+        if self.__index.is_none() {
+            return;
+        }
+        if let Ok(metadata) = self.vfs().metadata(output) {
+            if let Some(index) = self.__index.as_mut() {
+                index.insert(output.to_path_buf(), metadata.len);
+            }
+        }
+    }
+
+    /// Keeps the hashes taken during the pass for the next ingests into the target
+    pub(crate) fn save_index(&mut self) -> Result<()> {
+        let vfs = self.shared_vfs();
+        match self.__index.take() {
+            Some(mut index) => index.save(vfs.as_ref(), &self.target),
+            None => Ok(()),
+        }
+    }
+}
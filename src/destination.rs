@@ -0,0 +1,110 @@
+use crate::*;
+use futures::future::BoxFuture;
+
+/// The future returned by [`Destination::store`]
+pub type StoreFuture<'a> = BoxFuture<'a, std::result::Result<(), Error>>;
+/// The future returned by [`Destination::capacity`] and [`Destination::free`], None when the
+/// destination can't tell or has no limit
+pub type SpaceFuture<'a> = BoxFuture<'a, std::result::Result<Option<u64>, Error>>;
+
+/// Somewhere other than a folder the copies also go to, eg. cloud object storage. Every file
+/// copied to the target is stored at the destinations as well, with the same structure
+pub trait Destination: Send + Sync {
+    /// Where the files go, eg. `s3://bucket/prefix`
+    fn name(&self) -> String;
+
+    /// Stores the file at `path` as `key`, its path under the target with `/` separators. It
+    /// should fail unless the stored file was verified
+    fn store<'a>(&'a self, path: &'a Path, key: &'a str) -> StoreFuture<'a>;
+
+    /// Called once the ingest is over, eg. to close an archive
+    fn finish(&self) -> StoreFuture<'_> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Bytes the destination can hold in all, eg. the size of the disk or of the quota
+    fn capacity(&self) -> SpaceFuture<'_> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Bytes that can still be stored, the ingest only checks that everything fits when it's
+    /// known
+    fn free(&self) -> SpaceFuture<'_> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+/// The destinations an ingestor stores its copies at
+#[derive(Clone, Default)]
+pub struct Destinations {
+    destinations: Vec<Arc<dyn Destination>>,
+}
+
+impl std::fmt::Debug for Destinations {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_list()
+            .entries(
+                self.destinations
+                    .iter()
+                    .map(|destination| destination.name()),
+            )
+            .finish()
+    }
+}
+
+impl Destinations {
+    pub fn push(&mut self, destination: impl Destination + 'static) {
+        self.destinations.push(Arc::new(destination));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.destinations.is_empty()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Arc<dyn Destination>> {
+        self.destinations.iter()
+    }
+}
+
+/// The key of `output` under `target`, None when it's elsewhere
+pub(crate) fn key(target: &Path, output: &Path) -> Option<String> {
+    let relative = output.strip_prefix(target).ok()?;
+    let parts: Vec<_> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    Some(parts.join("/"))
+}
+
+/// The space of the disk holding `path`, or the closest folder above it that exists, for the
+/// destinations writing to local or mounted folders
+#[cfg(any(feature = "archive", feature = "encrypt"))]
+pub(crate) fn disk_space(path: &Path, space: fn(&Path) -> std::io::Result<u64>) -> SpaceFuture<'_> {
+    Box::pin(async move {
+        let existing = path.ancestors().find(|dir| dir.is_dir()).unwrap_or(path);
+        Ok(Some(space(existing)?))
+    })
+}
+
+/// Runs the blocking IO of a destination on the blocking pool, so it doesn't hold up the other
+/// copies on the async workers
+#[cfg(any(feature = "archive", feature = "encrypt"))]
+pub(crate) async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    #[cfg(feature = "async")]
+    return tokio::task::spawn_blocking(work)
+        .await
+        .map_err(Error::custom_error)?;
+    #[cfg(not(feature = "async"))]
+    work()
+}
+
+pub(crate) fn store_failed(destination: &dyn Destination, key: &str, error: Error) -> Error {
+    Error::custom_error(format!(
+        "Couldn't store {} at {}: {}",
+        key,
+        destination.name(),
+        error
+    ))
+}
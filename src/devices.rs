@@ -0,0 +1,142 @@
+//! Finding the camera cards that are mounted, by the folders cameras create at their root.
+use crate::*;
+
+/// Folders at the root of a card written by a camera, photos and video
+pub const CARD_ROOTS: [&str; 4] = ["DCIM", "PRIVATE", "XDROOT", "M4ROOT"];
+
+/// A mounted volume that looks like a camera card
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Card {
+    pub mount_point: PathBuf,
+    /// Name of the volume, eg. `NIKON Z 6` or `EOS_DIGITAL`
+    pub name: String,
+}
+
+/// Where the photos and clips are on a card, the rest of it is firmware, settings and the
+/// indexes of the camera. Matched whatever their case
+pub const SOURCE_ROOTS: [&str; 6] = [
+    "DCIM",
+    "PRIVATE/AVCHD",
+    "PRIVATE/M4ROOT",
+    "AVCHD",
+    "M4ROOT",
+    "XDROOT",
+];
+
+/// The folders of a card worth ingesting, to pass the root of a volume without copying its
+/// `MISC` folder or the firmware updates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub mount_point: PathBuf,
+    /// Name of the volume, the label of the roots
+    pub name: String,
+    /// The [`SOURCE_ROOTS`] found on the card
+    pub roots: Vec<PathBuf>,
+}
+
+impl Source {
+    /// Finds the [`SOURCE_ROOTS`] of the card mounted at `mount_point`, fails when it has none
+    pub fn from_card(mount_point: impl AsRef<Path>) -> Result<Self> {
+        let mount_point = mount_point.as_ref().to_path_buf();
+        let roots: Vec<PathBuf> = SOURCE_ROOTS
+            .iter()
+            .filter_map(|root| find_folder(&mount_point, root))
+            .collect();
+        if roots.is_empty() {
+            return Err(Error::custom_error(format!(
+                "No DCIM or video folder on {}",
+                mount_point.display()
+            )));
+        }
+        Ok(Source {
+            name: volume_name(&mount_point),
+            mount_point,
+            roots,
+        })
+    }
+}
+
+impl From<&Card> for Source {
+    /// The whole card when none of the [`SOURCE_ROOTS`] are found
+    fn from(card: &Card) -> Self {
+        Source::from_card(&card.mount_point).unwrap_or_else(|_| Source {
+            mount_point: card.mount_point.clone(),
+            name: card.name.clone(),
+            roots: vec![card.mount_point.clone()],
+        })
+    }
+}
+
+/// Follows `relative` from `parent` one folder at a time whatever their case
+fn find_folder(parent: &Path, relative: &str) -> Option<PathBuf> {
+    relative
+        .split('/')
+        .try_fold(parent.to_path_buf(), |folder, name| {
+            std::fs::read_dir(&folder)
+                .ok()?
+                .flatten()
+                .map(|entry| entry.path())
+                .find(|path| {
+                    path.is_dir()
+                        && path
+                            .file_name()
+                            .is_some_and(|n| n.eq_ignore_ascii_case(name))
+                })
+        })
+}
+
+fn volume_name(mount_point: &Path) -> String {
+    mount_point
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| mount_point.display().to_string())
+}
+
+/// Returns the mounted volumes that hold one of the [`CARD_ROOTS`]
+pub fn cards() -> Vec<Card> {
+    volumes()
+        .into_iter()
+        .filter(|volume| CARD_ROOTS.iter().any(|root| volume.join(root).is_dir()))
+        .map(|mount_point| Card {
+            name: volume_name(&mount_point),
+            mount_point,
+        })
+        .collect()
+}
+
+/// Returns where removable volumes are usually mounted on this platform
+pub fn volumes() -> Vec<PathBuf> {
+    #[cfg(windows)]
+    {
+        (b'A'..=b'Z')
+            .map(|letter| PathBuf::from(format!("{}:\\", letter as char)))
+            .filter(|drive| drive.exists())
+            .collect()
+    }
+    #[cfg(not(windows))]
+    {
+        let user = std::env::var("USER").unwrap_or_default();
+        let parents: Vec<PathBuf> = if cfg!(target_os = "macos") {
+            vec!["/Volumes".into()]
+        } else {
+            vec![
+                Path::new("/media").join(&user),
+                Path::new("/run/media").join(&user),
+                "/media".into(),
+                "/mnt".into(),
+            ]
+        };
+        let mut volumes: Vec<PathBuf> = parents
+            .iter()
+            .filter_map(|parent| std::fs::read_dir(parent).ok())
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect();
+        volumes.sort();
+        volumes.dedup();
+        volumes
+    }
+}
@@ -0,0 +1,71 @@
+use crate::*;
+
+/// When the copies are flushed to the disk, so that a finished ingest means the card can be
+/// formatted even if the power goes right after
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Durability {
+    /// Leave it to the OS, the fastest
+    #[default]
+    None,
+    /// Flush every copy before moving on
+    PerFile,
+    /// Flush every copy and the folder it was renamed in before moving on
+    PerFileAndDir,
+    /// Flush everything copied at the end of each pass, the whole target filesystem at once on
+    /// Linux
+    EndOfRun,
+}
+
+impl Durability {
+    pub fn syncs_files(&self) -> bool {
+        matches!(self, Durability::PerFile | Durability::PerFileAndDir)
+    }
+
+    pub fn syncs_folders(&self) -> bool {
+        *self == Durability::PerFileAndDir
+    }
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Remembers the copy at `output` to be flushed at the end of the pass
+    pub(crate) fn written(&mut self, output: &Path) {
+        if self.durability == Durability::EndOfRun && self.vfs.is_none() {
+            self.__unsynced.push(output.to_path_buf());
+        }
+    }
+
+    /// Flushes the copies of the pass and the folders they're in
+    pub(crate) fn sync_pass(&mut self) -> Result<()> {
+        let unsynced = std::mem::take(&mut self.__unsynced);
+        if unsynced.is_empty() {
+            return Ok(());
+        }
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd;
+            // The backup got its copies along with the target when they were teed
+            let teed = self.backup.as_ref().filter(|_| self.tees());
+            let mut synced = true;
+            for root in std::iter::once(&self.target).chain(teed) {
+                let root = std::fs::File::open(root)?;
+                // SAFETY: plain call on an open descriptor
+                synced &= unsafe { libc::syncfs(root.as_raw_fd()) } == 0;
+            }
+            if synced {
+                return Ok(());
+            }
+        }
+        let mut folders = HashSet::new();
+        for path in &unsynced {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(path)?
+                .sync_all()?;
+            if path.parent().is_some_and(|folder| folders.insert(folder)) {
+                crate::sync_dir(path)?;
+            }
+        }
+        Ok(())
+    }
+}
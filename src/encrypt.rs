@@ -0,0 +1,372 @@
+//! Encrypted backups, built with the `encrypt` feature, so the shuttle drives that leave the
+//! venue don't carry readable media.
+//!
+//! Every file is encrypted with AES-256-GCM under a key the ingest is given, in chunks that are
+//! each authenticated so a damaged, truncated or reordered file fails to decrypt instead of
+//! decrypting to garbage. The encrypted files keep their names, with [`ENCRYPTED_EXTENSION`]
+//! added, and are restored with [`decrypt`].
+use crate::*;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fs::File;
+use std::io::{Read, Write};
+
+/// Extension added to the encrypted files
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+/// Start of every encrypted file, with the version of the format
+const MAGIC: &[u8; 8] = b"INGESTE1";
+/// Random start of the nonces of a file, followed by the number of the chunk and whether it's
+/// the last one
+const PREFIX_LEN: usize = NONCE_LEN - 5;
+/// Size of the chunks before encryption
+const CHUNK: usize = 1024 * 1024;
+const TAG_LEN: usize = 16;
+
+/// A 256-bit key, kept as 64 hex digits in a key file
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// A random key
+    pub fn generate() -> Result<Self> {
+        let mut key = [0; 32];
+        random(&mut key)?;
+        Ok(EncryptionKey(key))
+    }
+
+    pub fn from_bytes(key: [u8; 32]) -> Self {
+        EncryptionKey(key)
+    }
+
+    /// Parses the 64 hex digits of a key
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let hex = hex.trim();
+        let mut key = [0; 32];
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(Error::custom_error("A key is 64 hex digits"));
+        }
+        for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let digits = std::str::from_utf8(digits).unwrap_or_default();
+            *byte = u8::from_str_radix(digits, 16)
+                .map_err(|_| Error::custom_error("A key is 64 hex digits"))?;
+        }
+        Ok(EncryptionKey(key))
+    }
+
+    pub fn to_hex(&self) -> String {
+        hash::to_hex(&self.0)
+    }
+
+    /// Reads the key file at `path`
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_hex(&std::fs::read_to_string(path)?)
+    }
+
+    /// Writes the key to a new file at `path`, only readable by its owner on unix
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path)?;
+        writeln!(file, "{}", self.to_hex())?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    fn cipher(&self) -> LessSafeKey {
+        // A 32 bytes key is always valid
+        LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &self.0).expect("AES-256 key"))
+    }
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+/// Encrypts the file at `input` to `output`, returning the size of `input`
+pub fn encrypt_file(
+    key: &EncryptionKey,
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+) -> Result<u64> {
+    let mut reader = File::open(input)?;
+    let mut writer = File::create(output)?;
+    let size = encrypt(key, &mut reader, &mut writer)?;
+    writer.sync_all()?;
+    Ok(size)
+}
+
+/// Decrypts the file at `input` to `output`, returning the size of `output`
+pub fn decrypt_file(
+    key: &EncryptionKey,
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+) -> Result<u64> {
+    let input = input.as_ref();
+    let mut reader = File::open(input)?;
+    let mut writer = File::create(output.as_ref())?;
+    match decrypt_to(key, &mut reader, &mut writer) {
+        Ok(size) => Ok(size),
+        Err(e) => {
+            drop(writer);
+            std::fs::remove_file(output).ok();
+            Err(Error::custom_error(format!("{}: {}", input.display(), e)))
+        }
+    }
+}
+
+/// Decrypts the file at `from`, or every encrypted file under the folder `from`, to `to` with
+/// the same structure, returning the number of files restored
+pub fn decrypt(key: &EncryptionKey, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<usize> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    if from.is_file() {
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        decrypt_file(key, from, to)?;
+        return Ok(1);
+    }
+    let mut restored = 0;
+    for entry in walkdir::WalkDir::new(from) {
+        let entry = entry.map_err(std::io::Error::from)?;
+        let path = entry.path();
+        let encrypted = path
+            .extension()
+            .is_some_and(|extension| extension == ENCRYPTED_EXTENSION);
+        if !entry.file_type().is_file() || !encrypted {
+            continue;
+        }
+        let Ok(relative) = path.strip_prefix(from) else {
+            continue;
+        };
+        let output = to.join(relative).with_extension("");
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        decrypt_file(key, path, output)?;
+        restored += 1;
+    }
+    Ok(restored)
+}
+
+/// Encrypts `reader` into `writer`, returning the number of bytes read
+fn encrypt(key: &EncryptionKey, reader: &mut impl Read, writer: &mut impl Write) -> Result<u64> {
+    let cipher = key.cipher();
+    let mut prefix = [0; PREFIX_LEN];
+    random(&mut prefix)?;
+    writer.write_all(MAGIC)?;
+    writer.write_all(&prefix)?;
+
+    let mut size = 0;
+    let mut chunk = Vec::with_capacity(CHUNK + TAG_LEN);
+    let mut next = vec![0; CHUNK];
+    let mut filled = fill(reader, &mut next)?;
+    let mut counter = 0u32;
+    loop {
+        chunk.clear();
+        chunk.extend_from_slice(&next[..filled]);
+        size += filled as u64;
+        // One more read to know whether this chunk is the last
+        filled = match filled {
+            CHUNK => fill(reader, &mut next)?,
+            _ => 0,
+        };
+        let last = filled == 0;
+        cipher
+            .seal_in_place_append_tag(nonce(&prefix, counter, last), Aad::from(MAGIC), &mut chunk)
+            .map_err(|_| Error::custom_error("Couldn't encrypt"))?;
+        writer.write_all(&chunk)?;
+        if last {
+            return Ok(size);
+        }
+        counter = next_counter(counter)?;
+    }
+}
+
+/// Decrypts `reader` into `writer`, returning the number of bytes written
+fn decrypt_to(key: &EncryptionKey, reader: &mut impl Read, writer: &mut impl Write) -> Result<u64> {
+    let damaged = || Error::custom_error("Wrong key or damaged file");
+    let cipher = key.cipher();
+    let mut header = [0; MAGIC.len() + PREFIX_LEN];
+    if fill(reader, &mut header)? != header.len() || &header[..MAGIC.len()] != MAGIC {
+        return Err(Error::custom_error("Not an encrypted file"));
+    }
+    let prefix: [u8; PREFIX_LEN] = header[MAGIC.len()..].try_into().unwrap_or_default();
+
+    let mut size = 0;
+    let mut chunk = vec![0; CHUNK + TAG_LEN];
+    let mut next = vec![0; CHUNK + TAG_LEN];
+    let mut filled = fill(reader, &mut next)?;
+    let mut counter = 0u32;
+    loop {
+        std::mem::swap(&mut chunk, &mut next);
+        let length = filled;
+        filled = match length == chunk.len() {
+            true => fill(reader, &mut next)?,
+            false => 0,
+        };
+        let last = filled == 0;
+        let plain = cipher
+            .open_in_place(
+                nonce(&prefix, counter, last),
+                Aad::from(MAGIC),
+                &mut chunk[..length],
+            )
+            .map_err(|_| damaged())?;
+        writer.write_all(plain)?;
+        size += plain.len() as u64;
+        if last {
+            return Ok(size);
+        }
+        counter = next_counter(counter)?;
+    }
+}
+
+fn nonce(prefix: &[u8; PREFIX_LEN], counter: u32, last: bool) -> Nonce {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[..PREFIX_LEN].copy_from_slice(prefix);
+    nonce[PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&counter.to_be_bytes());
+    nonce[NONCE_LEN - 1] = last as u8;
+    Nonce::assume_unique_for_key(nonce)
+}
+
+/// A nonce can't be used twice, the files are limited to 4 PiB
+fn next_counter(counter: u32) -> Result<u32> {
+    counter
+        .checked_add(1)
+        .ok_or_else(|| Error::custom_error("The file is too large to encrypt"))
+}
+
+fn random(bytes: &mut [u8]) -> Result<()> {
+    SystemRandom::new()
+        .fill(bytes)
+        .map_err(|_| Error::custom_error("No randomness available"))
+}
+
+/// Reads until `buffer` is full or the end of `reader`, returning how much was read
+fn fill(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// A folder the copies are stored in encrypted, eg. a backup drive that leaves the venue
+#[derive(Clone)]
+pub struct EncryptedFolder {
+    root: PathBuf,
+    key: EncryptionKey,
+}
+
+impl EncryptedFolder {
+    pub fn new(root: impl Into<PathBuf>, key: EncryptionKey) -> Self {
+        EncryptedFolder {
+            root: root.into(),
+            key,
+        }
+    }
+
+    /// Encrypts next to the final path and decrypts it back to check it before renaming it
+    fn encrypt(&self, path: &Path, key: &str) -> Result<()> {
+        let mut output = self.root.join(key).into_os_string();
+        output.push(".");
+        output.push(ENCRYPTED_EXTENSION);
+        let output = PathBuf::from(output);
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let partial = crate::part_path(&output);
+        let stored = self.encrypt_checked(path, &partial);
+        if stored.is_err() {
+            std::fs::remove_file(&partial).ok();
+        }
+        stored?;
+        std::fs::rename(&partial, &output)?;
+        Ok(())
+    }
+
+    fn encrypt_checked(&self, path: &Path, partial: &Path) -> Result<()> {
+        let algo = HashAlgo::default();
+        let mut reader = HashingReader {
+            inner: File::open(path)?,
+            hasher: algo.hasher(),
+        };
+        let mut writer = File::create(partial)?;
+        encrypt(&self.key, &mut reader, &mut writer)?;
+        writer.sync_all()?;
+        let expected = reader.hasher.finish();
+
+        let mut decrypted = HashingWriter(algo.hasher());
+        decrypt_to(&self.key, &mut File::open(partial)?, &mut decrypted)?;
+        let actual = decrypted.0.finish();
+        if actual != expected {
+            return Err(Error::custom_error(format!(
+                "{} decrypts to {} instead of {}",
+                partial.display(),
+                actual,
+                expected
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for EncryptedFolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("EncryptedFolder").field(&self.root).finish()
+    }
+}
+
+impl Destination for EncryptedFolder {
+    fn name(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn store<'a>(&'a self, path: &'a Path, key: &'a str) -> StoreFuture<'a> {
+        let (folder, path, key) = (self.clone(), path.to_path_buf(), key.to_string());
+        Box::pin(destination::blocking(move || folder.encrypt(&path, &key)))
+    }
+
+    fn capacity(&self) -> SpaceFuture<'_> {
+        destination::disk_space(&self.root, |path| fs2::total_space(path))
+    }
+
+    fn free(&self) -> SpaceFuture<'_> {
+        destination::disk_space(&self.root, |path| fs2::free_space(path))
+    }
+}
+
+struct HashingWriter(Hasher);
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+struct HashingReader<R> {
+    inner: R,
+    hasher: Hasher,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
@@ -0,0 +1,164 @@
+use crate::*;
+#[cfg(feature = "json")]
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often progress snapshots are sent while copying
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What happens during an ingest, sent to the [`EventSink`]s as it happens
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(tag = "event", rename_all = "snake_case")
+)]
+pub enum Event {
+    /// A file started copying to `destination`
+    Started { path: PathBuf, destination: PathBuf },
+    Finished {
+        path: PathBuf,
+        destination: PathBuf,
+        bytes: u64,
+    },
+    Failed {
+        path: PathBuf,
+        destination: PathBuf,
+        error: String,
+    },
+    /// The ingest moved on to `phase`
+    Phase { phase: Phase },
+    /// `files` to copy were found so far, sent every few hundred milliseconds while scanning
+    Scanning { files: usize },
+    /// The same numbers as the [`IngestHandle`], sent every few hundred milliseconds
+    Progress {
+        files: usize,
+        bytes: u64,
+        total_bytes: u64,
+        /// Bytes per second over the last few seconds
+        speed: f64,
+        /// Seconds left
+        eta: Option<f64>,
+        sources: Vec<SourceProgress>,
+    },
+}
+
+/// Receives the events of an ingest, called from the task or thread doing the copies so it
+/// shouldn't block for long
+pub trait EventSink: Send + Sync {
+    fn event(&self, event: &Event);
+}
+
+impl<F: Fn(&Event) + Send + Sync> EventSink for F {
+    fn event(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// The sinks an ingestor sends its events to
+#[derive(Clone, Default)]
+pub struct Events {
+    sinks: Vec<Arc<dyn EventSink>>,
+    last_progress: Arc<Mutex<Option<Instant>>>,
+}
+
+impl std::fmt::Debug for Events {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Events")
+            .field("sinks", &self.sinks.len())
+            .finish()
+    }
+}
+
+impl Events {
+    pub fn push(&mut self, sink: impl EventSink + 'static) {
+        self.sinks.push(Arc::new(sink));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    pub(crate) fn emit(&self, event: Event) {
+        for sink in &self.sinks {
+            sink.event(&event);
+        }
+    }
+
+    /// Whether the last snapshot is old enough for another one, always when `force`d
+    fn due(&self, force: bool) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        if let Ok(mut last) = self.last_progress.lock() {
+            let now = Instant::now();
+            if !force && last.is_some_and(|last| now.duration_since(last) < PROGRESS_INTERVAL) {
+                return false;
+            }
+            *last = Some(now);
+        }
+        true
+    }
+
+    /// Sends the number of files found while scanning, only if the last snapshot is old enough
+    pub(crate) fn scanning(&self, files: usize) {
+        if self.due(false) {
+            self.emit(Event::Scanning { files });
+        }
+    }
+
+    /// Sends a progress snapshot, only if the last one is old enough unless `force`d
+    pub(crate) fn progress(&self, handle: &IngestHandle, force: bool) {
+        if !self.due(force) {
+            return;
+        }
+        self.emit(Event::Progress {
+            files: handle.files(),
+            bytes: handle.bytes(),
+            total_bytes: handle.total_bytes(),
+            speed: handle.speed(),
+            eta: handle.eta().map(|eta| eta.as_secs_f64()),
+            sources: handle.sources(),
+        });
+    }
+}
+
+/// Writes every event as a line of JSON, for the programs wrapping the ingestor
+#[cfg(feature = "json")]
+pub struct JsonEventSink {
+    out: Mutex<Box<dyn std::io::Write + Send>>,
+}
+
+#[cfg(feature = "json")]
+impl JsonEventSink {
+    pub fn new(out: impl std::io::Write + Send + 'static) -> Self {
+        JsonEventSink {
+            out: Mutex::new(Box::new(out)),
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(std::io::stdout())
+    }
+
+    /// Writes to the file at `path`, replacing it
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = std::fs::File::create(path)?;
+        Ok(Self::new(std::io::LineWriter::new(file)))
+    }
+}
+
+#[cfg(feature = "json")]
+impl EventSink for JsonEventSink {
+    fn event(&self, event: &Event) {
+        let Ok(mut out) = self.out.lock() else {
+            return;
+        };
+        // A reader that went away isn't a reason to stop copying
+        if serde_json::to_writer(&mut *out, event).is_ok() {
+            writeln!(out).ok();
+            out.flush().ok();
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! A C interface to the ingestor, declared in `include/ingest.h`.
+//!
+//! An ingestor is created with `ingest_new`, configured with the JSON form of an
+//! [`IngestConfig`] and run with `ingest_run`, which blocks until it's done. Another thread can
+//! poll the progress and cancel it meanwhile. The strings returned are owned by the caller and
+//! freed with `ingest_string_free`.
+use crate::*;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+/// An ingestor as seen from C
+pub struct IngestFfi {
+    config: Mutex<IngestConfig>,
+    cancel: Arc<AtomicBool>,
+    handle: Mutex<Option<IngestHandle>>,
+    report: Mutex<Option<Report>>,
+    error: Mutex<Option<String>>,
+}
+
+/// How far along the ingest is, all zeroes before it starts
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IngestProgress {
+    pub files: u64,
+    pub bytes: u64,
+    pub total_bytes: u64,
+    /// Bytes per second over the last few seconds
+    pub speed: f64,
+}
+
+impl IngestFfi {
+    fn fail(&self, error: impl std::fmt::Display) -> c_int {
+        *lock(&self.error) = Some(error.to_string());
+        -1
+    }
+
+    fn run(&self) -> Result<()> {
+        let config = lock(&self.config).clone();
+        let mut ingestor = IngestorBuilder::from_config(&config).build()?;
+        ingestor.cancel = self.cancel.clone();
+        *lock(&self.handle) = Some(ingestor.handle());
+        // A cancel only stops the run it was meant for
+        self.cancel.store(false, Ordering::SeqCst);
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        let ingested = runtime.block_on(ingestor.ingest());
+        // Reported even when the ingest stopped early
+        *lock(&self.report) = Some(ingestor.report);
+        ingested
+    }
+}
+
+/// A poisoned lock only means a panic was caught while it was held
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Null for the strings with a nul in them, which JSON escapes
+fn to_c_string(string: String) -> *mut c_char {
+    CString::new(string).map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Creates an ingestor with the default configuration, freed with `ingest_free`
+#[no_mangle]
+pub extern "C" fn ingest_new() -> *mut IngestFfi {
+    Box::into_raw(Box::new(IngestFfi {
+        config: Mutex::new(IngestConfig::default()),
+        cancel: Arc::default(),
+        handle: Mutex::new(None),
+        report: Mutex::new(None),
+        error: Mutex::new(None),
+    }))
+}
+
+/// Frees an ingestor, which mustn't be running
+///
+/// # Safety
+/// `ingest` comes from `ingest_new` and isn't used afterwards, or is null
+#[no_mangle]
+pub unsafe extern "C" fn ingest_free(ingest: *mut IngestFfi) {
+    if !ingest.is_null() {
+        drop(Box::from_raw(ingest));
+    }
+}
+
+/// Replaces the configuration with `config`, the JSON form of an [`IngestConfig`]. Returns 0,
+/// or -1 with the reason in `ingest_last_error`
+///
+/// # Safety
+/// `ingest` comes from `ingest_new` and `config` is a nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn ingest_configure(
+    ingest: *const IngestFfi,
+    config: *const c_char,
+) -> c_int {
+    let Some(ingest) = ingest.as_ref() else {
+        return -1;
+    };
+    if config.is_null() {
+        return ingest.fail("No configuration");
+    }
+    let config = match CStr::from_ptr(config).to_str() {
+        Ok(config) => config,
+        Err(e) => return ingest.fail(e),
+    };
+    match serde_json::from_str::<IngestConfig>(config) {
+        Ok(config) => {
+            *lock(&ingest.config) = config;
+            0
+        }
+        Err(e) => ingest.fail(e),
+    }
+}
+
+/// Ingests, blocking until it's done. Returns 0, or -1 with the reason in `ingest_last_error`
+///
+/// # Safety
+/// `ingest` comes from `ingest_new`
+#[no_mangle]
+pub unsafe extern "C" fn ingest_run(ingest: *const IngestFfi) -> c_int {
+    let Some(ingest) = ingest.as_ref() else {
+        return -1;
+    };
+    *lock(&ingest.error) = None;
+    // Unwinding into C is undefined
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| ingest.run())) {
+        Ok(Ok(())) => 0,
+        Ok(Err(e)) => ingest.fail(e),
+        Err(_) => ingest.fail("The ingest panicked"),
+    }
+}
+
+/// Stops the running ingest. Callable from any thread
+///
+/// # Safety
+/// `ingest` comes from `ingest_new`
+#[no_mangle]
+pub unsafe extern "C" fn ingest_cancel(ingest: *const IngestFfi) {
+    if let Some(ingest) = ingest.as_ref() {
+        ingest.cancel.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Writes how far along the ingest is to `progress`. Callable from any thread, returns -1 when
+/// a pointer is null
+///
+/// # Safety
+/// `ingest` comes from `ingest_new` and `progress` points to an `IngestProgress`
+#[no_mangle]
+pub unsafe extern "C" fn ingest_progress(
+    ingest: *const IngestFfi,
+    progress: *mut IngestProgress,
+) -> c_int {
+    let (Some(ingest), Some(progress)) = (ingest.as_ref(), progress.as_mut()) else {
+        return -1;
+    };
+    *progress = match lock(&ingest.handle).as_ref() {
+        Some(handle) => IngestProgress {
+            files: handle.files() as u64,
+            bytes: handle.bytes(),
+            total_bytes: handle.total_bytes(),
+            speed: handle.speed(),
+        },
+        None => IngestProgress::default(),
+    };
+    0
+}
+
+/// Returns the report of the last ingest as JSON, null before the first one
+///
+/// # Safety
+/// `ingest` comes from `ingest_new`
+#[no_mangle]
+pub unsafe extern "C" fn ingest_report_json(ingest: *const IngestFfi) -> *mut c_char {
+    let Some(ingest) = ingest.as_ref() else {
+        return std::ptr::null_mut();
+    };
+    let report = lock(&ingest.report);
+    match report.as_ref().map(serde_json::to_string) {
+        Some(Ok(json)) => to_c_string(json),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Returns why the last call failed, null if it didn't
+///
+/// # Safety
+/// `ingest` comes from `ingest_new`
+#[no_mangle]
+pub unsafe extern "C" fn ingest_last_error(ingest: *const IngestFfi) -> *mut c_char {
+    match ingest
+        .as_ref()
+        .and_then(|ingest| lock(&ingest.error).clone())
+    {
+        Some(error) => to_c_string(error),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Frees a string returned by the other functions
+///
+/// # Safety
+/// `string` was returned by this library and isn't used afterwards, or is null
+#[no_mangle]
+pub unsafe extern "C" fn ingest_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
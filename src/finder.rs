@@ -0,0 +1,173 @@
+//! What the copies look like in the Finder on macOS, where the pro importers tag them with the
+//! card they came from. The options are ignored on the other platforms
+#[cfg(target_os = "macos")]
+use crate::*;
+
+/// Finder tags and comments of the copies, and how the ingest shows in the Finder meanwhile
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Finder {
+    /// Tags set on every copy in place of the ones copied along, may contain [`LABEL_TOKEN`](crate::LABEL_TOKEN)
+    pub tags: Vec<String>,
+    /// Comment set on every copy, may contain [`LABEL_TOKEN`](crate::LABEL_TOKEN)
+    pub comment: Option<String>,
+    /// Hide the `.ingestpart` files from the Finder and its Spotlight searches while they're
+    /// written
+    pub hide_partial: bool,
+    /// Set the modification date of the target folders to the end of the ingest, so the Finder
+    /// sorts them by the latest ingest and not by the files that happened to go in directly
+    pub touch_folders: bool,
+}
+
+#[cfg(target_os = "macos")]
+const TAGS: &str = "com.apple.metadata:_kMDItemUserTags";
+#[cfg(target_os = "macos")]
+const COMMENT: &str = "com.apple.metadata:kMDItemFinderComment";
+
+#[cfg(target_os = "macos")]
+impl<'ingest> Ingestor<'ingest> {
+    /// Sets the tags and comment on the copy of `input` at `output`
+    pub(crate) fn tag(&mut self, input: &Path, output: &Path) {
+        if self.vfs.is_some() || (self.finder.tags.is_empty() && self.finder.comment.is_none()) {
+            return;
+        }
+        let label = self
+            .source_of(input)
+            .map(|source| self.source_label(source).to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let expand = |text: &String| text.replace(LABEL_TOKEN, &label);
+        let tags: Vec<String> = self.finder.tags.iter().map(expand).collect();
+        let comment = self.finder.comment.as_ref().map(expand);
+        let tagged = (|| -> std::io::Result<()> {
+            if !tags.is_empty() {
+                xattr::set(output, TAGS, &plist(&tags, true))?;
+            }
+            if let Some(comment) = comment {
+                xattr::set(output, COMMENT, &plist(&[comment], false))?;
+            }
+            Ok(())
+        })();
+        if let Err(e) = tagged {
+            self.report.warn(Warning::Finder {
+                path: output.to_path_buf(),
+                error: e.to_string(),
+            });
+        }
+    }
+
+    /// Hides or shows the file at `path` being copied, when the copies are staged
+    pub(crate) fn hide_partial(&self, path: &Path, hidden: bool) -> std::io::Result<()> {
+        match self.hides_partial() {
+            true => hide(path, hidden),
+            false => Ok(()),
+        }
+    }
+
+    /// Whether the files are hidden while they're copied, see [`Finder::hide_partial`]
+    pub(crate) fn hides_partial(&self) -> bool {
+        self.finder.hide_partial && self.atomic && self.vfs.is_none()
+    }
+
+    /// Sets the modification date of the folders copied into during the pass and the ones
+    /// above them up to the target
+    pub(crate) fn touch_folders(&self) {
+        if !self.finder.touch_folders || self.vfs.is_some() {
+            return;
+        }
+        let now = std::time::SystemTime::now();
+        let mut touched = HashSet::new();
+        for folder in self.__taken.keys() {
+            for folder in folder.ancestors() {
+                if !folder.starts_with(&self.target) || !touched.insert(folder) {
+                    break;
+                }
+                std::fs::File::open(folder)
+                    .and_then(|dir| dir.set_modified(now))
+                    .ok();
+            }
+        }
+    }
+}
+
+/// A binary property list of `strings`, in an array or the first one on its own
+#[cfg(target_os = "macos")]
+fn plist(strings: &[String], array: bool) -> Vec<u8> {
+    // The references to the objects are single bytes
+    let strings = match array {
+        true => &strings[..strings.len().min(254)],
+        false => &strings[..strings.len().min(1)],
+    };
+    let mut plist = b"bplist00".to_vec();
+    let mut offsets = Vec::new();
+    if array {
+        offsets.push(plist.len());
+        marker(&mut plist, 0xA0, strings.len());
+        plist.extend(1..=strings.len() as u8);
+    }
+    for string in strings {
+        offsets.push(plist.len());
+        if string.is_ascii() {
+            marker(&mut plist, 0x50, string.len());
+            plist.extend_from_slice(string.as_bytes());
+        } else {
+            let units: Vec<u16> = string.encode_utf16().collect();
+            marker(&mut plist, 0x60, units.len());
+            plist.extend(units.iter().flat_map(|unit| unit.to_be_bytes()));
+        }
+    }
+    let table = plist.len();
+    let size = match table {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        _ => 4,
+    };
+    for offset in &offsets {
+        plist.extend_from_slice(&(*offset as u64).to_be_bytes()[8 - size..]);
+    }
+    plist.extend_from_slice(&[0; 6]);
+    plist.extend_from_slice(&[size as u8, 1]);
+    plist.extend_from_slice(&(offsets.len() as u64).to_be_bytes());
+    plist.extend_from_slice(&0u64.to_be_bytes());
+    plist.extend_from_slice(&(table as u64).to_be_bytes());
+    plist
+}
+
+/// The marker of an object of `kind` holding `len` items
+#[cfg(target_os = "macos")]
+fn marker(plist: &mut Vec<u8>, kind: u8, len: usize) {
+    if len < 0x0F {
+        plist.push(kind | len as u8);
+        return;
+    }
+    plist.push(kind | 0x0F);
+    match len {
+        0..=0xFF => plist.extend_from_slice(&[0x10, len as u8]),
+        0x100..=0xFFFF => {
+            plist.push(0x11);
+            plist.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            plist.push(0x12);
+            plist.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+}
+
+/// Hides or shows the file at `path` in the Finder and its Spotlight searches
+#[cfg(target_os = "macos")]
+pub(crate) fn hide(path: &Path, hidden: bool) -> std::io::Result<()> {
+    use std::os::macos::fs::MetadataExt;
+    use std::os::unix::ffi::OsStrExt;
+    let flags = std::fs::symlink_metadata(path)?.st_flags();
+    let flags = match hidden {
+        true => flags | libc::UF_HIDDEN,
+        false => flags & !libc::UF_HIDDEN,
+    };
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: the path is a valid nul terminated string
+    match unsafe { libc::chflags(path.as_ptr(), flags) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
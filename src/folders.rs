@@ -0,0 +1,117 @@
+//! The folders of the sources as a tree, for a source browser where folders are ticked to ingest
+use crate::*;
+
+/// A folder of a source with the files in it the filter keeps
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FolderTree {
+    pub path: PathBuf,
+    pub name: String,
+    /// Sorted by name
+    pub folders: Vec<FolderTree>,
+    /// Files kept by the filter right in the folder
+    pub files: usize,
+    pub bytes: u64,
+    /// Same as `files` and `bytes` with the files of the folders under it
+    pub total_files: usize,
+    pub total_bytes: u64,
+}
+
+impl FolderTree {
+    fn new(path: &Path) -> Self {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        Self {
+            path: path.to_path_buf(),
+            name,
+            ..Default::default()
+        }
+    }
+
+    fn add_file(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+        self.total_files += 1;
+        self.total_bytes += bytes;
+    }
+
+    fn add_folder(&mut self, folder: FolderTree) {
+        self.total_files += folder.total_files;
+        self.total_bytes += folder.total_bytes;
+        self.folders.push(folder);
+    }
+
+    /// Every folder of the tree, this one first
+    pub fn iter(&self) -> impl Iterator<Item = &FolderTree> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let folder = stack.pop()?;
+            stack.extend(folder.folders.iter().rev());
+            Some(folder)
+        })
+    }
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// The folders of every source with the files the filter keeps, walked like an ingest does
+    /// but leaving out the sampling of [`Filter::every_nth`] and [`Filter::max_files`]. The
+    /// sources that are files have no tree
+    pub fn folder_tree(&self) -> Vec<FolderTree> {
+        let mut sources: Vec<&Path> = self.sources.iter().copied().collect();
+        sources.sort();
+        sources
+            .into_iter()
+            .filter_map(|source| self.folder_tree_of(source))
+            .collect()
+    }
+
+    fn folder_tree_of(&self, source: &Path) -> Option<FolderTree> {
+        // Every folder is listed whichever are ticked
+        let scope = walk::Scope {
+            included: Vec::new(),
+            excluded: Vec::new(),
+            ..self.scope()
+        };
+        let vfs = self.vfs();
+        // The folders from the source to the current one, closed once the walk leaves them
+        let mut open: Vec<FolderTree> = Vec::new();
+        let close = |open: &mut Vec<FolderTree>| {
+            let folder = open.pop()?;
+            match open.last_mut() {
+                Some(parent) => {
+                    parent.add_folder(folder);
+                    None
+                }
+                None => Some(folder),
+            }
+        };
+        let walk = vfs::walk(vfs, source, scope.walked(), |entry| {
+            !entry.is_dir() || walk::enters(vfs, &self.filter, &scope, entry)
+        });
+        for entry in walk {
+            while open.len() > entry.depth() {
+                close(&mut open);
+            }
+            if entry.is_dir() {
+                open.push(FolderTree::new(entry.path()));
+            } else if let Some(folder) = open.last_mut() {
+                let kept = scope.keeps(&entry)
+                    && self.filter.matches_in(vfs, entry.path()).unwrap_or(false);
+                if kept {
+                    folder.add_file(
+                        vfs.metadata(entry.path())
+                            .map(|m| m.len)
+                            .unwrap_or_default(),
+                    );
+                }
+            }
+        }
+        let mut tree = None;
+        while !open.is_empty() {
+            tree = close(&mut open);
+        }
+        tree
+    }
+}
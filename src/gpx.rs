@@ -0,0 +1,176 @@
+use crate::*;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Furthest a photo can be from the track points around it and still be placed
+const MAX_GAP: Duration = Duration::from_secs(5 * 60);
+
+/// A position of a GPS track
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPoint {
+    pub time: SystemTime,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Meters above sea level
+    pub elevation: Option<f64>,
+}
+
+/// The points of a GPX track sorted by time
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Track {
+    points: Vec<TrackPoint>,
+}
+
+impl Track {
+    pub fn from_gpx(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Reads the `trkpt`s of a GPX document, the ones without a time are skipped
+    pub fn parse(gpx: &str) -> Result<Self> {
+        let mut points = Vec::new();
+        for point in gpx.split("<trkpt").skip(1) {
+            let point = point.split("</trkpt>").next().unwrap_or(point);
+            let (Some(latitude), Some(longitude), Some(time)) = (
+                attribute(point, "lat").and_then(|lat| lat.parse().ok()),
+                attribute(point, "lon").and_then(|lon| lon.parse().ok()),
+                element(point, "time").and_then(parse_time),
+            ) else {
+                continue;
+            };
+            points.push(TrackPoint {
+                time,
+                latitude,
+                longitude,
+                elevation: element(point, "ele").and_then(|ele| ele.parse().ok()),
+            });
+        }
+        if points.is_empty() {
+            return Err(Error::custom_error("No timed track points in the GPX"));
+        }
+        points.sort_by_key(|point| point.time);
+        Ok(Track { points })
+    }
+
+    pub fn points(&self) -> &[TrackPoint] {
+        &self.points
+    }
+
+    /// Returns where the track was at `time`, interpolated between the points around it
+    pub fn locate(&self, time: SystemTime) -> Option<TrackPoint> {
+        let after = self.points.partition_point(|point| point.time <= time);
+        let before = after.checked_sub(1).map(|i| self.points[i]);
+        let after = self.points.get(after).copied();
+        let gap = |a: SystemTime, b: SystemTime| {
+            a.duration_since(b).or_else(|_| b.duration_since(a)).unwrap_or_default()
+        };
+        match (before, after) {
+            (Some(before), Some(after)) if gap(after.time, before.time) <= MAX_GAP => {
+                let span = gap(after.time, before.time).as_secs_f64();
+                let t = match span {
+                    span if span > 0.0 => gap(time, before.time).as_secs_f64() / span,
+                    _ => 0.0,
+                };
+                let lerp = |a: f64, b: f64| a + (b - a) * t;
+                Some(TrackPoint {
+                    time,
+                    latitude: lerp(before.latitude, after.latitude),
+                    longitude: lerp(before.longitude, after.longitude),
+                    elevation: before.elevation.zip(after.elevation).map(|(a, b)| lerp(a, b)),
+                })
+            }
+            (before, after) => [before, after]
+                .into_iter()
+                .flatten()
+                .filter(|point| gap(point.time, time) <= MAX_GAP)
+                .min_by_key(|point| gap(point.time, time)),
+        }
+    }
+}
+
+/// A track to place the photos on and the offset of the camera clock from UTC in minutes, used
+/// when the camera didn't record one
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Geotag {
+    pub track: Track,
+    pub offset: i16,
+}
+
+fn attribute<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    let head = element.split('>').next()?;
+    let start = head.find(&format!("{}=", name))? + name.len() + 1;
+    let quote = head[start..].chars().next()?;
+    let value = &head[start + 1..];
+    Some(&value[..value.find(quote)?])
+}
+
+fn element<'a>(parent: &'a str, name: &str) -> Option<&'a str> {
+    let start = parent.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = parent[start..].find(&format!("</{}>", name))?;
+    Some(parent[start..start + end].trim())
+}
+
+/// Parses an ISO 8601 time as found in GPX files, eg. `2024-06-01T12:30:05Z`
+fn parse_time(time: &str) -> Option<SystemTime> {
+    let number = |range: std::ops::Range<usize>| time.get(range)?.parse::<u32>().ok();
+    let days = times::days_from_civil(
+        number(0..4)?.into(),
+        number(5..7)?,
+        number(8..10)?,
+    );
+    let mut secs = days * 86400
+        + i64::from(number(11..13)?) * 3600
+        + i64::from(number(14..16)?) * 60
+        + i64::from(number(17..19)?);
+
+    let rest = &time[19..];
+    let zone = rest.trim_start_matches(|c: char| c == '.' || c.is_ascii_digit());
+    let nanos = match &rest[..rest.len() - zone.len()] {
+        fraction if fraction.len() > 1 => (format!("0{}", fraction).parse::<f64>().ok()? * 1e9) as u32,
+        _ => 0,
+    };
+    if let Some(sign @ ('+' | '-')) = zone.chars().next() {
+        let offset = i64::from(number(time.len() - 5..time.len() - 3)?) * 3600
+            + i64::from(number(time.len() - 2..time.len())?) * 60;
+        secs -= if sign == '+' { offset } else { -offset };
+    }
+    Some(UNIX_EPOCH + Duration::new(u64::try_from(secs).ok()?, nanos))
+}
+
+impl Geotag {
+    /// When the photo at `path` was taken, from its EXIF when the camera recorded it and the
+    /// modification time otherwise
+    fn captured(&self, path: &Path) -> Option<SystemTime> {
+        #[cfg(feature = "exif")]
+        if let Some(mut captured) = crate::meta::read(path).ok().and_then(|meta| meta.captured) {
+            captured.offset = captured.offset.or(Some(self.offset));
+            return captured.to_system_time();
+        }
+        path.metadata().ok()?.modified().ok()
+    }
+
+    /// The GPS properties of the photo at `path`, empty when it was taken off the track
+    pub(crate) fn properties(&self, path: &Path) -> Vec<xmp::Property> {
+        let Some(point) = self.captured(path).and_then(|time| self.track.locate(time)) else {
+            return Vec::new();
+        };
+        let mut properties = vec![
+            xmp::Property::text("exif:GPSVersionID", "2.2.0.0"),
+            xmp::Property::text("exif:GPSLatitude", &coordinate(point.latitude, 'N', 'S')),
+            xmp::Property::text("exif:GPSLongitude", &coordinate(point.longitude, 'E', 'W')),
+        ];
+        if let Some(elevation) = point.elevation {
+            let reference = if elevation < 0.0 { "1" } else { "0" };
+            let altitude = format!("{}/100", (elevation.abs() * 100.0).round() as u64);
+            properties.push(xmp::Property::text("exif:GPSAltitudeRef", reference));
+            properties.push(xmp::Property::text("exif:GPSAltitude", &altitude));
+        }
+        properties
+    }
+}
+
+/// Eg. `48,51.396000N`, the XMP form of a GPS coordinate
+fn coordinate(degrees: f64, positive: char, negative: char) -> String {
+    let direction = if degrees < 0.0 { negative } else { positive };
+    let degrees = degrees.abs();
+    format!("{},{:.6}{}", degrees.trunc(), degrees.fract() * 60.0, direction)
+}
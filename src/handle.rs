@@ -0,0 +1,216 @@
+use crate::*;
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How far back the rolling speed looks
+const SPEED_WINDOW: Duration = Duration::from_secs(5);
+
+/// A cloneable handle to watch and control a running ingest from another task or thread
+#[derive(Debug, Clone)]
+pub struct IngestHandle {
+    progress: Arc<AtomicUsize>,
+    bytes: Arc<AtomicU64>,
+    cancel: Arc<AtomicBool>,
+    max_throughput: Arc<AtomicU64>,
+    total: Arc<AtomicU64>,
+    speed: Arc<Mutex<Speedometer>>,
+    current: Arc<Mutex<Option<PathBuf>>>,
+    phase: Arc<Mutex<Option<Phase>>>,
+    scanned: Arc<AtomicUsize>,
+    sources: Arc<Mutex<Vec<SourceProgress>>>,
+}
+
+impl IngestHandle {
+    /// Number of files copied so far
+    pub fn files(&self) -> usize {
+        self.progress.load(Ordering::SeqCst)
+    }
+
+    /// Number of bytes copied so far
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::SeqCst)
+    }
+
+    /// What the ingest is doing, `None` before it starts
+    pub fn phase(&self) -> Option<Phase> {
+        *self.phase.lock().ok()?
+    }
+
+    /// Number of files to copy found so far while [`Phase::Scanning`]
+    pub fn scanned(&self) -> usize {
+        self.scanned.load(Ordering::SeqCst)
+    }
+
+    /// How far each of the sources got
+    pub fn sources(&self) -> Vec<SourceProgress> {
+        self.sources
+            .lock()
+            .map(|sources| sources.clone())
+            .unwrap_or_default()
+    }
+
+    /// The file being copied, or the last one
+    pub fn current_file(&self) -> Option<PathBuf> {
+        self.current.lock().ok()?.clone()
+    }
+
+    /// Number of bytes the whole ingest copies, including the backup
+    pub fn total_bytes(&self) -> u64 {
+        self.total.load(Ordering::SeqCst)
+    }
+
+    /// Bytes per second over the last few seconds
+    pub fn speed(&self) -> f64 {
+        self.speed.lock().map(|s| s.rolling()).unwrap_or_default()
+    }
+
+    /// Bytes per second since the ingest started
+    pub fn average_speed(&self) -> f64 {
+        self.speed
+            .lock()
+            .map(|s| s.average(self.bytes()))
+            .unwrap_or_default()
+    }
+
+    /// Estimated time left at the current speed, `None` until anything has been copied
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.total_bytes().saturating_sub(self.bytes());
+        let speed = match self.speed() {
+            speed if speed > 0.0 => speed,
+            _ => self.average_speed(),
+        };
+        (speed > 0.0).then(|| Duration::from_secs_f64(remaining as f64 / speed))
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+
+    /// Limits the copy speed in bytes per second, `None` removes the limit
+    pub fn set_max_throughput(&self, max_throughput: Option<u64>) {
+        self.max_throughput
+            .store(max_throughput.unwrap_or_default(), Ordering::SeqCst);
+    }
+
+    pub fn max_throughput(&self) -> Option<u64> {
+        match self.max_throughput.load(Ordering::SeqCst) {
+            0 => None,
+            max => Some(max),
+        }
+    }
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    pub fn handle(&self) -> IngestHandle {
+        IngestHandle {
+            progress: self.progress.clone(),
+            bytes: self.bytes.clone(),
+            cancel: self.cancel.clone(),
+            max_throughput: self.max_throughput.clone(),
+            total: self.__total.clone(),
+            speed: self.__speed.clone(),
+            current: self.__current.clone(),
+            phase: self.__phase.clone(),
+            scanned: self.__scanned.clone(),
+            sources: self.__sources.clone(),
+        }
+    }
+
+    pub(crate) fn set_current(&self, path: &Path) {
+        if let Ok(mut current) = self.__current.lock() {
+            *current = Some(path.to_path_buf());
+        }
+    }
+}
+
+/// Samples of the bytes copied over time
+#[derive(Debug, Default)]
+pub(crate) struct Speedometer {
+    started: Option<Instant>,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl Speedometer {
+    pub(crate) fn start() -> Self {
+        Speedometer {
+            started: Some(Instant::now()),
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Records that `bytes` have been copied in total by now
+    pub(crate) fn record(&mut self, bytes: u64) {
+        let now = Instant::now();
+        self.samples.push_back((now, bytes));
+        while let Some(&(at, _)) = self.samples.front() {
+            if now.duration_since(at) <= SPEED_WINDOW {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    /// Time since the ingest started
+    #[cfg(feature = "metrics")]
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.started
+            .map(|started| started.elapsed())
+            .unwrap_or_default()
+    }
+
+    fn rolling(&self) -> f64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some(&(from, first)), Some(&(to, last))) if to > from => {
+                (last - first) as f64 / to.duration_since(from).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn average(&self, bytes: u64) -> f64 {
+        match self.started.map(|started| started.elapsed().as_secs_f64()) {
+            Some(elapsed) if elapsed > 0.0 => bytes as f64 / elapsed,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Keeps a copy under the max throughput by telling it how long to pause after every chunk
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    started: std::time::Instant,
+    bytes: u64,
+    limit: u64,
+}
+
+impl Throttle {
+    pub(crate) fn new() -> Self {
+        Throttle {
+            started: std::time::Instant::now(),
+            bytes: 0,
+            limit: 0,
+        }
+    }
+
+    /// Records `bytes` more copied and returns how long to wait with `limit` bytes per second
+    pub(crate) fn delay(&mut self, limit: u64, bytes: u64) -> Option<std::time::Duration> {
+        // Start measuring again when the limit changes so the time spent at the previous speed
+        // doesn't count
+        if limit != self.limit {
+            *self = Throttle::new();
+            self.limit = limit;
+        }
+        self.bytes += bytes;
+        if limit == 0 {
+            return None;
+        }
+        let expected = std::time::Duration::from_secs_f64(self.bytes as f64 / limit as f64);
+        expected.checked_sub(self.started.elapsed())
+    }
+}
@@ -0,0 +1,161 @@
+use crate::*;
+use md5::Digest;
+use std::io::Read;
+
+/// Size of the reads when hashing files
+const HASH_BUFFER: usize = 1024 * 1024;
+
+/// Hash algorithms used for manifests and verification
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HashAlgo {
+    Md5,
+    Sha1,
+    Sha256,
+    Xxh64,
+    Xxh3,
+    #[default]
+    Xxh128,
+}
+
+impl HashAlgo {
+    /// The name used for the algorithm in manifests
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgo::Md5 => "md5",
+            HashAlgo::Sha1 => "sha1",
+            HashAlgo::Sha256 => "sha256",
+            HashAlgo::Xxh64 => "xxh64",
+            HashAlgo::Xxh3 => "xxh3",
+            HashAlgo::Xxh128 => "xxh128",
+        }
+    }
+
+    pub fn hasher(&self) -> Hasher {
+        match self {
+            HashAlgo::Md5 => Hasher::Md5(md5::Md5::new()),
+            HashAlgo::Sha1 => Hasher::Sha1(sha1::Sha1::new()),
+            HashAlgo::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            HashAlgo::Xxh64 => Hasher::Xxh64(xxhash_rust::xxh64::Xxh64::new(0)),
+            HashAlgo::Xxh3 => Hasher::Xxh3(Box::new(xxhash_rust::xxh3::Xxh3::new())),
+            HashAlgo::Xxh128 => Hasher::Xxh128(Box::new(xxhash_rust::xxh3::Xxh3::new())),
+        }
+    }
+
+    /// Returns the hex encoded hash of the file at `path`
+    pub fn hash_file(&self, path: impl AsRef<Path>) -> std::io::Result<String> {
+        Ok(hash_file_with(path, &[*self])?.remove(0))
+    }
+}
+
+/// Hashes the file at `path` with all the `algos` in a single read
+pub fn hash_file_with(path: impl AsRef<Path>, algos: &[HashAlgo]) -> std::io::Result<Vec<String>> {
+    hash_reader_with(std::fs::File::open(path)?, algos)
+}
+
+/// Same as [`hash_file_with`] for anything that can be read, eg. a file of a [`Vfs`]
+pub fn hash_reader_with(mut file: impl Read, algos: &[HashAlgo]) -> std::io::Result<Vec<String>> {
+    let mut hashers: Vec<Hasher> = algos.iter().map(HashAlgo::hasher).collect();
+    let mut buffer = vec![0; HASH_BUFFER];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break,
+            read => hashers
+                .iter_mut()
+                .for_each(|hasher| hasher.update(&buffer[..read])),
+        }
+    }
+    Ok(hashers.into_iter().map(Hasher::finish).collect())
+}
+
+/// A file copied into the tree, as recorded in the manifests
+#[derive(Debug, Clone)]
+pub struct HashedFile {
+    /// Absolute path of the copy
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<std::time::SystemTime>,
+    pub hashes: Vec<(HashAlgo, String)>,
+    pub hashed_at: std::time::SystemTime,
+}
+
+impl HashedFile {
+    pub fn hash(&self, algo: HashAlgo) -> Option<&str> {
+        self.hashes
+            .iter()
+            .find(|(a, _)| *a == algo)
+            .map(|(_, hash)| hash.as_str())
+    }
+}
+
+impl std::str::FromStr for HashAlgo {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "md5" => Ok(HashAlgo::Md5),
+            "sha1" => Ok(HashAlgo::Sha1),
+            "sha256" => Ok(HashAlgo::Sha256),
+            "xxh64" => Ok(HashAlgo::Xxh64),
+            "xxh3" => Ok(HashAlgo::Xxh3),
+            "xxh128" => Ok(HashAlgo::Xxh128),
+            _ => Err(Error::custom_error(format!("Unknown hash algorithm {}", s))),
+        }
+    }
+}
+
+/// A running hash of one of the [`HashAlgo`]s
+pub enum Hasher {
+    Md5(md5::Md5),
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+    Xxh64(xxhash_rust::xxh64::Xxh64),
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+    Xxh128(Box<xxhash_rust::xxh3::Xxh3>),
+}
+
+impl Hasher {
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Md5(h) => h.update(bytes),
+            Hasher::Sha1(h) => h.update(bytes),
+            Hasher::Sha256(h) => h.update(bytes),
+            Hasher::Xxh64(h) => h.update(bytes),
+            Hasher::Xxh3(h) | Hasher::Xxh128(h) => h.update(bytes),
+        }
+    }
+
+    /// Returns the hex encoded hash
+    pub fn finish(self) -> String {
+        match self {
+            Hasher::Md5(h) => to_hex(&h.finalize()),
+            Hasher::Sha1(h) => to_hex(&h.finalize()),
+            Hasher::Sha256(h) => to_hex(&h.finalize()),
+            Hasher::Xxh64(h) => format!("{:016x}", h.digest()),
+            Hasher::Xxh3(h) => format!("{:016x}", h.digest()),
+            Hasher::Xxh128(h) => format!("{:032x}", h.digest128()),
+        }
+    }
+}
+
+impl std::fmt::Debug for Hasher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("Hasher").finish()
+    }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Keeps the last record of every path, a sidecar shared by a RAW and its jpeg is copied twice
+/// to the same place
+pub(crate) fn latest_by_path(files: &[HashedFile]) -> Vec<&HashedFile> {
+    let mut seen = HashSet::new();
+    let mut files: Vec<&HashedFile> = files
+        .iter()
+        .rev()
+        .filter(|file| seen.insert(&file.path))
+        .collect();
+    files.reverse();
+    files
+}
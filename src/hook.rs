@@ -0,0 +1,147 @@
+use crate::*;
+use futures::future::BoxFuture;
+use std::ffi::OsString;
+use std::future::Future;
+
+/// The future returned by a hook closure
+pub type HookFuture = BoxFuture<'static, std::result::Result<(), Error>>;
+
+/// Runs after every file copied successfully, to the target and to the backup, for custom steps
+/// like a DNG conversion or a catalog insertion. A failing hook is only a warning as the file was
+/// copied
+#[derive(Clone)]
+pub enum CopyHook {
+    /// Called with the source and the copy
+    Closure(Arc<dyn Fn(PathBuf, PathBuf) -> HookFuture + Send + Sync>),
+    /// A command line run with `{src}` and `{dst}` replaced by the paths, eg.
+    /// `dnglab convert {dst} {dst}.dng`
+    Command(String),
+}
+
+impl std::fmt::Debug for CopyHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CopyHook::Closure(_) => f.write_str("Closure"),
+            CopyHook::Command(command) => f.debug_tuple("Command").field(command).finish(),
+        }
+    }
+}
+
+impl CopyHook {
+    pub fn closure<F, Fut>(hook: F) -> Self
+    where
+        F: Fn(PathBuf, PathBuf) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = std::result::Result<(), Error>> + Send + 'static,
+    {
+        CopyHook::Closure(Arc::new(move |src, dst| Box::pin(hook(src, dst))))
+    }
+
+    pub fn command(template: impl Into<String>) -> Self {
+        CopyHook::Command(template.into())
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) async fn run(&self, src: &Path, dst: &Path) -> Result<()> {
+        match self {
+            CopyHook::Closure(hook) => hook(src.to_path_buf(), dst.to_path_buf()).await,
+            CopyHook::Command(template) => {
+                let (program, args) = command_line(template, src, dst)?;
+                let status = tokio::process::Command::new(&program).args(args).status().await?;
+                exited(&program, status)
+            }
+        }
+    }
+
+    #[cfg(feature = "sync")]
+    pub(crate) fn run_blocking(&self, src: &Path, dst: &Path) -> Result<()> {
+        match self {
+            CopyHook::Closure(hook) => {
+                ingest::sync::block_on(hook(src.to_path_buf(), dst.to_path_buf()))
+            }
+            CopyHook::Command(template) => {
+                let (program, args) = command_line(template, src, dst)?;
+                let status = std::process::Command::new(&program).args(args).status()?;
+                exited(&program, status)
+            }
+        }
+    }
+}
+
+/// Called with the report of the ingest so far
+pub type ReportHook = Arc<dyn Fn(Report) -> HookFuture + Send + Sync>;
+
+/// Run at the stages of an ingest, eg. to mount a volume, pause an indexer, send a notification
+/// or start a catalog import
+#[derive(Clone, Default)]
+pub struct LifecycleHooks {
+    /// Before anything is checked or copied, failing stops the ingest
+    pub before_ingest: Option<Arc<dyn Fn() -> HookFuture + Send + Sync>>,
+    /// Once every file is at the target, before the backup
+    pub after_ingest: Option<ReportHook>,
+    /// Once every file is at the backup
+    pub after_backup: Option<ReportHook>,
+}
+
+impl std::fmt::Debug for LifecycleHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("LifecycleHooks")
+            .field("before_ingest", &self.before_ingest.is_some())
+            .field("after_ingest", &self.after_ingest.is_some())
+            .field("after_backup", &self.after_backup.is_some())
+            .finish()
+    }
+}
+
+impl LifecycleHooks {
+    pub(crate) fn before_ingest(&self) -> Option<HookFuture> {
+        self.before_ingest.as_ref().map(|hook| hook())
+    }
+
+    pub(crate) fn after_ingest(&self, report: &Report) -> Option<HookFuture> {
+        self.after_ingest.as_ref().map(|hook| hook(report.clone()))
+    }
+
+    pub(crate) fn after_backup(&self, report: &Report) -> Option<HookFuture> {
+        self.after_backup.as_ref().map(|hook| hook(report.clone()))
+    }
+}
+
+/// Splits `template` on whitespace and fills in the placeholders, a path with spaces stays a
+/// single argument
+fn command_line(template: &str, src: &Path, dst: &Path) -> Result<(OsString, Vec<OsString>)> {
+    let mut words = template.split_whitespace().map(|word| {
+        let mut arg = OsString::new();
+        let mut rest = word;
+        while let Some(start) = rest.find('{') {
+            let path = match &rest[start..] {
+                placeholder if placeholder.starts_with("{src}") => src,
+                placeholder if placeholder.starts_with("{dst}") => dst,
+                _ => {
+                    arg.push(&rest[..=start]);
+                    rest = &rest[start + 1..];
+                    continue;
+                }
+            };
+            arg.push(&rest[..start]);
+            arg.push(path);
+            rest = &rest[start + 5..];
+        }
+        arg.push(rest);
+        arg
+    });
+    let program = words
+        .next()
+        .ok_or_else(|| Error::custom_error("Empty hook command"))?;
+    Ok((program, words.collect()))
+}
+
+fn exited(program: &OsString, status: std::process::ExitStatus) -> Result<()> {
+    match status.success() {
+        true => Ok(()),
+        false => Err(Error::custom_error(format!(
+            "{} exited with {}",
+            program.to_string_lossy(),
+            status
+        ))),
+    }
+}
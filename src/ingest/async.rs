@@ -2,270 +2,298 @@ use crate::*;
 use tokio::fs;
 use std::sync::atomic::Ordering;
 
-pub const TRASH_EXT: [&str; 20] = ["xmp", "dat", "bat", "exe", "bin", "fir", "dmg", "msi", "sh", "lut", "mo", "lua", "sym", "rbf",
-"txt", "rtf", "doc", "docx", "pdf", "ctg"];
+/// Number of walked entries buffered between the blocking walker and the ingest
+const WALK_CHANNEL: usize = 1024;
+/// Number of chunks read around the page cache buffered ahead of the writes
+const UNCACHED_CHANNEL: usize = 4;
 
-pub const TRASH_FILES: [&str; 1] = ["indexervolumeguid"];
-pub const TRASH_FOLDERS: [&str; 1] = ["system volume information"];
-
-
-impl<'filter> Filter<'filter> {
-    pub fn matches(&self, path: impl AsRef<Path>) -> Result<bool> {
-        if self.ignore_hidden && path.is_hidden() {
-            return Ok(false);
+impl<'ingest> Ingestor<'ingest> {
+    /// Returns the number of files that were ingested.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "ingest",
+            skip_all,
+            fields(target = %self.target.display(), backup = ?self.backup, sources = self.sources.len())
+        )
+    )]
+    pub async fn ingest(&mut self) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let _active = telemetry::Active::start();
+        if let Some(hook) = self.hooks.before_ingest() {
+            hook.await?;
         }
+        self.set_phase(Phase::Scanning);
+        let scan = self.scan().await?;
+        let needs = self.needs_of(scan.source_sizes(&self.sources)).await?;
+        if !needs.fits {
+            self.short_of(needs)?;
+        }
+        self.start(&scan)?;
+        // The passes copy what was scanned instead of walking the sources again
+        self.__scan = Some(scan);
+        let ingested = self.ingest_scanned().await;
+        self.__scan = None;
+        ingested
+    }
 
-        {
-            // Ignore trash folders
-            let file_name = path
-                .as_ref()
-                .file_stem()
-                .map(OsStr::to_ascii_lowercase)
-                .and_then(|ext| ext.into_string().ok());
-            let file_name = file_name.as_deref();
-
-            if let Some(file_name) = file_name {
-                if TRASH_FILES.contains(&file_name) || TRASH_FOLDERS.contains(&file_name){
-                    return Ok(false)
-                }
-            }
-            
-        }
-
-        let ext = path
-            .as_ref()
-            .extension()
-            .map(OsStr::to_ascii_lowercase)
-            .and_then(|ext| ext.into_string().ok());
-        let ext = ext.as_deref();
-
-        let size = path.as_ref().metadata()?.len();
-        if let Some(ext) = ext {
-            if (self.extensions.contains(&ext)
-                || self.extensions.is_empty()
-                || self.extensions.contains(&""))
-                && size >= self.min_size
-                && size <= self.max_size
-                && !TRASH_EXT.contains(&ext)
-            {
-                return Ok(true);
+    async fn ingest_scanned(&mut self) -> Result<()> {
+        // The archives are closed even when the pass failed, with what they got so far
+        let passed = self.pass(true).await;
+        let finished = self.finish_destinations().await;
+        passed?;
+        finished?;
+        if let Some(hook) = self.hooks.after_ingest(&self.report) {
+            if let Err(e) = hook.await {
+                self.lifecycle_failed("after_ingest", e);
             }
-        } else if self.extensions.is_empty()
-            || self.extensions.contains(&"") && size >= self.min_size && size <= self.max_size
-        {
-            return Ok(true);
         }
-        Ok(false)
-    }
-}
+        self.ensure_running()?;
+        self.backup().await?;
 
-impl<'ingest> Ingestor<'ingest> {
-    /// Returns the free space available at the target folder
-    pub fn free_space(&self) -> Result<u64> {
-        std::fs::create_dir_all(&self.target)?;
-        Ok(fs2::free_space(&self.target)?)
+        Ok(())
     }
 
-    /// Returns the total space available at the target folder
-    pub fn free_space_backup(&self) -> Result<u64> {
-        if let Some(ref backup) = self.backup {
-            std::fs::create_dir_all(backup)?;
-            Ok(fs2::free_space(backup)?)
-        } else {
-            Err(Error::custom_error("Backup directory not set"))
-        }
+    /// What the ingest needs at the target, the backup and the destinations
+    pub async fn needs(&self) -> Result<Needs> {
+        let scan = self.scan().await?;
+        self.needs_of(scan.source_sizes(&self.sources)).await
     }
 
-    /// Returns the total size of the files to be copied.
-    pub fn total_size(&self) -> Result<u64> {
-        Ok(self
-            .files()?
-            .iter()
-            .map(|path| path.metadata().map(|m| m.len()).unwrap_or_default())
-            .sum())
+    pub async fn fits(&self) -> Result<bool> {
+        Ok(self.needs().await?.fits)
     }
 
-    pub fn fits(&self) -> Result<bool> {
-        self.fits_with(0)
+    /// Whether everything fits given `size` more bytes of free space at each destination
+    pub async fn fits_with(&self, size: u64) -> Result<bool> {
+        Ok(self.needs().await?.fits_with(size))
     }
 
-    pub fn fits_with(&self, size: u64) -> Result<bool> {
-        let total = self.total_size()?;
-        let free = self.free_space()?;
-        Ok(if let Some(ref backup_dir) = self.backup {
-            if same_disk(backup_dir, &self.target)? {
-                free + size > total * 2
-            } else {
-                let free_backup = self.free_space_backup()?;
-                free + size > total && free_backup + size > total
-            }
+    /// Returns the number of files that were ingested.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(backup = ?self.backup))
+    )]
+    pub async fn backup(&mut self) -> Result<()> {
+        let teed = self.tees();
+        if let Some(backup) = &self.backup {
+            self.target = backup.to_owned();
+            self.backup = None;
+            // The destinations got their copies from the target
+            self.destinations = Destinations::default();
+            self.detect_network();
         } else {
-            free + size > total
-        })
+            return Ok(());
+        }
+        if teed {
+            self.finish_tee()?;
+        } else {
+            self.create_dir_all(&self.target).await?;
+            let scan = match self.__scan.take() {
+                Some(scan) => scan,
+                None => self.scan().await?,
+            };
+            if self.free_space()? < scan.total() {
+                self.short_of(self.needs_of(scan.source_sizes(&self.sources)).await?)?;
+            }
+            self.__scan = Some(scan);
+            let passed = self.pass(false).await;
+            self.__scan = None;
+            passed?;
+        }
+        if let Some(hook) = self.hooks.after_backup(&self.report) {
+            if let Err(e) = hook.await {
+                self.lifecycle_failed("after_backup", e);
+            }
+        }
+        Ok(())
     }
 
-    pub fn needs(&self) -> Result<crate::Needs> {
-        let free = self.free_space()?;
-        let total = self.total_size()?;
-        let backup = if let Some(ref backup) = self.backup {
-            Some(crate::BackupNeeds {
-                free: self.free_space_backup()?,
-                same_disk: same_disk(&self.target, backup)?,
-            })
-        } else {
-            None
+    /// Copies every source to the current target, orphans are only reported on the first pass
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), fields(target = %self.target.display()))
+    )]
+    async fn pass(&mut self, first: bool) -> Result<()> {
+        self.set_phase(match first {
+            true => Phase::Copying,
+            false => Phase::BackingUp,
+        });
+        if let Some(plan) = self.__plan.take() {
+            let executed = self.execute_pass(&plan).await;
+            self.__plan = Some(plan);
+            return executed;
+        }
+        let failed = self.report.failures.len();
+        let copied = match self.concurrent_sources() {
+            1 => self.copy_sources(first).await,
+            concurrency => self.copy_concurrently(first, concurrency).await,
         };
-        Ok(crate::Needs {
-            total,
-            free,
-            backup,
-        })
+        // Keep the manifests of what was copied before stopping
+        self.finish_pass(failed)?;
+        copied
     }
 
-    /// Returns the number of files that were ingested.
-    pub async fn ingest(&mut self) -> Result<()> {
-        if !self.fits()? {
-            return Err(Error::new(errors::ErrorKind::InsufficientSpace));
-        }
-
-        let mut rename = match self.structure {
-            Structure::Rename(ref rename) => Some(*rename),
-            _ => None,
-        }
-        .unwrap_or_default();
-        let filters = &self.filter.clone();
-
-        // TODO: futures::future::try_join_all
-        for source in self.sources.clone().iter() {
-            for entry in WalkDir::new(source)
-                .max_depth(self.depth)
-                .sort_by_file_name()
-                .into_iter()
-                .filter_entry(|e| {
-                    filters.matches(e.path()).ok().unwrap_or(true)
-                })
-                .into_iter()
-                .flatten()
-            {
-                self.map_entry(entry, &source, &mut rename).await?;
-            }
-        }
+    /// Copies the files of the sources and their orphan sidecars
+    async fn copy_sources(&mut self, first: bool) -> Result<()> {
+        let mut rename = self.renamer();
 
-        let jpegs: Vec<PathBuf> = self.__jpegs.drain().collect();
-        let __copy_xmp = self.copy_xmp;
-        let __copy_jpg = self.copy_jpg;
-        for jpeg in jpegs {
-            self.copy_xmp = false;
-            self.copy_jpg = false;
-            match self.structure {
-                Structure::Retain => {
-                    self.ingest_file_renamed(jpeg, &mut rename).await.ok();
-                }
-                _ => (),
-            };
+        let mut entries = match &self.__scan {
+            Some(scan) => scan.of(&self.sources),
+            None => self.scan().await?.entries,
+        };
+        self.placeholders(&mut entries)?;
+        let paths: Vec<PathBuf> = entries
+            .iter()
+            .map(|(_, entry)| entry.path().to_path_buf())
+            .collect();
+        let vfs = self.shared_vfs();
+        self.__pairs = tokio::task::spawn_blocking(move || Pairs::resolve_in(vfs.as_ref(), paths))
+            .await
+            .map_err(Error::custom_error)?;
+        self.plan_pass(
+            entries
+                .iter()
+                .filter(|(_, entry)| entry.is_file())
+                .map(|(_, entry)| entry.path()),
+        )?;
+        for (source, entry) in entries {
+            self.map_entry(entry, source, &mut rename).await?;
         }
 
-        if self.cancel.load(Ordering::SeqCst) {
-            return Err(Error::custom_error("Ingesting cancelled"));
+        for (source, orphan) in self.walk_orphans().await? {
+            if first {
+                self.report
+                    .warn(Warning::OrphanSidecar(orphan.path().to_path_buf()));
+            }
+            if self.copy_orphans {
+                let ingested = self.ingest_path(source, orphan.path(), &mut rename).await;
+                self.handle_failure(orphan.path(), ingested)?;
+            }
         }
+        Ok(())
+    }
 
-        self.copy_xmp = __copy_xmp;
-        self.copy_jpg = __copy_jpg;
-        self.backup().await?;
+    /// Copies every source in a pipeline of its own, up to `concurrency` of them at once. They
+    /// take turns on this task, so the copies do their blocking IO on the blocking pool
+    async fn copy_concurrently(&mut self, first: bool, concurrency: usize) -> Result<()> {
+        use futures::StreamExt;
+        let skipped = self.report.skipped.len();
+        let pipelines: Vec<_> = self
+            .pipelines()
+            .into_iter()
+            .map(|pipeline| pipeline.copy_pipeline(first))
+            .collect();
+        let copied = futures::stream::iter(pipelines)
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+        self.join_pipelines(copied, skipped)
+    }
 
-        Ok(())
+    /// Copies the only source of `self`, a pipeline
+    async fn copy_pipeline(mut self, first: bool) -> (Ingestor<'ingest>, Result<()>) {
+        let copied = self.copy_sources(first).await;
+        (self, copied)
     }
 
-    /// Returns the number of files that were ingested.
-    pub async fn backup(&mut self) -> Result<()> {
-        if let Some(backup) = &self.backup {
-            self.target = backup.to_owned();
-            self.backup = None;
-        } else {
-            return Ok(());
-        }
-        fs::create_dir_all(&self.target).await?;
-        if self.free_space()? < self.total_size()? {
-            return Err(Error::new(errors::ErrorKind::InsufficientSpace));
-        }
-        let mut rename = match self.structure {
-            Structure::Rename(ref rename) => Some(*rename),
-            _ => None,
-        }
-        .unwrap_or_default();
-        let filters = &self.filter.clone();
-
-        // TODO: futures::future::try_join_all
-        for source in self.sources.clone().iter() {
-            for entry in WalkDir::new(source)
-                .max_depth(self.depth)
-                .sort_by_file_name()
-                .into_iter()
-                .filter_entry(|e| {
-                    filters.matches(e.path()).ok().unwrap_or(true)
-                })
-                .into_iter()
-                .flatten()
-            {
-                self.map_entry(entry, &source, &mut rename).await?;
+    /// Copies the files of `plan` to the target and the backup like [`Ingestor::ingest`] copies
+    /// the sources, see [`Ingestor::plan`]
+    pub async fn execute(&mut self, plan: IngestPlan) -> Result<()> {
+        self.run_id = plan.run_id;
+        self.__plan = Some(plan);
+        let executed = self.ingest().await;
+        self.__plan = None;
+        executed
+    }
+
+    /// Copies the files of `plan` to the current target
+    async fn execute_pass(&mut self, plan: &IngestPlan) -> Result<()> {
+        let failed = self.report.failures.len();
+        self.reset_pass();
+        let paths: Vec<PathBuf> = plan.sources().map(Path::to_path_buf).collect();
+        let vfs = self.shared_vfs();
+        self.__pairs = tokio::task::spawn_blocking(move || Pairs::resolve_in(vfs.as_ref(), paths))
+            .await
+            .map_err(Error::custom_error)?;
+        for file in &plan.files {
+            // Keep the manifests of what was copied before stopping
+            if let Err(e) = self.execute_file(file).await {
+                self.finish_pass(failed)?;
+                return Err(e);
             }
         }
+        self.finish_pass(failed)
+    }
 
-        let jpegs: Vec<PathBuf> = self.__jpegs.drain().collect();
-        let __copy_xmp = self.copy_xmp;
-        let __copy_jpg = self.copy_jpg;
-        for jpeg in jpegs {
-            self.copy_xmp = false;
-            self.copy_jpg = false;
-            match self.structure {
-                Structure::Retain => {
-                    self.ingest_file_renamed(jpeg, &mut rename).await.ok();
-                }
-                _ => (),
-            };
+    async fn execute_file(&mut self, file: &PlannedFile) -> Result<()> {
+        self.ensure_running()?;
+        self.wait_for_target().await?;
+        if !self.ensure_space(&file.source)? {
+            return Ok(());
         }
+        let output = self.planned(file);
+        let ingested = self.ingest_planned(&file.source, output).await;
+        self.handle_failure(&file.source, ingested)
+    }
 
+    async fn ingest_planned(&mut self, input: &Path, output: PathBuf) -> Result<()> {
+        if let Some(parent) = output.parent() {
+            self.create_dir_all(parent).await?;
+        }
+        self.ingest_copy(input, output).await?;
         Ok(())
     }
 
+    /// Copies only the files that are missing or differ at the target and the backup, eg. to
+    /// finish an interrupted ingest. Returns the number of files copied
+    pub async fn ingest_missing(&mut self, mode: VerifyMode) -> Result<usize> {
+        self.report = Report::default();
+        let mut copied = self.repair(mode).await?;
+        if let Some(backup) = self.backup.clone() {
+            let target = std::mem::replace(&mut self.target, backup);
+            let repaired = self.repair(mode).await;
+            self.target = target;
+            copied += repaired?;
+        }
+        Ok(copied)
+    }
+
+    async fn repair(&mut self, mode: VerifyMode) -> Result<usize> {
+        let report = self.verify(mode)?;
+        let names = names::Names::load(self.vfs(), &self.target);
+        let failed = self.report.failures.len();
+        self.reset_pass();
+        self.__names.clear();
+        let mut copied = 0;
+        for (source, target) in report.missing.into_iter().chain(report.mismatched) {
+            self.ensure_running()?;
+            if let Some(parent) = target.parent() {
+                self.create_dir_all(parent).await?;
+            }
+            let (target, claimed) = self.repaired(&names, &source, target)?;
+            let repaired = self.copy_file(&source, &target).await.map(|_| ());
+            if repaired.is_ok() {
+                copied += 1;
+                self.record_file(&source);
+            } else if claimed {
+                self.vfs().remove_file(&target).ok();
+            }
+            self.handle_failure(&source, repaired)?;
+        }
+        self.write_manifests()?;
+        self.save_names(failed)?;
+        Ok(copied)
+    }
+
     /// This copies the files as is
     async fn ingest_file<P: AsRef<Path>, S: AsRef<Path>>(
         &mut self,
         source: S,
         path: P,
     ) -> Result<()> {
-        let source = source.as_ref();
-        // if the source folder is
-        // aaa/bbb
-        // and the file is
-        // aaa/bbb/ccc/ddd.jpg
-        // then the target is
-        // xxx/yyy
-        // then the target file must be
-        // xxx/yyy/bbb/ccc/ddd.jpg
-        //
-        // source
-        // /
-        // file
-        // /aaa/bbb/file.jpg
-        // target
-        // xxx/yyy
-        // target file
-        // xxx/yyy/aaa/bbb/file.jpg
-        let target = if let Some(source) = source.parent() {
-            self.target.join(path.as_ref().strip_prefix(source)?)
-        } else {
-            self.target.join(path.as_ref().strip_prefix(source)?)
-        };
-
-        if !self.cancel.load(Ordering::SeqCst) {
-            fs::create_dir_all(target.parent().unwrap()).await?;
-            self.ingest_copy(&path, &target).await?;
-        } else {
-            return Err(Error::custom_error("Ingesting cancelled"));
-        }
-
-        Ok(())
+        let target = self.retained(source.as_ref(), path.as_ref())?;
+        self.ingest_to(path, target).await
     }
 
     /// Since this doesn't retain the structure we need to rename the accompanying jpegs as well
@@ -274,88 +302,125 @@ impl<'ingest> Ingestor<'ingest> {
         path: P,
         rename: &mut Rename<'ingest>,
     ) -> Result<()> {
-        let file_extension = path
-            .as_ref()
-            .extension()
-            .and_then(OsStr::to_str)
-            .ok_or_else(|| Error::custom_error("File extension not found"))?;
-
-        let target =
-            self.target
-                .canonicalize()?
-                .join(format!("{}.{}", rename.next(&path)?, file_extension));
-        self.ingest_copy(path, target).await?;
-        Ok(())
+        let target = self.renamed(path.as_ref(), rename)?;
+        self.ingest_to(path, target).await
     }
 
     pub async fn ingest_file_preserve<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let target = self.target.canonicalize()?.join(
-            path.as_ref()
-                .file_name()
-                .ok_or_else(|| Error::custom_error("File name not found"))?,
-        );
-        self.ingest_copy(path, target).await?;
-        Ok(())
+        let target = self.preserved(path.as_ref())?;
+        self.ingest_to(path, target).await
     }
 
-    /// Returns all the files that match the filters
-    pub fn files(&self) -> Result<Vec<PathBuf>> {
-        let mut files = Vec::new();
-        for source in self.sources.iter() {
-            files.extend(
-                WalkDir::new(source)
-                    .max_depth(self.depth)
-                    .sort_by_file_name()
-                    .into_iter()
-                    .flatten()
-                    .filter_map(|entry| {
-                        let path = entry.path();
-                        if self.filter.matches(path).ok()? {
-                            Some(path.to_path_buf())
-                        } else {
-                            None
-                        }
-                    }),
-            )
+    async fn ingest_to(&mut self, path: impl AsRef<Path>, target: PathBuf) -> Result<()> {
+        self.ensure_running()?;
+        let target = self.placed(path.as_ref(), target);
+        if let Some(parent) = target.parent() {
+            self.create_dir_all(parent).await?;
         }
-        Ok(files)
+        self.ingest_copy(path, target).await?;
+        Ok(())
     }
 
-    /// This returns all the folders in the source folders
-    pub fn folders(&self) -> Result<Vec<PathBuf>> {
-        // let mut folders = Vec::new();
-        // for source in self.sources.iter() {
-        // folders.extend(
-        let folders = self
-            .sources
-            .clone()
-            .into_iter()
-            .fold(Vec::new(), |mut last, source| {
-                last.extend(
-                    WalkDir::new(source)
-                        .max_depth(self.depth)
-                        .sort_by_file_name()
-                        .into_iter()
-                        .flatten()
-                        .filter_map(|entry| {
-                            let path = entry.path();
-                            if entry.file_type().is_dir() {
-                                Some(path.to_path_buf())
-                            } else {
-                                None
-                            }
-                        }),
-                );
-                last
+    /// Same as [`Ingestor::entries`] along with the sizes of the files, walked on the blocking pool
+    /// so slow card readers don't stall the executor
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "scan",
+            level = "debug",
+            skip_all,
+            fields(sources = self.sources.len())
+        )
+    )]
+    async fn scan(&self) -> Result<walk::Scan<'ingest>> {
+        let sources: Vec<&'ingest Path> = self.sources.iter().copied().collect();
+        let roots: Vec<PathBuf> = sources.iter().map(|s| s.to_path_buf()).collect();
+        let extensions: Vec<String> = self.filter.extensions.iter().map(|e| e.to_string()).collect();
+        let Filter {
+            min_size,
+            max_size,
+            ignore_hidden,
+            video_cards,
+            attributes,
+            max_files,
+            every_nth,
+            sniff_contents,
+            ..
+        } = self.filter;
+        let (scope, vfs) = (self.scope(), self.shared_vfs());
+
+        let (tx, rx) = tokio::sync::mpsc::channel(WALK_CHANNEL);
+        let walker = tokio::task::spawn_blocking(move || {
+            let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+            let filter = Filter {
+                extensions: Cow::Borrowed(&extensions),
+                min_size,
+                max_size,
+                ignore_hidden,
+                video_cards,
+                attributes,
+                max_files,
+                every_nth,
+                sniff_contents,
+            };
+            walk::walk(vfs.as_ref(), &roots, &scope, &filter, |index, entry| {
+                let size = walk::size_of(vfs.as_ref(), &filter, &entry);
+                tx.blocking_send((index, entry, size)).is_ok()
             });
-        // .collect();
-        // )
-        // }
-        Ok(folders)
+        });
+        let (mut entries, sizes) = self.receive(&sources, rx).await;
+        walker.await.map_err(Error::custom_error)?;
+        self.order(&mut entries);
+        self.sample(&mut entries);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(entries = entries.len(), "scanned");
+        Ok(walk::Scan::new(entries, sizes))
     }
 
-    pub fn builder() -> IngestorBuilder<'ingest> {
-        Default::default()
+    /// Same as [`Ingestor::orphan_sidecars`] but walks on the blocking pool
+    async fn walk_orphans(&self) -> Result<Vec<(&'ingest Path, vfs::Entry)>> {
+        let sources: Vec<&'ingest Path> = self.sources.iter().copied().collect();
+        let roots: Vec<PathBuf> = sources.iter().map(|s| s.to_path_buf()).collect();
+        let extensions: Vec<String> =
+            self.sidecars.extensions.iter().map(|e| e.to_string()).collect();
+        let (scope, ignore_hidden) = (self.scope(), self.filter.ignore_hidden);
+        let vfs = self.shared_vfs();
+
+        let (tx, rx) = tokio::sync::mpsc::channel(WALK_CHANNEL);
+        let walker = tokio::task::spawn_blocking(move || {
+            let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+            let sidecars = SidecarPolicy {
+                extensions: Cow::Borrowed(&extensions),
+            };
+            walk::walk_orphans(
+                vfs.as_ref(),
+                &roots,
+                &scope,
+                ignore_hidden,
+                &sidecars,
+                |index, entry| tx.blocking_send((index, entry, None)).is_ok(),
+            );
+        });
+        let (orphans, _) = self.receive(&sources, rx).await;
+        walker.await.map_err(Error::custom_error)?;
+        Ok(orphans)
+    }
+
+    /// Gathers the walked entries along with the size of the files the filter keeps
+    async fn receive(
+        &self,
+        sources: &[&'ingest Path],
+        mut rx: tokio::sync::mpsc::Receiver<(usize, vfs::Entry, Option<u64>)>,
+    ) -> (Vec<(&'ingest Path, vfs::Entry)>, HashMap<PathBuf, u64>) {
+        let (mut entries, mut sizes) = (Vec::new(), HashMap::new());
+        while let Some((index, entry, size)) = rx.recv().await {
+            if let Some(size) = size {
+                self.found();
+                sizes.insert(entry.path().to_path_buf(), size);
+            }
+            entries.push((sources[index], entry));
+        }
+        (entries, sizes)
     }
 
     pub async fn ingest_copy<I: AsRef<Path>, O: AsRef<Path>>(
@@ -363,61 +428,552 @@ impl<'ingest> Ingestor<'ingest> {
         input: I,
         output: O,
     ) -> Result<u64> {
+        self.ensure_running()?;
+        let Some(plan) = self.plan_copy(input.as_ref(), output.as_ref())? else {
+            return Ok(0);
+        };
+        for (companion, target) in plan.companions {
+            if let Some(parent) = target.parent() {
+                self.create_dir_all(parent).await?;
+            }
+            if self.copy_file(&companion, &target).await.is_ok() {
+                self.store(&target).await.ok();
+            }
+        }
 
-        if self.cancel.load(Ordering::SeqCst) {
-            return Err(Error::custom_error("Ingesting cancelled"));
+        self.record_file(input.as_ref());
+        self.events.emit(Event::Started {
+            path: input.as_ref().to_path_buf(),
+            destination: plan.output.clone(),
+        });
+        let copied = self.copy_file(&input, &plan.output).await;
+        self.copy_finished(input.as_ref(), &plan.output, &copied);
+        // Without staging the claimed file holds what was copied of it
+        if copied.is_err() && plan.claimed && self.atomic {
+            self.vfs().remove_file(&plan.output).ok();
+        }
+        match copied {
+            Ok(_) => {
+                self.copied(input.as_ref(), &plan.output);
+                self.run_copy_hooks(input.as_ref(), &plan.output).await;
+                self.store(&plan.output).await?;
+            }
+            Err(_) if self.quarantine == Quarantine::Markers => {
+                let marker = crate::failed_path(&plan.output);
+                match self.vfs {
+                    Some(ref vfs) => vfs.create(&marker).map(drop).ok(),
+                    None => fs::File::create(marker).await.map(drop).ok(),
+                };
+            }
+            Err(_) => {}
         }
+        copied
+    }
 
-        let output = crate::exists_plus_one(output)?;
+    async fn run_copy_hooks(&mut self, input: &Path, output: &Path) {
+        for hook in self.copy_hooks.clone() {
+            if let Err(e) = hook.run(input, output).await {
+                self.hook_failed(output, e);
+            }
+        }
+    }
 
-        if self.copy_xmp {
-            fs::copy(
-                input.as_ref().with_extension("xmp"),
-                output.with_extension("xmp"),
-            )
-            .await
-            .ok();
-        }
-        if self.structure.is_renamed() && self.copy_jpg {
-            if let Ok(path) = accompanying_jpeg(&input) {
-                if self.__jpegs.contains(&path) {
-                    self.__jpegs.remove(&path);
-                } else {
-                    self.__jpegs.insert(path.clone());
+    /// Stores a copy made to the target at every destination, retried like the copies
+    async fn store(&self, output: &Path) -> Result<()> {
+        let Some(key) = destination::key(&self.target, output) else {
+            return Ok(());
+        };
+        for destination in self.destinations.iter() {
+            let mut attempt = 0;
+            loop {
+                match destination.store(output, &key).await {
+                    Ok(()) => break,
+                    Err(ref e) if self.should_retry(attempt, e) => {}
+                    Err(e) => {
+                        return Err(destination::store_failed(destination.as_ref(), &key, e));
+                    }
+                }
+                tokio::time::sleep(self.retry.delay_for(attempt)).await;
+                attempt += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lets every destination wrap up once the files were stored, an ingest that goes through
+    /// [`Ingestor::ingest_file_renamed`] instead of [`Ingestor::ingest`] should call it at the end
+    pub async fn finish_destinations(&self) -> Result<()> {
+        for destination in self.destinations.iter() {
+            destination.finish().await.map_err(|e| {
+                Error::custom_error(format!("Couldn't finish {}: {}", destination.name(), e))
+            })?;
+        }
+        Ok(())
+    }
+
+    async fn create_dir_all(&self, dir: &Path) -> Result<()> {
+        match self.vfs {
+            Some(ref vfs) => vfs.create_dir_all(dir)?,
+            None => fs::create_dir_all(dir).await?,
+        }
+        Ok(())
+    }
+
+    /// Waits for a network target that dropped out to come back, up to the reconnect timeout
+    async fn wait_for_target(&self) -> Result<()> {
+        let started = std::time::Instant::now();
+        while self.target_unreachable() {
+            self.ensure_running()?;
+            if started.elapsed() >= self.reconnect_timeout {
+                return Err(Error::custom_error(format!(
+                    "{} is unreachable",
+                    self.target.display()
+                )));
+            }
+            tokio::time::sleep(network::RECONNECT_INTERVAL).await;
+        }
+        Ok(())
+    }
+
+    /// Copies a single file, every copy made by the ingestor goes through here
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "copy",
+            level = "debug",
+            skip_all,
+            fields(file = %input.as_ref().display(), destination = %output.as_ref().display())
+        )
+    )]
+    async fn copy_file(&mut self, input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<u64> {
+        let (input, output) = (input.as_ref(), output.as_ref());
+        self.set_current(input);
+        let mut errors = Vec::new();
+        let mut attempt = 0;
+        let mirror = self.claim_mirror(input, output)?;
+        loop {
+            let copied = match &mirror {
+                Some((mirror, _)) => self.tee_file(input, output, mirror).await,
+                None => self.try_copy_file(input, output).await.map(|size| (size, Vec::new())),
+            };
+            match copied {
+                Ok((size, hashes)) => {
+                    match &mirror {
+                        Some((mirror, _)) => self.record_tee(input, output, mirror, hashes)?,
+                        None => self.record_hash_pooled(input, output).await?,
+                    }
+                    self.written(output);
+                    self.record_retries(input, errors, true);
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(bytes = size, attempts = attempt + 1, "copied");
+                    return Ok(size);
+                }
+                Err(e) => {
+                    errors.push(e.to_string());
+                    if !self.should_retry(attempt, &e) || self.wait_for_target().await.is_err() {
+                        #[cfg(feature = "tracing")]
+                        tracing::error!(error = %e, attempts = attempt + 1, "copy failed");
+                        if attempt > 0 {
+                            self.record_retries(input, errors, false);
+                        }
+                        // The mirror claimed for the staged copy is left empty
+                        if let Some((mirror, true)) = mirror.filter(|_| self.atomic) {
+                            self.vfs().remove_file(&mirror).ok();
+                        }
+                        return Err(e);
+                    }
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %e, attempt, "retrying the copy");
+                }
+            }
+            tokio::time::sleep(self.retry.delay_for(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    async fn try_copy_file(&self, input: &Path, output: &Path) -> Result<u64> {
+        let target = self.staging(output);
+        let size = match self.copy_contents(input, &target).await {
+            Ok(size) => size,
+            Err(e) => {
+                if self.atomic {
+                    match self.vfs {
+                        Some(ref vfs) => vfs.remove_file(&target).ok(),
+                        None => fs::remove_file(&target).await.ok(),
+                    };
+                }
+                return Err(e);
+            }
+        };
+        if let Some(ref vfs) = self.vfs {
+            if self.atomic {
+                vfs.rename(&target, output)?;
+            }
+            return Ok(size);
+        }
+        if self.atomic {
+            fs::rename(&target, output).await?;
+            #[cfg(target_os = "macos")]
+            self.hide_partial(output, false)?;
+        }
+        if self.durability.syncs_folders() {
+            crate::sync_dir(output)?;
+        }
+        Ok(size)
+    }
+
+    /// Copies `input` in chunks of `buffer_size`, counting the bytes and checking for
+    /// cancellation between chunks
+    async fn copy_chunked(&self, input: &Path, output: &Path) -> Result<u64> {
+        if self.no_cache {
+            return self.copy_uncached(input, output).await;
+        }
+        let mut reader = fs::File::open(input).await?;
+        let metadata = reader.metadata().await?;
+        let permissions = metadata.permissions();
+        let mut writer = fs::File::create(output).await?;
+        if self.preallocate {
+            preallocate::preallocate(&writer, metadata.len())?;
+        }
+        #[cfg(target_os = "macos")]
+        self.hide_partial(output, true).ok();
+        let mut copied = 0;
+        let result = self.copy_chunks(&mut reader, &mut writer, &mut copied).await;
+        if result.is_err() {
+            self.unrecord_bytes(copied);
+        }
+        result?;
+        fs::set_permissions(output, permissions).await?;
+        Ok(copied)
+    }
+
+    /// Copies `input` to `output` and `mirror` in a single read on the blocking pool and checks
+    /// both copies, returns the size and the hashes of the file
+    async fn tee_file(
+        &self,
+        input: &Path,
+        output: &Path,
+        mirror: &Path,
+    ) -> Result<(u64, Vec<(HashAlgo, String)>)> {
+        let tee = self.tee(input, output, mirror);
+        let (teed, copied) = self.copy_blocking(move |progress| tee.copy(progress)).await;
+        if teed.is_err() {
+            self.unrecord_bytes(copied);
+        }
+        Ok((copied, teed?))
+    }
+
+    /// Runs `copy` on the blocking pool, counting and throttling the chunks it reports from
+    /// here, and stops it once the ingest is cancelled. Returns what it returned along with the
+    /// bytes it reported
+    pub(crate) async fn copy_blocking<T: Send + 'static>(
+        &self,
+        copy: impl FnOnce(&mut dyn FnMut(u64) -> Result<()>) -> Result<T> + Send + 'static,
+    ) -> (Result<T>, u64) {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let copying = tokio::task::spawn_blocking(move || {
+            copy(&mut |read| {
+                tx.blocking_send(read)
+                    .map_err(|_| Error::custom_error("Ingesting cancelled"))
+            })
+        });
+        let mut copied = 0;
+        let mut throttle = handle::Throttle::new();
+        while let Some(read) = rx.recv().await {
+            if self.ensure_running().is_err() {
+                break;
+            }
+            copied += read;
+            self.record_bytes(read);
+            let limit = self.max_throughput.load(Ordering::SeqCst);
+            if let Some(delay) = throttle.delay(limit, read) {
+                tokio::time::sleep(delay).await;
+            }
+        }
+        // The copy fails on its next chunk once nothing takes them
+        drop(rx);
+        let result = copying.await.map_err(Error::custom_error).and_then(|copy| copy);
+        (result, copied)
+    }
+
+    /// Same as [`Self::copy_chunked`] with the source read around the page cache on the
+    /// blocking pool, and never through the ring
+    async fn copy_uncached(&self, input: &Path, output: &Path) -> Result<u64> {
+        use tokio::io::AsyncWriteExt;
+        let metadata = fs::metadata(input).await?;
+        let permissions = metadata.permissions();
+        let (path, chunk) = (input.to_path_buf(), self.chunk_size());
+        let (tx, mut rx) = tokio::sync::mpsc::channel(UNCACHED_CHANNEL);
+        let reader = tokio::task::spawn_blocking(move || {
+            let mut reader = match nocache::Uncached::open(&path, chunk) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    tx.blocking_send(Err(e)).ok();
+                    return;
+                }
+            };
+            loop {
+                let chunk = reader.read().map(<[u8]>::to_vec);
+                let last = chunk.as_ref().map_or(true, Vec::is_empty);
+                // Nothing takes the chunks once the copy failed
+                if tx.blocking_send(chunk).is_err() || last {
+                    break;
                 }
-                fs::copy(path, output.with_extension("jpg")).await.ok();
             }
+        });
+        let mut writer = fs::File::create(output).await?;
+        if self.preallocate {
+            preallocate::preallocate(&writer, metadata.len())?;
         }
+        #[cfg(target_os = "macos")]
+        self.hide_partial(output, true).ok();
+        let mut copied = 0;
+        let mut throttle = handle::Throttle::new();
+        let result: Result<()> = async {
+            while let Some(chunk) = rx.recv().await {
+                self.ensure_running()?;
+                let chunk = chunk?;
+                if chunk.is_empty() {
+                    break;
+                }
+                let read = chunk.len() as u64;
+                writer.write_all(&chunk).await?;
+                copied += read;
+                self.record_bytes(read);
+                let limit = self.max_throughput.load(Ordering::SeqCst);
+                if let Some(delay) = throttle.delay(limit, read) {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            writer.flush().await?;
+            Ok(())
+        }
+        .await;
+        drop(rx);
+        let result = result.and(reader.await.map_err(Error::custom_error));
+        if result.is_err() {
+            self.unrecord_bytes(copied);
+        }
+        result?;
+        fs::set_permissions(output, permissions).await?;
+        Ok(copied)
+    }
 
-        self.progress.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-        Ok(fs::copy(input, output).await?)
+    async fn copy_chunks(
+        &self,
+        reader: &mut fs::File,
+        writer: &mut fs::File,
+        copied: &mut u64,
+    ) -> Result<()> {
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        if self.copy_ring_pooled(reader, writer, copied).await? {
+            return Ok(());
+        }
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let mut buffer = vec![0; self.chunk_size()];
+        let mut throttle = handle::Throttle::new();
+        loop {
+            self.ensure_running()?;
+            let read = reader.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..read]).await?;
+            *copied += read as u64;
+            self.record_bytes(read as u64);
+            let limit = self.max_throughput.load(Ordering::SeqCst);
+            if let Some(delay) = throttle.delay(limit, read as u64) {
+                tokio::time::sleep(delay).await;
+            }
+        }
+        writer.flush().await?;
+        Ok(())
+    }
+
+    async fn copy_contents(&self, input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<u64> {
+        let (input, output) = (input.as_ref(), output.as_ref());
+        if let Some(ref vfs) = self.vfs {
+            return self.copy_through(vfs.as_ref(), input, output);
+        }
+        let size = match self.reflink {
+            Reflink::Never => self.copy_chunked(input, output).await?,
+            Reflink::Always => self.clone_pooled(input, output).await?,
+            Reflink::Auto => match self.clone_pooled(input, output).await {
+                Ok(size) => size,
+                Err(_) => self.copy_chunked(input, output).await?,
+            },
+        };
+        self.copy_metadata(input, output)?;
+        if self.durability.syncs_files() {
+            fs::OpenOptions::new()
+                .write(true)
+                .open(output).await?
+                .sync_all().await?;
+        }
+        self.copy_permissions(input, output)?;
+        Ok(size)
+    }
+
+    /// Same as [`Ingestor::record_hash`] with the input hashed on the blocking pool
+    async fn record_hash_pooled(&mut self, input: &Path, output: &Path) -> Result<()> {
+        let algos = self.manifest_algos();
+        if algos.is_empty() {
+            return Ok(());
+        }
+        let (vfs, path, hashed) = (self.shared_vfs(), input.to_path_buf(), algos.clone());
+        let hashes = tokio::task::spawn_blocking(move || {
+            hash::hash_reader_with(vfs.open(&path)?, &hashed)
+        })
+        .await
+        .map_err(Error::custom_error)??;
+        self.record_hashes(output, algos, hashes)
+    }
+
+    /// Clones `input` to `output` on the blocking pool, counting its bytes
+    async fn clone_pooled(&self, input: &Path, output: &Path) -> Result<u64> {
+        let (source, target) = (input.to_path_buf(), output.to_path_buf());
+        let size = tokio::task::spawn_blocking(move || reflink::clone_file(source, target))
+            .await
+            .map_err(Error::custom_error)??;
+        self.record_bytes(size);
+        Ok(size)
     }
 
     pub async fn map_entry(
         &mut self,
-        entry: walkdir::DirEntry,
+        entry: vfs::Entry,
         source: impl AsRef<Path>,
         rename: &mut Rename<'ingest>,
     ) -> Result<()> {
+        self.ensure_running()?;
+        self.wait_for_target().await?;
+        if self.skips(&entry) {
+            return Ok(());
+        }
+        if !self.ensure_space(entry.path())? {
+            return Ok(());
+        }
 
         let path = entry.path();
+        let ingested = self.ingest_path(source, path, rename).await;
+        self.handle_failure(path, ingested)
+    }
 
+    /// Copies a single path according to the structure
+    async fn ingest_path(
+        &mut self,
+        source: impl AsRef<Path>,
+        path: impl AsRef<Path>,
+        rename: &mut Rename<'ingest>,
+    ) -> Result<()> {
+        let path = path.as_ref();
         match self.structure {
-            Structure::Retain => self.ingest_file(source, path).await.ok(),
-            Structure::Rename(_) => {
-                if path.is_jpeg() {
-                    let path = path.to_path_buf();
-                    if self.__jpegs.contains(&path) {
-                        self.__jpegs.remove(&path);
-                        return Ok(());
-                    } else {
-                        self.__jpegs.insert(path);
-                    }
-                };
-                self.ingest_file_renamed(path, rename).await.ok()
-            }
-            Structure::Preserve => self.ingest_file_preserve(path).await.ok(),
-        };
-        Ok(())
+            Structure::Retain => self.ingest_file(source, path).await,
+            Structure::Rename(_) => self.ingest_file_renamed(path, rename).await,
+            Structure::Preserve => self.ingest_file_preserve(path).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vfs::{MemoryFs, Vfs};
+    use crate::*;
+
+    fn read(fs: &MemoryFs, path: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        fs.open(Path::new(path)).unwrap().read_to_end(&mut data).unwrap();
+        data
+    }
+
+    /// Two cards holding a `DSC_0001.NEF` each
+    fn cards() -> MemoryFs {
+        let fs = MemoryFs::new();
+        fs.add_file("/a/DCIM/100NIKON/DSC_0001.NEF", [1; 10])
+            .add_file("/b/DCIM/100NIKON/DSC_0001.NEF", [2; 20]);
+        fs
+    }
+
+    fn ingestor<'a>(fs: &MemoryFs, sources: &'a [PathBuf]) -> Ingestor<'a> {
+        IngestorBuilder::default()
+            .with_source(sources)
+            .with_target("/target")
+            .with_structure(Structure::Preserve)
+            .with_filter(Filter::default())
+            .with_vfs(fs.clone())
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn repairs_the_numbered_copy() {
+        let fs = cards();
+        let sources = [PathBuf::from("/a"), PathBuf::from("/b")];
+        let mut ingestor = ingestor(&fs, &sources);
+        ingestor.ingest().await.unwrap();
+        let copy_of_b = ingestor
+            .mapping()
+            .unwrap()
+            .into_iter()
+            .find(|(source, _)| source.starts_with("/b"))
+            .map(|(_, copy)| copy)
+            .unwrap();
+        assert_eq!(copy_of_b, Path::new("/target/DSC_0001-1.NEF"));
+        fs.remove_file(&copy_of_b).unwrap();
+
+        let copied = ingestor.ingest_missing(VerifyMode::default()).await.unwrap();
+        assert_eq!(copied, 1);
+        assert_eq!(read(&fs, "/target/DSC_0001.NEF"), [1; 10]);
+        assert_eq!(read(&fs, "/target/DSC_0001-1.NEF"), [2; 20]);
+        assert!(ingestor.verify(VerifyMode::default()).unwrap().is_complete());
+    }
+
+    #[tokio::test]
+    async fn never_repairs_over_another_file() {
+        let fs = cards();
+        fs.add_file("/target/DSC_0001.NEF", [3; 30]);
+        let sources = [PathBuf::from("/a"), PathBuf::from("/b")];
+        let mut ingestor = ingestor(&fs, &sources);
+
+        let copied = ingestor.ingest_missing(VerifyMode::default()).await.unwrap();
+        assert_eq!(copied, 2);
+        assert_eq!(read(&fs, "/target/DSC_0001.NEF"), [3; 30]);
+        let mut copies = [
+            read(&fs, "/target/DSC_0001-1.NEF"),
+            read(&fs, "/target/DSC_0001-2.NEF"),
+        ];
+        copies.sort();
+        assert_eq!(copies, [vec![1; 10], vec![2; 20]]);
+        let report = ingestor.verify(VerifyMode::default()).unwrap();
+        assert!(report.is_complete(), "{:?}", report);
+        assert_eq!(ingestor.ingest_missing(VerifyMode::default()).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn caps_the_folders_across_sources() {
+        let fs = MemoryFs::new();
+        fs.add_file("/a/DCIM/100NIKON/DSC_0001.NEF", [1; 10])
+            .add_file("/a/DCIM/100NIKON/DSC_0002.NEF", [2; 10])
+            .add_file("/b/DCIM/100NIKON/DSC_0003.NEF", [3; 10])
+            .add_file("/b/DCIM/100NIKON/DSC_0004.NEF", [4; 10]);
+        let sources = [PathBuf::from("/a"), PathBuf::from("/b")];
+        let mut ingestor = IngestorBuilder::default()
+            .with_source(&sources)
+            .with_target("/target")
+            .with_structure(Structure::Preserve)
+            .with_filter(Filter::default())
+            .with_concurrency(2)
+            .with_max_files_per_folder(2)
+            .with_vfs(fs.clone())
+            .build()
+            .unwrap();
+        ingestor.ingest().await.unwrap();
+        let mapping = ingestor.mapping().unwrap();
+        assert_eq!(mapping.len(), 4);
+        let in_target = mapping
+            .iter()
+            .filter(|(_, copy)| copy.parent() == Some(Path::new("/target")))
+            .count();
+        assert_eq!(in_target, 2);
     }
 }
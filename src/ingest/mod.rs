@@ -1,12 +1,10 @@
-#[cfg(all(feature = "async", not(feature = "sync")))]
-mod r#async;
-#[cfg(all(feature = "async", not(feature = "sync")))]
-pub use r#async::*;
+mod plan;
+pub use plan::{TRASH_EXT, TRASH_FILES, TRASH_FOLDERS};
 
-#[cfg(all(feature = "sync", not(feature = "async")))]
-mod sync;
-#[cfg(all(feature = "sync", not(feature = "async")))]
-pub use sync::*;
+#[cfg(feature = "async")]
+mod r#async;
 
-#[cfg(all(feature = "sync", feature = "async"))]
-compile_error!("Can't compile for both async and sync");
+#[cfg(feature = "sync")]
+pub(crate) mod sync;
+#[cfg(feature = "sync")]
+pub use sync::Blocking;
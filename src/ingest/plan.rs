@@ -0,0 +1,1319 @@
+//! The parts of an ingest that don't depend on the runtime, the async and sync backends only do
+//! the IO around them
+use crate::*;
+use std::sync::atomic::Ordering;
+
+pub const TRASH_EXT: [&str; 22] = ["xmp", "dat", "bat", "exe", "bin", "fir", "dmg", "msi", "sh", "lut", "mo", "lua", "sym", "rbf",
+"txt", "rtf", "doc", "docx", "pdf", "ctg", PART_EXTENSION, FAILED_EXTENSION];
+
+pub const TRASH_FILES: [&str; 1] = ["indexervolumeguid"];
+pub const TRASH_FOLDERS: [&str; 1] = ["system volume information"];
+
+impl<'filter> Filter<'filter> {
+    /// The files of `paths` kept by [`Filter::every_nth`] and [`Filter::max_files`] in the order
+    /// of their paths whatever the order they're copied in, `None` when every file is
+    pub(crate) fn sampled<'p>(
+        &self,
+        paths: impl IntoIterator<Item = &'p Path>,
+    ) -> Option<HashSet<PathBuf>> {
+        if self.every_nth.is_none() && self.max_files.is_none() {
+            return None;
+        }
+        let every = self.every_nth.unwrap_or(1).max(1);
+        let max = self.max_files.unwrap_or(usize::MAX);
+        let mut paths: Vec<&Path> = paths.into_iter().collect();
+        paths.sort();
+        // The files of a frame share their folder and stem
+        let mut frames: HashMap<PathBuf, bool> = HashMap::new();
+        let mut kept = HashSet::new();
+        for path in paths {
+            let frame = frames.len();
+            let sampled = *frames
+                .entry(path.with_extension(""))
+                .or_insert(frame.is_multiple_of(every));
+            if sampled && kept.len() < max {
+                kept.insert(path.to_path_buf());
+            }
+        }
+        Some(kept)
+    }
+
+    /// Whether the walk goes into the folder at `path`, going by the rules made for folders: hidden,
+    /// attributes and trash. The extensions and sizes are only for the files
+    pub fn enters(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        if (self.ignore_hidden && path.is_hidden()) || self.attributes.skips(path) {
+            return false;
+        }
+        let name = path.file_name().map(|name| name.to_string_lossy().to_lowercase());
+        !name.is_some_and(|name| TRASH_FOLDERS.contains(&name.as_str()))
+    }
+
+    /// Whether a walk keeps `entry`, the folders it [`Filter::enters`] and the files that match.
+    /// The root of the walk is always kept, it was picked as a source
+    pub(crate) fn walks(&self, vfs: &dyn Vfs, entry: &vfs::Entry) -> bool {
+        if entry.depth() == 0 {
+            return true;
+        }
+        match entry.is_dir() {
+            true => self.enters(entry.path()),
+            false => self.matches_in(vfs, entry.path()).ok().unwrap_or(true),
+        }
+    }
+
+    pub fn matches(&self, path: impl AsRef<Path>) -> Result<bool> {
+        self.matches_in(&vfs::RealFs, path)
+    }
+
+    /// Same as [`Filter::matches`] with the sizes of the files of `vfs`
+    pub fn matches_in(&self, vfs: &dyn Vfs, path: impl AsRef<Path>) -> Result<bool> {
+        if (self.ignore_hidden && path.is_hidden()) || self.attributes.skips(&path) {
+            return Ok(false);
+        }
+
+        {
+            // Ignore trash folders
+            let file_name = path
+                .as_ref()
+                .file_stem()
+                .map(OsStr::to_ascii_lowercase)
+                .and_then(|ext| ext.into_string().ok());
+            let file_name = file_name.as_deref();
+
+            if let Some(file_name) = file_name {
+                if TRASH_FILES.contains(&file_name) || TRASH_FOLDERS.contains(&file_name){
+                    return Ok(false)
+                }
+            }
+
+        }
+
+        let ext = path
+            .as_ref()
+            .extension()
+            .map(OsStr::to_ascii_lowercase)
+            .and_then(|ext| ext.into_string().ok());
+        let ext = ext.as_deref();
+
+        let metadata = vfs.metadata(path.as_ref())?;
+        if metadata.is_file() && !self.video_cards.keeps(path.as_ref()) {
+            return Ok(false);
+        }
+        let size = metadata.len;
+        if self.sniff_contents && metadata.is_file() && sniff::lacks_extension(path.as_ref()) {
+            let sniffed = sniff::extensions_in(vfs, path.as_ref())?;
+            return Ok(!sniffed.is_empty()
+                && (self.extensions.is_empty()
+                    || sniffed.iter().any(|ext| self.extensions.contains(ext)))
+                && size >= self.min_size
+                && size <= self.max_size);
+        }
+        if let Some(ext) = ext {
+            if (self.extensions.contains(&ext)
+                || self.extensions.is_empty()
+                || self.extensions.contains(&""))
+                && size >= self.min_size
+                && size <= self.max_size
+                && !TRASH_EXT.contains(&ext)
+            {
+                return Ok(true);
+            }
+        } else if self.extensions.is_empty()
+            || self.extensions.contains(&"") && size >= self.min_size && size <= self.max_size
+        {
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+fn append(name: &OsStr, suffix: &str) -> std::ffi::OsString {
+    let mut name = name.to_os_string();
+    name.push(suffix);
+    name
+}
+
+/// What has to be copied for a single file
+pub(crate) struct CopyPlan {
+    /// Where the file goes, after collisions were resolved
+    pub output: PathBuf,
+    /// Sidecars and accompanying jpeg along with their targets
+    pub companions: Vec<(PathBuf, PathBuf)>,
+    /// Whether `output` was created empty to hold the name, to be removed if the copy fails
+    pub claimed: bool,
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// The filesystem the ingest goes through, the disk unless another one was given
+    pub(crate) fn vfs(&self) -> &dyn Vfs {
+        match self.vfs {
+            Some(ref vfs) => vfs.as_ref(),
+            None => &vfs::RealFs,
+        }
+    }
+
+    /// Same as [`Ingestor::vfs`] for the blocking pool
+    pub(crate) fn shared_vfs(&self) -> Arc<dyn Vfs> {
+        match self.vfs {
+            Some(ref vfs) => vfs.clone(),
+            None => Arc::new(vfs::RealFs),
+        }
+    }
+
+    /// Returns the free space available at the target folder
+    pub fn free_space(&self) -> Result<u64> {
+        self.vfs().create_dir_all(&self.target)?;
+        Ok(self.vfs().free_space(&self.target)?)
+    }
+
+    /// Returns the total space available at the target folder
+    pub fn free_space_backup(&self) -> Result<u64> {
+        if let Some(ref backup) = self.backup {
+            self.vfs().create_dir_all(backup)?;
+            Ok(self.vfs().free_space(backup)?)
+        } else {
+            Err(Error::custom_error("Backup directory not set"))
+        }
+    }
+
+    /// Returns the total size of the files to be copied.
+    pub fn total_size(&self) -> Result<u64> {
+        Ok(self
+            .files()?
+            .iter()
+            .map(|path| self.vfs().metadata(path).map(|m| m.len).unwrap_or_default())
+            .sum())
+    }
+
+    /// What copying `sources`, the size of the files of each, needs at the target, the backup
+    /// and the destinations. The backends ask the destinations in their own way
+    pub(crate) async fn needs_of(&self, sources: Vec<(PathBuf, u64)>) -> Result<crate::Needs> {
+        let free = self.free_space()?;
+        let total = sources.iter().map(|(_, size)| size).sum();
+        let backup = if let Some(ref backup) = self.backup {
+            let free = self.free_space_backup()?;
+            let same_disk = self.vfs().same_disk(&self.target, backup)?;
+            let required = if same_disk {
+                0
+            } else {
+                total + self.reserved_space
+            };
+            Some(crate::BackupNeeds {
+                free,
+                same_disk,
+                required,
+                shortfall_bytes: required.saturating_sub(free),
+                fits: free >= required,
+            })
+        } else {
+            None
+        };
+        let required = match backup {
+            Some(ref backup) if backup.same_disk => total * 2,
+            _ => total,
+        } + self.reserved_space;
+        let destinations = self.destination_needs(total + self.reserved_space).await?;
+        Ok(crate::Needs {
+            total,
+            free,
+            required,
+            shortfall_bytes: required.saturating_sub(free),
+            sources,
+            fits: free >= required
+                && backup.as_ref().map(|b| b.fits).unwrap_or(true)
+                && destinations.iter().all(|d| d.fits),
+            backup,
+            destinations,
+        })
+    }
+
+    /// Asks the destinations for their space, leaving out the ones that can't tell
+    async fn destination_needs(&self, required: u64) -> Result<Vec<crate::DestinationNeeds>> {
+        let mut needs = Vec::new();
+        for destination in self.destinations.iter() {
+            let space = async {
+                Ok::<_, Error>((destination.free().await?, destination.capacity().await?))
+            }
+            .await;
+            let (free, capacity) = space.map_err(|e| {
+                Error::custom_error(format!(
+                    "Couldn't get the free space at {}: {}",
+                    destination.name(),
+                    e
+                ))
+            })?;
+            if let Some(free) = free {
+                needs.push(crate::DestinationNeeds {
+                    name: destination.name(),
+                    capacity,
+                    free,
+                    required,
+                    shortfall_bytes: required.saturating_sub(free),
+                    fits: free >= required,
+                });
+            }
+        }
+        Ok(needs)
+    }
+
+    /// Returns the size of the files to be copied from each source
+    pub fn source_sizes(&self) -> Vec<(PathBuf, u64)> {
+        self.sources
+            .iter()
+            .map(|source| {
+                let size = self
+                    .files_in(source)
+                    .iter()
+                    .map(|path| self.vfs().metadata(path).map(|m| m.len).unwrap_or_default())
+                    .sum();
+                (source.to_path_buf(), size)
+            })
+            .collect()
+    }
+
+    /// Returns all the files that match the filters
+    pub fn files(&self) -> Result<Vec<PathBuf>> {
+        let mut files: Vec<PathBuf> = self
+            .sources
+            .iter()
+            .flat_map(|source| self.files_in(source))
+            .collect();
+        if let Some(sampled) = self.filter.sampled(files.iter().map(PathBuf::as_path)) {
+            files.retain(|path| sampled.contains(path));
+        }
+        Ok(files)
+    }
+
+    pub(crate) fn files_in(&self, source: &Path) -> Vec<PathBuf> {
+        let scope = self.scope();
+        vfs::walk(self.vfs(), source, scope.walked(), |entry| {
+            !entry.is_dir() || walk::enters(self.vfs(), &self.filter, &scope, entry)
+        })
+        .filter(|entry| entry.is_file() && scope.keeps(entry))
+        .filter_map(|entry| {
+            let path = entry.path();
+            if self.filter.matches_in(self.vfs(), path).ok()? {
+                Some(path.to_path_buf())
+            } else {
+                None
+            }
+        })
+        .collect()
+    }
+
+    /// This returns all the folders in the source folders, see [`Ingestor::folder_tree`] for them
+    /// as a tree with what they hold
+    pub fn folders(&self) -> Result<Vec<PathBuf>> {
+        // let mut folders = Vec::new();
+        // for source in self.sources.iter() {
+        // folders.extend(
+        let folders = self
+            .sources
+            .clone()
+            .into_iter()
+            .fold(Vec::new(), |mut last, source| {
+                last.extend(
+                    vfs::walk(self.vfs(), source, self.depth, |_| true).filter_map(|entry| {
+                        if entry.is_dir() {
+                            Some(entry.into_path())
+                        } else {
+                            None
+                        }
+                    }),
+                );
+                last
+            });
+        // .collect();
+        // )
+        // }
+        Ok(folders)
+    }
+
+    pub fn builder() -> IngestorBuilder<'ingest> {
+        Default::default()
+    }
+
+    /// Resets the report and the counters before an ingest
+    pub(crate) fn start(&mut self, scan: &walk::Scan) -> Result<()> {
+        self.report = Report {
+            run_id: self.run_id,
+            ..Default::default()
+        };
+        self.__names.clear();
+        self.__mirror_names.clear();
+        self.__outputs = std::iter::once(&self.target).chain(&self.backup).cloned().collect();
+        for (source, within) in self.__collapsed.clone() {
+            self.report.warn(Warning::NestedSource { source, within });
+        }
+        for (source, output) in self.nested_outputs() {
+            let source = source.to_path_buf();
+            self.report.warn(Warning::NestedOutput { source, output });
+        }
+        // The files are only read once when they're teed to the backup
+        let passes = if self.backup.is_some() && !self.tees() { 2 } else { 1 };
+        self.__total.store(scan.total() * passes, Ordering::SeqCst);
+        self.start_sources(&scan.sizes, passes as usize);
+        if let Ok(mut speed) = self.__speed.lock() {
+            *speed = handle::Speedometer::start();
+        }
+        self.detect_network();
+        Ok(())
+    }
+
+    /// Looks at whether the current target is a network share, unless told
+    pub(crate) fn detect_network(&mut self) {
+        self.__network = self
+            .network
+            .unwrap_or_else(|| self.vfs.is_none() && network::is_network(&self.target));
+    }
+
+    /// Size of the chunks files are copied in, larger on network shares
+    pub(crate) fn chunk_size(&self) -> usize {
+        match self.__network {
+            true => self.buffer_size.max(network::NETWORK_BUFFER_SIZE),
+            false => self.buffer_size,
+        }
+    }
+
+    /// Whether the target is gone, eg. a network share that dropped out
+    pub(crate) fn target_unreachable(&self) -> bool {
+        self.__network && self.vfs().metadata(&self.target).is_err()
+    }
+
+    /// Returns a fresh renamer so that every destination gets the same names
+    pub(crate) fn renamer(&self) -> Rename<'ingest> {
+        match self.structure {
+            Structure::Rename(rename) => rename,
+            _ => Rename::default(),
+        }
+    }
+
+    pub(crate) fn ensure_running(&self) -> Result<()> {
+        if self.cancel.load(Ordering::SeqCst) {
+            return Err(Error::custom_error("Ingesting cancelled"));
+        }
+        Ok(())
+    }
+
+    /// Whether `path` is copied when that would eat into the reserved space of the target, or
+    /// of the backup it's teed to, in case another process filled the disk since the ingest
+    /// started. The conflict resolver decides, and without one it fails
+    pub(crate) fn ensure_space(&mut self, path: &Path) -> Result<bool> {
+        let size = self.vfs().metadata(path)?.len;
+        let mirror = self.backup.clone().filter(|_| self.tees());
+        for destination in std::iter::once(self.target.clone()).chain(mirror) {
+            if !self.ensure_space_at(&destination, path, size)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn ensure_space_at(&mut self, destination: &Path, path: &Path, size: u64) -> Result<bool> {
+        let free = self.vfs().free_space(destination)?;
+        let required = size + self.reserved_space;
+        if free >= required {
+            return Ok(true);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::error!(file = %path.display(), bytes = size, "out of space");
+        let conflict = Conflict::InsufficientSpace {
+            destination: destination.to_path_buf(),
+            needs: crate::Needs {
+                total: size,
+                free,
+                required,
+                shortfall_bytes: required - free,
+                sources: vec![(path.to_path_buf(), size)],
+                ..Default::default()
+            },
+        };
+        match self.conflicts.resolve(conflict) {
+            None | Some(Resolution::Abort) => Err(Error::new(errors::ErrorKind::InsufficientSpace)),
+            Some(Resolution::Skip) => {
+                if !self.report.skipped.iter().any(|skipped| skipped == path) {
+                    self.report.skipped.push(path.to_path_buf());
+                }
+                Ok(false)
+            }
+            Some(_) => Ok(true),
+        }
+    }
+
+    /// Whether `entry` isn't copied on its own, see [`Ingestor::skips_path`]
+    pub(crate) fn skips(&self, entry: &vfs::Entry) -> bool {
+        !entry.is_file() || self.skips_path(entry.path(), &self.__pairs)
+    }
+
+    /// Whether `path` is left out, either by the pair policy, because it wasn't picked or because
+    /// it's copied along with the file it belongs to like sidecars and accompanying jpegs
+    pub(crate) fn skips_path(&self, path: &Path, pairs: &Pairs) -> bool {
+        self.sidecars.is_sidecar(path)
+            || self.leaves_out(path, pairs)
+            || (self.copies_twins() && pairs.is_twin(path))
+    }
+
+    /// Whether `path` isn't copied at all, by the pair policy or because it wasn't picked
+    fn leaves_out(&self, path: &Path, pairs: &Pairs) -> bool {
+        !self.sidecars.is_sidecar(path)
+            && (!self.pair_policy.keeps(path, pairs) || !self.picks.keeps(path, pairs))
+    }
+
+    /// Whether the accompanying jpegs are copied and renamed along with their RAW
+    pub(crate) fn copies_twins(&self) -> bool {
+        self.structure.is_renamed() && self.copy_jpg && self.pair_policy == PairPolicy::Both
+    }
+
+    /// Returns where `path` from `source` goes according to the structure
+    pub(crate) fn destination(
+        &self,
+        source: &Path,
+        path: &Path,
+        rename: &mut Rename<'ingest>,
+    ) -> Result<PathBuf> {
+        match self.structure {
+            Structure::Retain => self.retained(source, path),
+            Structure::Rename(_) => self.renamed(path, rename),
+            Structure::Preserve => self.preserved(path),
+        }
+    }
+
+    pub(crate) fn retained(&self, source: &Path, path: &Path) -> Result<PathBuf> {
+        // if the source folder is
+        // aaa/bbb
+        // and the file is
+        // aaa/bbb/ccc/ddd.jpg
+        // then the target is
+        // xxx/yyy
+        // then the target file must be
+        // xxx/yyy/bbb/ccc/ddd.jpg
+        let root = source.parent().unwrap_or(source);
+        Ok(self.root(path).join(path.strip_prefix(root)?))
+    }
+
+    pub(crate) fn renamed(&self, path: &Path, rename: &mut Rename<'ingest>) -> Result<PathBuf> {
+        let extension = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase)
+            .ok_or_else(|| Error::custom_error("File extension not found"))?;
+        let mut name = rename.next(path)?;
+        if name.contains(LABEL_TOKEN) {
+            let label = self
+                .source_of(path)
+                .map(|source| self.source_label(source).to_string_lossy())
+                .unwrap_or_default();
+            name = name.replace(LABEL_TOKEN, &label);
+        }
+        if name.contains(RUN_TOKEN) {
+            name = name.replace(RUN_TOKEN, &self.run_id.simple().to_string()[..8]);
+        }
+        if name.contains(DAY_TOKEN) {
+            let day = self.day(path).zip(self.__first_day);
+            let day = day.map_or(1, |(day, first)| day.saturating_sub(first) + 1);
+            name = name.replace(DAY_TOKEN, &day.to_string());
+        }
+        if name.contains(JOB_TOKEN) {
+            name = name.replace(JOB_TOKEN, self.job_name.as_deref().unwrap_or_default());
+        }
+        Ok(self.root(path).join(format!("{}.{}", name, extension)))
+    }
+
+    /// The day `path` was shot on by the clock of the camera, counted from 1970
+    fn day(&self, path: &Path) -> Option<u64> {
+        let captured = self.capture_time(path)?;
+        let secs = captured.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs();
+        let offset = i64::from(self.timezone.unwrap_or_default()) * 60;
+        Some(secs.checked_add_signed(offset)? / 86400)
+    }
+
+    pub(crate) fn preserved(&self, path: &Path) -> Result<PathBuf> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| Error::custom_error("File name not found"))?;
+        let source = self.source_of(path);
+        match (self.collisions, source) {
+            (CollisionPolicy::SourceLabel, Some(source)) if self.__collisions.contains(path) => {
+                let mut name = path.file_stem().unwrap_or(name).to_os_string();
+                name.push("_");
+                name.push(self.source_label(source));
+                if let Some(extension) = path.extension() {
+                    name.push(".");
+                    name.push(extension);
+                }
+                Ok(self.root(path).join(name))
+            }
+            (CollisionPolicy::KeepSubfolder, Some(source)) if self.__collisions.contains(path) => {
+                self.retained(source, path)
+            }
+            _ => Ok(self.root(path).join(name)),
+        }
+    }
+
+    /// Returns the source `path` was found in
+    pub(crate) fn source_of(&self, path: &Path) -> Option<&'ingest Path> {
+        self.sources
+            .iter()
+            .copied()
+            .filter(|source| path.starts_with(source))
+            .max_by_key(|source| source.components().count())
+    }
+
+    /// The name the files of `source` are told apart with, its label or the name of its folder
+    pub(crate) fn source_label(&self, source: &'ingest Path) -> &'ingest OsStr {
+        match self.labels.get(source) {
+            Some(&label) => OsStr::new(label),
+            None => source.file_name().unwrap_or(source.as_os_str()),
+        }
+    }
+
+    /// The folder `path` is copied under, the target or its session, route and burst
+    fn root(&self, path: &Path) -> PathBuf {
+        let root = self.routed(self.session_root(path), path);
+        let folders = self.bursts.is_some_and(|detection| detection.folders);
+        match self.__bursts.folder(path).filter(|_| folders) {
+            Some(burst) => root.join(burst),
+            None => root,
+        }
+    }
+
+    fn session_root(&self, path: &Path) -> PathBuf {
+        let root = match self.source_of(path) {
+            Some(source) if self.source_folders => self.target.join(self.source_label(source)),
+            _ => self.target.clone(),
+        };
+        match self.__sessions.folder(path) {
+            Some(session) => root.join(session),
+            None => root,
+        }
+    }
+
+    fn routed(&self, root: PathBuf, path: &Path) -> PathBuf {
+        match self.routes.route(path) {
+            Some(route) => root.join(route),
+            None => root,
+        }
+    }
+
+    /// Resets what's tracked across the files of a pass
+    pub(crate) fn reset_pass(&mut self) {
+        self.__folders.clear();
+        self.__taken.clear();
+        self.__index = None;
+    }
+
+    /// Where the planned `file` goes in the current target, the name it got in the first pass
+    /// when it was copied there
+    pub(crate) fn planned(&self, file: &PlannedFile) -> PathBuf {
+        match self.__names.get(&file.source) {
+            Some(name) => self.target.join(name),
+            None => self.target.join(&file.destination),
+        }
+    }
+
+    /// Resets what's tracked across the files of a pass, splits them into sessions and bursts and
+    /// looks for the names that collide once flattened
+    pub(crate) fn plan_pass<P: AsRef<Path>>(
+        &mut self,
+        paths: impl IntoIterator<Item = P>,
+    ) -> Result<()> {
+        let paths: Vec<P> = paths.into_iter().collect();
+        self.reset_pass();
+        // Kept from the previous pass, for the files the conflict resolver skipped
+        let skipped: HashSet<&Path> = self.report.skipped.iter().map(PathBuf::as_path).collect();
+        let left_out: Vec<PathBuf> = paths
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|path| !skipped.contains(path) && self.leaves_out(path, &self.__pairs))
+            .map(Path::to_path_buf)
+            .collect();
+        self.report.skipped.extend(left_out);
+        self.__sessions = match self.session_gap {
+            Some(gap) => Sessions::from_times(
+                paths.iter().filter_map(|path| {
+                    let path = path.as_ref();
+                    Some((self.capture_time(path)?, path.to_path_buf()))
+                }),
+                gap,
+            ),
+            None => Sessions::default(),
+        };
+        self.__bursts = self.find_bursts(&paths);
+        self.report.bursts = self.__bursts.bursts.clone();
+        self.__first_day = match self.structure {
+            Structure::Rename(Rename {
+                name: Some(name), ..
+            }) if name.contains(DAY_TOKEN) => paths
+                .iter()
+                .filter(|path| !self.skips_path(path.as_ref(), &self.__pairs))
+                .filter_map(|path| self.day(path.as_ref()))
+                .min(),
+            _ => None,
+        };
+        self.__collisions = if self.structure.is_preserved() {
+            self.collisions(&paths)
+        } else {
+            HashSet::new()
+        };
+        if self.collisions == CollisionPolicy::Error && !self.__collisions.is_empty() {
+            let mut names: Vec<_> = self
+                .__collisions
+                .iter()
+                .filter_map(|path| path.file_name())
+                .map(OsStr::to_string_lossy)
+                .collect();
+            names.sort();
+            names.dedup();
+            return Err(Error::custom_error(format!(
+                "Files from different folders share the names {}",
+                names.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the files that share their name with another file copied on its own
+    fn collisions<P: AsRef<Path>>(&self, paths: &[P]) -> HashSet<PathBuf> {
+        let mut names: HashMap<_, Vec<&Path>> = HashMap::new();
+        for path in paths.iter().map(AsRef::as_ref) {
+            if self.skips_path(path, &self.__pairs) {
+                continue;
+            }
+            // Most card and target filesystems are case insensitive
+            if let Some(name) = path.file_name().map(OsStr::to_ascii_lowercase) {
+                names.entry(name).or_default().push(path);
+            }
+        }
+        names
+            .into_values()
+            .filter(|paths| paths.len() > 1)
+            .flatten()
+            .map(Path::to_path_buf)
+            .collect()
+    }
+
+    /// Where `path` goes in the current target, the name it got in the first pass when it was
+    /// copied there so the backup gets the same names whatever failed or was skipped in between,
+    /// otherwise `target` made Windows safe and spilled
+    pub(crate) fn placed(&mut self, path: &Path, target: PathBuf) -> PathBuf {
+        if let Some(name) = self.__names.get(path) {
+            return self.target.join(name);
+        }
+        let target = self.windows_safe(path, target);
+        self.spill(target)
+    }
+
+    /// `target` with names Windows accepts when [`Ingestor::windows_names`] is on, warning once
+    /// about the copies of `path` that get another name
+    pub(crate) fn windows_safe(&mut self, path: &Path, target: PathBuf) -> PathBuf {
+        if !self.windows_names {
+            return target;
+        }
+        let safe = sanitize::windows_path(&self.target, &target);
+        let warned = self.report.warnings.iter().any(|warning| {
+            matches!(warning, Warning::WindowsName { path: warned, .. } if warned == path)
+        });
+        if safe != target && !warned {
+            self.report.warn(Warning::WindowsName {
+                path: path.to_path_buf(),
+                destination: safe.clone(),
+            });
+        }
+        safe
+    }
+
+    /// Moves `target` to the next `folder-N` once its folder is full
+    pub(crate) fn spill(&mut self, target: PathBuf) -> PathBuf {
+        let (Some(max), Some(folder), Some(name)) = (
+            self.max_files_per_folder,
+            target.parent(),
+            target.file_name(),
+        ) else {
+            return target;
+        };
+        let count = self.__folders.entry(folder.to_path_buf()).or_default();
+        let spill = *count / max;
+        *count += 1;
+        if spill == 0 {
+            return target;
+        }
+
+        let suffix = format!("-{}", spill + 1);
+        // The target itself is never spilled next to it, only within it
+        let spilled = if folder == self.target {
+            let name = folder.file_name().unwrap_or_else(|| OsStr::new("folder"));
+            folder.join(append(name, &suffix))
+        } else {
+            folder.with_file_name(append(folder.file_name().unwrap_or_default(), &suffix))
+        };
+        spilled.join(name)
+    }
+
+    /// Where the accompanying `twin` of the file copied to `output` goes, next to it unless the
+    /// routes send it elsewhere
+    pub(crate) fn twin_destination(&self, input: &Path, output: &Path, twin: &Path) -> PathBuf {
+        let extension = twin
+            .extension()
+            .map(OsStr::to_ascii_lowercase)
+            .unwrap_or_default();
+        let output = output.with_extension(extension);
+        // The twin stays in the session of its RAW
+        match output.strip_prefix(self.root(input)) {
+            Ok(relative) => self.routed(self.session_root(input), twin).join(relative),
+            Err(_) => output,
+        }
+    }
+
+    /// Resolves the collisions at `output` and finds what has to be copied along with `input`,
+    /// `None` when the conflict resolver skips it
+    pub(crate) fn plan_copy(&mut self, input: &Path, output: &Path) -> Result<Option<CopyPlan>> {
+        if let Some(existing) = self.duplicate_in_target(input)? {
+            if !self.report.skipped.iter().any(|skipped| skipped == input) {
+                self.report.skipped.push(input.to_path_buf());
+            }
+            self.report.warn(Warning::Duplicate {
+                path: input.to_path_buf(),
+                existing,
+            });
+            return Ok(None);
+        }
+        let twin = if self.copies_twins() {
+            self.__pairs.twin(input).map(Path::to_path_buf)
+        } else {
+            None
+        };
+        let twin_extension = twin
+            .as_ref()
+            .and_then(|twin| twin.extension())
+            .map(OsStr::to_ascii_lowercase)
+            .and_then(|ext| ext.into_string().ok());
+
+        let twins: Vec<&str> = twin_extension.as_deref().into_iter().collect();
+        let taken = self.numbered(output, &twins) != output;
+        let resolution = match taken {
+            true => self.conflicts.resolve(Conflict::Exists {
+                source: input.to_path_buf(),
+                destination: output.to_path_buf(),
+            }),
+            false => None,
+        };
+        let (output, claimed) = match resolution {
+            Some(Resolution::Overwrite) => (output.to_path_buf(), false),
+            Some(Resolution::Skip) => {
+                if !self.report.skipped.iter().any(|skipped| skipped == input) {
+                    self.report.skipped.push(input.to_path_buf());
+                }
+                return Ok(None);
+            }
+            Some(Resolution::Abort) => return Err(Error::custom_error("Ingest aborted")),
+            _ => (self.claim(output, &twins)?, true),
+        };
+        if let Ok(name) = output.strip_prefix(&self.target) {
+            if !self.__names.contains_key(input) {
+                self.__names.insert(input.to_path_buf(), name.to_path_buf());
+            }
+        }
+
+        #[cfg(feature = "validate")]
+        if self.validate {
+            if let Ok(Some(corruption)) = validate::check(input) {
+                self.report.corrupt.push(validate::Corrupt {
+                    path: input.to_path_buf(),
+                    corruption,
+                });
+            }
+        }
+
+        let mut companions = self.sidecars.targets_in(self.vfs(), input, &output);
+        companions.extend(self.clip_metadata(input, &output));
+        if let Some(twin) = twin {
+            let destination = self.twin_destination(input, &output, &twin);
+            companions.push((twin, destination));
+        }
+        for path in std::iter::once(&output).chain(companions.iter().map(|(_, to)| to)) {
+            self.reserve(path);
+        }
+        Ok(Some(CopyPlan {
+            output,
+            companions,
+            claimed,
+        }))
+    }
+
+    /// Creates `output`, numbered as needed, empty for the copy to land in. Another ingest or
+    /// pass writing to the same folder then numbers its file instead of overwriting this one,
+    /// and when it got there first this one is numbered again
+    pub(crate) fn claim(&mut self, output: &Path, twins: &[&str]) -> Result<PathBuf> {
+        loop {
+            let numbered = self.numbered(output, twins);
+            match self.vfs().create_new(&numbered) {
+                Ok(_) => return Ok(numbered),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => self.reserve(&numbered),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Where the copy of `source` that's missing or differs at `target` is repaired, over the copy
+    /// recorded for it in `names` but never over another file, which gets the copy a new number.
+    /// Returns whether the file was claimed
+    pub(crate) fn repaired(
+        &mut self,
+        names: &names::Names,
+        source: &Path,
+        target: PathBuf,
+    ) -> Result<(PathBuf, bool)> {
+        let output = match names.of(self.vfs(), source) {
+            Some(written) if written == target => (target, false),
+            _ => (self.claim(&target, &[])?, true),
+        };
+        if let Ok(name) = output.0.strip_prefix(&self.target) {
+            self.__names.insert(source.to_path_buf(), name.to_path_buf());
+        }
+        Ok(output)
+    }
+
+    /// `output` numbered with the collision suffix until neither it nor its `twins` are taken
+    pub(crate) fn numbered(&mut self, output: &Path, twins: &[&str]) -> PathBuf {
+        let folder = output.parent().unwrap_or(Path::new(""));
+        self.list_taken(folder);
+        crate::exists_plus_one_in(&self.__taken[folder], output, twins, &self.collision_suffix)
+    }
+
+    /// Counts `path` as taken for the rest of the pass, before it's written
+    fn reserve(&mut self, path: &Path) {
+        let (Some(folder), Some(name)) = (path.parent(), path.file_name()) else {
+            return;
+        };
+        self.list_taken(folder);
+        if let Some(taken) = self.__taken.get_mut(folder) {
+            taken.insert(name.to_string_lossy().to_ascii_lowercase());
+        }
+    }
+
+    /// Lists the names in `folder` the first time a file is planned into it during the pass,
+    /// they're then kept up to date with [`Ingestor::reserve`]
+    fn list_taken(&mut self, folder: &Path) {
+        if !self.__taken.contains_key(folder) {
+            let names = companion::names_in(self.vfs(), folder);
+            self.__taken.insert(folder.to_path_buf(), names);
+        }
+    }
+
+    /// Fails for the lack of space at the target, short of `needs`, unless the conflict resolver
+    /// carries on
+    pub(crate) fn short_of(&self, needs: crate::Needs) -> Result<()> {
+        let conflict = Conflict::InsufficientSpace {
+            destination: self.target.clone(),
+            needs,
+        };
+        match self.conflicts.resolve(conflict) {
+            None | Some(Resolution::Abort) => Err(Error::new(errors::ErrorKind::InsufficientSpace)),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Where the contents of `output` are written before being renamed to it
+    pub(crate) fn staging(&self, output: &Path) -> PathBuf {
+        // Copy next to the target and rename once done so an interrupted copy can never be
+        // mistaken for a complete file
+        if self.atomic {
+            crate::part_path(output)
+        } else {
+            output.to_path_buf()
+        }
+    }
+
+    /// Whether a copy that failed with `error` should be attempted again, the transient errors
+    /// of network shares always are a few times
+    pub(crate) fn should_retry(&self, attempt: u32, error: &Error) -> bool {
+        let retries = match self.__network && network::is_transient(error) {
+            true => self.retry.count.max(network::NETWORK_RETRIES),
+            false => self.retry.count,
+        };
+        attempt < retries && !self.cancel.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn record_retries(&mut self, input: &Path, errors: Vec<String>, succeeded: bool) {
+        if !errors.is_empty() {
+            self.report.retries.push(RetryHistory {
+                path: input.to_path_buf(),
+                errors,
+                succeeded,
+            });
+        }
+    }
+
+    /// Whether the ingest writes to the XMP sidecars at the target, which then differ from the
+    /// sources
+    pub(crate) fn writes_xmp(&self) -> bool {
+        !self.metadata.is_empty() || self.generate_missing_xmp || self.geotag.is_some()
+    }
+
+    /// Everything done to a file once it's copied, none of which fails the copy
+    pub(crate) fn copied(&mut self, input: &Path, output: &Path) {
+        self.index(output);
+        self.stamp(input, output);
+        #[cfg(feature = "previews")]
+        self.extract_preview(input, output);
+        #[cfg(target_os = "macos")]
+        self.tag(input, output);
+    }
+
+    pub(crate) fn hook_failed(&mut self, output: &Path, error: Error) {
+        self.report.warn(Warning::Hook {
+            path: output.to_path_buf(),
+            error: error.to_string(),
+        });
+    }
+
+    pub(crate) fn lifecycle_failed(&mut self, stage: &'static str, error: Error) {
+        self.report.warn(Warning::Lifecycle {
+            stage,
+            error: error.to_string(),
+        });
+    }
+
+    /// Writes the embedded preview of the RAW at `output` to the same place in the previews tree
+    #[cfg(feature = "previews")]
+    fn extract_preview(&mut self, input: &Path, output: &Path) {
+        let Some(size) = self.previews.filter(|_| input.is_raw()) else {
+            return;
+        };
+        let extracted = (|| -> Result<()> {
+            let preview = preview::extract_in(self.vfs(), output, size)?
+                .ok_or_else(|| Error::custom_error("No embedded preview"))?;
+            let path = self
+                .target
+                .join(preview::PREVIEWS_FOLDER)
+                .join(output.strip_prefix(&self.target)?)
+                .with_extension("jpg");
+            if let Some(parent) = path.parent() {
+                self.vfs().create_dir_all(parent)?;
+            }
+            Ok(vfs::write(self.vfs(), &path, &preview)?)
+        })();
+        if let Err(e) = extracted {
+            self.report.warn(Warning::Preview {
+                path: output.to_path_buf(),
+                error: e.to_string(),
+            });
+        }
+    }
+
+    /// Writes the metadata preset and the GPS position to the XMP sidecar of `output` and creates
+    /// the missing sidecar of a RAW. A failure is only a warning as the file itself was copied
+    pub(crate) fn stamp(&mut self, input: &Path, output: &Path) {
+        if !self.writes_xmp() || self.sidecars.is_sidecar(input) {
+            return;
+        }
+        let sidecar = xmp::sidecar_of(self.vfs(), output);
+        let mut properties = self.metadata.properties();
+        if self.generate_missing_xmp && input.is_raw() && !self.vfs().exists(&sidecar) {
+            properties.extend(xmp::placeholders(self.vfs(), input));
+        }
+        if let Some(geotag) = self.geotag.as_ref().filter(|_| !input.is_video()) {
+            properties.extend(geotag.properties(input));
+        }
+        if properties.is_empty() {
+            return;
+        }
+        let stamped =
+            xmp::update(self.vfs(), &sidecar, &properties).and_then(|written| match written {
+                true => self.record_hash(&sidecar, &sidecar),
+                false => Ok(()),
+            });
+        if let Err(e) = stamped {
+            self.report.warn(Warning::Metadata {
+                path: output.to_path_buf(),
+                error: e.to_string(),
+            });
+        }
+    }
+
+    /// Adds the files copied in this pass to the target's manifests
+    pub(crate) fn write_manifests(&mut self) -> Result<()> {
+        let hashed = std::mem::take(&mut self.__hashed);
+        if hashed.is_empty() {
+            return Ok(());
+        }
+        if let Some(algo) = self.mhl {
+            mhl::write_generation_in(self.vfs(), &self.target, algo, &hashed, Some(self.run_id))?;
+        }
+        if let Some(algo) = self.checksums {
+            checksums::write_manifest_in(self.vfs(), &self.target, algo, &hashed)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the bookkeeping of a pass over the sources, `failed` is the number of failures from
+    /// the earlier passes
+    pub(crate) fn finish_pass(&mut self, failed: usize) -> Result<()> {
+        self.events.progress(&self.handle(), true);
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            files = self.progress.load(Ordering::SeqCst),
+            bytes = self.bytes.load(Ordering::SeqCst),
+            failures = self.report.failures.len() - failed,
+            "pass finished"
+        );
+        #[cfg(target_os = "macos")]
+        self.touch_folders();
+        if self.quarantine == Quarantine::Manifest {
+            crate::report::write_failure_manifest(
+                self.vfs(),
+                &self.target,
+                &self.report.failures[failed..],
+            )?;
+        }
+        self.write_manifests()?;
+        self.save_index()?;
+        self.save_names(failed)?;
+        self.sync_pass()
+    }
+
+    /// Remembers the hashes of a copied file for the manifests
+    pub(crate) fn record_hash(&mut self, input: &Path, output: &Path) -> Result<()> {
+        let algos = self.manifest_algos();
+        if algos.is_empty() {
+            return Ok(());
+        }
+        let hashes = hash::hash_reader_with(self.vfs().open(input)?, &algos)?;
+        self.record_hashes(output, algos, hashes)
+    }
+
+    /// The algorithms of the manifests, none when no manifest is written
+    pub(crate) fn manifest_algos(&self) -> Vec<HashAlgo> {
+        let mut algos: Vec<HashAlgo> = self.mhl.into_iter().chain(self.checksums).collect();
+        algos.dedup();
+        algos
+    }
+
+    /// Remembers the `hashes` of the copy at `output` for the manifests
+    pub(crate) fn record_hashes(
+        &mut self,
+        output: &Path,
+        algos: Vec<HashAlgo>,
+        hashes: Vec<String>,
+    ) -> Result<()> {
+        let metadata = self.vfs().metadata(output)?;
+        self.__hashed.push(HashedFile {
+            path: output.to_path_buf(),
+            size: metadata.len,
+            modified: metadata.modified,
+            hashes: algos.into_iter().zip(hashes).collect(),
+            hashed_at: std::time::SystemTime::now(),
+        });
+        Ok(())
+    }
+
+    /// Copies `input` to `output` through the filesystem given in place of the disk, in chunks
+    /// like the copies to the disk but without their metadata
+    pub(crate) fn copy_through(&self, vfs: &dyn Vfs, input: &Path, output: &Path) -> Result<u64> {
+        use std::io::{Read, Write};
+        let mut reader = vfs.open(input)?;
+        let mut writer = vfs.create(output)?;
+        let mut buffer = vec![0; self.chunk_size()];
+        let mut throttle = handle::Throttle::new();
+        let mut copied = 0;
+        let result = (|| -> Result<()> {
+            loop {
+                self.ensure_running()?;
+                let read = reader.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                writer.write_all(&buffer[..read])?;
+                copied += read as u64;
+                self.record_bytes(read as u64);
+                let limit = self.max_throughput.load(Ordering::SeqCst);
+                if let Some(delay) = throttle.delay(limit, read as u64) {
+                    std::thread::sleep(delay);
+                }
+            }
+            Ok(writer.flush()?)
+        })();
+        if let Err(e) = result {
+            self.unrecord_bytes(copied);
+            return Err(e);
+        }
+        Ok(copied)
+    }
+
+    #[cfg(feature = "sync")]
+    pub(crate) fn clone_file(&self, input: &Path, output: &Path) -> Result<u64> {
+        let size = reflink::clone_file(input, output)?;
+        self.record_bytes(size);
+        Ok(size)
+    }
+
+    /// Sends the outcome of copying `input` to `output` and where the ingest is at
+    pub(crate) fn copy_finished(&self, input: &Path, output: &Path, copied: &Result<u64>) {
+        #[cfg(feature = "metrics")]
+        {
+            let elapsed = self.__speed.lock().map(|speed| speed.elapsed());
+            telemetry::copy_finished(&self.handle(), elapsed.unwrap_or_default(), copied);
+        }
+        if self.events.is_empty() {
+            return;
+        }
+        let (path, destination) = (input.to_path_buf(), output.to_path_buf());
+        self.events.emit(match copied {
+            Ok(bytes) => Event::Finished {
+                path,
+                destination,
+                bytes: *bytes,
+            },
+            Err(e) => Event::Failed {
+                path,
+                destination,
+                error: e.to_string(),
+            },
+        });
+        self.events.progress(&self.handle(), false);
+    }
+
+    pub(crate) fn record_bytes(&self, bytes: u64) {
+        let copied = self.bytes.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        if let Ok(mut speed) = self.__speed.lock() {
+            speed.record(copied);
+        }
+        self.record_source(|progress| progress.bytes += bytes);
+        if !self.events.is_empty() {
+            self.events.progress(&self.handle(), false);
+        }
+        let total = self.__total.load(Ordering::SeqCst);
+        self.progress_sinks.bytes(copied, total);
+    }
+
+    /// Carries the attributes and timestamps of `input` over to its copy
+    pub(crate) fn copy_metadata(&self, input: &Path, output: &Path) -> Result<()> {
+        if self.preserve_metadata {
+            preserve::copy_attributes(input, output)?;
+        }
+        self.timestamps.apply(input, output)?;
+        Ok(())
+    }
+
+    /// Applied last since read-only permissions would get in the way of the rest
+    pub(crate) fn copy_permissions(&self, input: &Path, output: &Path) -> Result<()> {
+        if self.preserve_metadata {
+            preserve::copy_permissions(input, output)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the error policy to the outcome of ingesting `path`
+    pub(crate) fn handle_failure(&mut self, path: &Path, ingested: Result<()>) -> Result<()> {
+        match (ingested, self.on_error) {
+            (Ok(()), _) => Ok(()),
+            (Err(e), ErrorPolicy::Abort) => Err(e),
+            (Err(e), ErrorPolicy::ContinueAndReport) => {
+                self.report.failures.push(Failure {
+                    path: path.to_path_buf(),
+                    error: e.to_string(),
+                });
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vfs::MemoryFs;
+    use crate::*;
+
+    /// The files an ingest of the card at `/card` picks, relative to it
+    fn files(fs: &MemoryFs, configure: impl FnOnce(&mut IngestorBuilder)) -> Vec<PathBuf> {
+        let card = PathBuf::from("/card");
+        let mut builder = IngestorBuilder::default();
+        builder
+            .with_source([&card])
+            .with_target("/target")
+            .with_structure(Structure::Retain)
+            .with_filter(Filter {
+                extensions: Cow::Borrowed(&["nef"]),
+                min_size: 1,
+                ..Filter::default()
+            })
+            .with_vfs(fs.clone());
+        configure(&mut builder);
+        let mut files: Vec<PathBuf> = builder
+            .build()
+            .unwrap()
+            .files()
+            .unwrap()
+            .into_iter()
+            .map(|file| file.strip_prefix(&card).unwrap().to_path_buf())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn walks_dcim_under_folders_the_file_filters_reject() {
+        let fs = MemoryFs::new();
+        // Neither folder has the extension or the size the files need
+        fs.add_file("/card/Shoot A/DCIM/100NIKON/DSC_0001.NEF", [1; 10])
+            .add_file("/card/Shoot A/DCIM/100NIKON/DSC_0001.JPG", [2; 10])
+            .add_file("/card/.Trashes/DCIM/100NIKON/DSC_0002.NEF", [3; 10])
+            .add_file("/card/System Volume Information/DCIM/DSC_0003.NEF", [4; 10]);
+        assert_eq!(
+            files(&fs, |_| {}),
+            [PathBuf::from("Shoot A/DCIM/100NIKON/DSC_0001.NEF")]
+        );
+    }
+
+    #[test]
+    fn counts_the_depths_from_dcim() {
+        let fs = MemoryFs::new();
+        fs.add_file("/card/DSC_0001.NEF", [1; 10])
+            .add_file("/card/A/DCIM/100NIKON/DSC_0002.NEF", [2; 10])
+            .add_file("/card/A/DCIM/100NIKON/Burst/DSC_0003.NEF", [3; 10])
+            .add_file("/card/A/B/C/DCIM/DSC_0004.NEF", [4; 10]);
+        let at_depths = |min, max| {
+            files(&fs, |builder| {
+                builder
+                    .with_depth_from(DepthFrom::Dcim)
+                    .with_min_depth(min)
+                    .with_depth(max);
+            })
+        };
+        // The loose file counts from the card
+        assert_eq!(
+            at_depths(1, 2),
+            [
+                PathBuf::from("A/B/C/DCIM/DSC_0004.NEF"),
+                PathBuf::from("A/DCIM/100NIKON/DSC_0002.NEF"),
+                PathBuf::from("DSC_0001.NEF"),
+            ]
+        );
+        assert_eq!(
+            at_depths(2, 3),
+            [
+                PathBuf::from("A/DCIM/100NIKON/Burst/DSC_0003.NEF"),
+                PathBuf::from("A/DCIM/100NIKON/DSC_0002.NEF"),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_out_the_excluded_subfolders() {
+        let fs = MemoryFs::new();
+        fs.add_file("/card/DCIM/100NIKON/DSC_0001.NEF", [1; 10])
+            .add_file("/card/DCIM/101NIKON/DSC_0002.NEF", [2; 10])
+            .add_file("/card/DCIM/101NIKON/Sub/DSC_0003.NEF", [3; 10])
+            .add_file("/card/Other/101NIKON/DSC_0004.NEF", [4; 10]);
+        let files = files(&fs, |builder| {
+            builder.with_excluded_subfolders(["./DCIM/101NIKON/"]);
+        });
+        assert_eq!(
+            files,
+            [
+                PathBuf::from("DCIM/100NIKON/DSC_0001.NEF"),
+                PathBuf::from("Other/101NIKON/DSC_0004.NEF"),
+            ]
+        );
+    }
+}
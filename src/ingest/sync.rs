@@ -1,229 +1,350 @@
 use crate::*;
-impl<'filter> Filter<'filter> {
-    pub fn matches(&self, path: impl AsRef<Path>) -> Result<bool> {
-        if path.is_hidden() == self.ignore_hidden {
-            return Ok(false);
-        }
+use std::fs;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::Ordering;
 
-        let ext = path
-            .as_ref()
-            .extension()
-            .map(OsStr::to_ascii_lowercase)
-            .and_then(|ext| ext.into_string().ok());
-        let ext = ext.as_deref();
+/// Runs an [`Ingestor`] on the current thread, the blocking counterpart of its async methods so
+/// that both can be used from the same build
+pub struct Blocking<'ingestor, 'ingest>(&'ingestor mut Ingestor<'ingest>);
 
-        let size = path.as_ref().metadata()?.len();
-        if let Some(ext) = ext {
-            if self.extensions.contains(&ext) && size >= self.min_size && size <= self.max_size {
-                return Ok(true);
-            }
-        } else if (self.extensions.is_empty() || self.extensions.contains(&""))
-            && size >= self.min_size
-            && size <= self.max_size
-        {
-            return Ok(true);
+/// Runs `future` on the current thread, the hooks and the destinations of the ingest. With the
+/// `async` feature they may need tokio, like S3 and SFTP do, so outside of a runtime it runs on a
+/// current thread runtime shared by the blocking ingests
+pub(crate) fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    #[cfg(feature = "async")]
+    if tokio::runtime::Handle::try_current().is_err() {
+        static RUNTIME: std::sync::OnceLock<Option<tokio::runtime::Runtime>> =
+            std::sync::OnceLock::new();
+        let runtime = RUNTIME.get_or_init(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .ok()
+        });
+        if let Some(runtime) = runtime {
+            return runtime.block_on(future);
         }
-        Ok(false)
     }
+    futures::executor::block_on(future)
 }
 
 impl<'ingest> Ingestor<'ingest> {
-    /// Returns the free space available at the target folder
-    pub fn free_space(&self) -> Result<u64> {
-        std::fs::create_dir_all(&self.target)?;
-        Ok(fs2::free_space(&self.target)?)
+    pub fn blocking(&mut self) -> Blocking<'_, 'ingest> {
+        Blocking(self)
     }
+}
 
-    /// Returns the total size of the files to be copied.
-    pub fn total_size(&self) -> Result<u64> {
-        Ok(self
-            .files()?
-            .iter()
-            .map(|path| path.metadata().map(|m| m.len()).unwrap_or_default())
-            .sum())
+impl<'ingest> Deref for Blocking<'_, 'ingest> {
+    type Target = Ingestor<'ingest>;
+    fn deref(&self) -> &Self::Target {
+        self.0
     }
+}
 
+impl DerefMut for Blocking<'_, '_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+    }
+}
+
+impl<'ingest> Blocking<'_, 'ingest> {
     /// Returns the number of files that were ingested.
-    pub fn ingest(&mut self) -> Result<u64> {
-        fs::create_dir_all(&self.target)?;
-        if self.free_space()? < self.total_size()? {
-            return Err(Error::custom_error("Not enough space"));
-        }
-        let mut rename = match self.structure.clone() {
-            Structure::Rename(ref rename) => Some(rename.clone()),
-            _ => None,
-        }
-        .unwrap_or_default();
-
-        for source in self.sources.clone().iter() {
-            WalkDir::new(source)
-                .into_iter()
-                .flatten()
-                .try_for_each(|entry| -> Result<()> {
-                    let path = entry.path();
-                    if self.filter.matches(path)? {
-                        match self.structure {
-                            Structure::Retain => self.ingest_file(source, path).ok(),
-                            Structure::Rename(_) => {
-                                if path.is_jpeg() {
-                                    let path = path.to_path_buf();
-                                    if self.__jpegs.contains(&path) {
-                                        self.__jpegs.remove(&path);
-                                        return Ok(());
-                                    } else {
-                                        self.__jpegs.insert(path);
-                                    }
-                                };
-                                self.ingest_file_renamed(path, &mut rename).ok()
-                            }
-                            // Structure::Preserve => self.ingest_file_preserve(path).ok(),
-                            Structure::Preserve => todo!(),
-                        };
-                    }
-                    Ok(())
-                })?;
-        }
-
-        let jpegs: Vec<PathBuf> = self.__jpegs.drain().collect();
-        let __copy_xmp = self.copy_xmp;
-        let __copy_jpg = self.copy_jpg;
-        for jpeg in jpegs {
-            self.copy_xmp = false;
-            self.copy_jpg = false;
-            match self.structure {
-                Structure::Retain => {
-                    self.ingest_file_renamed(jpeg, &mut rename).ok();
-                }
-                _ => (),
-            };
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "ingest",
+            skip_all,
+            fields(target = %self.target.display(), backup = ?self.backup, sources = self.sources.len())
+        )
+    )]
+    pub fn ingest(&mut self) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let _active = telemetry::Active::start();
+        if let Some(hook) = self.hooks.before_ingest() {
+            block_on(hook)?;
+        }
+        self.set_phase(Phase::Scanning);
+        let scan = self.blocking_scan();
+        let needs = block_on(self.needs_of(scan.source_sizes(&self.sources)))?;
+        if !needs.fits {
+            self.short_of(needs)?;
         }
+        self.start(&scan)?;
+        // The passes copy what was scanned instead of walking the sources again
+        self.__scan = Some(scan);
+        let ingested = self.ingest_scanned();
+        self.__scan = None;
+        ingested
+    }
+
+    fn ingest_scanned(&mut self) -> Result<()> {
+        // The archives are closed even when the pass failed, with what they got so far
+        let passed = self.pass(true);
+        let finished = self.finish_destinations();
+        passed?;
+        finished?;
+        if let Some(hook) = self.hooks.after_ingest(&self.report) {
+            if let Err(e) = block_on(hook) {
+                self.lifecycle_failed("after_ingest", e);
+            }
+        }
+        self.ensure_running()?;
+        self.backup()?;
+
+        Ok(())
+    }
+
+    /// What the ingest needs at the target, the backup and the destinations
+    pub fn needs(&self) -> Result<Needs> {
+        let scan = self.blocking_scan();
+        block_on(self.needs_of(scan.source_sizes(&self.sources)))
+    }
+
+    pub fn fits(&self) -> Result<bool> {
+        Ok(self.needs()?.fits)
+    }
 
+    /// Whether everything fits given `size` more bytes of free space at each destination
+    pub fn fits_with(&self, size: u64) -> Result<bool> {
+        Ok(self.needs()?.fits_with(size))
+    }
+
+    /// Returns the number of files that were ingested.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(backup = ?self.backup))
+    )]
+    pub fn backup(&mut self) -> Result<()> {
+        let teed = self.tees();
         if let Some(backup) = &self.backup {
-            self.copy_xmp = __copy_xmp;
-            self.copy_jpg = __copy_jpg;
             self.target = backup.to_owned();
             self.backup = None;
-            self.ingest()?;
+            // The destinations got their copies from the target
+            self.destinations = Destinations::default();
+            self.detect_network();
+        } else {
+            return Ok(());
         }
+        if teed {
+            self.finish_tee()?;
+        } else {
+            self.vfs().create_dir_all(&self.target)?;
+            let scan = self.__scan.take().unwrap_or_else(|| self.blocking_scan());
+            if self.free_space()? < scan.total() {
+                self.short_of(block_on(self.needs_of(scan.source_sizes(&self.sources)))?)?;
+            }
+            self.__scan = Some(scan);
+            let passed = self.pass(false);
+            self.__scan = None;
+            passed?;
+        }
+        if let Some(hook) = self.hooks.after_backup(&self.report) {
+            if let Err(e) = block_on(hook) {
+                self.lifecycle_failed("after_backup", e);
+            }
+        }
+        Ok(())
+    }
 
-        Ok(0)
+    /// Copies every source to the current target, orphans are only reported on the first pass
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), fields(target = %self.target.display()))
+    )]
+    fn pass(&mut self, first: bool) -> Result<()> {
+        self.set_phase(match first {
+            true => Phase::Copying,
+            false => Phase::BackingUp,
+        });
+        if let Some(plan) = self.__plan.take() {
+            let executed = self.execute_pass(&plan);
+            self.__plan = Some(plan);
+            return executed;
+        }
+        let failed = self.report.failures.len();
+        let copied = match self.concurrent_sources() {
+            1 => self.copy_sources(first),
+            concurrency => self.copy_concurrently(first, concurrency),
+        };
+        // Keep the manifests of what was copied before stopping
+        self.finish_pass(failed)?;
+        copied
     }
 
-    /// This copies the files as is
-    fn ingest_file<P: AsRef<Path>, S: AsRef<Path>>(&mut self, source: S, path: P) -> Result<()> {
-        let source = source.as_ref();
-        // if the source folder is
-        // aaa/bbb
-        // and the file is
-        // aaa/bbb/ccc/ddd.jpg
-        // then the target is
-        // xxx/yyy
-        // then the target file must be
-        // xxx/yyy/bbb/ccc/ddd.jpg
-        //
-        // source
-        // /
-        // file
-        // /aaa/bbb/file.jpg
-        // target
-        // xxx/yyy
-        // target file
-        // xxx/yyy/aaa/bbb/file.jpg
-        let target = if let Some(source) = source.parent() {
-            self.target.join(path.as_ref().strip_prefix(source)?)
-        } else {
-            self.target.join(path.as_ref().strip_prefix(source)?)
+    /// Copies the files of the sources and their orphan sidecars
+    fn copy_sources(&mut self, first: bool) -> Result<()> {
+        let mut rename = self.renamer();
+
+        let mut entries = match &self.__scan {
+            Some(scan) => scan.of(&self.sources),
+            None => self.entries(),
         };
-        fs::create_dir_all(target.parent().unwrap())?;
-        self.ingest_copy(&path, &target)?;
+        self.placeholders(&mut entries)?;
+        self.__pairs = Pairs::resolve_in(self.vfs(), entries.iter().map(|(_, entry)| entry.path()));
+        self.plan_pass(
+            entries
+                .iter()
+                .filter(|(_, entry)| entry.is_file())
+                .map(|(_, entry)| entry.path()),
+        )?;
+        for (source, entry) in entries {
+            self.map_entry(entry, source, &mut rename)?;
+        }
 
+        for (source, orphan) in self.orphan_sidecars() {
+            if first {
+                self.report
+                    .warn(Warning::OrphanSidecar(orphan.path().to_path_buf()));
+            }
+            if self.copy_orphans {
+                let ingested = self.ingest_path(source, orphan.path(), &mut rename);
+                self.handle_failure(orphan.path(), ingested)?;
+            }
+        }
         Ok(())
     }
 
+    /// Copies every source in a pipeline of its own on up to `concurrency` threads
+    fn copy_concurrently(&mut self, first: bool, concurrency: usize) -> Result<()> {
+        let skipped = self.report.skipped.len();
+        let pipelines = std::sync::Mutex::new(self.pipelines().into_iter());
+        let copied = std::sync::Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            for _ in 0..concurrency {
+                scope.spawn(|| loop {
+                    let Some(mut pipeline) = pipelines.lock().ok().and_then(|mut p| p.next())
+                    else {
+                        break;
+                    };
+                    let result = pipeline.blocking().copy_sources(first);
+                    if let Ok(mut copied) = copied.lock() {
+                        copied.push((pipeline, result));
+                    }
+                });
+            }
+        });
+        let copied = copied.into_inner().unwrap_or_else(|e| e.into_inner());
+        self.join_pipelines(copied, skipped)
+    }
+
+    /// Copies the files of `plan` to the target and the backup like [`Blocking::ingest`] copies
+    /// the sources, see [`Ingestor::plan`]
+    pub fn execute(&mut self, plan: IngestPlan) -> Result<()> {
+        self.run_id = plan.run_id;
+        self.__plan = Some(plan);
+        let executed = self.ingest();
+        self.__plan = None;
+        executed
+    }
+
+    /// Copies the files of `plan` to the current target
+    fn execute_pass(&mut self, plan: &IngestPlan) -> Result<()> {
+        let failed = self.report.failures.len();
+        self.reset_pass();
+        self.__pairs = Pairs::resolve_in(self.vfs(), plan.sources());
+        for file in &plan.files {
+            // Keep the manifests of what was copied before stopping
+            if let Err(e) = self.execute_file(file) {
+                self.finish_pass(failed)?;
+                return Err(e);
+            }
+        }
+        self.finish_pass(failed)
+    }
+
+    fn execute_file(&mut self, file: &PlannedFile) -> Result<()> {
+        self.ensure_running()?;
+        self.wait_for_target()?;
+        if !self.ensure_space(&file.source)? {
+            return Ok(());
+        }
+        let output = self.planned(file);
+        let ingested = self.ingest_planned(&file.source, output);
+        self.handle_failure(&file.source, ingested)
+    }
+
+    fn ingest_planned(&mut self, input: &Path, output: PathBuf) -> Result<()> {
+        if let Some(parent) = output.parent() {
+            self.vfs().create_dir_all(parent)?;
+        }
+        self.ingest_copy(input, output)?;
+        Ok(())
+    }
+
+    /// Copies only the files that are missing or differ at the target and the backup, eg. to
+    /// finish an interrupted ingest. Returns the number of files copied
+    pub fn ingest_missing(&mut self, mode: VerifyMode) -> Result<usize> {
+        self.report = Report::default();
+        let mut copied = self.repair(mode)?;
+        if let Some(backup) = self.backup.clone() {
+            let target = std::mem::replace(&mut self.target, backup);
+            let repaired = self.repair(mode);
+            self.target = target;
+            copied += repaired?;
+        }
+        Ok(copied)
+    }
+
+    fn repair(&mut self, mode: VerifyMode) -> Result<usize> {
+        let report = self.verify(mode)?;
+        let names = names::Names::load(self.vfs(), &self.target);
+        let failed = self.report.failures.len();
+        self.reset_pass();
+        self.__names.clear();
+        let mut copied = 0;
+        for (source, target) in report.missing.into_iter().chain(report.mismatched) {
+            self.ensure_running()?;
+            if let Some(parent) = target.parent() {
+                self.vfs().create_dir_all(parent)?;
+            }
+            let (target, claimed) = self.repaired(&names, &source, target)?;
+            let repaired = self.copy_file(&source, &target).map(|_| ());
+            if repaired.is_ok() {
+                copied += 1;
+                self.record_file(&source);
+            } else if claimed {
+                self.vfs().remove_file(&target).ok();
+            }
+            self.handle_failure(&source, repaired)?;
+        }
+        self.write_manifests()?;
+        self.save_names(failed)?;
+        Ok(copied)
+    }
+
+    /// This copies the files as is
+    fn ingest_file<P: AsRef<Path>, S: AsRef<Path>>(
+        &mut self,
+        source: S,
+        path: P,
+    ) -> Result<()> {
+        let target = self.retained(source.as_ref(), path.as_ref())?;
+        self.ingest_to(path, target)
+    }
+
     /// Since this doesn't retain the structure we need to rename the accompanying jpegs as well
     pub fn ingest_file_renamed<P: AsRef<Path>>(
         &mut self,
         path: P,
-        rename: &mut Rename,
+        rename: &mut Rename<'ingest>,
     ) -> Result<()> {
-        let file_extension = path
-            .as_ref()
-            .extension()
-            .and_then(OsStr::to_str)
-            .ok_or_else(|| Error::custom_error("File extension not found"))?;
-
-        let target =
-            self.target
-                .canonicalize()?
-                .join(format!("{}.{}", rename.next(&path)?, file_extension));
-        self.ingest_copy(path, target)?;
-        Ok(())
+        let target = self.renamed(path.as_ref(), rename)?;
+        self.ingest_to(path, target)
     }
 
     pub fn ingest_file_preserve<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let target = self.target.canonicalize()?.join(
-            path.as_ref()
-                .file_name()
-                .ok_or_else(|| Error::custom_error("File name not found"))?,
-        );
-        self.ingest_copy(path, target)?;
-        Ok(())
+        let target = self.preserved(path.as_ref())?;
+        self.ingest_to(path, target)
     }
 
-    /// Returns all the files that match the filters
-    pub fn files(&self) -> Result<Vec<PathBuf>> {
-        let mut files = Vec::new();
-        for source in self.sources.iter() {
-            files.extend(
-                WalkDir::new(source)
-                    .into_iter()
-                    .flatten()
-                    .filter_map(|entry| {
-                        let path = entry.path();
-                        if entry.file_type().is_file() && self.filter.matches(path).ok()? {
-                            Some(path.to_path_buf())
-                        } else {
-                            None
-                        }
-                    }),
-            )
-        }
-        Ok(files)
-    }
-
-    /// This returns all the folders in the source folders
-    pub fn folders(&self) -> Result<Vec<PathBuf>> {
-        // let mut folders = Vec::new();
-        // for source in self.sources.iter() {
-        // folders.extend(
-        let folders = self
-            .sources
-            .clone()
-            .into_iter()
-            .fold(Vec::new(), |mut last, source| {
-                last.extend(
-                    WalkDir::new(source)
-                        .into_iter()
-                        .flatten()
-                        .filter_map(|entry| {
-                            let path = entry.path();
-                            if entry.file_type().is_dir() {
-                                Some(path.to_path_buf())
-                            } else {
-                                None
-                            }
-                        }),
-                );
-                last
-            });
-        // .collect();
-        // )
-        // }
-        Ok(folders)
-    }
-
-    pub fn builder() -> IngestorBuilder<'ingest> {
-        Default::default()
+    fn ingest_to(&mut self, path: impl AsRef<Path>, target: PathBuf) -> Result<()> {
+        self.ensure_running()?;
+        let target = self.placed(path.as_ref(), target);
+        if let Some(parent) = target.parent() {
+            self.vfs().create_dir_all(parent)?;
+        }
+        self.ingest_copy(path, target)?;
+        Ok(())
     }
 
     pub fn ingest_copy<I: AsRef<Path>, O: AsRef<Path>>(
@@ -231,25 +352,387 @@ impl<'ingest> Ingestor<'ingest> {
         input: I,
         output: O,
     ) -> Result<u64> {
-        let output = crate::exists_plus_one(output)?;
-        if self.copy_xmp {
-            fs::copy(
-                input.as_ref().with_extension("xmp"),
-                output.with_extension("xmp"),
-            )
-            .ok();
-        }
-        if !self.structure.is_retained() && self.copy_jpg {
-            if let Ok(path) = accompanying_jpeg(&input) {
-                if self.__jpegs.contains(&path) {
-                    self.__jpegs.remove(&path);
-                } else {
-                    self.__jpegs.insert(path.clone());
+        self.ensure_running()?;
+        let Some(plan) = self.plan_copy(input.as_ref(), output.as_ref())? else {
+            return Ok(0);
+        };
+        for (companion, target) in plan.companions {
+            if let Some(parent) = target.parent() {
+                self.vfs().create_dir_all(parent)?;
+            }
+            if self.copy_file(&companion, &target).is_ok() {
+                self.store(&target).ok();
+            }
+        }
+
+        self.record_file(input.as_ref());
+        self.events.emit(Event::Started {
+            path: input.as_ref().to_path_buf(),
+            destination: plan.output.clone(),
+        });
+        let copied = self.copy_file(&input, &plan.output);
+        self.copy_finished(input.as_ref(), &plan.output, &copied);
+        // Without staging the claimed file holds what was copied of it
+        if copied.is_err() && plan.claimed && self.atomic {
+            self.vfs().remove_file(&plan.output).ok();
+        }
+        match copied {
+            Ok(_) => {
+                self.copied(input.as_ref(), &plan.output);
+                self.run_copy_hooks(input.as_ref(), &plan.output);
+                self.store(&plan.output)?;
+            }
+            Err(_) if self.quarantine == Quarantine::Markers => {
+                self.vfs().create(&crate::failed_path(&plan.output)).ok();
+            }
+            Err(_) => {}
+        }
+        copied
+    }
+
+    fn run_copy_hooks(&mut self, input: &Path, output: &Path) {
+        for hook in self.copy_hooks.clone() {
+            if let Err(e) = hook.run_blocking(input, output) {
+                self.hook_failed(output, e);
+            }
+        }
+    }
+
+    /// Stores a copy made to the target at every destination, retried like the copies
+    fn store(&self, output: &Path) -> Result<()> {
+        let Some(key) = destination::key(&self.target, output) else {
+            return Ok(());
+        };
+        for destination in self.destinations.iter() {
+            let mut attempt = 0;
+            loop {
+                match block_on(destination.store(output, &key)) {
+                    Ok(()) => break,
+                    Err(ref e) if self.should_retry(attempt, e) => {}
+                    Err(e) => {
+                        return Err(destination::store_failed(destination.as_ref(), &key, e));
+                    }
                 }
-                fs::copy(path, output.with_extension("jpg")).ok();
+                std::thread::sleep(self.retry.delay_for(attempt));
+                attempt += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lets every destination wrap up once the files were stored, an ingest that goes through
+    /// [`Blocking::ingest_file_renamed`] instead of [`Blocking::ingest`] should call it at the end
+    pub fn finish_destinations(&self) -> Result<()> {
+        for destination in self.destinations.iter() {
+            block_on(destination.finish()).map_err(|e| {
+                Error::custom_error(format!("Couldn't finish {}: {}", destination.name(), e))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Waits for a network target that dropped out to come back, up to the reconnect timeout
+    fn wait_for_target(&self) -> Result<()> {
+        let started = std::time::Instant::now();
+        while self.target_unreachable() {
+            self.ensure_running()?;
+            if started.elapsed() >= self.reconnect_timeout {
+                return Err(Error::custom_error(format!(
+                    "{} is unreachable",
+                    self.target.display()
+                )));
             }
+            std::thread::sleep(network::RECONNECT_INTERVAL);
         }
+        Ok(())
+    }
+
+    /// Copies a single file, every copy made by the ingestor goes through here
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "copy",
+            level = "debug",
+            skip_all,
+            fields(file = %input.as_ref().display(), destination = %output.as_ref().display())
+        )
+    )]
+    fn copy_file(&mut self, input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<u64> {
+        let (input, output) = (input.as_ref(), output.as_ref());
+        self.set_current(input);
+        let mut errors = Vec::new();
+        let mut attempt = 0;
+        let mirror = self.claim_mirror(input, output)?;
+        loop {
+            let copied = match &mirror {
+                Some((mirror, _)) => self.tee_file(input, output, mirror),
+                None => self.try_copy_file(input, output).map(|size| (size, Vec::new())),
+            };
+            match copied {
+                Ok((size, hashes)) => {
+                    match &mirror {
+                        Some((mirror, _)) => self.record_tee(input, output, mirror, hashes)?,
+                        None => self.record_hash(input, output)?,
+                    }
+                    self.written(output);
+                    self.record_retries(input, errors, true);
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(bytes = size, attempts = attempt + 1, "copied");
+                    return Ok(size);
+                }
+                Err(e) => {
+                    errors.push(e.to_string());
+                    if !self.should_retry(attempt, &e) || self.wait_for_target().is_err() {
+                        #[cfg(feature = "tracing")]
+                        tracing::error!(error = %e, attempts = attempt + 1, "copy failed");
+                        if attempt > 0 {
+                            self.record_retries(input, errors, false);
+                        }
+                        // The mirror claimed for the staged copy is left empty
+                        if let Some((mirror, true)) = mirror.filter(|_| self.atomic) {
+                            self.vfs().remove_file(&mirror).ok();
+                        }
+                        return Err(e);
+                    }
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %e, attempt, "retrying the copy");
+                }
+            }
+            std::thread::sleep(self.retry.delay_for(attempt));
+            attempt += 1;
+        }
+    }
+
+    fn try_copy_file(&self, input: &Path, output: &Path) -> Result<u64> {
+        let target = self.staging(output);
+        let size = match self.copy_contents(input, &target) {
+            Ok(size) => size,
+            Err(e) => {
+                if self.atomic {
+                    self.vfs().remove_file(&target).ok();
+                }
+                return Err(e);
+            }
+        };
+        if self.atomic {
+            self.vfs().rename(&target, output)?;
+            #[cfg(target_os = "macos")]
+            self.hide_partial(output, false)?;
+        }
+        if self.durability.syncs_folders() && self.vfs.is_none() {
+            crate::sync_dir(output)?;
+        }
+        Ok(size)
+    }
+
+    /// Copies `input` in chunks of `buffer_size`, counting the bytes and checking for
+    /// cancellation between chunks
+    fn copy_chunked(&self, input: &Path, output: &Path) -> Result<u64> {
+        if self.no_cache {
+            return self.copy_uncached(input, output);
+        }
+        let mut reader = fs::File::open(input)?;
+        let metadata = reader.metadata()?;
+        let permissions = metadata.permissions();
+        let mut writer = fs::File::create(output)?;
+        if self.preallocate {
+            preallocate::preallocate(&writer, metadata.len())?;
+        }
+        #[cfg(target_os = "macos")]
+        self.hide_partial(output, true).ok();
+        let mut copied = 0;
+        let result = self.copy_chunks(&mut reader, &mut writer, &mut copied);
+        if result.is_err() {
+            self.unrecord_bytes(copied);
+        }
+        result?;
+        fs::set_permissions(output, permissions)?;
+        Ok(copied)
+    }
+
+    /// Same as [`Self::copy_chunked`] with the source read around the page cache, and never
+    /// through the ring
+    /// Copies `input` to `output` and `mirror` in a single read and checks both copies, returns
+    /// the size and the hashes of the file
+    fn tee_file(
+        &self,
+        input: &Path,
+        output: &Path,
+        mirror: &Path,
+    ) -> Result<(u64, Vec<(HashAlgo, String)>)> {
+        let mut copied = 0;
+        let mut throttle = handle::Throttle::new();
+        let teed = self.tee(input, output, mirror).copy(&mut |read| {
+            self.ensure_running()?;
+            copied += read;
+            self.record_bytes(read);
+            let limit = self.max_throughput.load(Ordering::SeqCst);
+            if let Some(delay) = throttle.delay(limit, read) {
+                std::thread::sleep(delay);
+            }
+            Ok(())
+        });
+        if teed.is_err() {
+            self.unrecord_bytes(copied);
+        }
+        Ok((copied, teed?))
+    }
+
+    fn copy_uncached(&self, input: &Path, output: &Path) -> Result<u64> {
+        use std::io::Write;
+        let mut reader = nocache::Uncached::open(input, self.chunk_size())?;
+        let metadata = reader.metadata()?;
+        let permissions = metadata.permissions();
+        let mut writer = fs::File::create(output)?;
+        if self.preallocate {
+            preallocate::preallocate(&writer, metadata.len())?;
+        }
+        #[cfg(target_os = "macos")]
+        self.hide_partial(output, true).ok();
+        let mut copied = 0;
+        let mut throttle = handle::Throttle::new();
+        let result = (|| -> Result<()> {
+            loop {
+                self.ensure_running()?;
+                let chunk = reader.read()?;
+                if chunk.is_empty() {
+                    break;
+                }
+                let read = chunk.len() as u64;
+                writer.write_all(chunk)?;
+                copied += read;
+                self.record_bytes(read);
+                let limit = self.max_throughput.load(Ordering::SeqCst);
+                if let Some(delay) = throttle.delay(limit, read) {
+                    std::thread::sleep(delay);
+                }
+            }
+            writer.flush()?;
+            Ok(())
+        })();
+        if result.is_err() {
+            self.unrecord_bytes(copied);
+        }
+        result?;
+        fs::set_permissions(output, permissions)?;
+        Ok(copied)
+    }
+
+    fn copy_chunks(
+        &self,
+        reader: &mut fs::File,
+        writer: &mut fs::File,
+        copied: &mut u64,
+    ) -> Result<()> {
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        if self.copy_ring(reader, writer, copied)? {
+            return Ok(());
+        }
+        use std::io::{Read, Write};
+        let mut buffer = vec![0; self.chunk_size()];
+        let mut throttle = handle::Throttle::new();
+        loop {
+            self.ensure_running()?;
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..read])?;
+            *copied += read as u64;
+            self.record_bytes(read as u64);
+            let limit = self.max_throughput.load(Ordering::SeqCst);
+            if let Some(delay) = throttle.delay(limit, read as u64) {
+                std::thread::sleep(delay);
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn copy_contents(&self, input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<u64> {
+        let (input, output) = (input.as_ref(), output.as_ref());
+        if let Some(ref vfs) = self.vfs {
+            return self.copy_through(vfs.as_ref(), input, output);
+        }
+        let size = match self.reflink {
+            Reflink::Never => self.copy_chunked(input, output)?,
+            Reflink::Always => self.clone_file(input, output)?,
+            Reflink::Auto => match self.clone_file(input, output) {
+                Ok(size) => size,
+                Err(_) => self.copy_chunked(input, output)?,
+            },
+        };
+        self.copy_metadata(input, output)?;
+        if self.durability.syncs_files() {
+            fs::OpenOptions::new()
+                .write(true)
+                .open(output)?
+                .sync_all()?;
+        }
+        self.copy_permissions(input, output)?;
+        Ok(size)
+    }
+
+    pub fn map_entry(
+        &mut self,
+        entry: vfs::Entry,
+        source: impl AsRef<Path>,
+        rename: &mut Rename<'ingest>,
+    ) -> Result<()> {
+        self.ensure_running()?;
+        self.wait_for_target()?;
+        if self.skips(&entry) {
+            return Ok(());
+        }
+        if !self.ensure_space(entry.path())? {
+            return Ok(());
+        }
+
+        let path = entry.path();
+        let ingested = self.ingest_path(source, path, rename);
+        self.handle_failure(path, ingested)
+    }
+
+    /// Copies a single path according to the structure
+    fn ingest_path(
+        &mut self,
+        source: impl AsRef<Path>,
+        path: impl AsRef<Path>,
+        rename: &mut Rename<'ingest>,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        match self.structure {
+            Structure::Retain => self.ingest_file(source, path),
+            Structure::Rename(_) => self.ingest_file_renamed(path, rename),
+            Structure::Preserve => self.ingest_file_preserve(path),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use crate::vfs::MemoryFs;
+    use crate::*;
 
-        Ok(fs::copy(input, output)?)
+    #[test]
+    fn runs_hooks_that_need_tokio() {
+        let fs = MemoryFs::new();
+        fs.add_file("/a/DCIM/100NIKON/DSC_0001.NEF", [1; 10]);
+        let sources = [PathBuf::from("/a")];
+        let mut ingestor = IngestorBuilder::default()
+            .with_source(&sources)
+            .with_target("/target")
+            .with_structure(Structure::Preserve)
+            .with_filter(Filter::default())
+            .with_vfs(fs.clone())
+            .with_post_copy_hook(|_, _| async {
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                Ok(())
+            })
+            .build()
+            .unwrap();
+        ingestor.blocking().ingest().unwrap();
+        assert!(ingestor.report.warnings.is_empty());
+        assert!(fs.is_file(Path::new("/target/DSC_0001.NEF")));
     }
 }
@@ -1,43 +1,228 @@
+#[cfg(feature = "archive")]
+pub mod archive;
+mod attributes;
+mod benchmark;
+mod bursts;
+pub mod checksums;
+mod clips;
+mod companion;
+#[cfg(feature = "serde")]
+mod config;
+mod conflict;
+#[cfg(feature = "daemon")]
+pub mod daemon;
+mod dedup;
+mod destination;
+pub mod devices;
+mod durability;
+#[cfg(feature = "encrypt")]
+pub mod encrypt;
 mod errors;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod events;
+mod finder;
+mod folders;
+mod gpx;
+mod handle;
+mod hook;
+mod hash;
+#[cfg(feature = "exif")]
+pub mod meta;
+#[cfg(feature = "uniffi")]
+pub mod mobile;
+pub mod mhl;
+mod names;
+pub mod network;
+mod nocache;
+mod order;
+mod pairs;
+mod pipelines;
+mod planner;
+mod preallocate;
+mod preserve;
+mod progress;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "s3")]
+pub mod s3;
+#[cfg(feature = "previews")]
+pub mod preview;
+mod reflink;
+mod report;
+mod retry;
+mod route;
+mod sanitize;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(all(feature = "sftp", unix))]
+pub mod sftp;
+mod sessions;
+mod sidecar;
+mod sniff;
+mod tee;
+#[cfg(feature = "metrics")]
+pub mod telemetry;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod times;
 mod traits;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
+mod verify;
+pub mod vfs;
+mod walk;
+mod xmp;
+#[cfg(feature = "validate")]
+pub mod validate;
 use std::sync::atomic::AtomicBool;
-use std::sync::{atomic::AtomicUsize, Arc};
+use std::sync::{atomic::AtomicU64, atomic::AtomicUsize, Arc};
 
 mod ingest;
 pub use ingest::*;
 
+pub use attributes::{Attributes, Placeholders};
+pub use benchmark::Benchmark;
+pub use bursts::{Burst, BurstDetection};
+#[cfg(feature = "serde")]
+pub use config::{FilterConfig, IngestConfig, RenameConfig, StructureConfig};
+pub use clips::VideoCardPolicy;
+pub use companion::{companions, Companion, CompanionKind};
+pub use conflict::{Conflict, ConflictResolver, Conflicts, Decision, Resolution};
+pub use destination::{Destination, Destinations, SpaceFuture, StoreFuture};
+pub use durability::Durability;
 pub use errors::Error;
+#[cfg(feature = "json")]
+pub use events::JsonEventSink;
+pub use events::{Event, EventSink, Events};
+pub use finder::Finder;
+pub use folders::FolderTree;
+pub use gpx::{Geotag, Track, TrackPoint};
+pub use handle::IngestHandle;
+pub use hook::{CopyHook, HookFuture, LifecycleHooks, ReportHook};
+pub use hash::{HashAlgo, HashedFile, Hasher};
+pub use order::{OrderBy, Priority, PriorityFn};
+pub use pairs::{PairPolicy, Pairs};
+pub use planner::{FileDiff, IngestPlan, PlannedFile};
+pub use progress::{
+    AtomicProgress, Phase, ProgressSink, ProgressSinks, ProgressUpdate, SourceProgress,
+};
+pub use reflink::Reflink;
+pub use report::{Failure, Report, RetryHistory, Warning};
+pub use retry::Retry;
+pub use route::Routes;
+pub use sessions::Sessions;
+pub use sidecar::{SidecarPolicy, SIDECAR_EXTENSIONS};
+pub use times::Timestamps;
+pub use verify::{VerifyMode, VerifyReport};
+pub use uuid::Uuid;
+pub use vfs::Vfs;
+pub use walk::DepthFrom;
+pub use xmp::{MetadataPreset, Picks};
 use errors::Result;
 use std::borrow::Cow;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 pub(crate) use traits::IsHidden;
-use traits::IsJpeg;
-use walkdir::WalkDir;
+use traits::{IsJpeg, IsLossyTwin, IsRaw, IsVideo};
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
 
 pub const RAW_EXTENSIONS: [&str; 37] = [
     "nef", "3fr", "ari", "arw", "bay", "crw", "cr2", "cr3", "cap", "dcs", "dcr", "dng", "drf",
     "eip", "erf", "fff", "gpr", "mdc", "mef", "mos", "mrw", "nrw", "obm", "orf", "pef", "ptx",
     "pxn", "r3d", "raw", "rwl", "rw2", "rwz", "sr2", "srf", "srw", "x3f", "raf",
 ];
+/// Extension of the partially copied files, renamed to their final name once complete
+pub const PART_EXTENSION: &str = "ingestpart";
+/// Extension of the empty marker left at the target for files that failed to copy
+pub const FAILED_EXTENSION: &str = "failed";
+/// File at the target root listing the source paths that failed to copy
+pub const FAILURE_MANIFEST: &str = "ingest-failed.txt";
+/// Size of the chunks files are copied in, large enough to keep USB card readers busy
+pub const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+pub const VIDEO_EXTENSIONS: [&str; 11] = [
+    "mp4", "mov", "avi", "mts", "m2ts", "mxf", "mkv", "m4v", "3gp", "insv", "360",
+];
 pub const LOSSY_EXTENSIONS: [&str; 9] = [
     "jpg", "jpeg", "png", "heic", "avif", "heif", "tiff", "tif", "hif",
 ];
+/// Lossy formats paired with a RAW of the same name, the jpegs in the rest of the docs
+pub const TWIN_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "heic", "heif", "hif"];
 
 #[derive(Debug, Clone, Default)]
 pub struct IngestorBuilder<'ingest> {
     pub structure: Option<Structure<'ingest>>,
     pub target: Option<PathBuf>,
     pub backup: Option<PathBuf>,
+    pub tee: Option<bool>,
     pub sources: Option<HashSet<&'ingest Path>>,
+    pub labels: Option<HashMap<&'ingest Path, &'ingest str>>,
+    pub source_folders: Option<bool>,
     pub filter: Option<Filter<'ingest>>,
-    pub copy_xmp: Option<bool>,
+    pub sidecars: Option<SidecarPolicy<'ingest>>,
+    pub routes: Option<Routes>,
+    pub session_gap: Option<std::time::Duration>,
+    pub bursts: Option<BurstDetection>,
+    pub order: Option<OrderBy>,
+    pub priority: Option<Priority>,
+    pub clock_offsets: Option<HashMap<&'ingest Path, i64>>,
+    pub timezone: Option<i16>,
+    pub max_files_per_folder: Option<usize>,
+    pub collisions: Option<CollisionPolicy>,
+    pub collision_suffix: Option<CollisionSuffix>,
+    pub skip_if_hash_exists_in_target: Option<bool>,
+    pub windows_names: Option<bool>,
+    pub finder: Option<Finder>,
     pub copy_jpg: Option<bool>,
+    pub pair_policy: Option<PairPolicy>,
+    pub copy_orphans: Option<bool>,
+    pub metadata: Option<MetadataPreset>,
+    pub generate_missing_xmp: Option<bool>,
+    pub gpx: Option<(&'ingest Path, i16)>,
+    pub picks: Option<Picks>,
+    pub copy_hooks: Option<Vec<CopyHook>>,
+    pub hooks: Option<LifecycleHooks>,
+    pub events: Option<Events>,
+    pub conflicts: Option<Conflicts>,
+    pub destinations: Option<Destinations>,
+    #[cfg(feature = "previews")]
+    pub previews: Option<preview::PreviewSize>,
+    pub timestamps: Option<Timestamps>,
+    pub preserve_metadata: Option<bool>,
+    pub atomic: Option<bool>,
+    pub durability: Option<Durability>,
+    pub no_cache: Option<bool>,
+    pub preallocate: Option<bool>,
+    pub retry: Option<Retry>,
+    pub reflink: Option<Reflink>,
+    pub on_error: Option<ErrorPolicy>,
+    pub quarantine: Option<Quarantine>,
+    pub mhl: Option<HashAlgo>,
+    pub checksums: Option<HashAlgo>,
+    #[cfg(feature = "validate")]
+    pub validate: Option<bool>,
     pub ignore_hidden: Option<bool>,
     pub progress: Option<Arc<AtomicUsize>>,
+    pub bytes: Option<Arc<AtomicU64>>,
+    pub progress_sinks: Option<ProgressSinks>,
+    pub buffer_size: Option<usize>,
+    pub network: Option<bool>,
+    pub reconnect_timeout: Option<std::time::Duration>,
+    pub max_throughput: Option<u64>,
+    pub reserved_space: Option<u64>,
     pub depth: Option<usize>,
-    pub cancel: Option<Arc<AtomicBool>>
+    pub min_depth: Option<usize>,
+    pub depth_from: Option<DepthFrom>,
+    pub included_subfolders: Option<Vec<PathBuf>>,
+    pub excluded_subfolders: Option<Vec<PathBuf>>,
+    pub concurrency: Option<usize>,
+    pub cancel: Option<Arc<AtomicBool>>,
+    pub vfs: Option<Arc<dyn Vfs>>,
+    pub run_id: Option<Uuid>,
+    pub job_name: Option<String>,
 }
 
 impl<'ingest> IngestorBuilder<'ingest> {
@@ -55,6 +240,41 @@ impl<'ingest> IngestorBuilder<'ingest> {
         self
     }
 
+    /// Leaves out the files above `min_depth`, eg. 2 for the files loose at the root of a card
+    pub fn with_min_depth(&mut self, min_depth: usize) -> &mut Self {
+        self.min_depth = Some(min_depth);
+        self
+    }
+
+    pub fn with_depth_from(&mut self, depth_from: DepthFrom) -> &mut Self {
+        self.depth_from = Some(depth_from);
+        self
+    }
+
+    /// Only ingests the files under `folders`, relative to each source, eg. `DCIM/105NIKON`
+    pub fn with_included_subfolders<P: AsRef<Path>>(
+        &mut self,
+        folders: impl IntoIterator<Item = P>,
+    ) -> &mut Self {
+        self.included_subfolders = Some(walk::subfolders(folders));
+        self
+    }
+
+    /// Leaves out the files under `folders`, relative to each source
+    pub fn with_excluded_subfolders<P: AsRef<Path>>(
+        &mut self,
+        folders: impl IntoIterator<Item = P>,
+    ) -> &mut Self {
+        self.excluded_subfolders = Some(walk::subfolders(folders));
+        self
+    }
+
+    /// Copies up to `concurrency` sources at the same time, see [`Ingestor::concurrency`]
+    pub fn with_concurrency(&mut self, concurrency: usize) -> &mut Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
     pub fn with_target<P: AsRef<Path>>(&mut self, target: P) -> &mut Self {
         self.target = Some(target.as_ref().to_path_buf());
         self
@@ -70,6 +290,44 @@ impl<'ingest> IngestorBuilder<'ingest> {
         self
     }
 
+    /// Adds a source named `label`, eg. the slot of the card, see [`LABEL_TOKEN`]
+    pub fn with_labeled_source<P: AsRef<Path> + ?Sized>(
+        &mut self,
+        label: &'ingest str,
+        source: &'ingest P,
+    ) -> &mut Self {
+        let source = source.as_ref();
+        self.sources.get_or_insert_with(HashSet::new).insert(source);
+        self.labels.get_or_insert_with(HashMap::new).insert(source, label);
+        self
+    }
+
+    /// Adds the roots of a card as sources labeled with its name
+    pub fn with_card(&mut self, card: &'ingest devices::Source) -> &mut Self {
+        for root in &card.roots {
+            self.with_labeled_source(&card.name, root);
+        }
+        self
+    }
+
+    /// Copy each source into a subfolder of the target named after its label
+    pub fn source_folders(&mut self, source_folders: bool) -> &mut Self {
+        self.source_folders = Some(source_folders);
+        self
+    }
+
+    /// What to copy of the AVCHD and XAVC structures, on top of the filter
+    pub fn with_video_cards(&mut self, policy: VideoCardPolicy) -> &mut Self {
+        self.filter.get_or_insert_with(Filter::default).video_cards = policy;
+        self
+    }
+
+    /// What to do with the files only in the cloud, on top of the filter
+    pub fn with_placeholders(&mut self, placeholders: Placeholders) -> &mut Self {
+        self.filter.get_or_insert_with(Filter::default).attributes.placeholders = placeholders;
+        self
+    }
+
     pub fn with_filter(&mut self, filter: impl Into<Filter<'ingest>>) -> &mut Self {
         self.filter = Some(filter.into());
         self
@@ -80,13 +338,203 @@ impl<'ingest> IngestorBuilder<'ingest> {
         self
     }
 
+    /// Counts the bytes copied so far
+    pub fn bytes_progress(&mut self, bytes: Arc<AtomicU64>) -> &mut Self {
+        self.bytes = Some(bytes);
+        self
+    }
+
+    /// Send the bytes and files copied and the phases of the ingest to `sink`, eg. an
+    /// [`AtomicProgress`], a channel or an `indicatif` progress bar
+    pub fn with_progress_sink(&mut self, sink: impl ProgressSink + 'static) -> &mut Self {
+        self.progress_sinks
+            .get_or_insert_with(ProgressSinks::default)
+            .push(sink);
+        self
+    }
+
+    pub fn with_buffer_size(&mut self, buffer_size: usize) -> &mut Self {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+
+    /// Whether the target and the backup are network shares, detected when unset
+    pub fn network(&mut self, network: bool) -> &mut Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// How long to wait for a network share that dropped out before failing, a minute by default
+    pub fn with_reconnect_timeout(&mut self, timeout: std::time::Duration) -> &mut Self {
+        self.reconnect_timeout = Some(timeout);
+        self
+    }
+
+    /// Limits the copy speed in bytes per second, it can be changed while ingesting through
+    /// [`Ingestor::handle`]
+    pub fn with_max_throughput(&mut self, bytes_per_sec: u64) -> &mut Self {
+        self.max_throughput = Some(bytes_per_sec);
+        self
+    }
+
+    /// Leave at least `bytes` free at the target and the backup
+    pub fn with_reserved_space(&mut self, bytes: u64) -> &mut Self {
+        self.reserved_space = Some(bytes);
+        self
+    }
+
     pub fn cancel(&mut self, cancel: Arc<AtomicBool>) -> &mut Self {
         self.cancel = Some(cancel);
         self
     }
 
+    /// Walk, plan and copy through `vfs` instead of the disk, eg. a [`vfs::MemoryFs`]
+    pub fn with_vfs(&mut self, vfs: impl Vfs + 'static) -> &mut Self {
+        self.vfs = Some(Arc::new(vfs));
+        self
+    }
+
+    /// Reuses the id of an earlier run, eg. to verify a run whose names contain [`RUN_TOKEN`]
+    pub fn with_run_id(&mut self, run_id: Uuid) -> &mut Self {
+        self.run_id = Some(run_id);
+        self
+    }
+
+    /// Name of the job, eg. `smith-wedding`, for [`JOB_TOKEN`]
+    pub fn with_job_name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.job_name = Some(name.into());
+        self
+    }
+
+    /// Shorthand for only copying the xmp sidecars or none at all
     pub fn copy_xmp(&mut self, copy_xmp: bool) -> &mut Self {
-        self.copy_xmp = Some(copy_xmp);
+        self.sidecars = Some(if copy_xmp {
+            SidecarPolicy::xmp()
+        } else {
+            SidecarPolicy::none()
+        });
+        self
+    }
+
+    pub fn with_sidecars(&mut self, sidecars: SidecarPolicy<'ingest>) -> &mut Self {
+        self.sidecars = Some(sidecars);
+        self
+    }
+
+    /// Send the files with `extension` to `folder` under the target, see [`Routes::add`]
+    pub fn with_route(&mut self, extension: &str, folder: impl AsRef<Path>) -> &mut Self {
+        self.routes
+            .get_or_insert_with(Routes::default)
+            .add(extension, folder);
+        self
+    }
+
+    /// Send the videos to `folder` under the target
+    pub fn with_route_video(&mut self, folder: impl AsRef<Path>) -> &mut Self {
+        self.routes.get_or_insert_with(Routes::default).video(folder);
+        self
+    }
+
+    /// Start a new `Session-NN` folder whenever there's more than `gap` between two shots
+    pub fn with_session_gap(&mut self, gap: std::time::Duration) -> &mut Self {
+        self.session_gap = Some(gap);
+        self
+    }
+
+    /// Flag the bursts in the report, and copy them into folders of their own if `detection` says
+    /// so
+    pub fn with_bursts(&mut self, detection: BurstDetection) -> &mut Self {
+        self.bursts = Some(detection);
+        self
+    }
+
+    /// Correct the capture times of the files of `source` for its camera's clock being `seconds`
+    /// ahead, behind when negative, eg. `192` for a second body running 3m12s fast
+    pub fn with_clock_offset<P: AsRef<Path> + ?Sized>(
+        &mut self,
+        source: &'ingest P,
+        seconds: i64,
+    ) -> &mut Self {
+        self.clock_offsets
+            .get_or_insert_with(HashMap::new)
+            .insert(source.as_ref(), seconds);
+        self
+    }
+
+    /// Read the capture times of the cameras that don't record their offset as `minutes` from
+    /// UTC, eg. `-300` for a shoot in New York, rather than UTC
+    pub fn with_timezone(&mut self, minutes: i16) -> &mut Self {
+        self.timezone = Some(minutes);
+        self
+    }
+
+    /// Copy and number the files of all the sources in `order`, by path otherwise
+    pub fn with_order(&mut self, order: OrderBy) -> &mut Self {
+        self.order = Some(order);
+        self
+    }
+
+    /// Copy the files `first` says so before the rest, see [`Priority`]
+    pub fn with_priority(
+        &mut self,
+        first: impl Fn(&Path) -> bool + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.priority.get_or_insert_with(Priority::default).predicate = Some(Arc::new(first));
+        self
+    }
+
+    /// Copy the files with these stems before the rest, eg. `DSC_0042` for `DSC_0042.NEF`
+    pub fn with_priority_stems<S: AsRef<str>>(
+        &mut self,
+        stems: impl IntoIterator<Item = S>,
+    ) -> &mut Self {
+        self.priority.get_or_insert_with(Priority::default).stems =
+            stems.into_iter().map(|stem| stem.as_ref().to_owned()).collect();
+        self
+    }
+
+    /// Copy the picks before the rest, eg. the frames rated on the camera
+    pub fn with_priority_picks(&mut self, picks: Picks) -> &mut Self {
+        self.priority.get_or_insert_with(Priority::default).picks = Some(picks);
+        self
+    }
+
+    /// Cap the number of files in each folder of the target, some DAMs and filesystems slow down
+    /// a lot past a few thousand
+    pub fn with_max_files_per_folder(&mut self, max: usize) -> &mut Self {
+        self.max_files_per_folder = Some(max);
+        self
+    }
+
+    /// What to do with files that end up with the same name when preserving the names
+    pub fn with_collisions(&mut self, collisions: CollisionPolicy) -> &mut Self {
+        self.collisions = Some(collisions);
+        self
+    }
+
+    /// Leave out the files whose contents are already in the target under any name, eg. a card
+    /// ingested again into an archive. The hashes of the target are cached at its root
+    pub fn skip_if_hash_exists_in_target(&mut self, skip: bool) -> &mut Self {
+        self.skip_if_hash_exists_in_target = Some(skip);
+        self
+    }
+
+    /// Make the names of the copies acceptable to Windows, on by default there and worth turning
+    /// on for the exFAT drives shared with it
+    pub fn with_windows_names(&mut self, windows_names: bool) -> &mut Self {
+        self.windows_names = Some(windows_names);
+        self
+    }
+
+    /// Tag the copies in the Finder and keep the ingest out of its way, on macOS only
+    pub fn with_finder(&mut self, finder: Finder) -> &mut Self {
+        self.finder = Some(finder);
+        self
+    }
+
+    /// How the files whose name is taken at the target are numbered
+    pub fn with_collision_suffix(&mut self, suffix: CollisionSuffix) -> &mut Self {
+        self.collision_suffix = Some(suffix);
         self
     }
 
@@ -95,11 +543,255 @@ impl<'ingest> IngestorBuilder<'ingest> {
         self
     }
 
+    /// What to copy when a RAW has an accompanying jpeg
+    pub fn with_pair_policy(&mut self, pair_policy: PairPolicy) -> &mut Self {
+        self.pair_policy = Some(pair_policy);
+        self
+    }
+
+    /// Copy sidecars whose file is missing as if they were regular files
+    pub fn copy_orphans(&mut self, copy_orphans: bool) -> &mut Self {
+        self.copy_orphans = Some(copy_orphans);
+        self
+    }
+
+    /// Stamp the creator and rights into the XMP sidecar of every ingested file
+    pub fn with_metadata(&mut self, metadata: MetadataPreset) -> &mut Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Create a minimal XMP sidecar with the capture date next to every RAW that has none, so
+    /// culling tools have a sidecar to write into
+    pub fn generate_missing_xmp(&mut self, generate: bool) -> &mut Self {
+        self.generate_missing_xmp = Some(generate);
+        self
+    }
+
+    /// Geotag the ingested photos from a GPX track, writing the position at their capture time
+    /// to their XMP sidecars. `offset` is the camera clock's offset from UTC in minutes, used
+    /// when the camera didn't record one
+    pub fn with_gpx<P: AsRef<Path> + ?Sized>(&mut self, gpx: &'ingest P, offset: i16) -> &mut Self {
+        self.gpx = Some((gpx.as_ref(), offset));
+        self
+    }
+
+    /// Run `hook` after every successful copy, with the source and the copy
+    pub fn with_post_copy_hook<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(PathBuf, PathBuf) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = std::result::Result<(), Error>> + Send + 'static,
+    {
+        self.copy_hooks
+            .get_or_insert_with(Vec::new)
+            .push(CopyHook::closure(hook));
+        self
+    }
+
+    /// Run a command after every successful copy, `{src}` and `{dst}` in `template` are replaced
+    /// by the source and the copy
+    pub fn with_post_copy_command(&mut self, template: impl Into<String>) -> &mut Self {
+        self.copy_hooks
+            .get_or_insert_with(Vec::new)
+            .push(CopyHook::command(template));
+        self
+    }
+
+    /// Run `hook` before the ingest checks or copies anything, eg. to mount a volume. A failure
+    /// stops the ingest
+    pub fn before_ingest<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = std::result::Result<(), Error>> + Send + 'static,
+    {
+        self.hooks.get_or_insert_with(LifecycleHooks::default).before_ingest =
+            Some(Arc::new(move || Box::pin(hook())));
+        self
+    }
+
+    /// Run `hook` with the report once every file is at the target, before the backup
+    pub fn after_ingest<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(Report) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = std::result::Result<(), Error>> + Send + 'static,
+    {
+        self.hooks.get_or_insert_with(LifecycleHooks::default).after_ingest =
+            Some(Arc::new(move |report| Box::pin(hook(report))));
+        self
+    }
+
+    /// Run `hook` with the report once every file is at the backup
+    pub fn after_backup<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(Report) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = std::result::Result<(), Error>> + Send + 'static,
+    {
+        self.hooks.get_or_insert_with(LifecycleHooks::default).after_backup =
+            Some(Arc::new(move |report| Box::pin(hook(report))));
+        self
+    }
+
+    /// Ask `resolver` what to do when a destination already exists or the space is short, eg.
+    /// to prompt the user
+    pub fn with_conflict_resolver(
+        &mut self,
+        resolver: impl ConflictResolver + 'static,
+    ) -> &mut Self {
+        self.conflicts = Some(Conflicts::new(resolver));
+        self
+    }
+
+    /// Also store every file copied to the target at `destination`, eg. an S3 bucket. A file
+    /// that couldn't be stored is a failure
+    pub fn with_destination(&mut self, destination: impl Destination + 'static) -> &mut Self {
+        self.destinations
+            .get_or_insert_with(Destinations::default)
+            .push(destination);
+        self
+    }
+
+    /// Also stream every file copied to the target into a tar, or a zip when `path` ends with
+    /// `.zip`, closed with a checksum manifest at the end of the ingest
+    #[cfg(feature = "archive")]
+    pub fn backup_to_archive(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.with_destination(archive::Archive::new(path))
+    }
+
+    /// Also store every file copied to the target encrypted with `key` under `path`, see
+    /// [`encrypt::decrypt`] to restore them
+    #[cfg(feature = "encrypt")]
+    pub fn backup_encrypted(
+        &mut self,
+        path: impl Into<PathBuf>,
+        key: encrypt::EncryptionKey,
+    ) -> &mut Self {
+        self.with_destination(encrypt::EncryptedFolder::new(path, key))
+    }
+
+    /// Send the files started, finished and failed and progress snapshots to `sink`
+    pub fn with_event_sink(&mut self, sink: impl EventSink + 'static) -> &mut Self {
+        self.events.get_or_insert_with(Events::default).push(sink);
+        self
+    }
+
+    /// Only ingest the files rated at least `min_rating` stars in their XMP sidecar, eg. when
+    /// the culling already happened in the field
+    pub fn with_min_rating(&mut self, min_rating: i8) -> &mut Self {
+        self.picks.get_or_insert_with(Picks::default).min_rating = Some(min_rating);
+        self
+    }
+
+    /// Extract the JPEG preview embedded in every ingested RAW to the `previews` folder of the
+    /// target
+    #[cfg(feature = "previews")]
+    pub fn with_previews(&mut self, size: preview::PreviewSize) -> &mut Self {
+        self.previews = Some(size);
+        self
+    }
+
+    /// Only ingest the files with one of these color labels in their XMP sidecar
+    pub fn with_labels<S: AsRef<str>>(&mut self, labels: impl IntoIterator<Item = S>) -> &mut Self {
+        self.picks.get_or_insert_with(Picks::default).labels =
+            labels.into_iter().map(|label| label.as_ref().to_owned()).collect();
+        self
+    }
+
+    pub fn with_timestamps(&mut self, timestamps: Timestamps) -> &mut Self {
+        self.timestamps = Some(timestamps);
+        self
+    }
+
+    /// Also copy permissions, ownership, extended attributes and alternate data streams
+    pub fn preserve_metadata(&mut self, preserve_metadata: bool) -> &mut Self {
+        self.preserve_metadata = Some(preserve_metadata);
+        self
+    }
+
+    /// Copy to a temporary file and rename it once complete, on by default
+    pub fn atomic(&mut self, atomic: bool) -> &mut Self {
+        self.atomic = Some(atomic);
+        self
+    }
+
+    /// Flush every copied file and its folder to disk before moving on, same as
+    /// [`Durability::PerFileAndDir`]
+    pub fn fsync(&mut self, fsync: bool) -> &mut Self {
+        self.durability = Some(match fsync {
+            true => Durability::PerFileAndDir,
+            false => Durability::None,
+        });
+        self
+    }
+
+    pub fn with_durability(&mut self, durability: Durability) -> &mut Self {
+        self.durability = Some(durability);
+        self
+    }
+
+    /// Read the sources around the page cache, so a large ingest doesn't evict the rest of it
+    pub fn no_cache(&mut self, no_cache: bool) -> &mut Self {
+        self.no_cache = Some(no_cache);
+        self
+    }
+
+    /// Reserve the space of every copy before writing it, so a full target fails it right away
+    pub fn preallocate(&mut self, preallocate: bool) -> &mut Self {
+        self.preallocate = Some(preallocate);
+        self
+    }
+
+    pub fn with_retry(&mut self, retry: Retry) -> &mut Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Clone the files instead of copying them when the source and target share a volume
+    pub fn reflink(&mut self, reflink: Reflink) -> &mut Self {
+        self.reflink = Some(reflink);
+        self
+    }
+
+    pub fn on_error(&mut self, on_error: ErrorPolicy) -> &mut Self {
+        self.on_error = Some(on_error);
+        self
+    }
+
+    pub fn with_quarantine(&mut self, quarantine: Quarantine) -> &mut Self {
+        self.quarantine = Some(quarantine);
+        self
+    }
+
+    /// Write an ASC MHL manifest of the copied files to the target (and backup) root
+    pub fn with_mhl(&mut self, algo: HashAlgo) -> &mut Self {
+        self.mhl = Some(algo);
+        self
+    }
+
+    /// Write a `checksums.<algo>` manifest of the copied files to the target (and backup) root
+    pub fn with_checksums(&mut self, algo: HashAlgo) -> &mut Self {
+        self.checksums = Some(algo);
+        self
+    }
+
+    /// Check the headers of the files for corruption while ingesting them
+    #[cfg(feature = "validate")]
+    pub fn validate(&mut self, validate: bool) -> &mut Self {
+        self.validate = Some(validate);
+        self
+    }
+
     pub fn backup<P: AsRef<Path>>(&mut self, backup: P) -> &mut Self {
         self.backup = Some(backup.as_ref().to_path_buf());
         self
     }
 
+    /// Write every file to the backup in the same read of the cards as to the target, and check
+    /// both copies against it
+    pub fn tee(&mut self, tee: bool) -> &mut Self {
+        self.tee = Some(tee);
+        self
+    }
+
     pub fn build(&self) -> Result<Ingestor<'ingest>> {
         let ingestor = self.to_owned();
         if let Self {
@@ -108,20 +800,94 @@ impl<'ingest> IngestorBuilder<'ingest> {
             sources: Some(sources),
             filter: Some(filter),
             backup,
+            sidecars,
             ..
         } = ingestor
         {
+            let vfs = ingestor.vfs.as_deref().unwrap_or(&vfs::RealFs);
+            let (sources, collapsed) = walk::collapse_sources(vfs, sources);
             Ok(Ingestor {
                 structure,
                 target,
                 sources,
+                labels: ingestor.labels.unwrap_or_default(),
+                source_folders: ingestor.source_folders.unwrap_or_default(),
                 filter,
                 backup,
-                copy_xmp: ingestor.copy_xmp.unwrap_or(true),
+                tee: ingestor.tee.unwrap_or_default(),
+                sidecars: sidecars.unwrap_or_default(),
+                routes: ingestor.routes.unwrap_or_default(),
+                session_gap: ingestor.session_gap,
+                bursts: ingestor.bursts,
+                order: ingestor.order.unwrap_or_default(),
+                priority: ingestor.priority.unwrap_or_default(),
+                clock_offsets: ingestor.clock_offsets.unwrap_or_default(),
+                timezone: ingestor.timezone,
+                max_files_per_folder: ingestor.max_files_per_folder.filter(|max| *max > 0),
+                collisions: ingestor.collisions.unwrap_or_default(),
+                collision_suffix: ingestor.collision_suffix.unwrap_or_default(),
+                skip_if_hash_exists_in_target: ingestor
+                    .skip_if_hash_exists_in_target
+                    .unwrap_or_default(),
+                windows_names: ingestor.windows_names.unwrap_or(cfg!(windows)),
+                finder: ingestor.finder.unwrap_or_default(),
                 copy_jpg: ingestor.copy_jpg.unwrap_or(true),
+                pair_policy: ingestor.pair_policy.unwrap_or_default(),
+                copy_orphans: ingestor.copy_orphans.unwrap_or_default(),
+                metadata: ingestor.metadata.unwrap_or_default(),
+                generate_missing_xmp: ingestor.generate_missing_xmp.unwrap_or_default(),
+                geotag: ingestor
+                    .gpx
+                    .map(|(gpx, offset)| -> Result<Geotag> {
+                        Ok(Geotag {
+                            track: Track::from_gpx(gpx)?,
+                            offset,
+                        })
+                    })
+                    .transpose()?,
+                picks: ingestor.picks.unwrap_or_default(),
+                copy_hooks: ingestor.copy_hooks.unwrap_or_default(),
+                hooks: ingestor.hooks.unwrap_or_default(),
+                events: ingestor.events.unwrap_or_default(),
+                conflicts: ingestor.conflicts.unwrap_or_default(),
+                destinations: ingestor.destinations.unwrap_or_default(),
+                #[cfg(feature = "previews")]
+                previews: ingestor.previews,
+                timestamps: ingestor.timestamps.unwrap_or_default(),
+                preserve_metadata: ingestor.preserve_metadata.unwrap_or_default(),
+                atomic: ingestor.atomic.unwrap_or(true),
+                durability: ingestor.durability.unwrap_or_default(),
+                no_cache: ingestor.no_cache.unwrap_or_default(),
+                preallocate: ingestor.preallocate.unwrap_or_default(),
+                retry: ingestor.retry.unwrap_or_default(),
+                reflink: ingestor.reflink.unwrap_or_default(),
+                on_error: ingestor.on_error.unwrap_or_default(),
+                quarantine: ingestor.quarantine.unwrap_or_default(),
+                mhl: ingestor.mhl,
+                checksums: ingestor.checksums,
+                #[cfg(feature = "validate")]
+                validate: ingestor.validate.unwrap_or_default(),
                 progress: ingestor.progress.unwrap_or_default(),
+                bytes: ingestor.bytes.unwrap_or_default(),
+                progress_sinks: ingestor.progress_sinks.unwrap_or_default(),
+                buffer_size: ingestor.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE).max(1),
+                network: ingestor.network,
+                reconnect_timeout: ingestor
+                    .reconnect_timeout
+                    .unwrap_or(network::DEFAULT_RECONNECT_TIMEOUT),
+                max_throughput: Arc::new(AtomicU64::new(ingestor.max_throughput.unwrap_or_default())),
+                reserved_space: ingestor.reserved_space.unwrap_or_default(),
                 cancel: ingestor.cancel.unwrap_or_default(),
                 depth: ingestor.depth.unwrap_or(usize::MAX),
+                min_depth: ingestor.min_depth.unwrap_or_default(),
+                depth_from: ingestor.depth_from.unwrap_or_default(),
+                included_subfolders: ingestor.included_subfolders.unwrap_or_default(),
+                excluded_subfolders: ingestor.excluded_subfolders.unwrap_or_default(),
+                concurrency: ingestor.concurrency.unwrap_or(1).max(1),
+                vfs: ingestor.vfs,
+                run_id: ingestor.run_id.unwrap_or_else(Uuid::new_v4),
+                job_name: ingestor.job_name,
+                __collapsed: collapsed,
                 ..Default::default()
             })
         } else {
@@ -142,14 +908,155 @@ pub struct Ingestor<'ingest> {
     pub structure: Structure<'ingest>,
     pub target: PathBuf,
     pub backup: Option<PathBuf>,
+    /// Copies to the backup in the same read as to the target instead of a second pass
+    pub tee: bool,
     pub sources: HashSet<&'ingest Path>,
+    /// Names the sources are told apart with, the folder name of the source when missing
+    pub labels: HashMap<&'ingest Path, &'ingest str>,
+    /// Copies each source into a subfolder named after its label
+    pub source_folders: bool,
     pub filter: Filter<'ingest>,
-    pub copy_xmp: bool,
+    pub sidecars: SidecarPolicy<'ingest>,
+    pub routes: Routes,
+    /// Splits the files into `Session-NN` folders wherever the capture times are further apart
+    pub session_gap: Option<std::time::Duration>,
+    /// Flags the bursts of frames in the report and puts them in folders of their own
+    pub bursts: Option<BurstDetection>,
+    /// The order the files of all the sources are copied in, and so numbered in
+    pub order: OrderBy,
+    /// The files copied before the rest
+    pub priority: Priority,
+    /// Seconds the camera clock of each source is ahead, taken off its capture times
+    pub clock_offsets: HashMap<&'ingest Path, i64>,
+    /// Offset from UTC in minutes of the camera clocks, for the EXIF capture times without one
+    pub timezone: Option<i16>,
+    /// Spills into `folder-2`, `folder-3`.. once a folder of the target holds that many files
+    pub max_files_per_folder: Option<usize>,
+    pub collisions: CollisionPolicy,
+    pub collision_suffix: CollisionSuffix,
+    /// Leaves out the files whose contents are already somewhere in the target
+    pub skip_if_hash_exists_in_target: bool,
+    /// Replaces the characters and device names Windows refuses in the names of the copies
+    pub windows_names: bool,
+    /// Finder tags and comments of the copies on macOS
+    pub finder: Finder,
+    /// Asked what to do when a destination is taken or the space is short, instead of numbering
+    /// the file or failing
+    pub conflicts: Conflicts,
+    /// Where the copies made to the target are stored as well
+    pub destinations: Destinations,
+    /// Copies the jpeg or HEIF of the RAWs along with them when renaming
     pub copy_jpg: bool,
+    pub pair_policy: PairPolicy,
+    pub copy_orphans: bool,
+    /// Written to the XMP sidecar of every ingested file
+    pub metadata: MetadataPreset,
+    /// Creates an XMP sidecar next to the RAWs that come without one
+    pub generate_missing_xmp: bool,
+    /// Writes the GPS position of the photos to their XMP sidecars
+    pub geotag: Option<Geotag>,
+    /// Leaves out the files that weren't picked, by their rating and color label
+    pub picks: Picks,
+    /// Run after every successful copy
+    pub copy_hooks: Vec<CopyHook>,
+    /// Run before the ingest, after it and after the backup
+    pub hooks: LifecycleHooks,
+    /// Told about every file copied and how far along the ingest is
+    pub events: Events,
+    /// Extracts the embedded preview of the RAWs to [`preview::PREVIEWS_FOLDER`]
+    #[cfg(feature = "previews")]
+    pub previews: Option<preview::PreviewSize>,
+    pub timestamps: Timestamps,
+    pub preserve_metadata: bool,
+    pub atomic: bool,
+    pub durability: Durability,
+    /// Reads the sources around the page cache
+    pub no_cache: bool,
+    /// Reserves the space of the copies before writing them
+    pub preallocate: bool,
+    pub retry: Retry,
+    pub reflink: Reflink,
+    pub on_error: ErrorPolicy,
+    pub quarantine: Quarantine,
+    pub mhl: Option<HashAlgo>,
+    pub checksums: Option<HashAlgo>,
+    #[cfg(feature = "validate")]
+    pub validate: bool,
     pub progress: Arc<AtomicUsize>,
+    pub bytes: Arc<AtomicU64>,
+    /// Told about the progress as it's made
+    pub progress_sinks: ProgressSinks,
+    pub buffer_size: usize,
+    /// Whether the target is a network share, detected when it's None, see [`network`]
+    pub network: Option<bool>,
+    /// How long to wait for a network share that dropped out
+    pub reconnect_timeout: std::time::Duration,
+    /// Bytes per second, 0 for unlimited
+    pub max_throughput: Arc<AtomicU64>,
+    /// Bytes left free at the target and the backup
+    pub reserved_space: u64,
+    /// How deep the files are taken from, 1 for the files at the root of a source whatever the
+    /// [`Structure`], counted from [`Ingestor::depth_from`]
     pub depth: usize,
+    /// How deep the files start being taken from, same as [`Ingestor::depth`]
+    pub min_depth: usize,
+    pub depth_from: DepthFrom,
+    /// The folders under each source the files are taken from, all of them when empty. The
+    /// folders above them are walked through for them only
+    pub included_subfolders: Vec<PathBuf>,
+    /// The folders under each source left out, even inside the included ones
+    pub excluded_subfolders: Vec<PathBuf>,
+    /// Sources copied at the same time, one per card reader. The sources are copied one at a time
+    /// anyway when the names of their files depend on each other, with [`Structure::Rename`],
+    /// sessions, bursts or any [`CollisionPolicy`] but numbering
+    pub concurrency: usize,
     pub cancel: Arc<AtomicBool>,
-    __jpegs: HashSet<PathBuf>,
+    /// What the ingest goes through instead of the disk, see [`vfs`]
+    pub vfs: Option<Arc<dyn Vfs>>,
+    /// Tells apart the offloads in the reports, MHL manifests and names of the copies made to
+    /// several destinations, a new one for every build unless given
+    pub run_id: Uuid,
+    /// Replaces [`JOB_TOKEN`] in the names of the copies
+    pub job_name: Option<String>,
+    /// Filled in while ingesting
+    pub report: Report,
+    __pairs: Pairs,
+    __sessions: Sessions,
+    __bursts: bursts::Bursts,
+    /// The day the earliest file of the pass was shot on, for [`DAY_TOKEN`]
+    __first_day: Option<u64>,
+    __folders: HashMap<PathBuf, usize>,
+    /// Where the files went relative to the target in the first pass, for the next ones
+    __names: HashMap<PathBuf, PathBuf>,
+    /// The target and the backup as the ingest started, the backup pass makes the backup the target
+    __outputs: Vec<PathBuf>,
+    /// The sources left out when building as they're inside another, along with that source
+    __collapsed: Vec<(PathBuf, PathBuf)>,
+    /// What the passes copy when executing a plan instead of walking the sources
+    __plan: Option<IngestPlan>,
+    /// The walk of the sources the ingest started with, reused by its passes
+    __scan: Option<walk::Scan<'ingest>>,
+    __collisions: HashSet<PathBuf>,
+    __taken: HashMap<PathBuf, HashSet<String>>,
+    __total: Arc<AtomicU64>,
+    __speed: Arc<std::sync::Mutex<handle::Speedometer>>,
+    __current: Arc<std::sync::Mutex<Option<PathBuf>>>,
+    __phase: Arc<std::sync::Mutex<Option<Phase>>>,
+    /// Files found while scanning
+    __scanned: Arc<AtomicUsize>,
+    __sources: Arc<std::sync::Mutex<Vec<SourceProgress>>>,
+    /// The index in the sources of the file being copied
+    __source: Option<usize>,
+    __hashed: Vec<HashedFile>,
+    /// Copies of the pass to flush at its end
+    __unsynced: Vec<PathBuf>,
+    /// The files teed to the backup, for its manifests
+    __mirrored: Vec<HashedFile>,
+    /// Where the files were teed to relative to the backup, for its names
+    __mirror_names: HashMap<PathBuf, PathBuf>,
+    /// The files of the target by contents, built on the first file of a pass that needs it
+    __index: Option<dedup::HashIndex>,
+    __network: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -158,24 +1065,64 @@ pub struct Filter<'filter> {
     pub min_size: u64,
     pub max_size: u64,
     pub ignore_hidden: bool,
+    /// What's copied of the AVCHD and XAVC structures of video cards
+    pub video_cards: VideoCardPolicy,
+    /// The files left out for their Windows attributes
+    pub attributes: Attributes,
+    /// Files ingested at most, going by path
+    pub max_files: Option<usize>,
+    /// Only every nth frame is ingested, the RAW and JPEG of a frame together
+    pub every_nth: Option<usize>,
+    /// Match the files without an extension, or with a numeric one like `.001`, by their first
+    /// bytes. Otherwise they're only ingested when every extension is
+    pub sniff_contents: bool,
 }
 
 impl<'filter> Filter<'filter> {
+    /// Only the first `max_files` files, eg. for a quick look at a huge card
+    pub fn max_files(mut self, max_files: usize) -> Self {
+        self.max_files = Some(max_files);
+        self
+    }
+
+    /// Only every `n`th frame, eg. every 10th for client previews before the full offload
+    pub fn every_nth(mut self, n: usize) -> Self {
+        self.every_nth = Some(n);
+        self
+    }
+
+    /// Tell what the files without an extension are by their contents, see
+    /// [`Filter::sniff_contents`]
+    pub fn sniff_contents(mut self, sniff: bool) -> Self {
+        self.sniff_contents = sniff;
+        self
+    }
+
     pub fn images() -> Self {
         let extensions = [RAW_EXTENSIONS.as_slice(), LOSSY_EXTENSIONS.as_slice()].concat();
         Filter {
             extensions: Cow::Owned(extensions),
             min_size: 0,
-            max_size: std::u64::MAX,
+            max_size: u64::MAX,
             ignore_hidden: true,
+            video_cards: VideoCardPolicy::Whole,
+            attributes: Attributes::default(),
+            max_files: None,
+            every_nth: None,
+            sniff_contents: false,
         }
     }
     pub fn raws() -> Self {
         Filter {
             extensions: Cow::Borrowed(RAW_EXTENSIONS.as_ref()),
             min_size: 0,
-            max_size: std::u64::MAX,
+            max_size: u64::MAX,
             ignore_hidden: true,
+            video_cards: VideoCardPolicy::Whole,
+            attributes: Attributes::default(),
+            max_files: None,
+            every_nth: None,
+            sniff_contents: false,
         }
     }
 
@@ -183,8 +1130,13 @@ impl<'filter> Filter<'filter> {
         Filter {
             extensions: Cow::Borrowed(LOSSY_EXTENSIONS.as_ref()),
             min_size: 0,
-            max_size: std::u64::MAX,
+            max_size: u64::MAX,
             ignore_hidden: true,
+            video_cards: VideoCardPolicy::Whole,
+            attributes: Attributes::default(),
+            max_files: None,
+            every_nth: None,
+            sniff_contents: false,
         }
     }
 }
@@ -194,8 +1146,13 @@ impl<'filter> Default for Filter<'filter> {
         Filter {
             extensions: Default::default(),
             min_size: 0,
-            max_size: std::u64::MAX,
+            max_size: u64::MAX,
             ignore_hidden: true,
+            video_cards: VideoCardPolicy::Whole,
+            attributes: Attributes::default(),
+            max_files: None,
+            every_nth: None,
+            sniff_contents: false,
         }
     }
 }
@@ -223,15 +1180,111 @@ impl<'st> Structure<'st> {
     }
 }
 
-#[derive(Debug, Clone, Default, Copy)]
+/// What to do when a single file fails to ingest
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ErrorPolicy {
+    /// Stop the ingest at the first failure
+    Abort,
+    /// Keep going and list the failure in the report
+    #[default]
+    ContinueAndReport,
+}
+
+/// How failed files are flagged at the target so they can be retried later
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Quarantine {
+    #[default]
+    None,
+    /// Leave an empty `name.ext.failed` where the file would have gone
+    Markers,
+    /// List the failed sources in [`FAILURE_MANIFEST`] at the target root
+    Manifest,
+}
+
+/// What [`Structure::Preserve`] does with distinct files that share a name, eg. `DSC_0001.NEF`
+/// from two cards
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CollisionPolicy {
+    /// Number them as they are copied, `DSC_0001-1.NEF`
+    #[default]
+    Number,
+    /// Suffix them with the label of their source, `DSC_0001_CardA.NEF`
+    SourceLabel,
+    /// Fail the ingest before copying anything
+    Error,
+    /// Keep them in their folder relative to the source like [`Structure::Retain`]
+    KeepSubfolder,
+}
+
+/// How a file is numbered when its name is taken at the target, `DSC_0001-1.NEF` by default
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct CollisionSuffix {
+    /// Between the name and the number
+    pub separator: String,
+    /// Digits the number is padded to with zeroes
+    pub padding: u8,
+    pub position: Position,
+}
+
+impl Default for CollisionSuffix {
+    fn default() -> Self {
+        Self {
+            separator: "-".to_owned(),
+            padding: 0,
+            position: Position::Suffix,
+        }
+    }
+}
+
+impl CollisionSuffix {
+    /// `path` numbered `count`, eg. `DSC_0001_002.NEF` with a `_` separator padded to 3 digits
+    pub fn number(&self, path: impl AsRef<Path>, count: usize) -> PathBuf {
+        let path = path.as_ref();
+        let (stem, extension) = match path.extension() {
+            Some(extension) => (path.file_stem(), Some(extension)),
+            None => (path.file_name(), None),
+        };
+        let stem = stem.unwrap_or_default().to_string_lossy();
+        let number = format!("{count:0width$}", width = usize::from(self.padding));
+        let mut name = match self.position {
+            Position::Prefix => format!("{number}{}{stem}", self.separator),
+            Position::Suffix => format!("{stem}{}{number}", self.separator),
+        };
+        if let Some(extension) = extension {
+            name.push('.');
+            name.push_str(&extension.to_string_lossy());
+        }
+        path.with_file_name(name)
+    }
+}
+
+#[derive(Debug, Clone, Default, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Position {
     /// Add the
     #[default]
     Prefix,
     Suffix,
 }
+/// Replaced by the label of the source in [`Rename::name`]
+pub const LABEL_TOKEN: &str = "{label}";
+/// Replaced by the first 8 hex digits of [`Ingestor::run_id`] in [`Rename::name`]
+pub const RUN_TOKEN: &str = "{run}";
+/// Replaced by the day of the job the file was shot on in [`Rename::name`], 1 for the day of the
+/// earliest file and counting the days without files, eg. `{daynum}` is 3 on the last day of a
+/// Friday to Sunday wedding
+pub const DAY_TOKEN: &str = "{daynum}";
+/// Replaced by [`Ingestor::job_name`] in [`Rename::name`]
+pub const JOB_TOKEN: &str = "{jobname}";
+
 #[derive(Debug, Clone, Default, Copy)]
 pub struct Rename<'ren> {
+    /// May contain [`LABEL_TOKEN`], [`RUN_TOKEN`], [`DAY_TOKEN`] and [`JOB_TOKEN`]
     pub name: Option<&'ren str>,
     pub position: Position,
     pub sequence: i32,
@@ -268,47 +1321,79 @@ impl<'ren> Rename<'ren> {
         }
         file_stem
     }
-}
 
-pub(crate) fn accompanying_jpeg(path: impl AsRef<Path>) -> Result<PathBuf> {
-    let path = path.as_ref();
-    let extension = path
-        .extension()
-        .map(OsStr::to_ascii_lowercase)
-        .and_then(|ext| ext.into_string().ok())
-        .ok_or_else(|| Error::custom_error("File extension not found"))?;
-
-    if matches!(extension.as_str(), "jpg" | "jpeg") {
-        Err(Error::custom_error(
-            "Jpeg file can't have accompanying jpeg",
-        ))
-    } else {
-        return ["jpg", "jpeg"]
-            .iter()
-            .find_map(|e| path.with_extension(e).canonicalize().ok())
-            .ok_or_else(|| Error::custom_error("No accompanying jpeg found"));
+    /// The names `paths` would be renamed to in this order, eg. to show them while the name is
+    /// typed. The files without an extension aren't renamed and the tokens, which depend on the
+    /// ingest, are left in
+    pub fn preview<P: AsRef<Path>>(
+        &self,
+        paths: impl IntoIterator<Item = P>,
+    ) -> Vec<(PathBuf, String)> {
+        let mut rename = *self;
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                let path = path.as_ref();
+                let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+                let name = rename.next(path).ok()?;
+                Some((path.to_path_buf(), format!("{}.{}", name, extension)))
+            })
+            .collect()
     }
 }
 
-pub(crate) fn exists_plus_one(path: impl AsRef<Path>) -> Result<PathBuf> {
-    let original_path = path.as_ref().to_owned();
+/// Returns `path`, numbered with `suffix` as long as it or any of its `companions` (the same stem
+/// with another extension) is among the `taken` names of its folder, in lowercase
+pub(crate) fn exists_plus_one_in(
+    taken: &HashSet<String>,
+    path: &Path,
+    companions: &[&str],
+    suffix: &CollisionSuffix,
+) -> PathBuf {
+    let is_taken = |path: &Path| {
+        std::iter::once(path.to_path_buf())
+            .chain(companions.iter().map(|ext| path.with_extension(ext)))
+            .filter_map(|path| Some(path.file_name()?.to_string_lossy().to_ascii_lowercase()))
+            .any(|name| taken.contains(&name))
+    };
     let mut count = 1;
-    let mut path = original_path.clone();
-    while path.exists() {
-        path = original_path.with_file_name(format!(
-            "{}-{count}.{}",
-            original_path
-                .file_stem()
-                .ok_or_else(|| Error::custom_error("File name not found"))?
-                .to_string_lossy(),
-            original_path
-                .extension()
-                .ok_or_else(|| Error::custom_error("Extension not found"))?
-                .to_string_lossy()
-        ));
+    let mut numbered = path.to_path_buf();
+    while is_taken(&numbered) {
+        numbered = suffix.number(path, count);
         count += 1;
     }
-    Ok(path)
+    numbered
+}
+
+/// Returns `name.ext.ingestpart` for `name.ext`
+pub(crate) fn part_path(path: impl AsRef<Path>) -> PathBuf {
+    append_extension(path, PART_EXTENSION)
+}
+
+/// Returns `name.ext.failed` for `name.ext`
+pub(crate) fn failed_path(path: impl AsRef<Path>) -> PathBuf {
+    append_extension(path, FAILED_EXTENSION)
+}
+
+fn append_extension(path: impl AsRef<Path>, extension: &str) -> PathBuf {
+    let mut path = path.as_ref().as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// Flushes the folder containing `path` so a rename or create in it is durable
+#[cfg(unix)]
+pub(crate) fn sync_dir(path: impl AsRef<Path>) -> std::io::Result<()> {
+    match path.as_ref().parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => std::fs::File::open(parent)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+/// Folders can't be opened for flushing on windows, NTFS journals the metadata anyway
+#[cfg(windows)]
+pub(crate) fn sync_dir(_path: impl AsRef<Path>) -> std::io::Result<()> {
+    Ok(())
 }
 
 #[cfg(unix)]
@@ -316,19 +1401,127 @@ pub(crate) fn same_disk<P1: AsRef<Path>, P2: AsRef<Path>>(p1: P1, p2: P2) -> std
     use std::os::unix::fs::MetadataExt;
     Ok(p1.as_ref().metadata()?.dev() == p2.as_ref().metadata()?.dev())
 }
+/// Compares the drives or shares, canonicalizing would turn a share into `\\?\UNC\` which
+/// never matches its mapped drive or plain form
 #[cfg(windows)]
 pub(crate) fn same_disk<P1: AsRef<Path>, P2: AsRef<Path>>(p1: P1, p2: P2) -> std::io::Result<bool> {
-    Ok(p1.as_ref().canonicalize()?.components().next()
-        == p2.as_ref().canonicalize()?.components().next())
+    Ok(std::path::absolute(p1)?.components().next()
+        == std::path::absolute(p2)?.components().next())
 }
 
+/// How much space an ingest takes and whether the target has it
+#[derive(Debug, Clone, Default)]
 pub struct Needs {
+    /// Size of the files to be copied
     pub total: u64,
+    /// Free space at the target
     pub free: u64,
+    /// Space the target needs, including the backup when it's on the same disk and the reserved
+    /// space
+    pub required: u64,
+    /// Bytes missing at the target, 0 when it fits
+    pub shortfall_bytes: u64,
+    /// Bytes each source contributes to the total
+    pub sources: Vec<(PathBuf, u64)>,
+    /// Whether the target, the backup and the destinations all fit
+    pub fits: bool,
     pub backup: Option<BackupNeeds>,
+    /// The [`Destination`]s that know how much they can take
+    pub destinations: Vec<DestinationNeeds>,
 }
 
+/// Same as [`Needs`] for the backup. On the same disk as the target the backup is already counted
+/// in the target's `required` and only `free` and `same_disk` are set
+#[derive(Debug, Clone, Default)]
 pub struct BackupNeeds {
     pub free: u64,
     pub same_disk: bool,
+    pub required: u64,
+    pub shortfall_bytes: u64,
+    pub fits: bool,
+}
+
+/// Same as [`Needs`] for a [`Destination`], which gets every file copied to the target
+#[derive(Debug, Clone, Default)]
+pub struct DestinationNeeds {
+    /// Its [`Destination::name`]
+    pub name: String,
+    pub capacity: Option<u64>,
+    pub free: u64,
+    pub required: u64,
+    pub shortfall_bytes: u64,
+    pub fits: bool,
+}
+
+impl Needs {
+    /// Whether everything fits given `size` more bytes of free space at each destination
+    pub fn fits_with(&self, size: u64) -> bool {
+        let backup_fits = self
+            .backup
+            .as_ref()
+            .map(|backup| backup.free.saturating_add(size) >= backup.required)
+            .unwrap_or(true);
+        let destinations_fit = self
+            .destinations
+            .iter()
+            .all(|destination| destination.free.saturating_add(size) >= destination.required);
+        self.free.saturating_add(size) >= self.required && backup_fits && destinations_fit
+    }
+
+    /// Eg. "Needs 2.1 GB more at the target (12.0 GB required, 9.9 GB free)"
+    pub fn summary(&self) -> String {
+        let mut lines = vec![describe("target", self.required, self.free)];
+        if let Some(ref backup) = self.backup {
+            if backup.same_disk {
+                lines.push("The backup shares the target's disk".to_string());
+            } else {
+                lines.push(describe("backup", backup.required, backup.free));
+            }
+        }
+        for destination in &self.destinations {
+            lines.push(describe(
+                &destination.name,
+                destination.required,
+                destination.free,
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+fn describe(destination: &str, required: u64, free: u64) -> String {
+    if free >= required {
+        format!(
+            "Fits at the {} ({} required, {} free)",
+            destination,
+            human_bytes(required),
+            human_bytes(free)
+        )
+    } else {
+        format!(
+            "Needs {} more at the {} ({} required, {} free)",
+            human_bytes(required - free),
+            destination,
+            human_bytes(required),
+            human_bytes(free)
+        )
+    }
+}
+
+/// Formats a size in decimal units the way file managers show them
+pub(crate) fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KB", "MB", "GB", "TB", "PB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1000.0 {
+            break;
+        }
+        size /= 1000.0;
+        unit = next;
+    }
+    format!("{:.1} {}", size, unit)
 }
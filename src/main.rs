@@ -1,23 +1,1056 @@
-use ingest::Rename;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use console::{style, StyledObject, Term};
+use indicatif::{DecimalBytes, HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
+#[cfg(feature = "encrypt")]
+use ingest::encrypt::EncryptionKey;
+use ingest::{
+    BurstDetection, Conflict, ConflictResolver, Decision, Durability, Filter, IngestHandle,
+    Ingestor, IngestorBuilder, OrderBy, Phase, Picks, Placeholders, Position, Rename, Resolution,
+    Structure, VerifyMode, VideoCardPolicy, Warning,
+};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often the progress bars are redrawn
+const TICK: Duration = Duration::from_millis(100);
+
+/// Copies photos and videos off camera cards
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Copy the sources to the target and the backup
+    Copy {
+        #[command(flatten)]
+        options: Options,
+        /// Number of sources copied at the same time, one per card reader
+        #[arg(long, default_value_t = 1)]
+        concurrency: usize,
+        /// Only show what would be copied
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show where every file goes and whether it all fits
+    Plan {
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Compare the target and the backup with the sources
+    Verify {
+        #[command(flatten)]
+        options: Options,
+    },
+    /// List the camera cards that are mounted
+    Devices,
+    /// Wait for camera cards and copy each one once it's mounted, the sources are ignored
+    Watch {
+        #[command(flatten)]
+        options: Options,
+        /// Seconds between two looks for new cards
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+    },
+    /// Watch for camera cards in the background and take commands on a local socket
+    #[cfg(feature = "daemon")]
+    Daemon {
+        #[command(flatten)]
+        options: Options,
+        /// Unix socket or named pipe to listen on
+        #[arg(long)]
+        socket: Option<PathBuf>,
+        /// Seconds between two looks for new cards
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+        /// Copy every card once it's mounted instead of waiting for a start command
+        #[arg(long)]
+        auto: bool,
+        /// Serve Prometheus metrics at this address, eg. 0.0.0.0:9474
+        #[cfg(feature = "prometheus")]
+        #[arg(long, value_name = "ADDRESS")]
+        metrics: Option<std::net::SocketAddr>,
+    },
+    /// Restore an encrypted backup, or a single file of it
+    #[cfg(feature = "encrypt")]
+    Decrypt {
+        /// Encrypted backup or file
+        from: PathBuf,
+        /// Folder the backup is restored to, or file the file is decrypted to
+        to: PathBuf,
+        /// File with the key the backup was encrypted with
+        #[arg(long, value_name = "KEYFILE")]
+        key: PathBuf,
+    },
+    /// Run ingests submitted over HTTP
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on, only reachable from this machine by default
+        #[arg(long, default_value = "127.0.0.1:7474")]
+        listen: String,
+        /// File holding the token the clients send as `Authorization: Bearer <token>`
+        #[arg(long, value_name = "FILE")]
+        token_file: Option<PathBuf>,
+        /// Name the server is reached at, eg. nas.local, besides localhost and its addresses
+        #[arg(long = "allowed-host", value_name = "HOST")]
+        allowed_hosts: Vec<String>,
+        /// Run the post copy commands of the jobs submitted, anyone who can submit a job can
+        /// then run commands on this machine
+        #[arg(long)]
+        allow_commands: bool,
+        /// Serve Prometheus metrics at this address, eg. 0.0.0.0:9474
+        #[cfg(feature = "prometheus")]
+        #[arg(long, value_name = "ADDRESS")]
+        metrics: Option<std::net::SocketAddr>,
+    },
+}
+
+#[derive(Args)]
+struct Options {
+    /// Folders to copy from
+    sources: Vec<PathBuf>,
+    #[arg(short, long)]
+    target: Option<PathBuf>,
+    #[arg(short, long)]
+    backup: Option<PathBuf>,
+    /// Write to the backup in the same read of the cards as to the target, checking both copies
+    #[arg(long, requires = "backup")]
+    tee: bool,
+    /// Encrypt the backup with the key in KEYFILE, a new key is written there when it's missing
+    #[cfg(feature = "encrypt")]
+    #[arg(long, value_name = "KEYFILE", requires = "backup")]
+    encrypt_backup: Option<PathBuf>,
+    #[arg(long, value_enum)]
+    structure: Option<StructureArg>,
+    /// Rename the files to NAME and a sequence number, implies --structure rename
+    #[arg(long, value_name = "NAME")]
+    rename: Option<String>,
+    /// Name of the job, replaces `{jobname}` in the names given by --rename
+    #[arg(long, value_name = "NAME")]
+    job_name: Option<String>,
+    /// First number of the sequence
+    #[arg(long, default_value_t = 1)]
+    sequence: i32,
+    /// Digits of the sequence number
+    #[arg(long, default_value_t = 5)]
+    zeroes: u8,
+    /// Put the sequence number before the name
+    #[arg(long)]
+    prefix: bool,
+    /// Finder tag set on every copy, `{label}` is replaced by the name of its card
+    #[cfg(target_os = "macos")]
+    #[arg(long = "finder-tag", value_name = "TAG")]
+    finder_tags: Vec<String>,
+    /// Finder comment set on every copy, `{label}` is replaced by the name of its card
+    #[cfg(target_os = "macos")]
+    #[arg(long, value_name = "TEXT")]
+    finder_comment: Option<String>,
+    /// Hide the files being copied from the Finder and Spotlight, and date the target folders to
+    /// the end of the ingest
+    #[cfg(target_os = "macos")]
+    #[arg(long)]
+    finder_friendly: bool,
+    /// Report the frames shot less than MS milliseconds apart by the same camera as bursts
+    #[arg(long, value_name = "MS")]
+    burst_gap: Option<u64>,
+    /// Copy each burst into a `Burst-NNN` folder of its own
+    #[arg(long)]
+    burst_folders: bool,
+    /// Skip the files already in the target under any name, eg. a card ingested twice
+    #[arg(long)]
+    skip_if_hash_exists: bool,
+    /// Give the copies names Windows accepts, the default on Windows, eg. for an exFAT drive
+    #[arg(long)]
+    windows_names: bool,
+    /// When the copies are flushed to the disk, so the cards can be formatted right away
+    #[arg(long, value_enum)]
+    durability: Option<DurabilityArg>,
+    /// Read the cards around the page cache, so the ingest doesn't evict everything else cached
+    #[arg(long)]
+    no_cache: bool,
+    /// Reserve the space of every copy before writing it, for less fragmented archive drives
+    #[arg(long)]
+    preallocate: bool,
+    /// Order the files of all the sources are copied and numbered in, `name` by default
+    #[arg(long, value_enum)]
+    order: Option<OrderArg>,
+    /// Copy the files with these stems before the rest, eg. `--first DSC_0042 DSC_0107`
+    #[arg(long, value_name = "STEM", num_args = 1..)]
+    first: Vec<String>,
+    /// Copy the files rated at least STARS in their XMP sidecar before the rest
+    #[arg(long, value_name = "STARS")]
+    first_rated: Option<i8>,
+    /// Seconds the camera clock of a source is ahead, negative when it's behind, eg.
+    /// `--clock-offset /Volumes/B=192` for a second body running 3m12s fast
+    #[arg(long = "clock-offset", value_name = "SOURCE=SECONDS", value_parser = clock_offset)]
+    clock_offsets: Vec<(PathBuf, i64)>,
+    /// Offset from UTC the camera clocks were set to when they don't record it, eg. `+02:00`
+    #[arg(long, value_name = "OFFSET", value_parser = timezone, allow_hyphen_values = true)]
+    timezone: Option<i16>,
+    #[arg(long, value_enum)]
+    filter: Option<FilterArg>,
+    /// Only copy these extensions, eg. `--ext nef --ext jpg`
+    #[arg(long = "ext", value_name = "EXT")]
+    extensions: Vec<String>,
+    /// Copy at most N files, eg. for a quick look at a huge card
+    #[arg(long, value_name = "N")]
+    max_files: Option<usize>,
+    /// Only copy every Nth frame, eg. `--every-nth 10` for client previews
+    #[arg(long, value_name = "N")]
+    every_nth: Option<usize>,
+    /// Tell the files without an extension, or with a numeric one, by their contents
+    #[arg(long)]
+    sniff: bool,
+    /// Only copy the files under FOLDER of each source, eg. `--subfolder DCIM/105NIKON`
+    #[arg(long = "subfolder", value_name = "FOLDER")]
+    subfolders: Vec<PathBuf>,
+    /// Leave out the files under FOLDER of each source
+    #[arg(long = "exclude-subfolder", value_name = "FOLDER")]
+    excluded_subfolders: Vec<PathBuf>,
+    /// Only copy the clips of the AVCHD and XAVC structures of video cards, with their XML
+    /// metadata for `metadata`
+    #[arg(long, value_enum, value_name = "WHAT", num_args = 0..=1, default_missing_value = "clips")]
+    essence: Option<EssenceArg>,
+    /// What to do with the files OneDrive, Dropbox or iCloud only keep in the cloud, they're
+    /// skipped with a warning by default
+    #[arg(long, value_enum, value_name = "WHAT")]
+    placeholders: Option<PlaceholdersArg>,
+    /// `size` or a hash algorithm, copies are only verified when it's set, verify defaults to
+    /// xxh128
+    #[arg(long)]
+    verify: Option<VerifyMode>,
+    /// TOML or JSON config the flags are applied over
+    #[cfg(feature = "config")]
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Write every file copied and the progress as lines of JSON to PATH, stdout when it's
+    /// missing or `-`. Hides the progress bar
+    #[cfg(feature = "json")]
+    #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = "-")]
+    emit_json: Option<PathBuf>,
+    /// Also upload every file copied to the target to an `s3://bucket/prefix` with the
+    /// credentials of the environment, or to an `sftp://user@host/folder` through ssh
+    #[cfg(any(feature = "s3", all(feature = "sftp", unix)))]
+    #[arg(long, value_name = "URL")]
+    upload: Option<String>,
+    /// Endpoint of an S3-compatible storage for --upload, eg. `http://nas.local:9000`
+    #[cfg(feature = "s3")]
+    #[arg(long, value_name = "URL", requires = "upload")]
+    s3_endpoint: Option<String>,
+    /// Also stream every file copied to the target into a tar, or a zip when PATH ends with
+    /// `.zip`, with a checksum manifest inside
+    #[cfg(feature = "archive")]
+    #[arg(long, value_name = "PATH")]
+    archive: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StructureArg {
+    Retain,
+    Preserve,
+    Rename,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FilterArg {
+    All,
+    Images,
+    Raws,
+    Jpegs,
+}
+
+fn clock_offset(arg: &str) -> Result<(PathBuf, i64), String> {
+    let (source, seconds) = arg
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected SOURCE=SECONDS, got `{arg}`"))?;
+    let seconds = seconds
+        .parse()
+        .map_err(|_| format!("`{seconds}` isn't a number of seconds"))?;
+    Ok((PathBuf::from(source), seconds))
+}
+
+/// `+HH:MM`, `-HH` or `Z` in minutes from UTC
+fn timezone(arg: &str) -> Result<i16, String> {
+    if arg.eq_ignore_ascii_case("z") || arg.eq_ignore_ascii_case("utc") {
+        return Ok(0);
+    }
+    let invalid = || format!("expected an offset like `+02:00` or `-05`, got `{arg}`");
+    let (sign, rest) = match arg.split_at_checked(1) {
+        Some(("+", rest)) => (1, rest),
+        Some(("-", rest)) => (-1, rest),
+        _ => return Err(invalid()),
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+    let (Ok(hours), Ok(minutes)) = (hours.parse::<i16>(), minutes.parse::<i16>()) else {
+        return Err(invalid());
+    };
+    if !(0..=14).contains(&hours) || !(0..60).contains(&minutes) {
+        return Err(invalid());
+    }
+    Ok(sign * (hours * 60 + minutes))
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OrderArg {
+    Name,
+    CaptureTime,
+    Modified,
+    SmallestFirst,
+    LargestFirst,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DurabilityArg {
+    None,
+    PerFile,
+    PerFileAndDir,
+    EndOfRun,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PlaceholdersArg {
+    Skip,
+    Hydrate,
+    Fail,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EssenceArg {
+    Clips,
+    Metadata,
+}
+
+#[cfg(feature = "config")]
+type Config = ingest::IngestConfig;
+#[cfg(not(feature = "config"))]
+type Config = ();
+
+impl Options {
+    #[cfg(feature = "config")]
+    fn config(&self) -> anyhow::Result<Option<Config>> {
+        Ok(self
+            .config
+            .as_deref()
+            .map(ingest::IngestConfig::from_file)
+            .transpose()?)
+    }
+
+    #[cfg(not(feature = "config"))]
+    fn config(&self) -> anyhow::Result<Option<Config>> {
+        Ok(None)
+    }
+
+    /// Sends the events of the ingests of `builder` where --emit-json asks for
+    #[cfg(feature = "json")]
+    fn emit_json(&self, builder: &mut IngestorBuilder) -> anyhow::Result<()> {
+        let sink = match self.emit_json.as_deref() {
+            None => return Ok(()),
+            Some(path) if path.as_os_str() == "-" => ingest::JsonEventSink::stdout(),
+            Some(path) => ingest::JsonEventSink::create(path)?,
+        };
+        builder.with_event_sink(sink);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "json"))]
+    fn emit_json(&self, _builder: &mut IngestorBuilder) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Adds the destination --upload asks for to `builder`
+    #[cfg(any(feature = "s3", all(feature = "sftp", unix)))]
+    async fn upload(&self, builder: &mut IngestorBuilder<'_>) -> anyhow::Result<()> {
+        let Some(ref url) = self.upload else {
+            return Ok(());
+        };
+        #[cfg(feature = "s3")]
+        if url.starts_with("s3://") {
+            let bucket = ingest::s3::S3Bucket::from_url(url, self.s3_endpoint.as_deref()).await?;
+            builder.with_destination(bucket);
+            return Ok(());
+        }
+        #[cfg(all(feature = "sftp", unix))]
+        if url.starts_with("sftp://") {
+            builder.with_destination(ingest::sftp::SftpServer::from_url(url)?);
+            return Ok(());
+        }
+        Err(anyhow::anyhow!("Can't upload to {}", url))
+    }
+
+    #[cfg(not(any(feature = "s3", all(feature = "sftp", unix))))]
+    async fn upload(&self, _builder: &mut IngestorBuilder<'_>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Adds the destinations the flags ask for to `builder`
+    async fn destinations(&self, builder: &mut IngestorBuilder<'_>) -> anyhow::Result<()> {
+        self.upload(builder).await?;
+        self.encrypt_backup(builder)?;
+        #[cfg(feature = "archive")]
+        if let Some(ref archive) = self.archive {
+            builder.backup_to_archive(archive);
+        }
+        Ok(())
+    }
+
+    /// Makes the backup of `builder` encrypted when --encrypt-backup asks for it
+    #[cfg(feature = "encrypt")]
+    fn encrypt_backup(&self, builder: &mut IngestorBuilder) -> anyhow::Result<()> {
+        let (Some(ref path), Some(backup)) = (&self.encrypt_backup, builder.backup.take()) else {
+            return Ok(());
+        };
+        let key = match path.exists() {
+            true => EncryptionKey::read(path)?,
+            false => {
+                let key = EncryptionKey::generate()?;
+                key.write(path)?;
+                eprintln!(
+                    "Wrote a new key to {}, the backup can't be decrypted without it",
+                    path.display()
+                );
+                key
+            }
+        };
+        builder.backup_encrypted(backup, key);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "encrypt"))]
+    fn encrypt_backup(&self, _builder: &mut IngestorBuilder) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Where the progress bars go, nowhere when the events are emitted instead
+    fn bars(&self) -> MultiProgress {
+        #[cfg(feature = "json")]
+        if self.emit_json.is_some() {
+            return MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        }
+        MultiProgress::new()
+    }
+
+    /// Whether the conflicts are asked about, only when someone is there to answer
+    fn prompts(&self) -> bool {
+        #[cfg(feature = "json")]
+        if self.emit_json.is_some() {
+            return false;
+        }
+        std::io::stdin().is_terminal() && console::user_attended_stderr()
+    }
+
+    /// A builder for `sources` set up by the config and then the flags
+    fn builder<'a>(
+        &'a self,
+        config: Option<&'a Config>,
+        sources: &'a [PathBuf],
+    ) -> IngestorBuilder<'a> {
+        let mut builder = configured(config);
+        if !sources.is_empty() {
+            builder.with_source(sources);
+        }
+        if let Some(ref target) = self.target {
+            builder.with_target(target);
+        }
+        if let Some(ref backup) = self.backup {
+            builder.backup(backup);
+        }
+        let rename = Rename {
+            name: self.rename.as_deref(),
+            position: match self.prefix {
+                true => Position::Prefix,
+                false => Position::Suffix,
+            },
+            sequence: self.sequence,
+            zeroes: self.zeroes,
+        };
+        match (self.structure, self.rename.is_some()) {
+            (Some(StructureArg::Rename), _) | (None, true) => {
+                builder.with_structure(Structure::Rename(rename));
+            }
+            (Some(StructureArg::Preserve), _) => {
+                builder.with_structure(Structure::Preserve);
+            }
+            (Some(StructureArg::Retain), _) => {
+                builder.with_structure(Structure::Retain);
+            }
+            (None, false) if builder.structure.is_none() => {
+                builder.with_structure(Structure::Retain);
+            }
+            (None, false) => {}
+        }
+        #[cfg(target_os = "macos")]
+        if !self.finder_tags.is_empty() || self.finder_comment.is_some() || self.finder_friendly {
+            builder.with_finder(ingest::Finder {
+                tags: self.finder_tags.clone(),
+                comment: self.finder_comment.clone(),
+                hide_partial: self.finder_friendly,
+                touch_folders: self.finder_friendly,
+            });
+        }
+        if self.burst_gap.is_some() || self.burst_folders {
+            let mut detection = BurstDetection {
+                folders: self.burst_folders,
+                ..Default::default()
+            };
+            if let Some(gap) = self.burst_gap {
+                detection.gap = Duration::from_millis(gap);
+            }
+            builder.with_bursts(detection);
+        }
+        if let Some(job_name) = &self.job_name {
+            builder.with_job_name(job_name);
+        }
+        if self.skip_if_hash_exists {
+            builder.skip_if_hash_exists_in_target(true);
+        }
+        if self.windows_names {
+            builder.with_windows_names(true);
+        }
+        if let Some(durability) = self.durability {
+            builder.with_durability(match durability {
+                DurabilityArg::None => Durability::None,
+                DurabilityArg::PerFile => Durability::PerFile,
+                DurabilityArg::PerFileAndDir => Durability::PerFileAndDir,
+                DurabilityArg::EndOfRun => Durability::EndOfRun,
+            });
+        }
+        if self.no_cache {
+            builder.no_cache(true);
+        }
+        if self.tee {
+            builder.tee(true);
+        }
+        if self.preallocate {
+            builder.preallocate(true);
+        }
+        if let Some(order) = self.order {
+            builder.with_order(match order {
+                OrderArg::Name => OrderBy::FileName,
+                OrderArg::CaptureTime => OrderBy::CaptureTime,
+                OrderArg::Modified => OrderBy::ModifiedTime,
+                OrderArg::SmallestFirst => OrderBy::SmallestFirst,
+                OrderArg::LargestFirst => OrderBy::LargestFirst,
+            });
+        }
+        if !self.first.is_empty() {
+            builder.with_priority_stems(&self.first);
+        }
+        if let Some(stars) = self.first_rated {
+            builder.with_priority_picks(Picks {
+                min_rating: Some(stars),
+                ..Default::default()
+            });
+        }
+        for (source, seconds) in &self.clock_offsets {
+            builder.with_clock_offset(source, *seconds);
+        }
+        if let Some(minutes) = self.timezone {
+            builder.with_timezone(minutes);
+        }
+        let filter = match self.filter {
+            Some(FilterArg::All) => Some(Filter::default()),
+            Some(FilterArg::Images) => Some(Filter::images()),
+            Some(FilterArg::Raws) => Some(Filter::raws()),
+            Some(FilterArg::Jpegs) => Some(Filter::jpegs()),
+            None if builder.filter.is_none() => Some(Filter::default()),
+            None => None,
+        };
+        if let Some(filter) = filter {
+            builder.with_filter(filter);
+        }
+        if !self.extensions.is_empty() {
+            let mut filter = builder.filter.clone().unwrap_or_default();
+            filter.extensions = Cow::Owned(self.extensions.iter().map(String::as_str).collect());
+            builder.with_filter(filter);
+        }
+        if let Some(max_files) = self.max_files {
+            let filter = builder.filter.clone().unwrap_or_default();
+            builder.with_filter(filter.max_files(max_files));
+        }
+        if let Some(n) = self.every_nth {
+            let filter = builder.filter.clone().unwrap_or_default();
+            builder.with_filter(filter.every_nth(n));
+        }
+        if self.sniff {
+            let filter = builder.filter.clone().unwrap_or_default();
+            builder.with_filter(filter.sniff_contents(true));
+        }
+        if !self.subfolders.is_empty() {
+            builder.with_included_subfolders(&self.subfolders);
+        }
+        if !self.excluded_subfolders.is_empty() {
+            builder.with_excluded_subfolders(&self.excluded_subfolders);
+        }
+        if let Some(essence) = self.essence {
+            builder.with_video_cards(match essence {
+                EssenceArg::Clips => VideoCardPolicy::Essence,
+                EssenceArg::Metadata => VideoCardPolicy::EssenceWithMetadata,
+            });
+        }
+        if let Some(placeholders) = self.placeholders {
+            builder.with_placeholders(match placeholders {
+                PlaceholdersArg::Skip => Placeholders::Skip,
+                PlaceholdersArg::Hydrate => Placeholders::Hydrate,
+                PlaceholdersArg::Fail => Placeholders::Fail,
+            });
+        }
+        builder
+    }
+}
+
+#[cfg(feature = "config")]
+fn configured(config: Option<&Config>) -> IngestorBuilder<'_> {
+    config.map(IngestorBuilder::from_config).unwrap_or_default()
+}
+
+#[cfg(not(feature = "config"))]
+fn configured(_config: Option<&Config>) -> IngestorBuilder<'_> {
+    IngestorBuilder::new()
+}
+
+/// Logs to stderr what `RUST_LOG` asks for, eg. `RUST_LOG=ingest=debug` for every copy, along
+/// with how long each span took
+#[cfg(feature = "tracing")]
+fn init_tracing() {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+#[cfg(all(feature = "prometheus", any(feature = "daemon", feature = "server")))]
+fn serve_metrics(address: Option<std::net::SocketAddr>) -> anyhow::Result<()> {
+    if let Some(address) = address {
+        ingest::telemetry::serve_prometheus(address)?;
+    }
+    Ok(())
+}
 
 #[tokio::main]
 pub async fn main() -> anyhow::Result<()> {
-    let mut args = std::env::args().skip(1).take(2);
-    let input = args.next().ok_or_else(|| anyhow::anyhow!("no input"))?;
-    let output = args.next().ok_or_else(|| anyhow::anyhow!("no output"))?;
-    let rename = Rename {
-        name: Some("image"),
-        position: ingest::Position::Suffix,
-        sequence: 1,
-        zeroes: 5,
+    #[cfg(feature = "tracing")]
+    init_tracing();
+    match Cli::parse().command {
+        Command::Copy {
+            options,
+            concurrency,
+            dry_run,
+        } => {
+            let config = options.config()?;
+            if dry_run {
+                return plan(&options.builder(config.as_ref(), &options.sources).build()?).await;
+            }
+            copy(&options, config.as_ref(), concurrency).await
+        }
+        Command::Plan { options } => {
+            let config = options.config()?;
+            let mut builder = options.builder(config.as_ref(), &options.sources);
+            options.destinations(&mut builder).await?;
+            plan(&builder.build()?).await
+        }
+        Command::Verify { options } => {
+            let config = options.config()?;
+            let mut builder = options.builder(config.as_ref(), &options.sources);
+            // An encrypted backup can't be compared with the sources
+            options.encrypt_backup(&mut builder)?;
+            let ingestor = builder.build()?;
+            verify(&ingestor, options.verify.unwrap_or_default())
+        }
+        Command::Devices => {
+            for card in ingest::devices::cards() {
+                println!("{}\t{}", card.name, card.mount_point.display());
+            }
+            Ok(())
+        }
+        Command::Watch { options, interval } => watch(&options, interval).await,
+        #[cfg(feature = "daemon")]
+        Command::Daemon {
+            options,
+            socket,
+            interval,
+            auto,
+            #[cfg(feature = "prometheus")]
+            metrics,
+        } => {
+            #[cfg(feature = "prometheus")]
+            serve_metrics(metrics)?;
+            let config = options.config()?;
+            let mut daemon = ingest::daemon::Daemon::new(CardIngest { options, config });
+            if let Some(socket) = socket {
+                daemon.with_socket(socket);
+            }
+            daemon
+                .with_interval(Duration::from_secs(interval))
+                .auto_ingest(auto);
+            Ok(daemon.run().await?)
+        }
+        #[cfg(feature = "encrypt")]
+        Command::Decrypt { from, to, key } => {
+            let key = EncryptionKey::read(key)?;
+            let restored = ingest::encrypt::decrypt(&key, from, &to)?;
+            println!("Restored {} files to {}", restored, to.display());
+            Ok(())
+        }
+        #[cfg(feature = "server")]
+        Command::Serve {
+            listen,
+            token_file,
+            allowed_hosts,
+            allow_commands,
+            #[cfg(feature = "prometheus")]
+            metrics,
+        } => {
+            #[cfg(feature = "prometheus")]
+            serve_metrics(metrics)?;
+            let token = match token_file {
+                Some(file) => Some(std::fs::read_to_string(file)?.trim().to_owned()),
+                None => None,
+            };
+            let access = ingest::server::Access {
+                token,
+                hosts: allowed_hosts,
+                allow_commands,
+            };
+            Ok(ingest::server::serve(listen, access).await?)
+        }
+    }
+}
+
+/// Copies the sources, `concurrency` of them at once
+async fn copy(
+    options: &Options,
+    config: Option<&Config>,
+    concurrency: usize,
+) -> anyhow::Result<()> {
+    let bars = options.bars();
+    let mut builder = options.builder(config, &options.sources);
+    options.emit_json(&mut builder)?;
+    options.destinations(&mut builder).await?;
+    if options.prompts() {
+        builder.with_conflict_resolver(Prompt { bars: bars.clone() });
+    }
+    builder.with_concurrency(concurrency);
+    let mut ingestor = builder.build()?;
+    ingest_with_progress(&mut ingestor, &bars).await?;
+    finish(&ingestor, options.verify)
+}
+
+/// The ingests of the daemon, set up by the flags
+#[cfg(feature = "daemon")]
+struct CardIngest {
+    options: Options,
+    config: Option<Config>,
+}
+
+#[cfg(feature = "daemon")]
+impl ingest::daemon::IngestFactory for CardIngest {
+    fn ingestor<'a>(
+        &'a self,
+        card: &'a ingest::devices::Card,
+    ) -> ingest::daemon::IngestorFuture<'a> {
+        Box::pin(async move {
+            let mut builder = self.options.builder(self.config.as_ref(), &[]);
+            // The card is the only source
+            builder.sources = None;
+            builder.with_labeled_source(&card.name, &card.mount_point);
+            let options = &self.options;
+            let configured = match options.emit_json(&mut builder) {
+                Ok(()) => options.destinations(&mut builder).await,
+                Err(e) => Err(e),
+            };
+            configured.map_err(ingest::Error::custom_error)?;
+            builder.build()
+        })
+    }
+}
+
+/// Asks on the terminal what to do about the conflicts, with the progress bars out of the way
+struct Prompt {
+    bars: MultiProgress,
+}
+
+impl ConflictResolver for Prompt {
+    fn resolve(&self, conflict: &Conflict) -> Decision {
+        let choices: &[(&str, Resolution)] = match conflict {
+            Conflict::Exists { .. } => &[
+                ("Overwrite", Resolution::Overwrite),
+                ("Skip", Resolution::Skip),
+                ("Rename", Resolution::Rename),
+            ],
+            Conflict::InsufficientSpace { .. } => &[("Copy anyway", Resolution::Overwrite)],
+        };
+        let mut decisions = Vec::new();
+        for &(name, resolution) in choices {
+            decisions.push((name.to_string(), resolution.into()));
+            if matches!(conflict, Conflict::Exists { .. }) {
+                decisions.push((
+                    format!("{} all", name),
+                    Decision {
+                        resolution,
+                        apply_to_all: true,
+                    },
+                ));
+            }
+        }
+        decisions.push(("Abort".to_string(), Resolution::Abort.into()));
+
+        let names: Vec<&str> = decisions.iter().map(|(name, _)| name.as_str()).collect();
+        let chosen = self.bars.suspend(|| {
+            dialoguer::Select::new()
+                .with_prompt(conflict.to_string())
+                .items(&names)
+                .default(0)
+                .interact_on_opt(&Term::stderr())
+        });
+        match chosen {
+            Ok(Some(index)) => decisions[index].1,
+            _ => Resolution::Abort.into(),
+        }
+    }
+}
+
+/// Ingests with a bar of the bytes copied and the current file, redrawn until it's done
+async fn ingest_with_progress(
+    ingestor: &mut Ingestor<'_>,
+    bars: &MultiProgress,
+) -> anyhow::Result<()> {
+    let bar = bars.add(ProgressBar::new(0));
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{bar:30.cyan/blue} {decimal_bytes}/{decimal_total_bytes} {decimal_bytes_per_sec} \
+             {eta} {wide_msg}",
+        )
+        .expect("Valid template")
+        .progress_chars("=> "),
+    );
+    let handle = ingestor.handle();
+    let cards = Arc::new(Mutex::new(Vec::new()));
+    let ticker = {
+        let (bar, bars, cards, handle) = (bar.clone(), bars.clone(), cards.clone(), handle.clone());
+        tokio::spawn(async move {
+            loop {
+                redraw(&bar, &handle);
+                redraw_sources(&bars, &cards, &handle);
+                tokio::time::sleep(TICK).await;
+            }
+        })
+    };
+    let started = Instant::now();
+    let ingested = ingestor.ingest().await;
+    ticker.abort();
+    redraw(&bar, &handle);
+    bar.finish_and_clear();
+    for card in cards.lock().unwrap_or_else(|e| e.into_inner()).drain(..) {
+        card.finish_and_clear();
+    }
+    summary(ingestor, &handle, started.elapsed());
+    Ok(ingested?)
+}
+
+fn redraw(bar: &ProgressBar, handle: &IngestHandle) {
+    if handle.phase() == Some(Phase::Scanning) {
+        bar.set_message(format!("Scanning… {} files found", handle.scanned()));
+        return;
+    }
+    // The sidecars copied along aren't part of the total
+    bar.set_length(handle.total_bytes().max(handle.bytes()));
+    bar.set_position(handle.bytes());
+    if let Some(name) = handle
+        .current_file()
+        .as_deref()
+        .and_then(|file| file.file_name())
+    {
+        bar.set_message(name.to_string_lossy().into_owned());
+    }
+}
+
+/// A bar per source under the main one when there are several, to tell which card is done
+fn redraw_sources(bars: &MultiProgress, cards: &Mutex<Vec<ProgressBar>>, handle: &IngestHandle) {
+    let sources = handle.sources();
+    let Ok(mut cards) = cards.lock() else {
+        return;
     };
-    let mut ingestor = ingest::IngestorBuilder::default()
-        .with_filter(ingest::Filter::default())
-        .with_source([&input])
-        .with_structure(ingest::Structure::Rename(rename))
-        .with_target(output)
-        .build()?;
-    // let mut ingest = builder.build()?;
-    ingestor.ingest().await?;
+    if sources.len() < 2 {
+        return;
+    }
+    while cards.len() < sources.len() {
+        let card = bars.add(ProgressBar::new(0));
+        card.set_style(
+            ProgressStyle::with_template(
+                "{bar:30.green/blue} {decimal_bytes}/{decimal_total_bytes} {wide_msg}",
+            )
+            .expect("Valid template")
+            .progress_chars("=> "),
+        );
+        cards.push(card);
+    }
+    for (card, source) in cards.iter().zip(&sources) {
+        card.set_length(source.total_bytes.max(source.bytes));
+        card.set_position(source.bytes);
+        card.set_message(match source.is_done() {
+            true => format!("{} done", source.label),
+            false => source.label.clone(),
+        });
+    }
+}
+
+/// Prints what was copied, skipped and failed
+fn summary(ingestor: &Ingestor, handle: &IngestHandle, took: Duration) {
+    let report = &ingestor.report;
+    let copied = handle.files().saturating_sub(report.failures.len());
+    let throughput = handle.bytes() as f64 / took.as_secs_f64().max(f64::EPSILON);
+    // Padded before styling, the escape codes would count in the width
+    let row = |name: StyledObject<String>, count: usize, detail: String| {
+        eprintln!("  {} {:>8}  {}", name, count, detail);
+    };
+    let label = |name: &str| style(format!("{:<10}", name));
+    eprintln!(
+        "{}  {}",
+        style(ingestor.target.display()).bold(),
+        style(report.run_id).dim()
+    );
+    row(
+        label("Copied").green(),
+        copied,
+        DecimalBytes(handle.bytes()).to_string(),
+    );
+    if !report.skipped.is_empty() {
+        row(
+            label("Skipped").yellow(),
+            report.skipped.len(),
+            String::new(),
+        );
+    }
+    if !report.bursts.is_empty() {
+        let frames: usize = report.bursts.iter().map(|burst| burst.frames).sum();
+        row(
+            label("Bursts"),
+            report.bursts.len(),
+            format!("{} frames", frames),
+        );
+    }
+    if report.has_failures() {
+        row(label("Failed").red(), report.failures.len(), String::new());
+    }
+    if report.has_warnings() {
+        row(
+            label("Warnings").yellow(),
+            report.warnings.len(),
+            String::new(),
+        );
+    }
+    if !report.retries.is_empty() {
+        row(label("Retried"), report.retries.len(), String::new());
+    }
+    eprintln!(
+        "  {:<10} {:>8}  {}/s",
+        "Took",
+        HumanDuration(took).to_string(),
+        DecimalBytes(throughput as u64)
+    );
+}
+
+/// Reports the warnings and failures of an ingest and verifies it if asked to
+fn finish(ingestor: &Ingestor, mode: Option<VerifyMode>) -> anyhow::Result<()> {
+    for warning in &ingestor.report.warnings {
+        eprintln!("{} {}", style("warning:").yellow(), warning);
+    }
+    for failure in &ingestor.report.failures {
+        eprintln!(
+            "{} {}: {}",
+            style("failed:").red(),
+            failure.path.display(),
+            failure.error
+        );
+    }
+    match mode {
+        Some(mode) => verify(ingestor, mode),
+        None => Ok(()),
+    }
+}
+
+async fn plan(ingestor: &Ingestor<'_>) -> anyhow::Result<()> {
+    for (source, output) in ingestor.nested_outputs() {
+        let source = source.to_path_buf();
+        let warning = Warning::NestedOutput { source, output };
+        eprintln!("{} {}", style("warning:").yellow(), warning);
+    }
+    for (source, target) in ingestor.mapping()? {
+        println!("{} -> {}", source.display(), target.display());
+    }
+    println!("{}", ingestor.needs().await?.summary());
     Ok(())
 }
+
+fn verify(ingestor: &Ingestor, mode: VerifyMode) -> anyhow::Result<()> {
+    let report = ingestor.verify(mode)?;
+    for (source, target) in &report.missing {
+        eprintln!("missing: {} -> {}", source.display(), target.display());
+    }
+    for (source, target) in &report.mismatched {
+        eprintln!("mismatched: {} -> {}", source.display(), target.display());
+    }
+    println!(
+        "{} verified, {} missing, {} mismatched",
+        report.matched.len(),
+        report.missing.len(),
+        report.mismatched.len()
+    );
+    match report.missing.is_empty() && report.mismatched.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow::anyhow!("The copy doesn't match the sources")),
+    }
+}
+
+/// Copies every card that gets mounted, labeled with its name
+async fn watch(options: &Options, interval: u64) -> anyhow::Result<()> {
+    let config = options.config()?;
+    let bars = options.bars();
+    let mut seen = HashSet::new();
+    loop {
+        let cards = ingest::devices::cards();
+        seen.retain(|card| cards.contains(card));
+        for card in cards {
+            if seen.contains(&card) {
+                continue;
+            }
+            eprintln!("Copying {} from {}", card.name, card.mount_point.display());
+            let source = ingest::devices::Source::from(&card);
+            let mut builder = options.builder(config.as_ref(), &[]);
+            // The card is the only source
+            builder.sources = None;
+            builder.with_card(&source);
+            if options.prompts() {
+                builder.with_conflict_resolver(Prompt { bars: bars.clone() });
+            }
+            let copied = match options.emit_json(&mut builder) {
+                Ok(()) => options.destinations(&mut builder).await,
+                Err(e) => Err(e),
+            };
+            let copied = match copied {
+                Ok(()) => builder.build().map_err(Into::into),
+                Err(e) => Err(e),
+            };
+            let copied = match copied {
+                Ok(mut ingestor) => match ingest_with_progress(&mut ingestor, &bars).await {
+                    Ok(()) => finish(&ingestor, options.verify),
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            if let Err(e) = copied {
+                eprintln!("{}: {}", card.name, e);
+            }
+            seen.insert(card);
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
@@ -0,0 +1,119 @@
+//! Camera metadata read from the EXIF of a file
+use crate::*;
+use exif::{In, Tag, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// When a photo was taken, as written by the camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+    /// Offset from UTC in minutes, most cameras don't record it
+    pub offset: Option<i16>,
+}
+
+impl CaptureTime {
+    /// Assumes the camera clock is in UTC when it didn't record its offset
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        let days = times::days_from_civil(i64::from(self.year), self.month.into(), self.day.into());
+        let secs = days * 86400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second)
+            - i64::from(self.offset.unwrap_or_default()) * 60;
+        let secs = u64::try_from(secs).ok()?;
+        Some(UNIX_EPOCH + Duration::new(secs, self.nanosecond))
+    }
+
+    /// Eg. `2024-06-01T14:30:05+02:00`, without an offset when the camera didn't record one
+    pub fn to_iso8601(&self) -> String {
+        let offset = match self.offset {
+            Some(offset) => format!(
+                "{}{:02}:{:02}",
+                if offset < 0 { '-' } else { '+' },
+                offset.unsigned_abs() / 60,
+                offset.unsigned_abs() % 60
+            ),
+            None => String::new(),
+        };
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, offset
+        )
+    }
+}
+
+/// What the camera recorded about a shot, every field is optional as cameras differ wildly
+#[derive(Debug, Clone, Default)]
+pub struct Meta {
+    pub captured: Option<CaptureTime>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub lens: Option<String>,
+    pub iso: Option<u32>,
+    /// The EXIF orientation, 1 to 8
+    pub orientation: Option<u16>,
+}
+
+/// Reads the EXIF of `path`, works with jpegs, HEIFs, PNGs and the TIFF based RAWs
+pub fn read(path: impl AsRef<Path>) -> Result<Meta> {
+    let file = std::fs::File::open(path)?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .map_err(Error::custom_error)?;
+
+    let text = |tag| {
+        let field = exif.get_field(tag, In::PRIMARY)?;
+        match field.value {
+            Value::Ascii(ref values) => values
+                .first()
+                .map(|value| String::from_utf8_lossy(value).trim().to_string())
+                .filter(|value| !value.is_empty()),
+            _ => None,
+        }
+    };
+    let number = |tag| exif.get_field(tag, In::PRIMARY)?.value.get_uint(0);
+
+    let captured = text(Tag::DateTimeOriginal)
+        .or_else(|| text(Tag::DateTime))
+        .and_then(|date| exif::DateTime::from_ascii(date.as_bytes()).ok())
+        .map(|mut date| {
+            if let Some(subsec) = text(Tag::SubSecTimeOriginal) {
+                date.parse_subsec(subsec.as_bytes()).ok();
+            }
+            if let Some(offset) = text(Tag::OffsetTimeOriginal) {
+                date.parse_offset(offset.as_bytes()).ok();
+            }
+            CaptureTime {
+                year: date.year,
+                month: date.month,
+                day: date.day,
+                hour: date.hour,
+                minute: date.minute,
+                second: date.second,
+                nanosecond: date.nanosecond.unwrap_or_default(),
+                offset: date.offset,
+            }
+        });
+
+    Ok(Meta {
+        captured,
+        make: text(Tag::Make),
+        model: text(Tag::Model),
+        serial: text(Tag::BodySerialNumber),
+        lens: text(Tag::LensModel),
+        iso: number(Tag::PhotographicSensitivity),
+        orientation: number(Tag::Orientation).and_then(|o| u16::try_from(o).ok()),
+    })
+}
+
+/// Returns when `path` was shot if its EXIF says so
+pub fn capture_time(path: impl AsRef<Path>) -> Option<SystemTime> {
+    read(path).ok()?.captured?.to_system_time()
+}
@@ -0,0 +1,163 @@
+//! ASC MHL (Media Hash List) v2 manifests.
+//!
+//! Every ingest into a target adds a new generation to the `ascmhl` folder at its root and
+//! references it from the chain file, so subsequent ingests into the same tree extend the history
+//! instead of replacing it.
+use crate::*;
+use sha2::Digest;
+use std::time::SystemTime;
+
+pub const MHL_FOLDER: &str = "ascmhl";
+pub const MHL_CHAIN: &str = "ascmhl_chain.xml";
+
+const BASE58: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Writes a new generation for `files` under `root` and appends it to the chain, noting the
+/// `run_id` of the ingest when given. Returns the path of the new manifest
+pub fn write_generation(
+    root: impl AsRef<Path>,
+    algo: HashAlgo,
+    files: &[HashedFile],
+    run_id: Option<Uuid>,
+) -> Result<PathBuf> {
+    write_generation_in(&vfs::RealFs, root, algo, files, run_id)
+}
+
+pub(crate) fn write_generation_in(
+    vfs: &dyn Vfs,
+    root: impl AsRef<Path>,
+    algo: HashAlgo,
+    files: &[HashedFile],
+    run_id: Option<Uuid>,
+) -> Result<PathBuf> {
+    let root = root.as_ref();
+    let folder = root.join(MHL_FOLDER);
+    vfs.create_dir_all(&folder)?;
+
+    let chain_path = folder.join(MHL_CHAIN);
+    let chain = vfs::read_to_string(vfs, &chain_path).unwrap_or_default();
+    let sequence = chain
+        .match_indices("sequencenr=\"")
+        .filter_map(|(i, m)| {
+            let rest = &chain[i + m.len()..];
+            rest[..rest.find('"')?].parse::<u32>().ok()
+        })
+        .max()
+        .unwrap_or_default()
+        + 1;
+
+    let now = SystemTime::now();
+    let (date, (h, m, s)) = times::utc(now);
+    let root_name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "root".into());
+    let name = format!("{:04}_{}_{}_{:02}{:02}{:02}Z.mhl", sequence, root_name, date, h, m, s);
+
+    let manifest = manifest(root, algo, files, run_id, now);
+    vfs::write(vfs, &folder.join(&name), manifest.as_bytes())?;
+
+    let entry = format!(
+        "  <hashlist sequencenr=\"{}\">\n    <path>{}</path>\n    <c4>{}</c4>\n  </hashlist>\n",
+        sequence,
+        escape(&name),
+        c4(manifest.as_bytes())
+    );
+    let chain = match chain.rfind("</ascmhldirectory>") {
+        Some(end) => format!("{}{}{}", &chain[..end], entry, &chain[end..]),
+        None => format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ascmhldirectory xmlns=\"urn:ASC:MHL:DIRECTORY:v2.0\">\n{}</ascmhldirectory>\n",
+            entry
+        ),
+    };
+    vfs::write(vfs, &chain_path, chain.as_bytes())?;
+    Ok(folder.join(name))
+}
+
+fn manifest(
+    root: &Path,
+    algo: HashAlgo,
+    files: &[HashedFile],
+    run_id: Option<Uuid>,
+    now: SystemTime,
+) -> String {
+    let hostname = std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .unwrap_or_else(|_| "localhost".into());
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<hashlist version="2.0" xmlns="urn:ASC:MHL:v2.0">
+  <creatorinfo>
+    <creationdate>{}</creationdate>
+    <hostname>{}</hostname>
+    <tool version="{}">{}</tool>{}
+  </creatorinfo>
+  <processinfo>
+    <process>transfer</process>
+    <ignore>
+      <pattern>.DS_Store</pattern>
+      <pattern>{}</pattern>
+    </ignore>
+  </processinfo>
+  <hashes>
+"#,
+        times::iso8601(now),
+        escape(&hostname),
+        env!("CARGO_PKG_VERSION"),
+        env!("CARGO_PKG_NAME"),
+        run_id
+            .map(|id| format!("\n    <comment>run {}</comment>", id))
+            .unwrap_or_default(),
+        MHL_FOLDER,
+    );
+    for file in hash::latest_by_path(files) {
+        let path = file.path.strip_prefix(root).unwrap_or(&file.path);
+        let path = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let modified = file
+            .modified
+            .map(|m| format!(" lastmodificationdate=\"{}\"", times::iso8601(m)))
+            .unwrap_or_default();
+        xml.push_str(&format!(
+            "    <hash>\n      <path size=\"{}\"{}>{}</path>\n      <{algo} action=\"original\" hashdate=\"{}\">{}</{algo}>\n    </hash>\n",
+            file.size,
+            modified,
+            escape(&path),
+            times::iso8601(file.hashed_at),
+            file.hash(algo).unwrap_or_default(),
+            algo = algo.name(),
+        ));
+    }
+    xml.push_str("  </hashes>\n</hashlist>\n");
+    xml
+}
+
+/// The C4 ID of `bytes`, the base58 encoded sha512 used to reference manifests from the chain
+pub(crate) fn c4(bytes: &[u8]) -> String {
+    let mut digits = sha2::Sha512::digest(bytes).to_vec();
+    let mut encoded = Vec::new();
+    while digits.iter().any(|&d| d != 0) {
+        let mut rem = 0u32;
+        for digit in digits.iter_mut() {
+            let acc = (rem << 8) | u32::from(*digit);
+            *digit = (acc / 58) as u8;
+            rem = acc % 58;
+        }
+        encoded.push(BASE58[rem as usize]);
+    }
+    // C4 IDs are always 90 characters, padded with the zero digit
+    encoded.resize(88, b'1');
+    encoded.reverse();
+    format!("c4{}", String::from_utf8_lossy(&encoded))
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,197 @@
+//! Bindings for Kotlin and Swift apps made with uniffi, built with the `uniffi` feature. The
+//! bindings are generated from the built library with
+//! `uniffi-bindgen generate --library libingest.so --language kotlin`.
+use crate::*;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+/// What to ingest and where to
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct IngestOptions {
+    pub sources: Vec<String>,
+    pub target: String,
+    pub backup: Option<String>,
+    /// Renames the files to this name and a sequence number, keeps the folders when missing
+    pub rename: Option<String>,
+    /// Only ingests these extensions, all of them when empty
+    pub extensions: Vec<String>,
+}
+
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct PlannedCopy {
+    pub source: String,
+    pub destination: String,
+}
+
+#[derive(Debug, Clone, Default, uniffi::Record)]
+pub struct IngestProgress {
+    pub files: u64,
+    pub bytes: u64,
+    pub total_bytes: u64,
+    /// Bytes per second over the last few seconds
+    pub speed: f64,
+}
+
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct IngestSummary {
+    pub files: u64,
+    pub warnings: Vec<String>,
+    /// The files that couldn't be ingested and why
+    pub failures: Vec<String>,
+    pub skipped: u64,
+}
+
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum IngestError {
+    #[error("{message}")]
+    Failed { message: String },
+}
+
+impl From<Error> for IngestError {
+    fn from(error: Error) -> Self {
+        IngestError::Failed {
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Implemented by the app to follow an ingest, called from the thread doing the copies
+#[uniffi::export(with_foreign)]
+pub trait IngestListener: Send + Sync {
+    fn on_progress(&self, progress: IngestProgress);
+    fn on_file_copied(&self, source: String, destination: String);
+    fn on_file_failed(&self, source: String, error: String);
+}
+
+/// An ingestor set up by its options anew for every call
+#[derive(uniffi::Object)]
+pub struct MobileIngestor {
+    config: IngestConfig,
+    cancel: Arc<AtomicBool>,
+    handle: Mutex<Option<IngestHandle>>,
+}
+
+impl MobileIngestor {
+    fn ingestor(&self) -> Result<Ingestor<'_>> {
+        let mut ingestor = IngestorBuilder::from_config(&self.config).build()?;
+        ingestor.cancel = self.cancel.clone();
+        Ok(ingestor)
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl MobileIngestor {
+    #[uniffi::constructor]
+    pub fn new(options: IngestOptions) -> Arc<Self> {
+        let structure = match options.rename {
+            Some(name) => StructureConfig::Rename(RenameConfig {
+                name: Some(name),
+                position: Position::Suffix,
+                sequence: 1,
+                zeroes: 5,
+            }),
+            None => StructureConfig::Retain,
+        };
+        Arc::new(MobileIngestor {
+            config: IngestConfig {
+                sources: options.sources.into_iter().map(PathBuf::from).collect(),
+                target: Some(options.target.into()),
+                backup: options.backup.map(PathBuf::from),
+                structure,
+                filter: FilterConfig {
+                    extensions: options.extensions,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            cancel: Arc::default(),
+            handle: Mutex::new(None),
+        })
+    }
+
+    /// Where every file would be copied
+    pub fn plan(&self) -> std::result::Result<Vec<PlannedCopy>, IngestError> {
+        Ok(self
+            .ingestor()?
+            .mapping()?
+            .into_iter()
+            .map(|(source, destination)| PlannedCopy {
+                source: source.display().to_string(),
+                destination: destination.display().to_string(),
+            })
+            .collect())
+    }
+
+    /// Copies everything, telling `listener` about the files and the progress
+    pub async fn ingest(
+        &self,
+        listener: Option<Arc<dyn IngestListener>>,
+    ) -> std::result::Result<IngestSummary, IngestError> {
+        let mut ingestor = self.ingestor()?;
+        if let Some(listener) = listener {
+            ingestor.events.push(move |event: &Event| match event {
+                Event::Finished {
+                    path, destination, ..
+                } => listener.on_file_copied(
+                    path.display().to_string(),
+                    destination.display().to_string(),
+                ),
+                Event::Failed { path, error, .. } => {
+                    listener.on_file_failed(path.display().to_string(), error.clone())
+                }
+                Event::Progress {
+                    files,
+                    bytes,
+                    total_bytes,
+                    speed,
+                    ..
+                } => listener.on_progress(IngestProgress {
+                    files: *files as u64,
+                    bytes: *bytes,
+                    total_bytes: *total_bytes,
+                    speed: *speed,
+                }),
+                Event::Started { .. } | Event::Phase { .. } | Event::Scanning { .. } => {}
+            });
+        }
+        let handle = ingestor.handle();
+        *self.handle.lock().unwrap_or_else(|e| e.into_inner()) = Some(handle.clone());
+        // A cancel only stops the run it was meant for
+        self.cancel.store(false, Ordering::SeqCst);
+        ingestor.ingest().await?;
+        let report = ingestor.report;
+        Ok(IngestSummary {
+            files: handle.files() as u64,
+            warnings: report.warnings.iter().map(Warning::to_string).collect(),
+            failures: report
+                .failures
+                .iter()
+                .map(|failure| format!("{}: {}", failure.path.display(), failure.error))
+                .collect(),
+            skipped: report.skipped.len() as u64,
+        })
+    }
+
+    /// Stops the running ingest
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+
+    /// How far along the running or last ingest is
+    pub fn progress(&self) -> IngestProgress {
+        match self
+            .handle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+        {
+            Some(handle) => IngestProgress {
+                files: handle.files() as u64,
+                bytes: handle.bytes(),
+                total_bytes: handle.total_bytes(),
+                speed: handle.speed(),
+            },
+            None => IngestProgress::default(),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! The names the source files were written under at a target. The structure only gives the name
+//! a file should get, which gets a collision suffix when it's taken, so every ingest appends
+//! where it put each file to [`NAMES_FILE`] at the root of the target. [`Ingestor::verify`] and
+//! [`Ingestor::ingest_missing`] then find the copies again whatever they were numbered.
+//!
+//! A source is only matched with its name while it has the size and the modification time it
+//! had when it was copied, so another card mounted at the same path isn't taken for it
+use crate::*;
+use std::io::{BufRead, Write};
+
+/// The names written by the ingests into the target, at its root
+pub(crate) const NAMES_FILE: &str = ".ingest-names";
+
+/// A source file as it was when copied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    size: u64,
+    modified: u128,
+}
+
+impl Stamp {
+    fn of(vfs: &dyn Vfs, path: &Path) -> Option<Self> {
+        let metadata = vfs.metadata(path).ok()?;
+        Some(Stamp {
+            size: metadata.len,
+            modified: dedup::nanos(metadata.modified),
+        })
+    }
+}
+
+/// Where the sources were written under a target, the latest copy of each
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Names {
+    root: PathBuf,
+    names: HashMap<PathBuf, (PathBuf, Stamp)>,
+}
+
+impl Names {
+    /// Reads the names recorded at `root`, none when nothing was
+    pub fn load(vfs: &dyn Vfs, root: &Path) -> Self {
+        let mut names = Names {
+            root: root.to_path_buf(),
+            ..Default::default()
+        };
+        let Ok(file) = vfs.open(&root.join(NAMES_FILE)) else {
+            return names;
+        };
+        for line in std::io::BufReader::new(file)
+            .lines()
+            .map_while(|line| line.ok())
+        {
+            let mut fields = line.splitn(4, '\t');
+            let (Some(name), Some(size), Some(modified), Some(source)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if let (Ok(size), Ok(modified)) = (size.parse(), modified.parse()) {
+                let stamp = Stamp { size, modified };
+                names
+                    .names
+                    .insert(PathBuf::from(source), (PathBuf::from(name), stamp));
+            }
+        }
+        names
+    }
+
+    /// Where `source` was written under the root, as long as it's still the file that was copied
+    pub fn of(&self, vfs: &dyn Vfs, source: &Path) -> Option<PathBuf> {
+        let (name, stamp) = self.names.get(source)?;
+        (Stamp::of(vfs, source)? == *stamp).then(|| self.root.join(name))
+    }
+}
+
+/// Appends the `names` under `root` of the sources that were written there, by source
+pub(crate) fn append<'a>(
+    vfs: &dyn Vfs,
+    root: &Path,
+    names: impl IntoIterator<Item = (&'a Path, &'a Path)>,
+) -> Result<()> {
+    let mut lines = String::new();
+    for (source, name) in names {
+        let (Some(stamp), Some(name), Some(source)) =
+            (Stamp::of(vfs, source), name.to_str(), source.to_str())
+        else {
+            continue;
+        };
+        lines.push_str(&format!(
+            "{}\t{}\t{}\t{}\n",
+            name, stamp.size, stamp.modified, source
+        ));
+    }
+    if lines.is_empty() {
+        return Ok(());
+    }
+    let mut file = vfs.append(&root.join(NAMES_FILE))?;
+    file.write_all(lines.as_bytes())?;
+    Ok(file.flush()?)
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Records the names the files of the pass got at the target, leaving out the ones that
+    /// failed after `failed` or were skipped
+    pub(crate) fn save_names(&self, failed: usize) -> Result<()> {
+        let left_out: HashSet<&Path> = self.report.failures[failed..]
+            .iter()
+            .map(|failure| failure.path.as_path())
+            .chain(self.report.skipped.iter().map(PathBuf::as_path))
+            .collect();
+        let mut names: Vec<(&Path, &Path)> = self
+            .__names
+            .iter()
+            .map(|(source, name)| (source.as_path(), name.as_path()))
+            .filter(|(source, name)| {
+                !left_out.contains(source) && self.vfs().is_file(&self.target.join(name))
+            })
+            .collect();
+        names.sort();
+        append(self.vfs(), &self.target, names)
+    }
+}
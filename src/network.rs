@@ -0,0 +1,142 @@
+//! Targets on network shares (SMB, NFS, AFP), which answer slower and drop out now and then.
+//! Their copies go in larger chunks, the errors a flaky connection causes are retried and the
+//! ingest waits a while for a share that dropped out to come back instead of failing.
+use crate::*;
+use std::time::Duration;
+
+/// Size of the chunks copied to network shares, fewer round trips
+pub const NETWORK_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+/// How long the ingest waits for a network share that dropped out by default
+pub const DEFAULT_RECONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Retries of the transient errors on network shares when [`Retry`] allows fewer
+pub(crate) const NETWORK_RETRIES: u32 = 3;
+/// How often a share that dropped out is checked
+pub(crate) const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether `path`, or the closest folder above it that exists, is on a network share
+pub fn is_network(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    let existing = path.ancestors().find(|dir| dir.exists()).unwrap_or(path);
+    remote(existing)
+}
+
+/// Whether `error` is what a share that dropped out or timed out causes, worth retrying
+pub(crate) fn is_transient(error: &Error) -> bool {
+    use std::io::ErrorKind::*;
+    let errors::ErrorKind::IOError(ref error) = error.kind else {
+        return false;
+    };
+    if matches!(
+        error.kind(),
+        TimedOut
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | BrokenPipe
+            | HostUnreachable
+            | NetworkUnreachable
+            | NetworkDown
+            | StaleNetworkFileHandle
+    ) {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        matches!(
+            error.raw_os_error(),
+            Some(libc::EIO | libc::EHOSTDOWN | libc::ETIMEDOUT)
+        )
+    }
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::Foundation::{
+            ERROR_BAD_NETPATH, ERROR_NETNAME_DELETED, ERROR_SEM_TIMEOUT, ERROR_UNEXP_NET_ERR,
+        };
+        // STATUS_IO_TIMEOUT surfaces as ERROR_SEM_TIMEOUT
+        error.raw_os_error().is_some_and(|code| {
+            matches!(
+                code as u32,
+                ERROR_SEM_TIMEOUT | ERROR_NETNAME_DELETED | ERROR_UNEXP_NET_ERR | ERROR_BAD_NETPATH
+            )
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn remote(path: &Path) -> bool {
+    const SMB: u32 = 0x517B;
+    const CIFS: u32 = 0xFF53_4D42;
+    const SMB2: u32 = 0xFE53_4D42;
+    const NFS: u32 = 0x6969;
+    const AFS: u32 = 0x5346_414F;
+    const CEPH: u32 = 0x00C3_6400;
+    match statfs(path) {
+        // The type is signed on some architectures
+        Some(stat) => matches!(stat.f_type as u32, SMB | CIFS | SMB2 | NFS | AFS | CEPH),
+        None => false,
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+fn remote(path: &Path) -> bool {
+    match statfs(path) {
+        Some(stat) => stat.f_flags as u64 & libc::MNT_LOCAL as u64 == 0,
+        None => false,
+    }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+))]
+fn statfs(path: &Path) -> Option<libc::statfs> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statfs is plain data, filled in by the call
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: the path is nul terminated and outlives the call
+    match unsafe { libc::statfs(path.as_ptr(), &mut stat) } {
+        0 => Some(stat),
+        _ => None,
+    }
+}
+
+#[cfg(windows)]
+fn remote(path: &Path) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::{Component, Prefix};
+    use windows_sys::Win32::Storage::FileSystem::GetDriveTypeW;
+    use windows_sys::Win32::System::WindowsProgramming::DRIVE_REMOTE;
+
+    let Some(Component::Prefix(prefix)) = path.components().next() else {
+        return false;
+    };
+    match prefix.kind() {
+        Prefix::UNC(..) | Prefix::VerbatimUNC(..) => true,
+        Prefix::Disk(_) | Prefix::VerbatimDisk(_) => {
+            // A mapped drive
+            let root: Vec<u16> = Path::new(prefix.as_os_str())
+                .join("\\")
+                .as_os_str()
+                .encode_wide()
+                .chain(Some(0))
+                .collect();
+            // SAFETY: the root is nul terminated and outlives the call
+            unsafe { GetDriveTypeW(root.as_ptr()) == DRIVE_REMOTE }
+        }
+        _ => false,
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    windows
+)))]
+fn remote(_path: &Path) -> bool {
+    false
+}
@@ -0,0 +1,109 @@
+//! Reads of the sources that don't fill the page cache, so ingesting a whole card doesn't evict
+//! everything else the machine had cached.
+//!
+//! - Linux drops the pages of every chunk from the cache once read, with `POSIX_FADV_DONTNEED`.
+//!   `O_DIRECT` isn't used as the FUSE mounts of exFAT cards refuse it
+//! - macOS reads with `F_NOCACHE`
+//! - Windows reads with `FILE_FLAG_NO_BUFFERING`, into a buffer aligned to the sectors
+use std::path::Path;
+
+/// Alignment of the buffer and the chunks, a multiple of the sectors of any drive
+const ALIGNMENT: usize = 4096;
+
+/// A source file read in chunks around the page cache
+pub(crate) struct Uncached {
+    file: std::fs::File,
+    buffer: Vec<u8>,
+    /// Where the aligned part of the buffer starts
+    start: usize,
+    len: usize,
+    #[cfg(target_os = "linux")]
+    offset: u64,
+}
+
+impl Uncached {
+    /// Opens `path` to be read in chunks of about `chunk` bytes
+    pub(crate) fn open(path: &Path, chunk: usize) -> std::io::Result<Self> {
+        let file = open(path)?;
+        let len = chunk.div_ceil(ALIGNMENT).max(1) * ALIGNMENT;
+        let buffer = vec![0; len + ALIGNMENT];
+        let start = buffer.as_ptr().align_offset(ALIGNMENT);
+        Ok(Uncached {
+            file,
+            buffer,
+            start,
+            len,
+            #[cfg(target_os = "linux")]
+            offset: 0,
+        })
+    }
+
+    #[cfg(feature = "sync")]
+    pub(crate) fn metadata(&self) -> std::io::Result<std::fs::Metadata> {
+        self.file.metadata()
+    }
+
+    /// Reads the next chunk, empty at the end of the file
+    pub(crate) fn read(&mut self) -> std::io::Result<&[u8]> {
+        use std::io::Read;
+        let chunk = &mut self.buffer[self.start..self.start + self.len];
+        let read = loop {
+            match self.file.read(chunk) {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                read => break read?,
+            }
+        };
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd;
+            // SAFETY: plain advice on an open descriptor
+            unsafe {
+                libc::posix_fadvise(
+                    self.file.as_raw_fd(),
+                    self.offset as libc::off_t,
+                    read as libc::off_t,
+                    libc::POSIX_FADV_DONTNEED,
+                )
+            };
+            self.offset += read as u64;
+        }
+        Ok(&self.buffer[self.start..self.start + read])
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open(path: &Path) -> std::io::Result<std::fs::File> {
+    use std::os::fd::AsRawFd;
+    let file = std::fs::File::open(path)?;
+    // SAFETY: plain advice on an open descriptor
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_SEQUENTIAL) };
+    Ok(file)
+}
+
+#[cfg(target_os = "macos")]
+fn open(path: &Path) -> std::io::Result<std::fs::File> {
+    use std::os::fd::AsRawFd;
+    let file = std::fs::File::open(path)?;
+    // SAFETY: plain flag on an open descriptor
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+#[cfg(windows)]
+fn open(path: &Path) -> std::io::Result<std::fs::File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_NO_BUFFERING, FILE_FLAG_SEQUENTIAL_SCAN,
+    };
+    std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(FILE_FLAG_NO_BUFFERING | FILE_FLAG_SEQUENTIAL_SCAN)
+        .open(path)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn open(path: &Path) -> std::io::Result<std::fs::File> {
+    std::fs::File::open(path)
+}
@@ -0,0 +1,176 @@
+use crate::*;
+use std::time::{Duration, SystemTime};
+
+/// The order the files of all the sources are copied in, and so numbered in when renamed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OrderBy {
+    /// By path, folder by folder as each source is walked
+    #[default]
+    FileName,
+    /// By when the photos were taken, so the first number is the first frame shot whatever the
+    /// card or camera it's on, see [`Sessions`] for where the time comes from
+    CaptureTime,
+    /// By when the files were last written
+    ModifiedTime,
+    /// The small files first, to get the JPEGs and previews across quickly
+    SmallestFirst,
+    /// The large files first, so the videos overlap with the rest of the pipeline
+    LargestFirst,
+}
+
+/// Decides whether a file is copied first from its path
+pub type PriorityFn = Arc<dyn Fn(&Path) -> bool + Send + Sync>;
+
+/// The files copied before the rest whatever the [`OrderBy`], eg. the selects already rated on
+/// the camera so they land at the target before the bulk of the card. Their RAW/JPEG twins come
+/// along
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Priority {
+    /// Stems of the files, eg. `DSC_0042`, compared ignoring case
+    pub stems: Vec<String>,
+    /// The picks by the rating and color label of their XMP sidecars
+    pub picks: Option<Picks>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub predicate: Option<PriorityFn>,
+}
+
+impl std::fmt::Debug for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Priority")
+            .field("stems", &self.stems)
+            .field("picks", &self.picks)
+            .field("predicate", &self.predicate.is_some())
+            .finish()
+    }
+}
+
+impl PartialEq for Priority {
+    fn eq(&self, other: &Self) -> bool {
+        self.stems == other.stems
+            && self.picks == other.picks
+            && match (&self.predicate, &other.predicate) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            }
+    }
+}
+
+impl Priority {
+    pub fn is_empty(&self) -> bool {
+        self.stems.is_empty() && self.picks.is_none() && self.predicate.is_none()
+    }
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Sorts the walked `entries` of all the sources in [`Ingestor::order`], ties go by path, then
+    /// moves the files of [`Ingestor::priority`] to the front
+    pub(crate) fn order(&self, entries: &mut [(&'ingest Path, vfs::Entry)]) {
+        self.sort(entries);
+        if self.priority.is_empty() {
+            return;
+        }
+        let stems: HashSet<String> = self
+            .priority
+            .stems
+            .iter()
+            .map(|stem| stem.to_lowercase())
+            .collect();
+        let pairs = match self.priority.picks {
+            Some(_) => Pairs::resolve_in(self.vfs(), entries.iter().map(|(_, e)| e.path())),
+            None => Pairs::default(),
+        };
+        let first = |path: &Path| {
+            let stem = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_lowercase());
+            stem.is_some_and(|stem| stems.contains(&stem))
+                || self
+                    .priority
+                    .picks
+                    .as_ref()
+                    .is_some_and(|picks| !picks.is_empty() && picks.keeps(path, &pairs))
+                || self
+                    .priority
+                    .predicate
+                    .as_ref()
+                    .is_some_and(|predicate| predicate(path))
+        };
+        let prioritized: HashSet<PathBuf> = entries
+            .iter()
+            .map(|(_, entry)| entry.path())
+            .filter(|path| first(path))
+            .flat_map(|path| std::iter::once(path).chain(pairs.twin(path)))
+            .map(Path::to_path_buf)
+            .collect();
+        // Stable, the order holds among the files of each group
+        entries.sort_by_key(|(_, entry)| !prioritized.contains(entry.path()));
+    }
+
+    fn sort(&self, entries: &mut [(&'ingest Path, vfs::Entry)]) {
+        let vfs = self.vfs();
+        match self.order {
+            OrderBy::FileName => entries.sort_by(|(_, a), (_, b)| a.path().cmp(b.path())),
+            OrderBy::CaptureTime => entries.sort_by_cached_key(|(_, entry)| {
+                (self.capture_time(entry.path()), entry.path().to_path_buf())
+            }),
+            OrderBy::ModifiedTime => entries.sort_by_cached_key(|(_, entry)| {
+                let modified = vfs.metadata(entry.path()).ok().and_then(|m| m.modified);
+                (modified, entry.path().to_path_buf())
+            }),
+            OrderBy::SmallestFirst => entries.sort_by_cached_key(|(_, entry)| {
+                let size = vfs
+                    .metadata(entry.path())
+                    .map(|m| m.len)
+                    .unwrap_or_default();
+                (size, entry.path().to_path_buf())
+            }),
+            OrderBy::LargestFirst => entries.sort_by_cached_key(|(_, entry)| {
+                let size = vfs
+                    .metadata(entry.path())
+                    .map(|m| m.len)
+                    .unwrap_or_default();
+                (std::cmp::Reverse(size), entry.path().to_path_buf())
+            }),
+        }
+    }
+
+    /// When the file at `path` was taken, corrected for the clock of the camera of its source
+    pub(crate) fn capture_time(&self, path: &Path) -> Option<SystemTime> {
+        let captured = capture_time(self.vfs(), path, self.timezone)?;
+        // The innermost source when they're nested
+        let offset = self
+            .clock_offsets
+            .iter()
+            .filter(|(source, _)| path.starts_with(source))
+            .max_by_key(|(source, _)| source.as_os_str().len())
+            .map_or(0, |(_, seconds)| *seconds);
+        let shift = Duration::from_secs(offset.unsigned_abs());
+        match offset < 0 {
+            true => captured.checked_add(shift),
+            false => captured.checked_sub(shift),
+        }
+    }
+}
+
+/// When the file at `path` was taken according to its EXIF with the `exif` feature, read in
+/// `timezone` (minutes from UTC) when the camera didn't record its own, or UTC. Otherwise and for
+/// the files without one it's their modification time, which cameras set when they write them
+pub(crate) fn capture_time(
+    vfs: &dyn Vfs,
+    path: &Path,
+    timezone: Option<i16>,
+) -> Option<SystemTime> {
+    #[cfg(feature = "exif")]
+    if let Some(mut captured) = crate::meta::read(path).ok().and_then(|meta| meta.captured) {
+        captured.offset = captured.offset.or(timezone);
+        if let Some(captured) = captured.to_system_time() {
+            return Some(captured);
+        }
+    }
+    #[cfg(not(feature = "exif"))]
+    let _ = timezone;
+    vfs.metadata(path).ok()?.modified
+}
@@ -0,0 +1,97 @@
+use crate::*;
+use std::collections::HashMap;
+
+/// Which half of the RAW/JPEG pairs gets copied, the HEIFs of a RAW count as its jpeg
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PairPolicy {
+    /// Copy both, renamed together
+    #[default]
+    Both,
+    /// Only copy the RAWs, every jpeg is skipped
+    RawOnly,
+    /// Only copy the jpegs, every RAW is skipped
+    JpegOnly,
+    /// Skip the jpegs that have a RAW, lone jpegs are still copied
+    PreferRaw,
+}
+
+impl PairPolicy {
+    /// Whether `path` is copied under this policy, files that are neither RAW nor jpeg always are
+    pub fn keeps(&self, path: impl AsRef<Path>, pairs: &Pairs) -> bool {
+        let path = path.as_ref();
+        match self {
+            PairPolicy::Both => true,
+            PairPolicy::RawOnly => !path.is_lossy_twin(),
+            PairPolicy::JpegOnly => !path.is_raw(),
+            PairPolicy::PreferRaw => !pairs.is_twin(path),
+        }
+    }
+}
+
+/// RAW/JPEG and RAW/HEIF pairs resolved before renaming so that both halves of a pair end up
+/// with the same stem at the target.
+#[derive(Debug, Clone, Default)]
+pub struct Pairs {
+    twins: HashMap<PathBuf, PathBuf>,
+    jpegs: HashSet<PathBuf>,
+}
+
+impl Pairs {
+    /// Finds the accompanying jpeg of every RAW file in `paths`, or its HEIF when it has none
+    pub fn resolve<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> Self {
+        Self::resolve_in(&vfs::RealFs, paths)
+    }
+
+    /// Same as [`Pairs::resolve`] with the files of `vfs`
+    pub fn resolve_in<P: AsRef<Path>>(vfs: &dyn Vfs, paths: impl IntoIterator<Item = P>) -> Self {
+        let mut pairs = Self::default();
+        for path in paths {
+            let path = path.as_ref();
+            if !path.is_raw() || !vfs.is_file(path) {
+                continue;
+            }
+            let twins: Vec<PathBuf> = companion::companions_in(vfs, path)
+                .into_iter()
+                .filter(|companion| companion.kind == CompanionKind::Twin)
+                .map(|companion| companion.path)
+                .filter(|twin| twin.is_lossy_twin())
+                .collect();
+            let jpeg = twins
+                .iter()
+                .find(|twin| twin.is_jpeg())
+                .or_else(|| twins.first())
+                .and_then(|twin| vfs.canonicalize(twin).ok());
+            if let Some(jpeg) = jpeg {
+                pairs.jpegs.insert(jpeg.clone());
+                pairs.twins.insert(path.to_path_buf(), jpeg);
+            }
+        }
+        pairs
+    }
+
+    /// Returns the jpeg or HEIF paired with `path` if any
+    pub fn twin(&self, path: impl AsRef<Path>) -> Option<&Path> {
+        self.twins.get(path.as_ref()).map(PathBuf::as_path)
+    }
+
+    /// Whether `path` is a jpeg or HEIF that has a RAW
+    pub fn is_twin(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        // The paths of another filesystem can't be canonicalized, they are as walked
+        path.is_lossy_twin()
+            && (self.jpegs.contains(path)
+                || path
+                    .canonicalize()
+                    .map(|path| self.jpegs.contains(&path))
+                    .unwrap_or_default())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.twins.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.twins.len()
+    }
+}
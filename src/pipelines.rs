@@ -0,0 +1,80 @@
+//! The sources copied at the same time, one pipeline per card reader since reads from distinct
+//! readers don't contend, up to [`Ingestor::concurrency`] of them.
+//!
+//! A pipeline is a clone of the ingestor with a single source, sharing its counters, events and
+//! destinations. What it adds to the report and the manifests is taken back once it's done so the
+//! pass finishes as if the sources were copied one after the other
+use crate::*;
+
+impl<'ingest> Ingestor<'ingest> {
+    /// How many sources are copied at the same time, one at a time when the names of the files
+    /// of a source depend on the others, or the folders they go to on how full the others made
+    /// them
+    pub(crate) fn concurrent_sources(&self) -> usize {
+        let shared = self.structure.is_renamed()
+            || (self.structure.is_preserved() && self.collisions != CollisionPolicy::Number)
+            || self.max_files_per_folder.is_some()
+            || self.session_gap.is_some()
+            || self.bursts.is_some()
+            || self.skip_if_hash_exists_in_target;
+        match shared {
+            true => 1,
+            false => self.concurrency.clamp(1, self.sources.len().max(1)),
+        }
+    }
+
+    /// A pipeline for every source, in no particular order
+    pub(crate) fn pipelines(&self) -> Vec<Ingestor<'ingest>> {
+        self.sources
+            .iter()
+            .map(|&source| {
+                let mut pipeline = self.clone();
+                pipeline.sources = HashSet::from([source]);
+                // The files skipped by an earlier pass are left out again
+                pipeline.report = Report {
+                    run_id: self.report.run_id,
+                    skipped: self.report.skipped.clone(),
+                    ..Default::default()
+                };
+                pipeline.__hashed.clear();
+                pipeline.__mirrored.clear();
+                pipeline.__mirror_names.clear();
+                pipeline.__unsynced.clear();
+                pipeline
+            })
+            .collect()
+    }
+
+    /// Takes back what `pipeline` copied, `skipped` being the files skipped before it started
+    pub(crate) fn join_pipeline(&mut self, mut pipeline: Ingestor<'ingest>, skipped: usize) {
+        pipeline.report.skipped.drain(..skipped);
+        let report = pipeline.report;
+        self.report.warnings.extend(report.warnings);
+        self.report.retries.extend(report.retries);
+        self.report.failures.extend(report.failures);
+        self.report.skipped.extend(report.skipped);
+        self.report.bursts.extend(report.bursts);
+        #[cfg(feature = "validate")]
+        self.report.corrupt.extend(report.corrupt);
+        self.__hashed.extend(pipeline.__hashed);
+        self.__mirrored.extend(pipeline.__mirrored);
+        self.__unsynced.extend(pipeline.__unsynced);
+        self.__names.extend(pipeline.__names);
+        self.__mirror_names.extend(pipeline.__mirror_names);
+    }
+
+    /// Takes back every pipeline in the order of their sources, returning the first error
+    pub(crate) fn join_pipelines(
+        &mut self,
+        mut pipelines: Vec<(Ingestor<'ingest>, Result<()>)>,
+        skipped: usize,
+    ) -> Result<()> {
+        pipelines.sort_by(|(a, _), (b, _)| a.sources.iter().cmp(b.sources.iter()));
+        let mut joined = Ok(());
+        for (pipeline, copied) in pipelines {
+            self.join_pipeline(pipeline, skipped);
+            joined = joined.and(copied);
+        }
+        joined
+    }
+}
@@ -0,0 +1,168 @@
+//! The ingest split in two: [`Ingestor::plan`] works out where every file goes from the sources
+//! alone, and [`Ingestor::execute`] copies a plan to the target and the backup.
+//!
+//! A plan can be shown before anything is copied, kept as JSON with the `serde` feature and
+//! executed later or by another process. The destinations are relative to the target so the
+//! backup gets the same names
+use crate::*;
+
+/// Where every file of the sources goes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IngestPlan {
+    /// The run the names were given for, see [`RUN_TOKEN`]
+    pub run_id: Uuid,
+    pub files: Vec<PlannedFile>,
+}
+
+/// How a planned file compares with what's already at its destination, see [`IngestPlan::diff`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FileDiff {
+    /// Nothing there yet
+    New,
+    /// The same contents are there already, eg. the frames of a card copied earlier in the day
+    AlreadyPresentIdentical,
+    /// Another file has the name, the ingest numbers the copy next to it
+    PresentDifferent,
+}
+
+/// A file of the sources and where it goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlannedFile {
+    pub source: PathBuf,
+    /// Relative to the target
+    pub destination: PathBuf,
+    pub size: u64,
+    /// The twins, sidecars and clip metadata copied along, with their destinations relative to
+    /// the target
+    pub companions: Vec<(PathBuf, PathBuf)>,
+}
+
+impl IngestPlan {
+    /// Bytes of the planned files, without their companions
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+
+    /// Every file and its companions with where they go under `target`
+    pub fn mapping(&self, target: &Path) -> Vec<(PathBuf, PathBuf)> {
+        let mut mapping = Vec::new();
+        for file in &self.files {
+            for (source, destination) in &file.companions {
+                mapping.push((source.clone(), target.join(destination)));
+            }
+            mapping.push((file.source.clone(), target.join(&file.destination)));
+        }
+        mapping
+    }
+
+    /// Compares every planned file with its destination under `target`, so only the frames shot
+    /// since the card was last copied need to be. The companions aren't compared as the ingest
+    /// writes to the sidecars
+    pub fn diff(&self, target: impl AsRef<Path>) -> Result<Vec<(&PlannedFile, FileDiff)>> {
+        self.diff_in(&vfs::RealFs, target)
+    }
+
+    /// Same as [`IngestPlan::diff`] with the files of `vfs`
+    pub fn diff_in(
+        &self,
+        vfs: &dyn Vfs,
+        target: impl AsRef<Path>,
+    ) -> Result<Vec<(&PlannedFile, FileDiff)>> {
+        let mode = VerifyMode::Hash(HashAlgo::default());
+        self.files
+            .iter()
+            .map(|file| {
+                let destination = target.as_ref().join(&file.destination);
+                if vfs.metadata(&destination).is_err() {
+                    return Ok((file, FileDiff::New));
+                }
+                let diff = match verify::same_content(vfs, &file.source, &destination, mode)? {
+                    true => FileDiff::AlreadyPresentIdentical,
+                    false => FileDiff::PresentDifferent,
+                };
+                Ok((file, diff))
+            })
+            .collect()
+    }
+
+    /// Every source of the plan, the companions included
+    pub(crate) fn sources(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().flat_map(|file| {
+            std::iter::once(file.source.as_path())
+                .chain(file.companions.iter().map(|(source, _)| source.as_path()))
+        })
+    }
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Works out where every file of the sources goes without touching the target. The names are
+    /// the ones the structure gives, before any collision suffix
+    pub fn plan(&self) -> Result<IngestPlan> {
+        let mut rename = self.renamer();
+        let mut planner = self.clone();
+        let mut entries = self.entries();
+        planner.placeholders(&mut entries)?;
+        let pairs = Pairs::resolve_in(self.vfs(), entries.iter().map(|(_, entry)| entry.path()));
+        // The destinations depend on the sessions and collisions of the whole set of files
+        planner.__pairs = pairs.clone();
+        planner.plan_pass(
+            entries
+                .iter()
+                .filter(|(_, entry)| entry.is_file())
+                .map(|(_, entry)| entry.path()),
+        )?;
+
+        let mut files: Vec<(&Path, PathBuf)> = entries
+            .iter()
+            .filter(|(_, entry)| entry.is_file())
+            .filter(|(_, entry)| !self.skips_path(entry.path(), &pairs))
+            .map(|(source, entry)| (*source, entry.path().to_path_buf()))
+            .collect();
+        if self.copy_orphans {
+            files.extend(
+                self.orphan_sidecars()
+                    .into_iter()
+                    .map(|(source, entry)| (source, entry.into_path())),
+            );
+        }
+
+        let relative = |path: PathBuf| -> Result<PathBuf> {
+            Ok(path.strip_prefix(&self.target)?.to_path_buf())
+        };
+        let mut plan = IngestPlan {
+            run_id: self.run_id,
+            files: Vec::new(),
+        };
+        for (source, path) in files {
+            let target = planner.destination(source, &path, &mut rename)?;
+            let target = planner.windows_safe(&path, target);
+            let target = planner.spill(target);
+            let mut companions = Vec::new();
+            if self.copies_twins() {
+                if let Some(twin) = pairs.twin(&path) {
+                    let destination = planner.twin_destination(&path, &target, twin);
+                    companions.push((twin.to_path_buf(), destination));
+                }
+            }
+            companions.extend(self.sidecars.targets_in(self.vfs(), &path, &target));
+            companions.extend(self.clip_metadata(&path, &target));
+            plan.files.push(PlannedFile {
+                size: self
+                    .vfs()
+                    .metadata(&path)
+                    .map(|m| m.len)
+                    .unwrap_or_default(),
+                destination: relative(target)?,
+                companions: companions
+                    .into_iter()
+                    .map(|(source, destination)| Ok((source, relative(destination)?)))
+                    .collect::<Result<_>>()?,
+                source: path,
+            });
+        }
+        Ok(plan)
+    }
+}
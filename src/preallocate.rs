@@ -0,0 +1,99 @@
+//! Space reserved for the copies up front, so they're laid out in one piece on the spinning
+//! archives and a full target fails the copy before it starts rather than halfway through.
+//! Filesystems that can't preallocate are copied to as usual
+use std::io::{Error, ErrorKind};
+
+/// Whether the filesystem can't preallocate, as opposed to being out of space
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn unsupported(e: &Error) -> bool {
+    e.kind() == ErrorKind::Unsupported
+        || matches!(
+            e.raw_os_error(),
+            Some(libc::EOPNOTSUPP | libc::ENOSYS | libc::EINVAL)
+        )
+}
+
+/// Reserves `len` bytes for `file` without changing its size
+#[cfg(target_os = "linux")]
+pub(crate) fn preallocate(file: &impl std::os::fd::AsRawFd, len: u64) -> std::io::Result<()> {
+    // SAFETY: plain call on an open descriptor
+    let allocated = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_KEEP_SIZE,
+            0,
+            len as libc::off_t,
+        )
+    };
+    match allocated {
+        0 => Ok(()),
+        _ => checked(Error::last_os_error()),
+    }
+}
+
+/// Reserves `len` bytes for `file` without changing its size, in one piece if possible
+#[cfg(target_os = "macos")]
+pub(crate) fn preallocate(file: &impl std::os::fd::AsRawFd, len: u64) -> std::io::Result<()> {
+    let mut store = libc::fstore_t {
+        fst_flags: libc::F_ALLOCATECONTIG | libc::F_ALLOCATEALL,
+        fst_posmode: libc::F_PEOFPOSMODE,
+        fst_offset: 0,
+        fst_length: len as libc::off_t,
+        fst_bytesalloc: 0,
+    };
+    // SAFETY: store is a valid fstore_t for the duration of the calls
+    unsafe {
+        if libc::fcntl(file.as_raw_fd(), libc::F_PREALLOCATE, &store) == 0 {
+            return Ok(());
+        }
+        store.fst_flags = libc::F_ALLOCATEALL;
+        if libc::fcntl(file.as_raw_fd(), libc::F_PREALLOCATE, &store) == 0 {
+            return Ok(());
+        }
+    }
+    checked(Error::last_os_error())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn checked(e: Error) -> std::io::Result<()> {
+    match unsupported(&e) {
+        true => Ok(()),
+        false => Err(e),
+    }
+}
+
+/// Reserves `len` bytes for `file` without changing its size
+#[cfg(windows)]
+pub(crate) fn preallocate(
+    file: &impl std::os::windows::io::AsRawHandle,
+    len: u64,
+) -> std::io::Result<()> {
+    use windows_sys::Win32::Storage::FileSystem::{
+        FileAllocationInfo, SetFileInformationByHandle, FILE_ALLOCATION_INFO,
+    };
+    let info = FILE_ALLOCATION_INFO {
+        AllocationSize: len as i64,
+    };
+    // SAFETY: info is a valid FILE_ALLOCATION_INFO of the size given for the duration of the call
+    let allocated = unsafe {
+        SetFileInformationByHandle(
+            file.as_raw_handle() as _,
+            FileAllocationInfo,
+            &info as *const FILE_ALLOCATION_INFO as *const _,
+            std::mem::size_of::<FILE_ALLOCATION_INFO>() as u32,
+        )
+    };
+    if allocated != 0 {
+        return Ok(());
+    }
+    let e = Error::last_os_error();
+    match e.kind() {
+        ErrorKind::StorageFull | ErrorKind::QuotaExceeded => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub(crate) fn preallocate<F>(_file: &F, _len: u64) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,112 @@
+//! Copies the metadata that `fs::copy` leaves behind: ownership, extended attributes (which
+//! include the macOS Finder tags) and windows alternate data streams.
+use std::path::Path;
+
+/// Copies everything except the permissions, which go last in [`copy_permissions`] since a
+/// readonly target can't have its timestamps set anymore
+pub(crate) fn copy_attributes(source: impl AsRef<Path>, target: impl AsRef<Path>) -> std::io::Result<()> {
+    let (source, target) = (source.as_ref(), target.as_ref());
+    #[cfg(unix)]
+    {
+        copy_owner(source, target)?;
+        copy_xattrs(source, target)?;
+    }
+    #[cfg(windows)]
+    copy_streams(source, target)?;
+    Ok(())
+}
+
+pub(crate) fn copy_permissions(source: impl AsRef<Path>, target: impl AsRef<Path>) -> std::io::Result<()> {
+    std::fs::set_permissions(target, source.as_ref().metadata()?.permissions())
+}
+
+/// Only root can give files away so a permission error here is not fatal
+#[cfg(unix)]
+fn copy_owner(source: &Path, target: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = source.metadata()?;
+    match std::os::unix::fs::chown(target, Some(metadata.uid()), Some(metadata.gid())) {
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(unix)]
+fn copy_xattrs(source: &Path, target: &Path) -> std::io::Result<()> {
+    if !xattr::SUPPORTED_PLATFORM {
+        return Ok(());
+    }
+    let names = match xattr::list(source) {
+        Ok(names) => names,
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for name in names {
+        if let Some(value) = xattr::get(source, &name)? {
+            // security.* and friends need privileges we usually don't have
+            match xattr::set(target, &name, &value) {
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => (),
+                result => result?,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn copy_streams(source: &Path, target: &Path) -> std::io::Result<()> {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard,
+        WIN32_FIND_STREAM_DATA,
+    };
+
+    let wide: Vec<u16> = crate::sanitize::long_path(source)
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    let mut data: WIN32_FIND_STREAM_DATA = unsafe { std::mem::zeroed() };
+    let handle = unsafe {
+        FindFirstStreamW(
+            wide.as_ptr(),
+            FindStreamInfoStandard,
+            &mut data as *mut _ as *mut _,
+            0,
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        // Either there are no streams or the filesystem doesn't support them
+        return Ok(());
+    }
+    let mut streams = Vec::new();
+    loop {
+        let len = data
+            .cStreamName
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(data.cStreamName.len());
+        streams.push(OsString::from_wide(&data.cStreamName[..len]));
+        if unsafe { FindNextStreamW(handle, &mut data as *mut _ as *mut _) } == 0 {
+            break;
+        }
+    }
+    unsafe { FindClose(handle) };
+
+    for stream in streams {
+        // The unnamed stream is the file contents itself
+        let stream = stream.to_string_lossy();
+        let stream = stream.trim_end_matches(":$DATA");
+        if stream.is_empty() || stream == ":" {
+            continue;
+        }
+        let mut from = source.as_os_str().to_owned();
+        from.push(stream);
+        let mut to = target.as_os_str().to_owned();
+        to.push(stream);
+        std::io::copy(&mut std::fs::File::open(from)?, &mut std::fs::File::create(to)?)?;
+    }
+    Ok(())
+}
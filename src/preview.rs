@@ -0,0 +1,124 @@
+//! Extraction of the JPEG previews cameras embed in their RAWs, so a gallery or a first cull can
+//! start before the RAWs are processed.
+use crate::*;
+
+/// Folder of the target the previews are extracted to, mirroring the tree of the RAWs
+pub const PREVIEWS_FOLDER: &str = "previews";
+
+/// Which of the embedded previews is extracted, most RAWs carry a small thumbnail and a larger
+/// preview, some a full size one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PreviewSize {
+    #[default]
+    Largest,
+    Smallest,
+    /// The smallest one with a long edge of at least that many pixels, the largest if none is
+    AtLeast(u32),
+}
+
+/// A JPEG found inside a RAW
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Embedded {
+    start: usize,
+    end: usize,
+    width: u16,
+    height: u16,
+}
+
+impl Embedded {
+    fn long_edge(&self) -> u32 {
+        self.width.max(self.height).into()
+    }
+}
+
+/// Returns the embedded preview of the RAW at `path` of the given `size`, `None` if it has none
+pub fn extract(path: impl AsRef<Path>, size: PreviewSize) -> Result<Option<Vec<u8>>> {
+    extract_in(&vfs::RealFs, path.as_ref(), size)
+}
+
+pub(crate) fn extract_in(vfs: &dyn Vfs, path: &Path, size: PreviewSize) -> Result<Option<Vec<u8>>> {
+    let mut data = vfs::read(vfs, path)?;
+    let previews = embedded(&data);
+    let by_size = |a: &&Embedded, b: &&Embedded| a.long_edge().cmp(&b.long_edge());
+    let preview = match size {
+        PreviewSize::Largest => previews.iter().max_by(by_size),
+        PreviewSize::Smallest => previews.iter().min_by(by_size),
+        PreviewSize::AtLeast(edge) => previews
+            .iter()
+            .filter(|preview| preview.long_edge() >= edge)
+            .min_by(by_size)
+            .or_else(|| previews.iter().max_by(by_size)),
+    };
+    Ok(preview.map(|preview| {
+        data.truncate(preview.end);
+        data.split_off(preview.start)
+    }))
+}
+
+/// Finds every baseline or progressive JPEG in `data`. The lossless JPEGs some RAWs store their
+/// sensor data in aren't previews and are left out
+fn embedded(data: &[u8]) -> Vec<Embedded> {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while let Some(start) = data[pos..]
+        .windows(3)
+        .position(|soi| soi == [0xff, 0xd8, 0xff])
+        .map(|offset| pos + offset)
+    {
+        match parse(data, start) {
+            Some(jpeg) => {
+                pos = jpeg.end;
+                found.push(jpeg);
+            }
+            None => pos = start + 2,
+        }
+    }
+    found
+}
+
+/// Walks the segments of the JPEG starting at `start` to find its end and dimensions
+fn parse(data: &[u8], start: usize) -> Option<Embedded> {
+    let u16_at = |pos: usize| Some(u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]));
+    let mut size = None;
+    let mut pos = start + 2;
+    loop {
+        if *data.get(pos)? != 0xff {
+            return None;
+        }
+        while *data.get(pos + 1)? == 0xff {
+            pos += 1;
+        }
+        let marker = data[pos + 1];
+        pos += 2;
+        match marker {
+            0xd9 => break,
+            0x01 | 0xd0..=0xd7 => continue,
+            _ => {}
+        }
+        let length = usize::from(u16_at(pos)?);
+        match marker {
+            0xc0..=0xc2 => size = Some((u16_at(pos + 5)?, u16_at(pos + 3)?)),
+            0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => return None,
+            _ => {}
+        }
+        pos += length;
+        if marker == 0xda {
+            // Entropy coded data runs until the next marker that isn't a stuffed byte or a restart
+            loop {
+                pos += data.get(pos..)?.iter().position(|byte| *byte == 0xff)?;
+                match *data.get(pos + 1)? {
+                    0x00 | 0xd0..=0xd7 => pos += 2,
+                    _ => break,
+                }
+            }
+        }
+    }
+    let (width, height) = size?;
+    Some(Embedded {
+        start,
+        end: pos,
+        width,
+        height,
+    })
+}
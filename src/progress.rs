@@ -0,0 +1,311 @@
+use crate::*;
+use std::sync::atomic::Ordering;
+
+/// The stage an ingest is at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Phase {
+    /// Looking for the files of the sources, before the total is known
+    Scanning,
+    /// Copying the sources to the target
+    Copying,
+    /// Copying the sources to the backup
+    BackingUp,
+    /// Comparing the copies with the sources
+    Verifying,
+}
+
+/// Receives the progress of an ingest as it's made, without the host depending on how it's
+/// counted. Called from the task or thread doing the copies for every chunk, so it shouldn't
+/// block for long
+pub trait ProgressSink: Send + Sync {
+    /// `bytes` of the `total` were copied so far, the backup included
+    fn on_bytes(&self, bytes: u64, total: u64) {
+        let _ = (bytes, total);
+    }
+
+    /// `path` started copying as the `files`th file
+    fn on_file(&self, path: &Path, files: usize) {
+        let _ = (path, files);
+    }
+
+    fn on_phase(&self, phase: Phase) {
+        let _ = phase;
+    }
+
+    /// `files` to copy were found so far while [`Phase::Scanning`]
+    fn on_scan(&self, files: usize) {
+        let _ = files;
+    }
+
+    /// One of the sources got further, along with the bytes
+    fn on_source(&self, progress: &SourceProgress) {
+        let _ = progress;
+    }
+}
+
+/// The sinks an ingestor sends its progress to
+#[derive(Clone, Default)]
+pub struct ProgressSinks {
+    sinks: Vec<Arc<dyn ProgressSink>>,
+}
+
+impl std::fmt::Debug for ProgressSinks {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ProgressSinks")
+            .field("sinks", &self.sinks.len())
+            .finish()
+    }
+}
+
+impl ProgressSinks {
+    pub fn push(&mut self, sink: impl ProgressSink + 'static) {
+        self.sinks.push(Arc::new(sink));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    pub(crate) fn bytes(&self, bytes: u64, total: u64) {
+        self.sinks
+            .iter()
+            .for_each(|sink| sink.on_bytes(bytes, total));
+    }
+
+    pub(crate) fn file(&self, path: &Path, files: usize) {
+        self.sinks.iter().for_each(|sink| sink.on_file(path, files));
+    }
+
+    pub(crate) fn phase(&self, phase: Phase) {
+        self.sinks.iter().for_each(|sink| sink.on_phase(phase));
+    }
+
+    pub(crate) fn scan(&self, files: usize) {
+        self.sinks.iter().for_each(|sink| sink.on_scan(files));
+    }
+
+    pub(crate) fn source(&self, progress: &SourceProgress) {
+        self.sinks.iter().for_each(|sink| sink.on_source(progress));
+    }
+}
+
+/// How far the files of one of the sources got, for a bar per card
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceProgress {
+    pub source: PathBuf,
+    /// The label of the source or the name of its folder
+    pub label: String,
+    /// Files started so far, the backup included
+    pub files: usize,
+    pub total_files: usize,
+    pub bytes: u64,
+    pub total_bytes: u64,
+}
+
+impl SourceProgress {
+    /// Whether everything of the source was copied, so its card can be unplugged
+    pub fn is_done(&self) -> bool {
+        self.files >= self.total_files && self.bytes >= self.total_bytes
+    }
+}
+
+/// Keeps the progress in counters shared with the host
+#[derive(Debug, Clone, Default)]
+pub struct AtomicProgress {
+    pub files: Arc<AtomicUsize>,
+    pub bytes: Arc<AtomicU64>,
+    pub total: Arc<AtomicU64>,
+    /// Files found while scanning
+    pub scanned: Arc<AtomicUsize>,
+}
+
+impl ProgressSink for AtomicProgress {
+    fn on_bytes(&self, bytes: u64, total: u64) {
+        self.bytes.store(bytes, Ordering::SeqCst);
+        self.total.store(total, Ordering::SeqCst);
+    }
+
+    fn on_file(&self, _path: &Path, files: usize) {
+        self.files.store(files, Ordering::SeqCst);
+    }
+
+    fn on_scan(&self, files: usize) {
+        self.scanned.store(files, Ordering::SeqCst);
+    }
+}
+
+/// The progress of an ingest as sent down a channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressUpdate {
+    Bytes { bytes: u64, total: u64 },
+    File { path: PathBuf, files: usize },
+    Phase(Phase),
+    Scanned { files: usize },
+    Source(SourceProgress),
+}
+
+/// Sends the progress to the receiver, which going away doesn't stop the ingest
+impl ProgressSink for std::sync::mpsc::Sender<ProgressUpdate> {
+    fn on_bytes(&self, bytes: u64, total: u64) {
+        self.send(ProgressUpdate::Bytes { bytes, total }).ok();
+    }
+
+    fn on_file(&self, path: &Path, files: usize) {
+        let path = path.to_path_buf();
+        self.send(ProgressUpdate::File { path, files }).ok();
+    }
+
+    fn on_phase(&self, phase: Phase) {
+        self.send(ProgressUpdate::Phase(phase)).ok();
+    }
+
+    fn on_scan(&self, files: usize) {
+        self.send(ProgressUpdate::Scanned { files }).ok();
+    }
+
+    fn on_source(&self, progress: &SourceProgress) {
+        self.send(ProgressUpdate::Source(progress.clone())).ok();
+    }
+}
+
+/// Sends the progress to the receiver, which going away doesn't stop the ingest
+#[cfg(feature = "async")]
+impl ProgressSink for tokio::sync::mpsc::UnboundedSender<ProgressUpdate> {
+    fn on_bytes(&self, bytes: u64, total: u64) {
+        self.send(ProgressUpdate::Bytes { bytes, total }).ok();
+    }
+
+    fn on_file(&self, path: &Path, files: usize) {
+        let path = path.to_path_buf();
+        self.send(ProgressUpdate::File { path, files }).ok();
+    }
+
+    fn on_phase(&self, phase: Phase) {
+        self.send(ProgressUpdate::Phase(phase)).ok();
+    }
+
+    fn on_scan(&self, files: usize) {
+        self.send(ProgressUpdate::Scanned { files }).ok();
+    }
+
+    fn on_source(&self, progress: &SourceProgress) {
+        self.send(ProgressUpdate::Source(progress.clone())).ok();
+    }
+}
+
+/// Shows the bytes copied on the bar, the file being copied as its message and the phase as its
+/// prefix
+impl ProgressSink for indicatif::ProgressBar {
+    fn on_bytes(&self, bytes: u64, total: u64) {
+        // The sidecars copied along aren't part of the total
+        self.set_length(total.max(bytes));
+        self.set_position(bytes);
+    }
+
+    fn on_file(&self, path: &Path, _files: usize) {
+        if let Some(name) = path.file_name() {
+            self.set_message(name.to_string_lossy().into_owned());
+        }
+    }
+
+    fn on_phase(&self, phase: Phase) {
+        self.set_prefix(match phase {
+            Phase::Scanning => "Scanning",
+            Phase::Copying => "Copying",
+            Phase::BackingUp => "Backing up",
+            Phase::Verifying => "Verifying",
+        });
+    }
+
+    fn on_scan(&self, files: usize) {
+        self.set_message(format!("{} files found", files));
+    }
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Moves the ingest to `phase`, starting the count of the files found over when scanning
+    pub(crate) fn set_phase(&self, phase: Phase) {
+        if let Ok(mut current) = self.__phase.lock() {
+            *current = Some(phase);
+        }
+        if phase == Phase::Scanning {
+            self.__scanned.store(0, Ordering::SeqCst);
+        }
+        self.progress_sinks.phase(phase);
+        self.events.emit(Event::Phase { phase });
+    }
+
+    /// Counts a file to copy found while scanning
+    pub(crate) fn found(&self) {
+        if self.__phase.lock().ok().and_then(|phase| *phase) != Some(Phase::Scanning) {
+            return;
+        }
+        let files = self.__scanned.fetch_add(1, Ordering::SeqCst) + 1;
+        self.progress_sinks.scan(files);
+        self.events.scanning(files);
+    }
+
+    /// Counts the file at `path` as started, along with the bytes copied next
+    pub(crate) fn record_file(&mut self, path: &Path) {
+        let files = self.progress.fetch_add(1, Ordering::SeqCst) + 1;
+        self.progress_sinks.file(path, files);
+        // By path as the pipelines only have their own source
+        self.__source = self.source_of(path).and_then(|source| {
+            let sources = self.__sources.lock().ok()?;
+            sources.iter().position(|progress| progress.source == source)
+        });
+        self.record_source(|progress| progress.files += 1);
+    }
+
+    /// Starts the count of every source over with the `files` of the pass and their sizes, copied
+    /// `passes` times
+    pub(crate) fn start_sources(&self, files: &HashMap<PathBuf, u64>, passes: usize) {
+        let mut sources: Vec<SourceProgress> = self
+            .sources
+            .iter()
+            .map(|&source| SourceProgress {
+                source: source.to_path_buf(),
+                label: self.source_label(source).to_string_lossy().into_owned(),
+                ..Default::default()
+            })
+            .collect();
+        for (path, &size) in files {
+            let Some(source) = self.source_of(path) else {
+                continue;
+            };
+            let Some(progress) = sources
+                .iter_mut()
+                .find(|progress| progress.source == source)
+            else {
+                continue;
+            };
+            progress.total_files += passes;
+            progress.total_bytes += size * passes as u64;
+        }
+        if let Ok(mut progress) = self.__sources.lock() {
+            *progress = sources;
+        }
+    }
+
+    /// Updates the count of the source of the file being copied
+    pub(crate) fn record_source(&self, update: impl FnOnce(&mut SourceProgress)) {
+        let Some(source) = self.__source else {
+            return;
+        };
+        let Ok(mut sources) = self.__sources.lock() else {
+            return;
+        };
+        if let Some(progress) = sources.get_mut(source) {
+            update(progress);
+            self.progress_sinks.source(progress);
+        }
+    }
+
+    /// Takes back the `bytes` counted of a copy that failed
+    pub(crate) fn unrecord_bytes(&self, bytes: u64) {
+        self.bytes.fetch_sub(bytes, Ordering::SeqCst);
+        self.record_source(|progress| progress.bytes = progress.bytes.saturating_sub(bytes));
+    }
+}
@@ -0,0 +1,237 @@
+//! Python bindings, the `ingest` module built with the `python` feature:
+//!
+//! ```python
+//! import ingest
+//!
+//! ingestor = ingest.Ingestor(["/Volumes/EOS_DIGITAL"], "/Photos", backup="/Backup",
+//!                            structure=ingest.Structure.rename("Wedding"),
+//!                            filter=ingest.Filter.raws())
+//! report = ingestor.ingest()
+//! ```
+//!
+//! The ingestor keeps its configuration and is set up anew by every call, the copies run
+//! without holding the GIL so another thread can follow the progress or cancel.
+use crate::*;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+fn py_error(error: Error) -> PyErr {
+    PyRuntimeError::new_err(error.to_string())
+}
+
+/// Which files are ingested
+#[pyclass(name = "Filter", module = "ingest")]
+pub struct PyFilter(FilterConfig);
+
+#[pymethods]
+impl PyFilter {
+    #[new]
+    #[pyo3(signature = (extensions = Vec::new(), min_size = 0, max_size = None, ignore_hidden = true))]
+    fn new(
+        extensions: Vec<String>,
+        min_size: u64,
+        max_size: Option<u64>,
+        ignore_hidden: bool,
+    ) -> Self {
+        PyFilter(FilterConfig {
+            extensions,
+            min_size,
+            max_size,
+            ignore_hidden,
+            video_cards: VideoCardPolicy::Whole,
+            attributes: Attributes::default(),
+            max_files: None,
+            every_nth: None,
+            sniff_contents: false,
+        })
+    }
+
+    #[staticmethod]
+    fn images() -> Self {
+        PyFilter(Filter::images().into())
+    }
+
+    #[staticmethod]
+    fn raws() -> Self {
+        PyFilter(Filter::raws().into())
+    }
+
+    #[staticmethod]
+    fn jpegs() -> Self {
+        PyFilter(Filter::jpegs().into())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+/// Where the files go at the target
+#[pyclass(name = "Structure", module = "ingest")]
+pub struct PyStructure(StructureConfig);
+
+#[pymethods]
+impl PyStructure {
+    /// Keeps the folders of the sources
+    #[staticmethod]
+    fn retain() -> Self {
+        PyStructure(StructureConfig::Retain)
+    }
+
+    /// Puts every file at the root of the target
+    #[staticmethod]
+    fn preserve() -> Self {
+        PyStructure(StructureConfig::Preserve)
+    }
+
+    /// Renames the files to `name` and a sequence number
+    #[staticmethod]
+    #[pyo3(signature = (name = None, sequence = 1, zeroes = 5, prefix = false))]
+    fn rename(name: Option<String>, sequence: i32, zeroes: u8, prefix: bool) -> Self {
+        PyStructure(StructureConfig::Rename(RenameConfig {
+            name,
+            position: match prefix {
+                true => Position::Prefix,
+                false => Position::Suffix,
+            },
+            sequence,
+            zeroes,
+        }))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+/// Copies the sources to the target and the backup
+#[pyclass(name = "Ingestor", module = "ingest")]
+pub struct PyIngestor {
+    config: IngestConfig,
+    cancel: Arc<AtomicBool>,
+    handle: Mutex<Option<IngestHandle>>,
+}
+
+impl PyIngestor {
+    fn ingestor(&self) -> PyResult<Ingestor<'_>> {
+        let mut ingestor = IngestorBuilder::from_config(&self.config)
+            .build()
+            .map_err(py_error)?;
+        ingestor.cancel = self.cancel.clone();
+        Ok(ingestor)
+    }
+}
+
+#[pymethods]
+impl PyIngestor {
+    #[new]
+    #[pyo3(signature = (sources, target, backup = None, structure = None, filter = None))]
+    fn new(
+        sources: Vec<PathBuf>,
+        target: PathBuf,
+        backup: Option<PathBuf>,
+        structure: Option<PyRef<PyStructure>>,
+        filter: Option<PyRef<PyFilter>>,
+    ) -> Self {
+        PyIngestor {
+            config: IngestConfig {
+                sources,
+                target: Some(target),
+                backup,
+                structure: structure
+                    .map(|structure| structure.0.clone())
+                    .unwrap_or_default(),
+                filter: filter.map(|filter| filter.0.clone()).unwrap_or_default(),
+                ..Default::default()
+            },
+            cancel: Arc::default(),
+            handle: Mutex::new(None),
+        }
+    }
+
+    /// Copies everything, returns the report as a dict
+    fn ingest<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let mut ingestor = self.ingestor()?;
+        *self.handle.lock().unwrap_or_else(|e| e.into_inner()) = Some(ingestor.handle());
+        // A cancel only stops the run it was meant for
+        self.cancel.store(false, Ordering::SeqCst);
+        let ingested = py.detach(|| {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(ingestor.ingest())
+        });
+        ingested.map_err(py_error)?;
+        report(py, &ingestor.report)
+    }
+
+    /// Where every file would be copied, as `(source, destination)` pairs
+    fn plan(&self, py: Python) -> PyResult<Vec<(PathBuf, PathBuf)>> {
+        let ingestor = self.ingestor()?;
+        py.detach(|| ingestor.mapping()).map_err(py_error)
+    }
+
+    /// Compares the target with the sources by `mode`, `size` or a hash algorithm
+    #[pyo3(signature = (mode = "xxh128"))]
+    fn verify<'py>(&self, py: Python<'py>, mode: &str) -> PyResult<Bound<'py, PyDict>> {
+        let mode: VerifyMode = mode.parse().map_err(py_error)?;
+        let ingestor = self.ingestor()?;
+        let verified = py.detach(|| ingestor.verify(mode)).map_err(py_error)?;
+        let dict = PyDict::new(py);
+        dict.set_item("matched", verified.matched)?;
+        dict.set_item("missing", verified.missing)?;
+        dict.set_item("mismatched", verified.mismatched)?;
+        dict.set_item("extra", verified.extra)?;
+        Ok(dict)
+    }
+
+    /// Stops the running ingest
+    fn cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+
+    /// `(files, bytes, total_bytes)` copied so far by the running or last ingest
+    #[getter]
+    fn progress(&self) -> (usize, u64, u64) {
+        match self
+            .handle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+        {
+            Some(handle) => (handle.files(), handle.bytes(), handle.total_bytes()),
+            None => (0, 0, 0),
+        }
+    }
+}
+
+fn report<'py>(py: Python<'py>, report: &Report) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    let warnings: Vec<String> = report.warnings.iter().map(Warning::to_string).collect();
+    dict.set_item("warnings", warnings)?;
+    let failures: Vec<(&Path, &str)> = report
+        .failures
+        .iter()
+        .map(|failure| (failure.path.as_path(), failure.error.as_str()))
+        .collect();
+    dict.set_item("failures", failures)?;
+    let retried: Vec<&Path> = report
+        .retries
+        .iter()
+        .map(|retry| retry.path.as_path())
+        .collect();
+    dict.set_item("retried", retried)?;
+    dict.set_item("skipped", &report.skipped)?;
+    Ok(dict)
+}
+
+#[pymodule]
+fn ingest(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyFilter>()?;
+    module.add_class::<PyStructure>()?;
+    module.add_class::<PyIngestor>()?;
+    Ok(())
+}
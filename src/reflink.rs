@@ -0,0 +1,62 @@
+//! Block cloning for copies within the same APFS / Btrfs / XFS volume, which completes
+//! instantly and takes no extra space until either copy is modified.
+use std::path::Path;
+
+/// Whether copies are cloned instead of written byte by byte
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Reflink {
+    /// Clone when the filesystem supports it and fall back to a regular copy otherwise
+    #[default]
+    Auto,
+    /// Fail the copy if it can't be cloned
+    Always,
+    Never,
+}
+
+/// Clones `source` to `target`, returning the size of the file.
+/// Fails with [`std::io::ErrorKind::Unsupported`] (or whatever the filesystem says) when it can't
+#[cfg(target_os = "linux")]
+pub(crate) fn clone_file(source: impl AsRef<Path>, target: impl AsRef<Path>) -> std::io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+    let source = std::fs::File::open(source)?;
+    let metadata = source.metadata()?;
+    let target_path = target.as_ref();
+    let target = std::fs::File::create(target_path)?;
+    // SAFETY: both descriptors are open for the duration of the call
+    if unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } != 0 {
+        let e = std::io::Error::last_os_error();
+        drop(target);
+        std::fs::remove_file(target_path).ok();
+        return Err(e);
+    }
+    target.set_permissions(metadata.permissions())?;
+    Ok(metadata.len())
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn clone_file(source: impl AsRef<Path>, target: impl AsRef<Path>) -> std::io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let (source, target) = (source.as_ref(), target.as_ref());
+    let src = CString::new(source.as_os_str().as_bytes())?;
+    let dst = CString::new(target.as_os_str().as_bytes())?;
+    // clonefile refuses to overwrite
+    match std::fs::remove_file(target) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => (),
+    }
+    // SAFETY: both paths are valid nul terminated strings
+    if unsafe { libc::clonefile(src.as_ptr(), dst.as_ptr(), 0) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(source.metadata()?.len())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn clone_file(_source: impl AsRef<Path>, _target: impl AsRef<Path>) -> std::io::Result<u64> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Cloning files is not supported on this platform",
+    ))
+}
@@ -0,0 +1,134 @@
+use crate::*;
+
+/// What happened during an ingest that the caller should know about
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Report {
+    /// Same for the target and the backup, see [`Ingestor::run_id`]
+    pub run_id: Uuid,
+    pub warnings: Vec<Warning>,
+    /// Files whose copy had to be retried
+    pub retries: Vec<RetryHistory>,
+    /// Files that couldn't be ingested
+    pub failures: Vec<Failure>,
+    /// Files left out by the pair policy or because they weren't picked
+    pub skipped: Vec<PathBuf>,
+    /// Frames shot in bursts, see [`BurstDetection`]
+    pub bursts: Vec<Burst>,
+    /// Files that look truncated or corrupted at the source, they are still copied
+    #[cfg(feature = "validate")]
+    pub corrupt: Vec<crate::validate::Corrupt>,
+}
+
+impl Report {
+    pub fn warn(&mut self, warning: Warning) {
+        self.warnings.push(warning);
+    }
+
+    pub fn has_warnings(&self) -> bool {
+        !self.warnings.is_empty()
+    }
+
+    pub fn has_failures(&self) -> bool {
+        !self.failures.is_empty()
+    }
+}
+
+/// A file that couldn't be ingested and why
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Failure {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Writes the failed source paths, one per line, to [`FAILURE_MANIFEST`] in `dir`
+pub(crate) fn write_failure_manifest(
+    vfs: &dyn Vfs,
+    dir: impl AsRef<Path>,
+    failures: &[Failure],
+) -> std::io::Result<()> {
+    use std::io::Write;
+    if failures.is_empty() {
+        return Ok(());
+    }
+    let mut manifest = vfs.append(&dir.as_ref().join(FAILURE_MANIFEST))?;
+    for failure in failures {
+        writeln!(manifest, "{}", failure.path.display())?;
+    }
+    Ok(())
+}
+
+/// The errors a file went through before it was copied or given up on
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RetryHistory {
+    pub path: PathBuf,
+    pub errors: Vec<String>,
+    pub succeeded: bool,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Warning {
+    /// A sidecar whose file doesn't exist next to it
+    OrphanSidecar(PathBuf),
+    /// A file was copied but its XMP sidecar couldn't be written
+    Metadata { path: PathBuf, error: String },
+    /// A RAW was copied but its embedded preview couldn't be extracted
+    Preview { path: PathBuf, error: String },
+    /// A file was copied but a post copy hook failed on it
+    Hook { path: PathBuf, error: String },
+    /// A hook run after the ingest or the backup failed
+    Lifecycle { stage: &'static str, error: String },
+    /// A file was copied but its Finder tags or comment couldn't be set
+    Finder { path: PathBuf, error: String },
+    /// A file was copied under another name than its structure gave, which Windows refuses
+    WindowsName { path: PathBuf, destination: PathBuf },
+    /// A cloud placeholder whose contents weren't downloaded was left out
+    Placeholder(PathBuf),
+    /// A file was left out as its contents are already in the target at `existing`
+    Duplicate { path: PathBuf, existing: PathBuf },
+    /// The target or the backup is inside a source, `output` was left out of its walk
+    NestedOutput { source: PathBuf, output: PathBuf },
+    /// A source is inside another source or is the same folder, it was only copied `within` it
+    NestedSource { source: PathBuf, within: PathBuf },
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Warning::OrphanSidecar(path) => {
+                write!(f, "Sidecar without a matching file: {}", path.display())
+            }
+            Warning::Metadata { path, error } => {
+                write!(f, "Couldn't write the metadata of {}: {}", path.display(), error)
+            }
+            Warning::Preview { path, error } => {
+                write!(f, "Couldn't extract the preview of {}: {}", path.display(), error)
+            }
+            Warning::Hook { path, error } => {
+                write!(f, "Post copy hook failed on {}: {}", path.display(), error)
+            }
+            Warning::Lifecycle { stage, error } => write!(f, "{} hook failed: {}", stage, error),
+            Warning::Finder { path, error } => {
+                write!(f, "Couldn't tag {} in the Finder: {}", path.display(), error)
+            }
+            Warning::WindowsName { path, destination } => {
+                write!(f, "Copied {} to {} for Windows", path.display(), destination.display())
+            }
+            Warning::Placeholder(path) => {
+                write!(f, "Skipped {} which is only in the cloud", path.display())
+            }
+            Warning::Duplicate { path, existing } => {
+                write!(f, "Skipped {} which is already at {}", path.display(), existing.display())
+            }
+            Warning::NestedOutput { source, output } => {
+                write!(f, "Left {} out of the source {}", output.display(), source.display())
+            }
+            Warning::NestedSource { source, within } => {
+                write!(f, "Copied the source {} as part of {}", source.display(), within.display())
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+/// How failed copies are retried, eg. for flaky card readers or network targets
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Retry {
+    /// Number of retries after the first attempt
+    pub count: u32,
+    /// Delay before the first retry
+    pub delay: Duration,
+    /// Multiplier applied to the delay after every retry
+    pub backoff: f32,
+}
+
+impl Retry {
+    pub fn new(count: u32) -> Self {
+        Retry {
+            count,
+            ..Default::default()
+        }
+    }
+
+    pub fn none() -> Self {
+        Self::new(0)
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: f32) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Returns the delay before the `attempt`th retry (starting at 0)
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        self.delay
+            .mul_f32(self.backoff.max(1.0).powi(attempt.min(i32::MAX as u32) as i32))
+    }
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            count: 0,
+            delay: Duration::from_millis(500),
+            backoff: 2.0,
+        }
+    }
+}
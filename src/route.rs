@@ -0,0 +1,50 @@
+use crate::*;
+
+/// Subfolders of the target that files are sent to by extension, eg. RAWs to `RAW/` and JPEGs
+/// to `JPEG/`. Files without a route go to the target itself
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Routes {
+    extensions: Vec<(String, PathBuf)>,
+    video: Option<PathBuf>,
+}
+
+impl Routes {
+    /// Sends the files with `extension` to `folder`. `raw` matches every RAW extension and `jpg`
+    /// matches `jpeg` as well
+    pub fn add(&mut self, extension: &str, folder: impl AsRef<Path>) -> &mut Self {
+        self.extensions.push((
+            extension.trim_start_matches('.').to_ascii_lowercase(),
+            folder.as_ref().to_path_buf(),
+        ));
+        self
+    }
+
+    /// Sends all the videos to `folder`
+    pub fn video(&mut self, folder: impl AsRef<Path>) -> &mut Self {
+        self.video = Some(folder.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.extensions.is_empty() && self.video.is_none()
+    }
+
+    /// Returns the subfolder `path` is sent to, the first matching route wins
+    pub fn route(&self, path: impl AsRef<Path>) -> Option<&Path> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(OsStr::to_ascii_lowercase)
+            .and_then(|ext| ext.into_string().ok())?;
+        self.extensions
+            .iter()
+            .find(|(route, _)| {
+                *route == extension
+                    || (route == "raw" && path.is_raw())
+                    || (route == "jpg" && path.is_jpeg())
+            })
+            .map(|(_, folder)| folder.as_path())
+            .or_else(|| self.video.as_deref().filter(|_| path.is_video()))
+    }
+}
@@ -0,0 +1,275 @@
+//! Stores the copies in an S3 bucket or an S3-compatible object storage like MinIO, R2 or B2,
+//! built with the `s3` feature.
+//!
+//! The files are uploaded under a prefix with the structure they have at the target, larger
+//! ones in parts. Every part is sent with its SHA-256 which the storage checks on receipt, and
+//! the checksum it reports for the object is compared with the one of the file.
+use crate::*;
+use aws_sdk_s3::error::DisplayErrorContext;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{ChecksumAlgorithm, CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::Client;
+use aws_smithy_types::base64;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+
+/// Files larger than this are uploaded in parts of this size
+pub const DEFAULT_PART_SIZE: usize = 16 * 1024 * 1024;
+/// The storages refuse smaller parts, but for the last one
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+/// The storages refuse more parts, the parts get larger for the files that would need more
+const MAX_PARTS: u64 = 10_000;
+
+/// A bucket and a prefix the copies are stored under
+#[derive(Debug, Clone)]
+pub struct S3Bucket {
+    client: Client,
+    bucket: String,
+    prefix: String,
+    part_size: usize,
+    quota: Option<u64>,
+}
+
+impl S3Bucket {
+    /// `prefix` is put before the keys, eg. `2024/wedding`
+    pub fn new(client: Client, bucket: impl Into<String>, prefix: impl AsRef<str>) -> Self {
+        S3Bucket {
+            client,
+            bucket: bucket.into(),
+            prefix: prefix.as_ref().trim_matches('/').to_owned(),
+            part_size: DEFAULT_PART_SIZE,
+            quota: None,
+        }
+    }
+
+    /// Uses the credentials, region and endpoint of the environment like the aws CLI does, eg.
+    /// `AWS_PROFILE` and `AWS_ENDPOINT_URL`
+    pub async fn from_env(bucket: impl Into<String>, prefix: impl AsRef<str>) -> Self {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Self::new(Client::new(&config), bucket, prefix)
+    }
+
+    /// An S3-compatible storage at `endpoint`, eg. `http://nas.local:9000`, with the credentials
+    /// of the environment
+    pub async fn with_endpoint(
+        endpoint: &str,
+        bucket: impl Into<String>,
+        prefix: impl AsRef<str>,
+    ) -> Self {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        let config = aws_sdk_s3::config::Builder::from(&config)
+            .endpoint_url(endpoint)
+            // Most of them don't serve the buckets as subdomains
+            .force_path_style(true)
+            .build();
+        Self::new(Client::from_conf(config), bucket, prefix)
+    }
+
+    /// Parses `s3://bucket/prefix`, at `endpoint` when it's an S3-compatible storage
+    pub async fn from_url(url: &str, endpoint: Option<&str>) -> Result<Self> {
+        let (bucket, prefix) = url
+            .strip_prefix("s3://")
+            .map(|rest| rest.split_once('/').unwrap_or((rest, "")))
+            .filter(|(bucket, _)| !bucket.is_empty())
+            .ok_or_else(|| {
+                Error::custom_error(format!("{} isn't an s3://bucket/prefix URL", url))
+            })?;
+        Ok(match endpoint {
+            Some(endpoint) => Self::with_endpoint(endpoint, bucket, prefix).await,
+            None => Self::from_env(bucket, prefix).await,
+        })
+    }
+
+    /// Upload the files larger than `part_size` in parts, at least 5 MiB
+    pub fn with_part_size(mut self, part_size: usize) -> Self {
+        self.part_size = part_size.max(MIN_PART_SIZE);
+        self
+    }
+
+    /// The most the prefix may hold, the buckets themselves have no limit
+    pub fn with_quota(mut self, quota: u64) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// What's left of the quota once the objects under the prefix are counted
+    async fn left(&self, quota: u64) -> Result<u64> {
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(self.key(""))
+            .into_paginator()
+            .send();
+        let mut used = 0;
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(s3_error)?;
+            used += page
+                .contents()
+                .iter()
+                .filter_map(|object| object.size())
+                .map(|size| size.max(0) as u64)
+                .sum::<u64>();
+        }
+        Ok(quota.saturating_sub(used))
+    }
+
+    fn key(&self, key: &str) -> String {
+        match self.prefix.is_empty() {
+            true => key.to_owned(),
+            false => format!("{}/{}", self.prefix, key),
+        }
+    }
+
+    async fn upload(&self, path: &Path, key: &str) -> Result<()> {
+        let size = tokio::fs::metadata(path).await?.len();
+        let part_size = (self.part_size as u64).max(size.div_ceil(MAX_PARTS));
+        let key = self.key(key);
+        if size <= part_size {
+            self.put(path, &key).await
+        } else {
+            self.put_parts(path, &key, part_size).await
+        }
+    }
+
+    async fn put(&self, path: &Path, key: &str) -> Result<()> {
+        let body = tokio::fs::read(path).await?;
+        let checksum = base64::encode(Sha256::digest(&body));
+        let put = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .checksum_sha256(&checksum)
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .map_err(s3_error)?;
+        verify(key, put.checksum_sha256(), &checksum)
+    }
+
+    async fn put_parts(&self, path: &Path, key: &str, part_size: u64) -> Result<()> {
+        let upload = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .checksum_algorithm(ChecksumAlgorithm::Sha256)
+            .send()
+            .await
+            .map_err(s3_error)?;
+        let id = upload
+            .upload_id()
+            .ok_or_else(|| Error::custom_error(format!("No upload id for {}", key)))?;
+        let uploaded = self.upload_parts(path, key, id, part_size).await;
+        if uploaded.is_err() {
+            // Otherwise the parts are kept, and billed, until a lifecycle rule removes them
+            self.client
+                .abort_multipart_upload()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(id)
+                .send()
+                .await
+                .ok();
+        }
+        uploaded
+    }
+
+    async fn upload_parts(&self, path: &Path, key: &str, id: &str, part_size: u64) -> Result<()> {
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut parts = Vec::new();
+        let mut digests = Vec::new();
+        loop {
+            let mut buffer = Vec::with_capacity(part_size as usize);
+            (&mut file).take(part_size).read_to_end(&mut buffer).await?;
+            if buffer.is_empty() {
+                break;
+            }
+            let digest = Sha256::digest(&buffer);
+            digests.extend_from_slice(&digest);
+            let checksum = base64::encode(digest);
+            let number = parts.len() as i32 + 1;
+            let part = self
+                .client
+                .upload_part()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(id)
+                .part_number(number)
+                .checksum_sha256(&checksum)
+                .body(ByteStream::from(buffer))
+                .send()
+                .await
+                .map_err(s3_error)?;
+            verify(key, part.checksum_sha256(), &checksum)?;
+            parts.push(
+                CompletedPart::builder()
+                    .part_number(number)
+                    .set_e_tag(part.e_tag().map(str::to_owned))
+                    .checksum_sha256(checksum)
+                    .build(),
+            );
+        }
+        // The checksum of an object uploaded in parts is the checksum of the checksums of its
+        // parts followed by their count
+        let checksum = format!(
+            "{}-{}",
+            base64::encode(Sha256::digest(&digests)),
+            parts.len()
+        );
+        let completed = self
+            .client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(s3_error)?;
+        verify(key, completed.checksum_sha256(), &checksum)
+    }
+}
+
+impl Destination for S3Bucket {
+    fn name(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+
+    fn store<'a>(&'a self, path: &'a Path, key: &'a str) -> StoreFuture<'a> {
+        Box::pin(self.upload(path, key))
+    }
+
+    fn capacity(&self) -> SpaceFuture<'_> {
+        Box::pin(async { Ok(self.quota) })
+    }
+
+    fn free(&self) -> SpaceFuture<'_> {
+        Box::pin(async {
+            match self.quota {
+                Some(quota) => self.left(quota).await.map(Some),
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+/// The storages that don't report checksums still checked the one sent along
+fn verify(key: &str, stored: Option<&str>, expected: &str) -> Result<()> {
+    match stored {
+        Some(stored) if stored != expected => Err(Error::custom_error(format!(
+            "The checksum of {} is {} in the bucket instead of {}",
+            key, stored, expected
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn s3_error(error: impl std::error::Error) -> Error {
+    Error::custom_error(DisplayErrorContext(error))
+}
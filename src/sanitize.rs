@@ -0,0 +1,80 @@
+//! Names Windows accepts, for the targets on Windows and the exFAT and NTFS drives shared with it.
+//!
+//! - `< > : " / \ | ? *` and the control characters become `_`
+//! - the trailing dots and spaces Windows drops are trimmed
+//! - the device names `CON`, `PRN`, `AUX`, `NUL`, `COM0`-`COM9` and `LPT0`-`LPT9` get a `_`,
+//!   whatever their extension, `CON_.jpg` for `CON.jpg`
+use crate::*;
+
+const RESERVED: [&str; 4] = ["con", "prn", "aux", "nul"];
+
+/// `name` as Windows accepts it, borrowed when it already does
+pub(crate) fn windows_name(name: &str) -> Cow<'_, str> {
+    let invalid = |c: char| {
+        c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*')
+    };
+    let trimmed = name.trim_end_matches(['.', ' ']);
+    let stem = trimmed.split('.').next().unwrap_or_default().trim_end();
+    if trimmed.len() == name.len()
+        && !trimmed.is_empty()
+        && !name.contains(invalid)
+        && !is_device(stem)
+    {
+        return Cow::Borrowed(name);
+    }
+
+    let mut safe: String = trimmed
+        .chars()
+        .map(|c| if invalid(c) { '_' } else { c })
+        .collect();
+    if is_device(stem) {
+        safe.insert(stem.len(), '_');
+    }
+    if safe.is_empty() {
+        safe.push('_');
+    }
+    Cow::Owned(safe)
+}
+
+fn is_device(stem: &str) -> bool {
+    let stem = stem.to_ascii_lowercase();
+    RESERVED.contains(&stem.as_str())
+        || (stem.len() == 4
+            && (stem.starts_with("com") || stem.starts_with("lpt"))
+            && stem.as_bytes()[3].is_ascii_digit())
+}
+
+/// `path` with every name below `root` made acceptable to Windows, `root` itself is left as given
+pub(crate) fn windows_path(root: &Path, path: &Path) -> PathBuf {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return match path.file_name().and_then(OsStr::to_str) {
+            Some(name) => path.with_file_name(windows_name(name).as_ref()),
+            None => path.to_path_buf(),
+        };
+    };
+    let mut safe = root.to_path_buf();
+    for component in relative.components() {
+        match component.as_os_str().to_str() {
+            Some(name) => safe.push(windows_name(name).as_ref()),
+            None => safe.push(component),
+        }
+    }
+    safe
+}
+
+/// `path` prefixed with `\\?\` when it's too long for the Win32 calls that don't do it themselves,
+/// the standard library already does for its own
+#[cfg(windows)]
+pub(crate) fn long_path(path: &Path) -> Cow<'_, Path> {
+    const MAX_PATH: usize = 260;
+    let long = path.as_os_str().len() >= MAX_PATH;
+    match path.to_str() {
+        Some(unc) if long && unc.starts_with(r"\\") && !unc.starts_with(r"\\?\") => {
+            Cow::Owned(PathBuf::from(format!(r"\\?\UNC\{}", &unc[2..])))
+        }
+        Some(absolute) if long && path.is_absolute() && !absolute.starts_with(r"\\") => {
+            Cow::Owned(PathBuf::from(format!(r"\\?\{}", absolute)))
+        }
+        _ => Cow::Borrowed(path),
+    }
+}
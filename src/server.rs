@@ -0,0 +1,356 @@
+//! Ingests as a service over HTTP, eg. on a studio NAS with the cards read from workstations.
+//!
+//! - `POST /jobs` with the JSON form of an [`IngestConfig`] starts an ingest and returns its id
+//! - `GET /jobs` and `GET /jobs/{id}` return the state, progress and report of the jobs
+//! - `GET /jobs/{id}/events` streams the [`Event`]s of a job as server-sent events
+//! - `POST /jobs/{id}/cancel` stops it
+//!
+//! The requests are only served for the [`Access`] given: with its bearer token when it has one,
+//! and for the hosts it names so a web page can't reach the service through DNS rebinding. The
+//! configs with post copy commands are refused unless it allows them, as anyone who can reach the
+//! service could run them.
+use crate::*;
+use axum::extract::{FromRef, Path as UrlPath, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{self, Sse};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::Stream;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+/// How many events a slow client can lag behind before missing some
+const BACKLOG: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct JobStatus {
+    pub id: u64,
+    pub state: JobState,
+    pub files: usize,
+    pub bytes: u64,
+    pub total_bytes: u64,
+    /// Why the job failed
+    pub error: Option<String>,
+    /// Once the job is over
+    pub report: Option<Report>,
+}
+
+struct Job {
+    /// Set before the ingestor exists so a cancel can't be missed
+    cancel: Arc<AtomicBool>,
+    handle: Option<IngestHandle>,
+    state: JobState,
+    error: Option<String>,
+    report: Option<Report>,
+    /// Dropped once the job is over to end the event streams
+    events: Option<broadcast::Sender<Event>>,
+}
+
+impl Job {
+    fn status(&self, id: u64) -> JobStatus {
+        let handle = self.handle.as_ref();
+        JobStatus {
+            id,
+            state: self.state,
+            files: handle.map(IngestHandle::files).unwrap_or_default(),
+            bytes: handle.map(IngestHandle::bytes).unwrap_or_default(),
+            total_bytes: handle.map(IngestHandle::total_bytes).unwrap_or_default(),
+            error: self.error.clone(),
+            report: self.report.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Jobs {
+    next: u64,
+    jobs: BTreeMap<u64, Job>,
+}
+
+type Shared = Arc<Mutex<Jobs>>;
+
+/// Who the service answers
+#[derive(Debug, Clone, Default)]
+pub struct Access {
+    /// Required as `Authorization: Bearer <token>` on every request when set
+    pub token: Option<String>,
+    /// The names the service is reached at besides `localhost` and the IP addresses, checked
+    /// against the `Host` of the requests
+    pub hosts: Vec<String>,
+    /// Runs the post copy commands of the configs submitted instead of refusing them
+    pub allow_commands: bool,
+}
+
+impl Access {
+    fn allows_host(&self, host: &str) -> bool {
+        // Without the port, which IPv6 addresses keep in brackets
+        let name = match host.rsplit_once(':') {
+            Some((name, port)) if !name.ends_with(':') && port.parse::<u16>().is_ok() => name,
+            _ => host,
+        };
+        let name = name.trim_start_matches('[').trim_end_matches(']');
+        // An address can't be rebound to another machine, only a name can
+        name.eq_ignore_ascii_case("localhost")
+            || name.parse::<std::net::IpAddr>().is_ok()
+            || self
+                .hosts
+                .iter()
+                .any(|host| host.eq_ignore_ascii_case(name))
+    }
+
+    fn allows_token(&self, authorization: Option<&str>) -> bool {
+        let Some(ref token) = self.token else {
+            return true;
+        };
+        let Some(given) = authorization.and_then(|value| value.strip_prefix("Bearer ")) else {
+            return false;
+        };
+        // In constant time so the token can't be guessed from how long the comparison takes
+        given.len() == token.len()
+            && given
+                .bytes()
+                .zip(token.bytes())
+                .fold(0, |differ, (a, b)| differ | (a ^ b))
+                == 0
+    }
+}
+
+#[derive(Clone)]
+struct Service {
+    jobs: Shared,
+    access: Arc<Access>,
+}
+
+impl FromRef<Service> for Shared {
+    fn from_ref(service: &Service) -> Self {
+        service.jobs.clone()
+    }
+}
+
+impl FromRef<Service> for Arc<Access> {
+    fn from_ref(service: &Service) -> Self {
+        service.access.clone()
+    }
+}
+
+/// Refuses the requests the [`Access`] doesn't allow
+async fn guard(
+    State(access): State<Arc<Access>>,
+    request: Request,
+    next: Next,
+) -> Response<axum::response::Response> {
+    let headers = request.headers();
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    if !header(header::HOST).is_some_and(|host| access.allows_host(host)) {
+        return Err((StatusCode::FORBIDDEN, "Host not allowed".into()));
+    }
+    if !access.allows_token(header(header::AUTHORIZATION)) {
+        return Err((StatusCode::UNAUTHORIZED, "Missing or wrong token".into()));
+    }
+    Ok(next.run(request).await)
+}
+
+fn jobs(shared: &Shared) -> std::sync::MutexGuard<'_, Jobs> {
+    // The jobs stay consistent even if a holder panicked
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The routes of the service for `access`, to serve or nest in another router
+pub fn router(access: Access) -> Router {
+    let service = Service {
+        jobs: Shared::default(),
+        access: Arc::new(access),
+    };
+    Router::new()
+        .route("/jobs", get(list).post(submit))
+        .route("/jobs/{id}", get(status))
+        .route("/jobs/{id}/events", get(events))
+        .route("/jobs/{id}/cancel", post(cancel))
+        .layer(middleware::from_fn_with_state(service.clone(), guard))
+        .with_state(service)
+}
+
+/// Serves the ingests at `address`, eg. `127.0.0.1:7474`
+pub async fn serve(address: impl tokio::net::ToSocketAddrs, access: Access) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    axum::serve(listener, router(access)).await?;
+    Ok(())
+}
+
+type Response<T> = std::result::Result<T, (StatusCode, String)>;
+
+fn not_found(id: u64) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("No job {}", id))
+}
+
+async fn list(State(shared): State<Shared>) -> Json<Vec<JobStatus>> {
+    let jobs = jobs(&shared);
+    Json(jobs.jobs.iter().map(|(&id, job)| job.status(id)).collect())
+}
+
+async fn status(
+    State(shared): State<Shared>,
+    UrlPath(id): UrlPath<u64>,
+) -> Response<Json<JobStatus>> {
+    let jobs = jobs(&shared);
+    let job = jobs.jobs.get(&id).ok_or_else(|| not_found(id))?;
+    Ok(Json(job.status(id)))
+}
+
+async fn cancel(State(shared): State<Shared>, UrlPath(id): UrlPath<u64>) -> Response<StatusCode> {
+    let mut jobs = jobs(&shared);
+    let job = jobs.jobs.get_mut(&id).ok_or_else(|| not_found(id))?;
+    if job.state != JobState::Running {
+        return Err((StatusCode::CONFLICT, format!("Job {} isn't running", id)));
+    }
+    job.cancel.store(true, std::sync::atomic::Ordering::SeqCst);
+    job.state = JobState::Cancelled;
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn events(
+    State(shared): State<Shared>,
+    UrlPath(id): UrlPath<u64>,
+) -> Response<Sse<impl Stream<Item = std::result::Result<sse::Event, axum::Error>>>> {
+    let receiver = {
+        let jobs = jobs(&shared);
+        let job = jobs.jobs.get(&id).ok_or_else(|| not_found(id))?;
+        match job.events {
+            Some(ref events) => events.subscribe(),
+            // Closed right away
+            None => broadcast::channel(1).1,
+        }
+    };
+    let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((sse::Event::default().json_data(event), receiver)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                // The job is over
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(sse::KeepAlive::default()))
+}
+
+async fn submit(
+    State(shared): State<Shared>,
+    State(access): State<Arc<Access>>,
+    Json(config): Json<IngestConfig>,
+) -> Response<(StatusCode, Json<JobStatus>)> {
+    if !config.post_copy_commands.is_empty() && !access.allow_commands {
+        let refused = "Post copy commands aren't allowed by this server";
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, refused.into()));
+    }
+    // Checked before answering so a bad config is an error of the request
+    IngestorBuilder::from_config(&config)
+        .build()
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    let (events, _) = broadcast::channel(BACKLOG);
+    let id = {
+        let mut jobs = jobs(&shared);
+        jobs.next += 1;
+        let id = jobs.next;
+        let job = Job {
+            cancel: Arc::default(),
+            handle: None,
+            state: JobState::Running,
+            error: None,
+            report: None,
+            events: Some(events.clone()),
+        };
+        jobs.jobs.insert(id, job);
+        id
+    };
+    let status = jobs(&shared).jobs[&id].status(id);
+    tokio::spawn(run(shared, id, config, events));
+    Ok((StatusCode::CREATED, Json(status)))
+}
+
+async fn run(shared: Shared, id: u64, config: IngestConfig, events: broadcast::Sender<Event>) {
+    let mut ingestor = match IngestorBuilder::from_config(&config).build() {
+        Ok(ingestor) => ingestor,
+        Err(e) => return finish(&shared, id, None, Err(e)),
+    };
+    ingestor.events.push(move |event: &Event| {
+        events.send(event.clone()).ok();
+    });
+    if let Some(job) = jobs(&shared).jobs.get_mut(&id) {
+        ingestor.cancel = job.cancel.clone();
+        job.handle = Some(ingestor.handle());
+    }
+    let ingested = ingestor.ingest().await;
+    finish(&shared, id, Some(ingestor.report), ingested);
+}
+
+fn finish(shared: &Shared, id: u64, report: Option<Report>, ingested: Result<()>) {
+    let mut jobs = jobs(shared);
+    let Some(job) = jobs.jobs.get_mut(&id) else {
+        return;
+    };
+    match ingested {
+        Ok(()) => job.state = JobState::Finished,
+        // Cancelling fails the ingest
+        Err(_) if job.state == JobState::Cancelled => {}
+        Err(e) => {
+            job.state = JobState::Failed;
+            job.error = Some(e.to_string());
+        }
+    }
+    job.report = report;
+    job.events = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_the_local_and_named_hosts() {
+        let access = Access {
+            hosts: vec!["nas.local".into()],
+            ..Default::default()
+        };
+        for host in [
+            "localhost:7474",
+            "127.0.0.1:7474",
+            "[::1]:7474",
+            "10.0.0.2",
+            "NAS.local",
+        ] {
+            assert!(access.allows_host(host), "{}", host);
+        }
+        for host in [
+            "evil.example:7474",
+            "127.0.0.1.evil.example",
+            "nas.local.evil.example",
+        ] {
+            assert!(!access.allows_host(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn checks_the_token() {
+        assert!(Access::default().allows_token(None));
+        let access = Access {
+            token: Some("secret".into()),
+            ..Default::default()
+        };
+        assert!(access.allows_token(Some("Bearer secret")));
+        assert!(!access.allows_token(Some("Bearer secreT")));
+        assert!(!access.allows_token(Some("secret")));
+        assert!(!access.allows_token(None));
+    }
+}
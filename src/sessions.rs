@@ -0,0 +1,75 @@
+use crate::*;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// Files split into sessions wherever there's a gap of more than `gap` between two consecutive
+/// capture times, eg. the ceremony and the reception of a wedding on the same card.
+///
+/// The capture time comes from the EXIF with the `exif` feature, otherwise and for the files
+/// without one it's the modification time which cameras set when the file is written
+#[derive(Debug, Clone, Default)]
+pub struct Sessions {
+    sessions: HashMap<PathBuf, usize>,
+}
+
+impl Sessions {
+    pub fn resolve<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>, gap: Duration) -> Self {
+        Self::resolve_in(&vfs::RealFs, paths, gap)
+    }
+
+    /// Same as [`Sessions::resolve`] with the modification times of `vfs`
+    pub fn resolve_in<P: AsRef<Path>>(
+        vfs: &dyn Vfs,
+        paths: impl IntoIterator<Item = P>,
+        gap: Duration,
+    ) -> Self {
+        let times = paths.into_iter().filter_map(|path| {
+            let path = path.as_ref();
+            Some((order::capture_time(vfs, path, None)?, path.to_path_buf()))
+        });
+        Self::from_times(times, gap)
+    }
+
+    /// Same as [`Sessions::resolve`] with capture times already known
+    pub(crate) fn from_times(
+        times: impl IntoIterator<Item = (SystemTime, PathBuf)>,
+        gap: Duration,
+    ) -> Self {
+        let mut times: Vec<(SystemTime, PathBuf)> = times.into_iter().collect();
+        times.sort();
+
+        let mut sessions = HashMap::new();
+        let mut session = 1;
+        let mut last = None;
+        for (time, path) in times {
+            if let Some(last) = last {
+                if time.duration_since(last).unwrap_or_default() > gap {
+                    session += 1;
+                }
+            }
+            last = Some(time);
+            sessions.insert(path, session);
+        }
+        Sessions { sessions }
+    }
+
+    /// Returns the session of `path` starting from 1
+    pub fn session(&self, path: impl AsRef<Path>) -> Option<usize> {
+        self.sessions.get(path.as_ref()).copied()
+    }
+
+    /// Returns the folder of the session `path` belongs to, eg. `Session-02`
+    pub fn folder(&self, path: impl AsRef<Path>) -> Option<String> {
+        self.session(path)
+            .map(|session| format!("Session-{:02}", session))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Number of sessions
+    pub fn len(&self) -> usize {
+        self.sessions.values().max().copied().unwrap_or_default()
+    }
+}
@@ -0,0 +1,204 @@
+//! Stores the copies on a server over SFTP, built with the `sftp` feature on unix. The connection
+//! goes through the system's `ssh` so the hosts, keys and agent set up in `~/.ssh` apply, and the
+//! server has to be in `known_hosts` already.
+//!
+//! Every file is written next to its final path with the [`PART_EXTENSION`] and renamed once its
+//! size was checked. An upload that was interrupted, eg. by a dropped connection, picks up where
+//! the partial file ends on the next attempt.
+use crate::*;
+use openssh::{KnownHosts, Session};
+use openssh_sftp_client::fs::Fs;
+use openssh_sftp_client::{Sftp, SftpOptions};
+use std::io::SeekFrom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::Mutex;
+
+/// A folder on a server the copies are stored under
+pub struct SftpServer {
+    name: String,
+    destination: String,
+    root: PathBuf,
+    /// Connected on the first upload, and again after a failed one
+    sftp: Mutex<Option<Arc<Sftp>>>,
+}
+
+impl SftpServer {
+    /// `destination` is a host of the ssh config or `user@host`, `root` the folder on the server
+    pub fn new(destination: impl Into<String>, root: impl Into<PathBuf>) -> Self {
+        let (destination, root) = (destination.into(), root.into());
+        SftpServer {
+            name: format!("{}:{}", destination, root.display()),
+            destination,
+            root,
+            sftp: Mutex::new(None),
+        }
+    }
+
+    /// Parses `sftp://user@host:port/folder`
+    pub fn from_url(url: &str) -> Result<Self> {
+        let (host, root) = url
+            .strip_prefix("sftp://")
+            .map(|rest| rest.split_once('/').unwrap_or((rest, "")))
+            .filter(|(host, _)| !host.is_empty())
+            .ok_or_else(|| {
+                Error::custom_error(format!("{} isn't an sftp://host/folder URL", url))
+            })?;
+        let mut server = Self::new(format!("ssh://{}", host), format!("/{}", root));
+        server.name = url.to_owned();
+        Ok(server)
+    }
+
+    async fn connect(&self) -> Result<Arc<Sftp>> {
+        let mut sftp = self.sftp.lock().await;
+        if let Some(ref sftp) = *sftp {
+            return Ok(sftp.clone());
+        }
+        let session = Session::connect(&self.destination, KnownHosts::Strict)
+            .await
+            .map_err(Error::custom_error)?;
+        let connected = Sftp::from_session(session, SftpOptions::default())
+            .await
+            .map_err(Error::custom_error)?;
+        Ok(sftp.insert(Arc::new(connected)).clone())
+    }
+
+    /// The size and free space of the disk holding the folder, from `df` on the server. None
+    /// when the server has no shell or no `df`, eg. on Windows
+    async fn disk(&self) -> Result<Option<(u64, u64)>> {
+        let session = Session::connect(&self.destination, KnownHosts::Strict)
+            .await
+            .map_err(Error::custom_error)?;
+        let output = session
+            .command("df")
+            .arg("-Pk")
+            .arg(self.root.to_string_lossy())
+            .output()
+            .await;
+        session.close().await.ok();
+        let Ok(output) = output else {
+            return Ok(None);
+        };
+        // The sizes are in KiB on the second line, after the name of the filesystem
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let sizes: Vec<u64> = match stdout.lines().nth(1) {
+            Some(line) => line
+                .split_whitespace()
+                .skip(1)
+                .take(3)
+                .filter_map(|size| size.parse().ok())
+                .collect(),
+            None => return Ok(None),
+        };
+        Ok(match sizes[..] {
+            [total, _, available] => Some((total * 1024, available * 1024)),
+            _ => None,
+        })
+    }
+
+    async fn upload(&self, path: &Path, key: &str) -> Result<()> {
+        let sftp = self.connect().await?;
+        let uploaded = self.upload_with(&sftp, path, key).await;
+        if uploaded.is_err() {
+            // The connection may be what failed, the next attempt starts a new one
+            *self.sftp.lock().await = None;
+        }
+        uploaded
+    }
+
+    async fn upload_with(&self, sftp: &Sftp, path: &Path, key: &str) -> Result<()> {
+        let remote = self.root.join(key);
+        let partial = crate::part_path(&remote);
+        let mut fs = sftp.fs();
+        if let Some(parent) = remote.parent() {
+            create_dir_all(&mut fs, parent).await;
+        }
+
+        let size = tokio::fs::metadata(path).await?.len();
+        let mut file = sftp
+            .options()
+            .write(true)
+            .create(true)
+            .open(&partial)
+            .await
+            .map_err(Error::custom_error)?;
+        let mut offset = file
+            .metadata()
+            .await
+            .map_err(Error::custom_error)?
+            .len()
+            .unwrap_or_default();
+        // Left behind by another file
+        if offset > size {
+            file.set_len(0).await.map_err(Error::custom_error)?;
+            offset = 0;
+        }
+        let mut local = tokio::fs::File::open(path).await?;
+        local.seek(SeekFrom::Start(offset)).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        let mut buffer = vec![0; DEFAULT_BUFFER_SIZE];
+        loop {
+            let read = local.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            file.write_all(&buffer[..read])
+                .await
+                .map_err(Error::custom_error)?;
+        }
+        let written = file
+            .metadata()
+            .await
+            .map_err(Error::custom_error)?
+            .len()
+            .unwrap_or_default();
+        file.close().await.map_err(Error::custom_error)?;
+        if written != size {
+            return Err(Error::custom_error(format!(
+                "{} is {} bytes on the server instead of {}",
+                partial.display(),
+                written,
+                size
+            )));
+        }
+        fs.rename(&partial, &remote)
+            .await
+            .map_err(Error::custom_error)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for SftpServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("SftpServer").field(&self.name).finish()
+    }
+}
+
+impl Destination for SftpServer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn store<'a>(&'a self, path: &'a Path, key: &'a str) -> StoreFuture<'a> {
+        Box::pin(self.upload(path, key))
+    }
+
+    fn capacity(&self) -> SpaceFuture<'_> {
+        Box::pin(async { Ok(self.disk().await?.map(|(total, _)| total)) })
+    }
+
+    fn free(&self) -> SpaceFuture<'_> {
+        Box::pin(async { Ok(self.disk().await?.map(|(_, free)| free)) })
+    }
+}
+
+/// Creates `dir` and its missing parents, the upload fails later if it still doesn't exist
+async fn create_dir_all(fs: &mut Fs, dir: &Path) {
+    if fs.metadata(dir).await.is_ok() {
+        return;
+    }
+    let mut missing: Vec<&Path> = dir.ancestors().collect();
+    missing.reverse();
+    for dir in missing {
+        fs.create_dir(dir).await.ok();
+    }
+}
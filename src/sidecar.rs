@@ -0,0 +1,205 @@
+use crate::*;
+
+/// Sidecar extensions carried along with the file they belong to.
+/// The match is case insensitive so `IMG_0001.XMP` is picked up the same as `IMG_0001.xmp`.
+pub const SIDECAR_EXTENSIONS: [&str; 10] = [
+    "xmp", "aae", "on1", "pp3", "dop", "cos", "thm", "lrv", "lrf", "srt",
+];
+
+/// Which sidecars to copy along with each ingested file.
+///
+/// A sidecar either shares the stem of its file (`IMG_0001.xmp`) or appends to its full name
+/// (`IMG_0001.NEF.pp3`), both are matched. So are the GoPro proxies, see [`same_family`].
+#[derive(Debug, Clone)]
+pub struct SidecarPolicy<'sidecar> {
+    pub extensions: Cow<'sidecar, [&'sidecar str]>,
+}
+
+impl<'sidecar> SidecarPolicy<'sidecar> {
+    /// Don't copy any sidecars
+    pub fn none() -> Self {
+        SidecarPolicy {
+            extensions: Cow::Borrowed(&[]),
+        }
+    }
+
+    /// Only copy xmp sidecars
+    pub fn xmp() -> Self {
+        SidecarPolicy {
+            extensions: Cow::Borrowed(&["xmp"]),
+        }
+    }
+
+    /// Copy all the known sidecars
+    pub fn all() -> Self {
+        SidecarPolicy {
+            extensions: Cow::Borrowed(SIDECAR_EXTENSIONS.as_ref()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.extensions.is_empty()
+    }
+
+    /// Whether the path has one of the sidecar extensions of this policy
+    pub fn is_sidecar(&self, path: impl AsRef<Path>) -> bool {
+        path.as_ref()
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(|ext| self.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+            .unwrap_or_default()
+    }
+
+    /// Returns the sidecars of `input` found next to it
+    pub fn sidecars(&self, input: impl AsRef<Path>) -> Vec<PathBuf> {
+        self.sidecars_in(&vfs::RealFs, input.as_ref())
+    }
+
+    pub(crate) fn sidecars_in(&self, vfs: &dyn Vfs, input: &Path) -> Vec<PathBuf> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let (Some(parent), Some(stem), Some(name)) =
+            (input.parent(), input.file_stem(), input.file_name())
+        else {
+            return Vec::new();
+        };
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        let Ok(dir) = vfs.read_dir(parent) else {
+            return Vec::new();
+        };
+        let mut sidecars: Vec<PathBuf> = dir
+            .into_iter()
+            .map(vfs::Entry::into_path)
+            .filter(|path| path.as_path() != input && self.is_sidecar(path))
+            .filter(|path| {
+                path.file_stem()
+                    .map(|s| same_family(s, stem) || s.eq_ignore_ascii_case(name))
+                    .unwrap_or_default()
+            })
+            .collect();
+        sidecars.sort();
+        sidecars
+    }
+
+    /// Returns the file `sidecar` belongs to if it exists next to it
+    pub fn owner(&self, sidecar: impl AsRef<Path>) -> Option<PathBuf> {
+        self.owner_in(&vfs::RealFs, sidecar.as_ref())
+    }
+
+    pub(crate) fn owner_in(&self, vfs: &dyn Vfs, sidecar: &Path) -> Option<PathBuf> {
+        let stem = sidecar.file_stem()?;
+        let parent = match sidecar.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        vfs.read_dir(parent)
+            .ok()?
+            .into_iter()
+            .map(vfs::Entry::into_path)
+            .filter(|path| !self.is_sidecar(path) && vfs.is_file(path))
+            .find(|path| {
+                path.file_name()
+                    .map(|name| name.eq_ignore_ascii_case(stem))
+                    .unwrap_or_default()
+                    || path
+                        .file_stem()
+                        .map(|s| same_family(s, stem))
+                        .unwrap_or_default()
+            })
+    }
+
+    /// Returns the sidecars of `input` along with where they go for `output`
+    pub fn targets(
+        &self,
+        input: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+    ) -> Vec<(PathBuf, PathBuf)> {
+        self.targets_in(&vfs::RealFs, input.as_ref(), output.as_ref())
+    }
+
+    pub(crate) fn targets_in(
+        &self,
+        vfs: &dyn Vfs,
+        input: &Path,
+        output: &Path,
+    ) -> Vec<(PathBuf, PathBuf)> {
+        self.sidecars_in(vfs, input)
+            .into_iter()
+            .filter_map(|sidecar| {
+                let ext = sidecar.extension()?.to_owned();
+                let appended = sidecar
+                    .file_stem()
+                    .zip(input.file_name())
+                    .map(|(stem, name)| stem.eq_ignore_ascii_case(name))
+                    .unwrap_or_default();
+                let own_stem = sidecar
+                    .file_stem()
+                    .zip(input.file_stem())
+                    .map(|(stem, input)| stem.eq_ignore_ascii_case(input))
+                    .unwrap_or_default();
+                let target = if appended {
+                    let mut name = output.file_name()?.to_owned();
+                    name.push(".");
+                    name.push(ext);
+                    output.with_file_name(name)
+                } else if !own_stem && output.file_stem() == input.file_stem() {
+                    // A GoPro proxy keeps its name unless the file is renamed
+                    output.with_file_name(sidecar.file_name()?)
+                } else {
+                    output.with_extension(ext)
+                };
+                Some((sidecar, target))
+            })
+            .collect()
+    }
+}
+
+/// Whether the stems `a` and `b` name the same shot or clip. Besides the same stem in any case,
+/// the GoPro proxies (`GL010001.LRV`) go with their clip (`GX010001.MP4` or `GH010001.MP4`)
+pub(crate) fn same_family(a: &OsStr, b: &OsStr) -> bool {
+    a.eq_ignore_ascii_case(b)
+        || gopro_clip(a)
+            .zip(gopro_clip(b))
+            .is_some_and(|(a, b)| a == b)
+}
+
+/// The chapter and number of a GoPro clip, `010001` for `GX010001` and its proxy `GL010001`
+fn gopro_clip(stem: &OsStr) -> Option<&str> {
+    let stem = stem.to_str()?;
+    let (prefix, clip) = (stem.get(..2)?, stem.get(2..)?);
+    let gopro = ["GX", "GH", "GL"]
+        .iter()
+        .any(|p| prefix.eq_ignore_ascii_case(p));
+    (gopro && clip.len() == 6 && clip.bytes().all(|b| b.is_ascii_digit())).then_some(clip)
+}
+
+impl<'sidecar> Default for SidecarPolicy<'sidecar> {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Returns the sidecars in the sources whose file is missing along with their source
+    pub fn orphan_sidecars(&self) -> Vec<(&'ingest Path, vfs::Entry)> {
+        let sources: Vec<&'ingest Path> = self.sources.iter().copied().collect();
+        let mut orphans = Vec::new();
+        walk::walk_orphans(
+            self.vfs(),
+            &sources,
+            &self.scope(),
+            self.filter.ignore_hidden,
+            &self.sidecars,
+            |index, entry| {
+                orphans.push((sources[index], entry));
+                true
+            },
+        );
+        orphans
+    }
+}
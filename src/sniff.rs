@@ -0,0 +1,69 @@
+//! What a file is from its first bytes, for the recorders and cameras that write files without an
+//! extension or with a numeric one, eg. `CLIP` or `TAKE.001`
+use crate::*;
+use std::io::Read;
+
+/// Bytes read at the start of a file, enough for the MPEG-TS packets
+const HEAD: usize = 200;
+
+/// The TIFF based formats, which only differ by their maker notes
+const TIFF: [&str; 22] = [
+    "tif", "tiff", "nef", "nrw", "arw", "srf", "sr2", "cr2", "dng", "orf", "rw2", "rwl", "pef",
+    "srw", "3fr", "erf", "mef", "mos", "dcr", "iiq", "gpr", "fff",
+];
+
+/// Whether `path` has no extension or a numeric one, eg. `.001` for a split recording
+pub(crate) fn lacks_extension(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().chars().all(|c| c.is_ascii_digit()))
+        .unwrap_or(true)
+}
+
+/// The extensions the contents of `path` could have, empty when they aren't recognized
+pub(crate) fn extensions_in(vfs: &dyn Vfs, path: &Path) -> Result<&'static [&'static str]> {
+    let mut head = Vec::with_capacity(HEAD);
+    vfs.open(path)?.take(HEAD as u64).read_to_end(&mut head)?;
+    Ok(extensions(&head))
+}
+
+/// The extensions a file starting with `head` could have
+pub(crate) fn extensions(head: &[u8]) -> &'static [&'static str] {
+    let magic = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+    match () {
+        _ if magic(0, &[0xff, 0xd8, 0xff]) => &["jpg", "jpeg"],
+        _ if magic(0, b"\x89PNG\r\n\x1a\n") => &["png"],
+        _ if magic(0, b"II*\0") || magic(0, b"MM\0*") => &TIFF,
+        // Olympus and Panasonic mark the byte order their own way
+        _ if magic(0, b"IIRO") || magic(0, b"IIRS") => &["orf"],
+        _ if magic(0, b"IIU\0") => &["rw2", "rwl"],
+        _ if magic(0, b"FUJIFILMCCD-RAW") => &["raf"],
+        _ if magic(6, b"HEAPCCDR") => &["crw"],
+        _ if magic(0, b"FOVb") => &["x3f"],
+        _ if magic(0, b"\0MRM") => &["mrw"],
+        _ if magic(4, b"ftyp") => brand(head.get(8..12).unwrap_or_default()),
+        _ if magic(0, b"RIFF") && magic(8, b"WAVE") => &["wav"],
+        _ if magic(0, b"RIFF") && magic(8, b"AVI ") => &["avi"],
+        _ if magic(0, &[0x06, 0x0e, 0x2b, 0x34]) => &["mxf"],
+        _ if magic(0, &[0x1a, 0x45, 0xdf, 0xa3]) => &["mkv"],
+        _ if magic(0, b"fLaC") => &["flac"],
+        _ if magic(0, b"ID3") || magic(0, &[0xff, 0xfb]) => &["mp3"],
+        // 188 byte transport stream packets, behind a 4 byte timecode in M2TS
+        _ if magic(0, &[0x47]) && magic(188, &[0x47]) => &["mts", "m2ts"],
+        _ if magic(4, &[0x47]) && magic(196, &[0x47]) => &["m2ts", "mts"],
+        _ => &[],
+    }
+}
+
+/// The extensions of the ISO media files of `brand`
+fn brand(brand: &[u8]) -> &'static [&'static str] {
+    match brand {
+        b"crx " => &["cr3"],
+        b"heic" | b"heix" | b"heim" | b"heis" | b"mif1" | b"msf1" => &["heic", "heif", "hif"],
+        b"avif" | b"avis" => &["avif"],
+        b"qt  " => &["mov"],
+        b"3gp4" | b"3gp5" | b"3g2a" => &["3gp"],
+        b"M4V " => &["m4v", "mp4"],
+        b"M4A " => &["m4a"],
+        _ => &["mp4", "mov", "m4v", "insv"],
+    }
+}
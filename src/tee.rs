@@ -0,0 +1,280 @@
+//! Offloads that read every file of the cards once, writing it to the target and the backup and
+//! hashing it as it's read, instead of a second pass over the cards for the backup.
+//!
+//! The backup mirrors the names and folders of the target, and numbers the names another file
+//! already holds there like the target does. Both copies are read back and checked against the
+//! hash of what was read from the card, with the algorithm of the manifests or xxh128. The XMP
+//! metadata and the previews are written into the copies after they're made, so those ingests
+//! still go through a second pass
+use crate::*;
+use std::io::{Read, Write};
+
+/// Where the chunks of the source come from
+enum Reader {
+    Cached(std::fs::File, Vec<u8>),
+    Uncached(nocache::Uncached),
+}
+
+impl Reader {
+    fn read(&mut self) -> std::io::Result<&[u8]> {
+        match self {
+            Reader::Cached(file, buffer) => {
+                let read = loop {
+                    match file.read(buffer) {
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        read => break read?,
+                    }
+                };
+                Ok(&buffer[..read])
+            }
+            Reader::Uncached(uncached) => uncached.read(),
+        }
+    }
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Whether the copies are written to the backup in the same read as to the target
+    pub(crate) fn tees(&self) -> bool {
+        self.tee
+            && self.backup.is_some()
+            && self.vfs.is_none()
+            && !self.writes_xmp()
+            && !self.extracts_previews()
+    }
+
+    #[cfg(feature = "previews")]
+    fn extracts_previews(&self) -> bool {
+        self.previews.is_some()
+    }
+
+    #[cfg(not(feature = "previews"))]
+    fn extracts_previews(&self) -> bool {
+        false
+    }
+
+    /// Where the copy at `output` in the target goes in the backup, when they're teed
+    pub(crate) fn mirror(&self, output: &Path) -> Option<PathBuf> {
+        let backup = self.backup.as_ref().filter(|_| self.tees())?;
+        Some(backup.join(output.strip_prefix(&self.target).ok()?))
+    }
+
+    /// The algorithms the copies are hashed with as they're read, the ones of the manifests
+    fn tee_algos(&self) -> Vec<HashAlgo> {
+        let mut algos = self.manifest_algos();
+        if algos.is_empty() {
+            algos.push(HashAlgo::default());
+        }
+        algos
+    }
+
+    /// Where the copy of `input` at `output` goes in the backup when the files are teed, claimed
+    /// like the target when it's taken: the conflict resolver decides and without one it's
+    /// numbered. `None` when the backup copy is skipped, along with whether it was claimed
+    pub(crate) fn claim_mirror(
+        &mut self,
+        input: &Path,
+        output: &Path,
+    ) -> Result<Option<(PathBuf, bool)>> {
+        let Some(mirror) = self.mirror(output) else {
+            return Ok(None);
+        };
+        if let Some(parent) = mirror.parent() {
+            self.vfs().create_dir_all(parent)?;
+        }
+        let resolution = match self.numbered(&mirror, &[]) != mirror {
+            true => self.conflicts.resolve(Conflict::Exists {
+                source: input.to_path_buf(),
+                destination: mirror.clone(),
+            }),
+            false => None,
+        };
+        match resolution {
+            Some(Resolution::Overwrite) => Ok(Some((mirror, false))),
+            Some(Resolution::Skip) => Ok(None),
+            Some(Resolution::Abort) => Err(Error::custom_error("Ingest aborted")),
+            _ => Ok(Some((self.claim(&mirror, &[])?, true))),
+        }
+    }
+
+    /// The copy of `input` to `output` and `mirror` in a single read, see [`Tee::copy`]
+    pub(crate) fn tee(&self, input: &Path, output: &Path, mirror: &Path) -> Tee {
+        Tee {
+            input: input.to_path_buf(),
+            copies: [output.to_path_buf(), mirror.to_path_buf()],
+            staged: [self.staging(output), self.staging(mirror)],
+            chunk_size: self.chunk_size(),
+            no_cache: self.no_cache,
+            preallocate: self.preallocate,
+            atomic: self.atomic,
+            durability: self.durability,
+            preserve_metadata: self.preserve_metadata,
+            timestamps: self.timestamps,
+            algos: self.tee_algos(),
+            #[cfg(target_os = "macos")]
+            hide_partial: self.hides_partial(),
+        }
+    }
+
+    /// Remembers the copies of a teed file for the manifests and the names of the target and the
+    /// backup
+    pub(crate) fn record_tee(
+        &mut self,
+        input: &Path,
+        output: &Path,
+        mirror: &Path,
+        hashes: Vec<(HashAlgo, String)>,
+    ) -> Result<()> {
+        self.written(mirror);
+        let name = self
+            .backup
+            .as_ref()
+            .and_then(|backup| mirror.strip_prefix(backup).ok());
+        if let Some(name) = name {
+            self.__mirror_names
+                .insert(input.to_path_buf(), name.to_path_buf());
+        }
+        if self.mhl.is_none() && self.checksums.is_none() {
+            return Ok(());
+        }
+        for (copy, hashed) in [(output, &mut self.__hashed), (mirror, &mut self.__mirrored)] {
+            let metadata = std::fs::metadata(copy)?;
+            hashed.push(HashedFile {
+                path: copy.to_path_buf(),
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+                hashes: hashes.clone(),
+                hashed_at: std::time::SystemTime::now(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Writes the manifests of the backup the files were teed to, once it's the target
+    pub(crate) fn finish_tee(&mut self) -> Result<()> {
+        self.__hashed = std::mem::take(&mut self.__mirrored);
+        self.__names = std::mem::take(&mut self.__mirror_names);
+        self.save_names(0)?;
+        self.write_manifests()
+    }
+}
+
+/// The part of a teed copy that goes through the files, taken out of the ingestor so it can run
+/// on the blocking pool
+pub(crate) struct Tee {
+    input: PathBuf,
+    copies: [PathBuf; 2],
+    staged: [PathBuf; 2],
+    chunk_size: usize,
+    no_cache: bool,
+    preallocate: bool,
+    atomic: bool,
+    durability: Durability,
+    preserve_metadata: bool,
+    timestamps: Timestamps,
+    algos: Vec<HashAlgo>,
+    #[cfg(target_os = "macos")]
+    hide_partial: bool,
+}
+
+impl Tee {
+    /// Copies the input to the target and the backup and checks both copies, telling `progress`
+    /// about every chunk read, the copy stops when it fails. Returns the hashes of the file
+    pub(crate) fn copy(
+        &self,
+        progress: &mut dyn FnMut(u64) -> Result<()>,
+    ) -> Result<Vec<(HashAlgo, String)>> {
+        let teed = self.copy_contents(progress);
+        if teed.is_err() {
+            for staged in &self.staged {
+                std::fs::remove_file(staged).ok();
+            }
+        }
+        let hashes = teed?;
+        for (staged, copy) in self.staged.iter().zip(&self.copies) {
+            if self.atomic {
+                std::fs::rename(staged, copy)?;
+                #[cfg(target_os = "macos")]
+                self.hide(copy, false)?;
+            }
+            if self.durability.syncs_folders() {
+                crate::sync_dir(copy)?;
+            }
+            if self.preserve_metadata {
+                preserve::copy_attributes(&self.input, copy)?;
+            }
+            self.timestamps.apply(&self.input, copy)?;
+            // Last since read-only permissions would get in the way of the rest
+            if self.preserve_metadata {
+                preserve::copy_permissions(&self.input, copy)?;
+            }
+        }
+        Ok(hashes)
+    }
+
+    fn copy_contents(
+        &self,
+        progress: &mut dyn FnMut(u64) -> Result<()>,
+    ) -> Result<Vec<(HashAlgo, String)>> {
+        let (input, staged) = (&self.input, &self.staged);
+        let mut reader = match self.no_cache {
+            true => Reader::Uncached(nocache::Uncached::open(input, self.chunk_size)?),
+            false => Reader::Cached(std::fs::File::open(input)?, vec![0; self.chunk_size]),
+        };
+        let metadata = std::fs::metadata(input)?;
+        let mut writers = Vec::new();
+        for staged in staged {
+            let writer = std::fs::File::create(staged)?;
+            #[cfg(target_os = "macos")]
+            self.hide(staged, true).ok();
+            if self.preallocate {
+                preallocate::preallocate(&writer, metadata.len())?;
+            }
+            writers.push(writer);
+        }
+        let algos = &self.algos;
+        let mut hashers: Vec<Hasher> = algos.iter().map(HashAlgo::hasher).collect();
+        loop {
+            let chunk = reader.read()?;
+            if chunk.is_empty() {
+                break;
+            }
+            for writer in &mut writers {
+                writer.write_all(chunk)?;
+            }
+            hashers.iter_mut().for_each(|hasher| hasher.update(chunk));
+            progress(chunk.len() as u64)?;
+        }
+        for writer in &mut writers {
+            writer.flush()?;
+            if self.durability.syncs_files() {
+                writer.sync_all()?;
+            }
+        }
+        std::fs::set_permissions(&staged[0], metadata.permissions())?;
+        std::fs::set_permissions(&staged[1], metadata.permissions())?;
+
+        let hashes: Vec<String> = hashers.into_iter().map(Hasher::finish).collect();
+        for staged in staged {
+            let written = algos[0].hash_file(staged)?;
+            if written != hashes[0] {
+                return Err(Error::custom_error(format!(
+                    "{} doesn't match {}, {} {} instead of {}",
+                    staged.display(),
+                    input.display(),
+                    algos[0].name(),
+                    written,
+                    hashes[0]
+                )));
+            }
+        }
+        Ok(algos.iter().copied().zip(hashes).collect())
+    }
+
+    #[cfg(target_os = "macos")]
+    fn hide(&self, path: &Path, hidden: bool) -> std::io::Result<()> {
+        match self.hide_partial {
+            true => finder::hide(path, hidden),
+            false => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Publishes the health of the ingests through the [`metrics`] facade, built with the `metrics`
+//! feature, to whichever recorder the app installed. The `prometheus` feature adds one serving
+//! them over HTTP for the dashboards of a studio server running the daemon.
+//!
+//! - `ingest_bytes_copied_total` and `ingest_files_copied_total` count the copies that succeeded
+//! - `ingest_failures_total` counts the copies that failed, after their retries
+//! - `ingest_active` is the number of ingests running
+//! - `ingest_files_per_second` and `ingest_bytes_per_second` are the speeds of the latest ingest
+//!   to copy a file, with several ingests at once `rate()` over the counters is more telling
+use crate::*;
+use metrics::{counter, describe_counter, describe_gauge, gauge, Unit};
+use std::time::Duration;
+
+pub const BYTES_COPIED: &str = "ingest_bytes_copied_total";
+pub const FILES_COPIED: &str = "ingest_files_copied_total";
+pub const FAILURES: &str = "ingest_failures_total";
+pub const ACTIVE: &str = "ingest_active";
+pub const FILES_PER_SECOND: &str = "ingest_files_per_second";
+pub const BYTES_PER_SECOND: &str = "ingest_bytes_per_second";
+
+/// Tells the recorder what the metrics are, the exporters that support it show it next to them
+pub fn describe() {
+    describe_counter!(BYTES_COPIED, Unit::Bytes, "Bytes copied by the ingests");
+    describe_counter!(FILES_COPIED, Unit::Count, "Files copied by the ingests");
+    describe_counter!(
+        FAILURES,
+        Unit::Count,
+        "Copies that failed after their retries"
+    );
+    describe_gauge!(ACTIVE, Unit::Count, "Ingests running");
+    describe_gauge!(
+        FILES_PER_SECOND,
+        "Files copied per second by the latest ingest"
+    );
+    describe_gauge!(
+        BYTES_PER_SECOND,
+        Unit::Bytes,
+        "Bytes copied per second by the latest ingest"
+    );
+}
+
+/// Serves the metrics at `address` in the Prometheus text format, eg. `0.0.0.0:9474/metrics`.
+/// Must be called from within a tokio runtime, and only once as it installs the recorder
+#[cfg(feature = "prometheus")]
+pub fn serve_prometheus(address: impl Into<std::net::SocketAddr>) -> Result<()> {
+    metrics_exporter_prometheus::PrometheusBuilder::new()
+        .with_http_listener(address)
+        .install()
+        .map_err(Error::custom_error)?;
+    describe();
+    Ok(())
+}
+
+/// Counts an ingest as running for as long as it's alive
+pub(crate) struct Active(());
+
+impl Active {
+    pub(crate) fn start() -> Self {
+        gauge!(ACTIVE).increment(1.0);
+        Active(())
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        gauge!(ACTIVE).decrement(1.0);
+    }
+}
+
+/// Records the outcome of a copy and the speeds of the ingest it belongs to
+pub(crate) fn copy_finished(handle: &IngestHandle, elapsed: Duration, copied: &Result<u64>) {
+    match copied {
+        Ok(bytes) => {
+            counter!(BYTES_COPIED).increment(*bytes);
+            counter!(FILES_COPIED).increment(1);
+        }
+        Err(_) => counter!(FAILURES).increment(1),
+    }
+    if !elapsed.is_zero() {
+        gauge!(FILES_PER_SECOND).set(handle.files() as f64 / elapsed.as_secs_f64());
+    }
+    gauge!(BYTES_PER_SECOND).set(handle.speed());
+}
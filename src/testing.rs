@@ -0,0 +1,212 @@
+//! A filesystem that fails on purpose, built with the `testing` feature, for the integration tests
+//! of apps that want to see their retries and verification at work without a flaky card reader.
+//!
+//! Hand a [`FaultyFs`] to [`IngestorBuilder::with_vfs`], eg. failing the first write with
+//! [`std::io::ErrorKind::TimedOut`] to see the copy retried, or flipping a byte of it to see the
+//! verification catch it.
+use crate::*;
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// What goes wrong, the writes are counted from 1 across all the files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The nth write fails with `kind`, the ones after it go through
+    FailWrite { nth: usize, kind: io::ErrorKind },
+    /// The nth write goes through with its first byte flipped, as a bad cable would
+    FlipBytes { nth: usize },
+    /// Every read waits `delay` first, as a slow card reader would
+    DelayReads(Duration),
+}
+
+#[derive(Debug, Default)]
+struct State {
+    faults: Vec<Fault>,
+    writes: usize,
+    injected: usize,
+}
+
+impl State {
+    /// Counts a write and returns the fault it runs into
+    fn write(&mut self) -> Option<Fault> {
+        self.writes += 1;
+        let writes = self.writes;
+        let fault = self
+            .faults
+            .iter()
+            .find(|fault| match fault {
+                Fault::FailWrite { nth, .. } | Fault::FlipBytes { nth } => *nth == writes,
+                Fault::DelayReads(_) => false,
+            })
+            .cloned();
+        if fault.is_some() {
+            self.injected += 1;
+        }
+        fault
+    }
+
+    fn read_delay(&self) -> Option<Duration> {
+        self.faults.iter().find_map(|fault| match fault {
+            Fault::DelayReads(delay) => Some(*delay),
+            _ => None,
+        })
+    }
+}
+
+/// Wraps another filesystem, a [`vfs::MemoryFs`] by default, and injects the [`Fault`]s into its
+/// writes and reads. The clones share the faults and counters so one can be handed to the
+/// ingestor and the other asked what happened
+#[derive(Debug, Clone)]
+pub struct FaultyFs<V = vfs::MemoryFs> {
+    inner: V,
+    state: Arc<Mutex<State>>,
+}
+
+impl<V: Vfs> FaultyFs<V> {
+    pub fn new(inner: V) -> Self {
+        FaultyFs {
+            inner,
+            state: Arc::default(),
+        }
+    }
+
+    pub fn with_fault(self, fault: Fault) -> Self {
+        self.state().faults.push(fault);
+        self
+    }
+
+    /// Fails the nth write with `kind`, eg. [`io::ErrorKind::StorageFull`] for a full disk
+    pub fn fail_nth_write(self, nth: usize, kind: io::ErrorKind) -> Self {
+        self.with_fault(Fault::FailWrite { nth, kind })
+    }
+
+    /// Corrupts the nth write without an error, only a verification notices
+    pub fn flip_nth_write(self, nth: usize) -> Self {
+        self.with_fault(Fault::FlipBytes { nth })
+    }
+
+    pub fn delay_reads(self, delay: Duration) -> Self {
+        self.with_fault(Fault::DelayReads(delay))
+    }
+
+    /// The filesystem the faults are injected into
+    pub fn inner(&self) -> &V {
+        &self.inner
+    }
+
+    /// Number of writes so far
+    pub fn writes(&self) -> usize {
+        self.state().writes
+    }
+
+    /// Number of faults injected into the writes so far
+    pub fn injected(&self) -> usize {
+        self.state().injected
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn writer(&self, inner: Box<dyn Write + Send>) -> Box<dyn Write + Send> {
+        Box::new(FaultyWriter {
+            inner,
+            state: self.state.clone(),
+        })
+    }
+}
+
+impl Default for FaultyFs {
+    fn default() -> Self {
+        Self::new(vfs::MemoryFs::new())
+    }
+}
+
+impl<V: Vfs> Vfs for FaultyFs<V> {
+    fn metadata(&self, path: &Path) -> io::Result<vfs::Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<vfs::Entry>> {
+        self.inner.read_dir(dir)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        self.inner.create_dir_all(dir)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        let inner = self.inner.open(path)?;
+        Ok(match self.state().read_delay() {
+            Some(delay) => Box::new(DelayedReader { inner, delay }),
+            None => inner,
+        })
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(self.writer(self.inner.create(path)?))
+    }
+
+    fn append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(self.writer(self.inner.append(path)?))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+
+    fn free_space(&self, path: &Path) -> io::Result<u64> {
+        self.inner.free_space(path)
+    }
+
+    fn same_disk(&self, a: &Path, b: &Path) -> io::Result<bool> {
+        self.inner.same_disk(a, b)
+    }
+}
+
+struct FaultyWriter {
+    inner: Box<dyn Write + Send>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Write for FaultyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let fault = self.state.lock().unwrap_or_else(|e| e.into_inner()).write();
+        match fault {
+            Some(Fault::FailWrite { kind, .. }) => {
+                Err(io::Error::new(kind, "Write failed on purpose"))
+            }
+            Some(Fault::FlipBytes { .. }) if !buf.is_empty() => {
+                let mut flipped = buf.to_vec();
+                flipped[0] = !flipped[0];
+                self.inner.write(&flipped)
+            }
+            _ => self.inner.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct DelayedReader {
+    inner: Box<dyn Read + Send>,
+    delay: Duration,
+}
+
+impl Read for DelayedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        std::thread::sleep(self.delay);
+        self.inner.read(buf)
+    }
+}
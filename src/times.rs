@@ -0,0 +1,102 @@
+use std::fs::{File, FileTimes};
+use std::path::Path;
+
+/// What timestamps the copied files end up with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Timestamps {
+    /// Whatever the platform's copy leaves behind
+    #[default]
+    Default,
+    /// Set the modified, accessed and (on windows / macos) created times to the source's
+    Preserve,
+    /// Set the modified time to when the photo was taken according to its EXIF, falls back to
+    /// [`Timestamps::Preserve`] for the files without one
+    #[cfg(feature = "exif")]
+    CaptureTime,
+}
+
+impl Timestamps {
+    /// Applies the timestamps to `target` after it has been copied from `source`
+    pub fn apply(&self, source: impl AsRef<Path>, target: impl AsRef<Path>) -> std::io::Result<()> {
+        match self {
+            Timestamps::Default => Ok(()),
+            Timestamps::Preserve => copy_times(source, target),
+            #[cfg(feature = "exif")]
+            Timestamps::CaptureTime => match crate::meta::capture_time(&source) {
+                Some(captured) => File::options()
+                    .write(true)
+                    .open(target.as_ref())?
+                    .set_times(FileTimes::new().set_modified(captured)),
+                None => copy_times(source, target),
+            },
+        }
+    }
+}
+
+pub(crate) fn copy_times(source: impl AsRef<Path>, target: impl AsRef<Path>) -> std::io::Result<()> {
+    let metadata = source.as_ref().metadata()?;
+    #[allow(unused_mut)]
+    let mut times = FileTimes::new()
+        .set_modified(metadata.modified()?)
+        .set_accessed(metadata.accessed()?);
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::macos::fs::FileTimesExt;
+        times = times.set_created(metadata.created()?);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileTimesExt;
+        times = times.set_created(metadata.created()?);
+    }
+    File::options()
+        .write(true)
+        .open(target.as_ref())?
+        .set_times(times)
+}
+
+/// Formats `time` as an ISO 8601 UTC date, eg. `2024-06-01T12:00:00+00:00`
+pub(crate) fn iso8601(time: std::time::SystemTime) -> String {
+    let (date, (hours, minutes, seconds)) = utc(time);
+    format!("{}T{:02}:{:02}:{:02}+00:00", date, hours, minutes, seconds)
+}
+
+/// Splits `time` into a `YYYY-MM-DD` date and the time of day in UTC
+pub(crate) fn utc(time: std::time::SystemTime) -> (String, (u64, u64, u64)) {
+    let secs = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400) as u64);
+    let (year, month, day) = civil_from_days(days);
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        (rem / 3600, rem % 3600 / 60, rem % 60),
+    )
+}
+
+/// Howard Hinnant's days to civil date conversion
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Howard Hinnant's civil date to days conversion, the inverse of [`civil_from_days`]
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
@@ -17,6 +17,41 @@ where
             .unwrap_or_default()
     }
 }
+/// JPEG or HEIF, the lossy half a camera writes along with its RAW
+pub trait IsLossyTwin {
+    fn is_lossy_twin(&self) -> bool;
+}
+
+impl<T> IsLossyTwin for T
+where
+    T: AsRef<Path>,
+{
+    fn is_lossy_twin(&self) -> bool {
+        self.as_ref()
+            .extension()
+            .map(OsStr::to_ascii_lowercase)
+            .and_then(|ext| ext.into_string().ok())
+            .map(|ext| crate::TWIN_EXTENSIONS.contains(&ext.as_str()))
+            .unwrap_or_default()
+    }
+}
+pub trait IsRaw {
+    fn is_raw(&self) -> bool;
+}
+
+impl<T> IsRaw for T
+where
+    T: AsRef<Path>,
+{
+    fn is_raw(&self) -> bool {
+        self.as_ref()
+            .extension()
+            .map(OsStr::to_ascii_lowercase)
+            .and_then(|ext| ext.into_string().ok())
+            .map(|ext| crate::RAW_EXTENSIONS.contains(&ext.as_str()))
+            .unwrap_or_default()
+    }
+}
 pub trait IsHidden {
     fn is_hidden(&self) -> bool;
 }
@@ -25,13 +60,14 @@ impl<T> IsHidden for T
 where
     T: AsRef<Path>,
 {
+    /// The hidden attribute on windows, of the file itself and not of what it links to, and a
+    /// leading dot elsewhere
     fn is_hidden(&self) -> bool {
         #[cfg(windows)]
-        use std::os::windows::fs::MetadataExt;
-        #[cfg(windows)]
-        return std::fs::metadata(self.as_ref())
-            .map(|m| m.file_attributes() & 0x02 != 0)
-            .unwrap_or_default();
+        return crate::attributes::windows(self.as_ref()).is_some_and(|attributes| {
+            attributes & windows_sys::Win32::Storage::FileSystem::FILE_ATTRIBUTE_HIDDEN != 0
+        });
+        #[cfg(not(windows))]
         self.as_ref()
             .file_name()
             .and_then(OsStr::to_str)
@@ -39,3 +75,20 @@ where
             .unwrap_or(true)
     }
 }
+pub trait IsVideo {
+    fn is_video(&self) -> bool;
+}
+
+impl<T> IsVideo for T
+where
+    T: AsRef<Path>,
+{
+    fn is_video(&self) -> bool {
+        self.as_ref()
+            .extension()
+            .map(OsStr::to_ascii_lowercase)
+            .and_then(|ext| ext.into_string().ok())
+            .map(|ext| crate::VIDEO_EXTENSIONS.contains(&ext.as_str()))
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,465 @@
+//! Copies through io_uring on Linux with the `uring` feature, for the ingest servers writing to
+//! NVMe arrays where the read and write syscalls of the chunked copy are the bottleneck.
+//!
+//! Every thread keeps a ring with [`QUEUE_DEPTH`] buffers registered with the kernel, so that
+//! many chunks of a file are read and written at once without a syscall each. The copies fall
+//! back to the chunked one when the kernel doesn't have io_uring or forbids it.
+use crate::*;
+use std::cell::RefCell;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// The chunks of a file read or written at once
+const QUEUE_DEPTH: u32 = 8;
+
+const IORING_OFF_SQ_RING: i64 = 0;
+const IORING_OFF_CQ_RING: i64 = 0x8000000;
+const IORING_OFF_SQES: i64 = 0x10000000;
+const IORING_ENTER_GETEVENTS: u32 = 1;
+const IORING_REGISTER_BUFFERS: u32 = 0;
+const IORING_OP_READ_FIXED: u8 = 4;
+const IORING_OP_WRITE_FIXED: u8 = 5;
+
+#[repr(C)]
+#[derive(Default)]
+struct Offsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    // `flags`, `dropped` and `array` of the submission ring, `overflow`, `cqes` and `flags` of
+    // the completion one
+    first: u32,
+    second: u32,
+    third: u32,
+    resv: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: Offsets,
+    cq_off: Offsets,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mmap {
+    fn new(fd: RawFd, len: usize, offset: i64) -> std::io::Result<Self> {
+        // SAFETY: a fresh shared mapping of the ring, unmapped on drop
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd,
+                offset,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Mmap { ptr, len })
+    }
+
+    /// The field at `offset` into the mapping
+    fn at<T>(&self, offset: u32) -> *mut T {
+        // SAFETY: the offsets come from the kernel and are within the mapping
+        unsafe { self.ptr.cast::<u8>().add(offset as usize).cast() }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: mapped in `Mmap::new` with this length
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
+struct Ring {
+    fd: OwnedFd,
+    sq: Mmap,
+    cq: Mmap,
+    sqes: Mmap,
+    params: Params,
+    /// Submitted to the ring but not to the kernel yet
+    queued: u32,
+    /// Failed to submit or wait, with operations that may still be in flight
+    broken: bool,
+    buffers: Vec<Vec<u8>>,
+}
+
+thread_local! {
+    static RING: RefCell<Option<Ring>> = const { RefCell::new(None) };
+}
+
+impl Ring {
+    fn new(chunk: usize) -> std::io::Result<Self> {
+        let mut params = Params::default();
+        // SAFETY: params is a valid io_uring_params the kernel fills in
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_setup,
+                QUEUE_DEPTH,
+                &mut params as *mut Params,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: the descriptor was just returned by the kernel and is owned by nothing else
+        let fd = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+        let raw = fd.as_raw_fd();
+        let sq_len = params.sq_off.third as usize + params.sq_entries as usize * 4;
+        let cq_len = params.cq_off.second as usize + params.cq_entries as usize * 16;
+        let sqes_len = params.sq_entries as usize * std::mem::size_of::<Sqe>();
+        let sq = Mmap::new(raw, sq_len, IORING_OFF_SQ_RING)?;
+        let cq = Mmap::new(raw, cq_len, IORING_OFF_CQ_RING)?;
+        let sqes = Mmap::new(raw, sqes_len, IORING_OFF_SQES)?;
+
+        let mut buffers: Vec<Vec<u8>> = (0..QUEUE_DEPTH).map(|_| vec![0; chunk]).collect();
+        let iovecs: Vec<libc::iovec> = buffers
+            .iter_mut()
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_mut_ptr().cast(),
+                iov_len: buffer.len(),
+            })
+            .collect();
+        // SAFETY: the buffers outlive the ring, which is dropped before them
+        let registered = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_register,
+                raw,
+                IORING_REGISTER_BUFFERS,
+                iovecs.as_ptr(),
+                iovecs.len() as u32,
+            )
+        };
+        if registered < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Ring {
+            fd,
+            sq,
+            cq,
+            sqes,
+            params,
+            queued: 0,
+            broken: false,
+            buffers,
+        })
+    }
+
+    /// Queues a read or write of `len` bytes of `buffer` from `start` at `offset` into `fd`
+    fn push(
+        &mut self,
+        opcode: u8,
+        fd: RawFd,
+        buffer: usize,
+        start: usize,
+        len: usize,
+        offset: u64,
+    ) {
+        let sq_off = &self.params.sq_off;
+        // SAFETY: the mask is constant, the tail is only written by this thread
+        let (mask, tail) = unsafe {
+            (
+                *self.sq.at::<u32>(sq_off.ring_mask),
+                &*self.sq.at::<AtomicU32>(sq_off.tail),
+            )
+        };
+        let index = tail.load(Ordering::Relaxed) & mask;
+        let sqe = Sqe {
+            opcode,
+            fd,
+            off: offset,
+            addr: self.buffers[buffer][start..].as_ptr() as u64,
+            len: len as u32,
+            user_data: buffer as u64,
+            buf_index: buffer as u16,
+            ..Sqe::default()
+        };
+        // SAFETY: the entry at `index` isn't in use, at most QUEUE_DEPTH operations are in flight
+        unsafe {
+            self.sqes.at::<Sqe>(0).add(index as usize).write(sqe);
+            *self.sq.at::<u32>(sq_off.third).add(index as usize) = index;
+        }
+        tail.store(
+            tail.load(Ordering::Relaxed).wrapping_add(1),
+            Ordering::Release,
+        );
+        self.queued += 1;
+    }
+
+    /// Submits the queued operations and waits for at least one of them to complete
+    fn enter(&mut self) -> std::io::Result<()> {
+        loop {
+            // SAFETY: plain syscall on the ring
+            let submitted = unsafe {
+                libc::syscall(
+                    libc::SYS_io_uring_enter,
+                    self.fd.as_raw_fd(),
+                    self.queued,
+                    1,
+                    IORING_ENTER_GETEVENTS,
+                    std::ptr::null::<libc::sigset_t>(),
+                    0,
+                )
+            };
+            if submitted >= 0 {
+                self.queued -= submitted as u32;
+                return Ok(());
+            }
+            let e = std::io::Error::last_os_error();
+            if e.kind() != std::io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+
+    /// The completed operations, as the buffer they used and their result
+    fn reap(&mut self) -> Vec<(usize, i32)> {
+        let cq_off = &self.params.cq_off;
+        // SAFETY: the mask is constant, the head is only written by this thread and the tail by
+        // the kernel
+        let (mask, head, tail) = unsafe {
+            (
+                *self.cq.at::<u32>(cq_off.ring_mask),
+                &*self.cq.at::<AtomicU32>(cq_off.head),
+                &*self.cq.at::<AtomicU32>(cq_off.tail),
+            )
+        };
+        let mut completed = Vec::new();
+        let mut at = head.load(Ordering::Relaxed);
+        while at != tail.load(Ordering::Acquire) {
+            // SAFETY: the entries between head and tail were written by the kernel
+            let cqe = unsafe { &*self.cq.at::<Cqe>(cq_off.second).add((at & mask) as usize) };
+            completed.push((cqe.user_data as usize, cqe.res));
+            at = at.wrapping_add(1);
+        }
+        head.store(at, Ordering::Release);
+        completed
+    }
+}
+
+/// A chunk of the file going through one of the buffers
+#[derive(Clone, Copy, Default)]
+struct Slot {
+    offset: u64,
+    /// Bytes read into the buffer
+    read: usize,
+    /// Bytes of them written
+    written: usize,
+    writing: bool,
+}
+
+/// Copies `reader` into `writer` from their start, calling `progress` with the bytes of every
+/// chunk written. Returns whether the ring was available, nothing was copied when it wasn't
+fn copy(
+    reader: RawFd,
+    writer: RawFd,
+    chunk: usize,
+    mut progress: impl FnMut(u64) -> Result<()>,
+) -> Result<bool> {
+    RING.with(|ring| {
+        let mut ring = ring.borrow_mut();
+        if ring
+            .as_ref()
+            .is_none_or(|ring| ring.buffers[0].len() != chunk)
+        {
+            *ring = None;
+            *ring = Ring::new(chunk).ok();
+        }
+        let Some(running) = ring.as_mut() else {
+            return Ok(false);
+        };
+        let copied = pump(running, reader, writer, chunk, &mut progress);
+        if running.broken {
+            if let Some(mut broken) = ring.take() {
+                // The operations still in flight may read or write the buffers until the kernel
+                // tears the ring down after it's closed, they're leaked instead of freed under it
+                std::mem::forget(std::mem::take(&mut broken.buffers));
+            }
+        }
+        copied.map(|()| true)
+    })
+}
+
+/// Runs the copy on `ring`, which is left broken when it failed to submit or wait
+fn pump(
+    ring: &mut Ring,
+    reader: RawFd,
+    writer: RawFd,
+    chunk: usize,
+    progress: &mut impl FnMut(u64) -> Result<()>,
+) -> Result<()> {
+    let mut slots = [Slot::default(); QUEUE_DEPTH as usize];
+    let (mut next, mut end, mut in_flight) = (0, false, 0);
+    let mut failed: Option<Error> = None;
+    for (buffer, slot) in slots.iter_mut().enumerate() {
+        slot.offset = next;
+        next += chunk as u64;
+        ring.push(IORING_OP_READ_FIXED, reader, buffer, 0, chunk, slot.offset);
+        in_flight += 1;
+    }
+    while in_flight > 0 {
+        if let Err(e) = ring.enter() {
+            ring.broken = true;
+            return Err(e.into());
+        }
+        for (buffer, res) in ring.reap() {
+            in_flight -= 1;
+            let slot = &mut slots[buffer];
+            if res < 0 {
+                failed.get_or_insert(std::io::Error::from_raw_os_error(-res).into());
+                continue;
+            }
+            let res = res as usize;
+            if failed.is_some() {
+                continue;
+            }
+            if slot.writing {
+                if res == 0 {
+                    failed = Some(std::io::Error::from(std::io::ErrorKind::WriteZero).into());
+                    continue;
+                }
+                slot.written += res;
+                if slot.written < slot.read {
+                    let (start, len) = (slot.written, slot.read - slot.written);
+                    let offset = slot.offset + start as u64;
+                    ring.push(IORING_OP_WRITE_FIXED, writer, buffer, start, len, offset);
+                    in_flight += 1;
+                    continue;
+                }
+                if let Err(e) = progress(slot.read as u64) {
+                    failed = Some(e);
+                    continue;
+                }
+                if end {
+                    continue;
+                }
+                *slot = Slot {
+                    offset: next,
+                    ..Slot::default()
+                };
+                next += chunk as u64;
+                ring.push(IORING_OP_READ_FIXED, reader, buffer, 0, chunk, slot.offset);
+                in_flight += 1;
+                continue;
+            }
+            slot.read += res;
+            if res > 0 && slot.read < chunk {
+                // Short read, the rest of the chunk or the end of the file comes next
+                let offset = slot.offset + slot.read as u64;
+                let (start, len) = (slot.read, chunk - slot.read);
+                ring.push(IORING_OP_READ_FIXED, reader, buffer, start, len, offset);
+                in_flight += 1;
+                continue;
+            }
+            end |= res == 0;
+            if slot.read > 0 {
+                slot.writing = true;
+                let (offset, len) = (slot.offset, slot.read);
+                ring.push(IORING_OP_WRITE_FIXED, writer, buffer, 0, len, offset);
+                in_flight += 1;
+            }
+        }
+    }
+    match failed {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Copies `reader` into `writer` through the ring of the thread with the same accounting as
+    /// the chunked copy, returns false when there's no ring to copy with
+    #[cfg(feature = "sync")]
+    pub(crate) fn copy_ring(
+        &self,
+        reader: &impl AsRawFd,
+        writer: &impl AsRawFd,
+        copied: &mut u64,
+    ) -> Result<bool> {
+        let mut throttle = handle::Throttle::new();
+        self.ensure_running()?;
+        copy(
+            reader.as_raw_fd(),
+            writer.as_raw_fd(),
+            self.chunk_size(),
+            |bytes| {
+                self.ensure_running()?;
+                *copied += bytes;
+                self.record_bytes(bytes);
+                let limit = self
+                    .max_throughput
+                    .load(std::sync::atomic::Ordering::SeqCst);
+                if let Some(delay) = throttle.delay(limit, bytes) {
+                    std::thread::sleep(delay);
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Same as the blocking copy through the ring, with the ring of a thread of the blocking pool
+    /// so the copies of the other sources go on meanwhile
+    #[cfg(feature = "async")]
+    pub(crate) async fn copy_ring_pooled(
+        &self,
+        reader: &impl std::os::fd::AsFd,
+        writer: &impl std::os::fd::AsFd,
+        copied: &mut u64,
+    ) -> Result<bool> {
+        self.ensure_running()?;
+        let reader = reader.as_fd().try_clone_to_owned()?;
+        let writer = writer.as_fd().try_clone_to_owned()?;
+        let chunk = self.chunk_size();
+        let (ringed, bytes) = self
+            .copy_blocking(move |progress| {
+                copy(reader.as_raw_fd(), writer.as_raw_fd(), chunk, progress)
+            })
+            .await;
+        *copied += bytes;
+        ringed
+    }
+}
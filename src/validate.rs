@@ -0,0 +1,97 @@
+//! Cheap header checks catching files that are truncated or corrupted on the card, so the
+//! photographer knows before formatting it.
+use crate::*;
+use std::io::{Read, Seek, SeekFrom};
+
+/// How many bytes at the end of a jpeg are searched for the end of image marker, some cameras
+/// pad the files after it
+const JPEG_TAIL: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Corruption {
+    /// The file has no content at all
+    Empty,
+    /// The file doesn't start like its extension says it should
+    BadMagic,
+    /// The file ends before its header says it should
+    Truncated,
+}
+
+impl std::fmt::Display for Corruption {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Corruption::Empty => write!(f, "File is empty"),
+            Corruption::BadMagic => write!(f, "File header doesn't match its extension"),
+            Corruption::Truncated => write!(f, "File is truncated"),
+        }
+    }
+}
+
+/// A file that failed validation
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Corrupt {
+    pub path: PathBuf,
+    pub corruption: Corruption,
+}
+
+/// Checks the header of `path` according to its extension.
+/// Returns `None` for valid files and for formats that aren't known
+pub fn check(path: impl AsRef<Path>) -> std::io::Result<Option<Corruption>> {
+    let path = path.as_ref();
+    let Some(ext) = path
+        .extension()
+        .map(OsStr::to_ascii_lowercase)
+        .and_then(|ext| ext.into_string().ok())
+    else {
+        return Ok(None);
+    };
+
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok(Some(Corruption::Empty));
+    }
+    let mut head = [0u8; 16];
+    let read = file.read(&mut head)?;
+    let head = &head[..read];
+
+    let magic = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+    Ok(match ext.as_str() {
+        "jpg" | "jpeg" => {
+            if !magic(0, &[0xff, 0xd8, 0xff]) {
+                Some(Corruption::BadMagic)
+            } else {
+                let start = len.saturating_sub(JPEG_TAIL);
+                file.seek(SeekFrom::Start(start))?;
+                let mut tail = Vec::with_capacity((len - start) as usize);
+                file.read_to_end(&mut tail)?;
+                (!tail.windows(2).any(|w| w == [0xff, 0xd9])).then_some(Corruption::Truncated)
+            }
+        }
+        "png" => (!magic(0, b"\x89PNG\r\n\x1a\n")).then_some(Corruption::BadMagic),
+        "cr3" | "heic" | "heif" | "hif" | "avif" => {
+            (!magic(4, b"ftyp")).then_some(Corruption::BadMagic)
+        }
+        "raf" => (!magic(0, b"FUJIFILMCCD-RAW")).then_some(Corruption::BadMagic),
+        "crw" => (!magic(6, b"HEAPCCDR")).then_some(Corruption::BadMagic),
+        "x3f" => (!magic(0, b"FOVb")).then_some(Corruption::BadMagic),
+        "mrw" => (!magic(0, b"\0MRM")).then_some(Corruption::BadMagic),
+        "tif" | "tiff" | "nef" | "nrw" | "arw" | "srf" | "sr2" | "cr2" | "dng" | "orf" | "rw2"
+        | "rwl" | "pef" | "srw" | "3fr" | "erf" | "mef" | "mos" | "dcr" | "iiq" | "gpr" => {
+            check_tiff(head, len)
+        }
+        _ => None,
+    })
+}
+
+/// TIFF based RAWs, including the olympus and panasonic variants of the byte order mark
+fn check_tiff(head: &[u8], len: u64) -> Option<Corruption> {
+    let offset = match head.get(..4)? {
+        [b'I', b'I', _, _] => u32::from_le_bytes(head.get(4..8)?.try_into().ok()?),
+        [b'M', b'M', _, _] => u32::from_be_bytes(head.get(4..8)?.try_into().ok()?),
+        _ => return Some(Corruption::BadMagic),
+    };
+    (u64::from(offset) >= len).then_some(Corruption::Truncated)
+}
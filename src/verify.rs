@@ -0,0 +1,262 @@
+use crate::*;
+
+/// How a copy is compared against its source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VerifyMode {
+    Size,
+    /// Compares the sizes first and then the hashes
+    Hash(HashAlgo),
+}
+
+impl Default for VerifyMode {
+    fn default() -> Self {
+        VerifyMode::Hash(HashAlgo::default())
+    }
+}
+
+/// The differences between the sources and a destination
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// Files that match their source
+    pub matched: Vec<(PathBuf, PathBuf)>,
+    /// Source and destination of the files that weren't copied
+    pub missing: Vec<(PathBuf, PathBuf)>,
+    /// Source and destination of the copies that differ from their source
+    pub mismatched: Vec<(PathBuf, PathBuf)>,
+    /// Files at the destination that don't come from any of the sources
+    pub extra: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    /// Whether every source file has an identical copy
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+/// Files the ingestor writes next to the copies which aren't part of the sources
+const BOOKKEEPING: [&str; 4] = [
+    mhl::MHL_FOLDER,
+    FAILURE_MANIFEST,
+    dedup::INDEX_FILE,
+    names::NAMES_FILE,
+];
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Returns where every source file goes at the target, including accompanying jpegs, sidecars
+    /// and orphans. The files an earlier ingest wrote there have the name they were written
+    /// under, the others the one the structure gives them, before any collision suffix
+    pub fn mapping(&self) -> Result<Vec<(PathBuf, PathBuf)>> {
+        self.mapping_at(&self.target)
+    }
+
+    fn mapping_at(&self, root: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let names = names::Names::load(self.vfs(), root);
+        Ok(self
+            .plan()?
+            .mapping(root)
+            .into_iter()
+            .map(|(source, planned)| {
+                let written = names.of(self.vfs(), &source).unwrap_or(planned);
+                (source, written)
+            })
+            .collect())
+    }
+
+    /// Compares every source file with its copy at the target without copying anything
+    pub fn verify(&self, mode: VerifyMode) -> Result<VerifyReport> {
+        self.verify_at(&self.target, mode)
+    }
+
+    /// Same as [`Ingestor::verify`] for the backup
+    pub fn verify_backup(&self, mode: VerifyMode) -> Result<VerifyReport> {
+        let backup = self
+            .backup
+            .as_ref()
+            .ok_or_else(|| Error::custom_error("Backup directory not set"))?;
+        self.verify_at(backup, mode)
+    }
+
+    /// Compares every source file with where it was copied to under `root`, or would be
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, root), fields(root = %root.as_ref().display()))
+    )]
+    pub fn verify_at(&self, root: impl AsRef<Path>, mode: VerifyMode) -> Result<VerifyReport> {
+        let root = root.as_ref();
+        self.set_phase(Phase::Verifying);
+        let mut report = VerifyReport::default();
+        let mut expected = HashSet::new();
+        for (source, target) in self.mapping_at(root)? {
+            expected.insert(target.clone());
+            // The ingest writes to the sidecars so only their presence is checked
+            let same = match self.writes_xmp() && is_xmp(&target) {
+                true => self.vfs().metadata(&target).map(|_| true),
+                false => same_content(self.vfs(), &source, &target, mode),
+            };
+            match same {
+                Ok(true) => report.matched.push((source, target)),
+                Ok(false) => report.mismatched.push((source, target)),
+                Err(_) => report.missing.push((source, target)),
+            }
+        }
+
+        report.extra = vfs::walk(self.vfs(), root, usize::MAX, |e| {
+            e.depth() != 1
+                || !e
+                    .file_name()
+                    .to_str()
+                    .map(|name| {
+                        BOOKKEEPING.contains(&name)
+                            || name.starts_with("checksums.")
+                            || self.is_previews_folder(name)
+                    })
+                    .unwrap_or_default()
+        })
+        .filter(|entry| entry.is_file() && !expected.contains(entry.path()))
+        .filter(|entry| !(self.writes_xmp() && is_xmp(entry.path())))
+        .map(vfs::Entry::into_path)
+        .collect();
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            matched = report.matched.len(),
+            missing = report.missing.len(),
+            mismatched = report.mismatched.len(),
+            extra = report.extra.len(),
+            "verified"
+        );
+        Ok(report)
+    }
+
+    /// Whether `name` at the root of the target holds the extracted previews
+    #[cfg(feature = "previews")]
+    fn is_previews_folder(&self, name: &str) -> bool {
+        self.previews.is_some() && name == preview::PREVIEWS_FOLDER
+    }
+
+    #[cfg(not(feature = "previews"))]
+    fn is_previews_folder(&self, _name: &str) -> bool {
+        false
+    }
+}
+
+impl std::str::FromStr for VerifyMode {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "size" => Ok(VerifyMode::Size),
+            algo => algo.parse().map(VerifyMode::Hash),
+        }
+    }
+}
+
+/// Errors if `target` can't be read
+pub(crate) fn same_content(
+    vfs: &dyn Vfs,
+    source: &Path,
+    target: &Path,
+    mode: VerifyMode,
+) -> std::io::Result<bool> {
+    let size = vfs.metadata(target)?.len;
+    if vfs.metadata(source)?.len != size {
+        return Ok(false);
+    }
+    Ok(match mode {
+        VerifyMode::Size => true,
+        VerifyMode::Hash(algo) => {
+            hash::hash_reader_with(vfs.open(source)?, &[algo])?
+                == hash::hash_reader_with(vfs.open(target)?, &[algo])?
+        }
+    })
+}
+
+fn is_xmp(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("xmp"))
+        .unwrap_or_default()
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use vfs::MemoryFs;
+
+    #[tokio::test]
+    async fn verifies_numbered_copies() {
+        let fs = MemoryFs::new();
+        fs.add_file("/a/DCIM/100NIKON/DSC_0001.NEF", [1; 10])
+            .add_file("/b/DCIM/100NIKON/DSC_0001.NEF", [2; 20])
+            // Left by an earlier run
+            .add_file("/target/DSC_0001.NEF", [3; 30]);
+        let (a, b) = (PathBuf::from("/a"), PathBuf::from("/b"));
+        let mut ingestor = IngestorBuilder::default()
+            .with_source([&a, &b])
+            .with_target("/target")
+            .with_structure(Structure::Preserve)
+            .with_filter(Filter::default())
+            .with_vfs(fs.clone())
+            .build()
+            .unwrap();
+        ingestor.ingest().await.unwrap();
+
+        let report = ingestor.verify(VerifyMode::default()).unwrap();
+        assert!(report.is_complete(), "{:?}", report);
+        let mut copies: Vec<&Path> = report
+            .matched
+            .iter()
+            .map(|(_, copy)| copy.as_path())
+            .collect();
+        copies.sort();
+        assert_eq!(
+            copies,
+            [
+                Path::new("/target/DSC_0001-1.NEF"),
+                Path::new("/target/DSC_0001-2.NEF")
+            ]
+        );
+        assert_eq!(report.extra, [PathBuf::from("/target/DSC_0001.NEF")]);
+        let mapped: Vec<PathBuf> = ingestor
+            .mapping()
+            .unwrap()
+            .into_iter()
+            .map(|(_, copy)| copy)
+            .collect();
+        assert!(mapped.iter().all(|copy| copies.contains(&copy.as_path())));
+    }
+
+    #[tokio::test]
+    async fn verifies_renamed_copies_after_an_earlier_run() {
+        let fs = MemoryFs::new();
+        fs.add_file("/a/DCIM/100NIKON/DSC_0001.NEF", [1; 10])
+            .add_file("/b/DCIM/100NIKON/DSC_0001.NEF", [2; 20]);
+        let rename = Rename {
+            name: Some("wedding"),
+            ..Default::default()
+        };
+        let mut reports = Vec::new();
+        for card in ["/a", "/b"] {
+            let card = PathBuf::from(card);
+            let mut ingestor = IngestorBuilder::default()
+                .with_source([&card])
+                .with_target("/target")
+                .with_structure(Structure::Rename(rename))
+                .with_filter(Filter::default())
+                .with_vfs(fs.clone())
+                .build()
+                .unwrap();
+            ingestor.ingest().await.unwrap();
+            reports.push(ingestor.verify(VerifyMode::default()).unwrap());
+        }
+        assert!(
+            reports.iter().all(VerifyReport::is_complete),
+            "{:?}",
+            reports
+        );
+        assert_eq!(
+            reports[1].matched[0].1,
+            Path::new("/target/0-wedding-1.nef")
+        );
+        assert_eq!(reports[1].extra, [PathBuf::from("/target/0-wedding.nef")]);
+    }
+}
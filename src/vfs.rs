@@ -0,0 +1,744 @@
+//! The filesystem the ingest walks, plans and copies through. It's the disk unless an ingestor
+//! is given another one with [`IngestorBuilder::with_vfs`], eg. a [`MemoryFs`] to run the
+//! renaming, collision and pairing logic without touching the disk.
+//!
+//! The copies made through another filesystem are plain: no reflinks, and neither the metadata,
+//! the timestamps nor the permissions are carried over. The manifests, the XMP sidecars and the
+//! previews are written to it too, while the capture times, the validation and the
+//! [`Destination`]s still read from the disk.
+use crate::*;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::path::Component;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// What [`Vfs::metadata`] tells about a file or a folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub len: u64,
+    pub is_dir: bool,
+    pub modified: Option<SystemTime>,
+}
+
+impl Metadata {
+    pub fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+}
+
+/// A file or folder found by [`Vfs::read_dir`] or [`walk`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    path: PathBuf,
+    is_dir: bool,
+    is_file: bool,
+    depth: usize,
+}
+
+impl Entry {
+    /// `is_dir` and `is_file` are both false for the links, which aren't followed
+    pub fn new(path: impl Into<PathBuf>, is_dir: bool, is_file: bool) -> Self {
+        Entry {
+            path: path.into(),
+            is_dir,
+            is_file,
+            depth: 0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    pub fn file_name(&self) -> &OsStr {
+        self.path.file_name().unwrap_or(self.path.as_os_str())
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.is_file
+    }
+
+    /// How far below the root of the walk it is, 0 for the root itself
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+/// The filesystem operations of an ingest
+pub trait Vfs: Send + Sync + std::fmt::Debug {
+    /// Follows the links
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// The files and folders in `dir`, in any order
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<Entry>>;
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()>;
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+
+    /// Creates the file at `path` or empties it, its folder has to exist
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>>;
+
+    /// Creates the file at `path`, failing with [`io::ErrorKind::AlreadyExists`] when there's one.
+    /// Only atomic when overridden, as [`RealFs`] and [`MemoryFs`] do
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        if self.exists(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        self.create(path)
+    }
+
+    /// Writes at the end of the file at `path`, created when it's missing
+    fn append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Bytes that can still be written to the disk holding `path`
+    fn free_space(&self, path: &Path) -> io::Result<u64>;
+
+    /// Whether the files at `a` and `b` share their free space
+    fn same_disk(&self, a: &Path, b: &Path) -> io::Result<bool>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok_and(|metadata| metadata.is_file())
+    }
+}
+
+/// The disk
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+impl Vfs for RealFs {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(Metadata {
+            len: metadata.len(),
+            is_dir: metadata.is_dir(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            entries.push(Entry::new(
+                entry.path(),
+                file_type.is_dir(),
+                file_type.is_file(),
+            ));
+        }
+        Ok(entries)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(dir)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(std::fs::File::open(path)?))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(std::fs::File::create(path)?))
+    }
+
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        Ok(Box::new(file))
+    }
+
+    fn append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Box::new(file))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        path.canonicalize()
+    }
+
+    fn free_space(&self, path: &Path) -> io::Result<u64> {
+        fs2::free_space(path)
+    }
+
+    fn same_disk(&self, a: &Path, b: &Path) -> io::Result<bool> {
+        crate::same_disk(a, b)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Dir,
+    File {
+        contents: Vec<u8>,
+        modified: SystemTime,
+    },
+}
+
+#[derive(Debug)]
+struct Nodes {
+    nodes: BTreeMap<PathBuf, Node>,
+    capacity: u64,
+}
+
+impl Nodes {
+    fn used(&self) -> u64 {
+        self.nodes
+            .values()
+            .map(|node| match node {
+                Node::File { contents, .. } => contents.len() as u64,
+                Node::Dir => 0,
+            })
+            .sum()
+    }
+
+    fn parent_exists(&self, path: &Path) -> io::Result<()> {
+        match path.parent() {
+            Some(parent) if !matches!(self.nodes.get(parent), Some(Node::Dir)) => {
+                Err(not_found(parent))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A filesystem held in memory, empty but for its root folders until files are added with
+/// [`MemoryFs::add_file`]. Its paths are absolute, the relative ones are taken from `/`
+#[derive(Debug, Clone)]
+pub struct MemoryFs {
+    nodes: Arc<Mutex<Nodes>>,
+}
+
+impl Default for MemoryFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(PathBuf::from("/"), Node::Dir);
+        MemoryFs {
+            nodes: Arc::new(Mutex::new(Nodes {
+                nodes,
+                capacity: u64::MAX,
+            })),
+        }
+    }
+
+    /// Writes fail once the files add up to `capacity` bytes, like on a full disk
+    pub fn with_capacity(self, capacity: u64) -> Self {
+        self.lock().capacity = capacity;
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Nodes> {
+        self.nodes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Adds a file with `contents` along with its missing folders, modified now
+    pub fn add_file(&self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) -> &Self {
+        self.add_file_modified(path, contents, SystemTime::now())
+    }
+
+    /// Same as [`MemoryFs::add_file`] with the time it was modified, eg. for the sessions
+    pub fn add_file_modified(
+        &self,
+        path: impl AsRef<Path>,
+        contents: impl Into<Vec<u8>>,
+        modified: SystemTime,
+    ) -> &Self {
+        let path = normalize(path.as_ref());
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent).ok();
+        }
+        let contents = contents.into();
+        self.lock()
+            .nodes
+            .insert(path, Node::File { contents, modified });
+        self
+    }
+
+    /// The contents of the file at `path`
+    pub fn read(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        match self.lock().nodes.get(&normalize(path.as_ref())) {
+            Some(Node::File { contents, .. }) => Some(contents.clone()),
+            _ => None,
+        }
+    }
+
+    /// Every file, sorted
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.lock()
+            .nodes
+            .iter()
+            .filter(|(_, node)| matches!(node, Node::File { .. }))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    fn writer(&self, path: PathBuf) -> Box<dyn Write + Send> {
+        Box::new(MemoryWriter {
+            nodes: self.nodes.clone(),
+            path,
+        })
+    }
+}
+
+impl Vfs for MemoryFs {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        match self.lock().nodes.get(&normalize(path)) {
+            Some(Node::Dir) => Ok(Metadata {
+                len: 0,
+                is_dir: true,
+                modified: None,
+            }),
+            Some(Node::File { contents, modified }) => Ok(Metadata {
+                len: contents.len() as u64,
+                is_dir: false,
+                modified: Some(*modified),
+            }),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<Entry>> {
+        let dir = normalize(dir);
+        let nodes = self.lock();
+        if !matches!(nodes.nodes.get(&dir), Some(Node::Dir)) {
+            return Err(not_found(&dir));
+        }
+        Ok(nodes
+            .nodes
+            .range(dir.clone()..)
+            .skip(1)
+            .take_while(|(path, _)| path.starts_with(&dir))
+            .filter(|(path, _)| path.parent() == Some(dir.as_path()))
+            .map(|(path, node)| {
+                let is_dir = matches!(node, Node::Dir);
+                Entry::new(path.clone(), is_dir, !is_dir)
+            })
+            .collect())
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        let dir = normalize(dir);
+        let mut nodes = self.lock();
+        for ancestor in dir.ancestors() {
+            match nodes.nodes.get(ancestor) {
+                Some(Node::Dir) => break,
+                Some(Node::File { .. }) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{} is a file", ancestor.display()),
+                    ))
+                }
+                None => {
+                    nodes.nodes.insert(ancestor.to_path_buf(), Node::Dir);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        match self.read(path) {
+            Some(contents) => Ok(Box::new(io::Cursor::new(contents))),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        let path = normalize(path);
+        let mut nodes = self.lock();
+        nodes.parent_exists(&path)?;
+        if let Some(Node::Dir) = nodes.nodes.get(&path) {
+            return Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("{} is a folder", path.display()),
+            ));
+        }
+        let file = Node::File {
+            contents: Vec::new(),
+            modified: SystemTime::now(),
+        };
+        nodes.nodes.insert(path.clone(), file);
+        drop(nodes);
+        Ok(self.writer(path))
+    }
+
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        let path = normalize(path);
+        let mut nodes = self.lock();
+        nodes.parent_exists(&path)?;
+        if nodes.nodes.contains_key(&path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        let file = Node::File {
+            contents: Vec::new(),
+            modified: SystemTime::now(),
+        };
+        nodes.nodes.insert(path.clone(), file);
+        drop(nodes);
+        Ok(self.writer(path))
+    }
+
+    fn append(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        if !self.exists(path) {
+            return self.create(path);
+        }
+        Ok(self.writer(normalize(path)))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (normalize(from), normalize(to));
+        let mut nodes = self.lock();
+        nodes.parent_exists(&to)?;
+        let moved: Vec<PathBuf> = nodes
+            .nodes
+            .keys()
+            .filter(|path| path.starts_with(&from))
+            .cloned()
+            .collect();
+        if moved.is_empty() {
+            return Err(not_found(&from));
+        }
+        for path in moved {
+            if let Some(node) = nodes.nodes.remove(&path) {
+                let relative = path.strip_prefix(&from).unwrap_or(Path::new(""));
+                let destination = match relative.as_os_str().is_empty() {
+                    true => to.clone(),
+                    false => to.join(relative),
+                };
+                nodes.nodes.insert(destination, node);
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        let mut nodes = self.lock();
+        match nodes.nodes.get(&path) {
+            Some(Node::File { .. }) => {
+                nodes.nodes.remove(&path);
+                Ok(())
+            }
+            _ => Err(not_found(&path)),
+        }
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = normalize(path);
+        match self.exists(&path) {
+            true => Ok(path),
+            false => Err(not_found(&path)),
+        }
+    }
+
+    fn free_space(&self, _path: &Path) -> io::Result<u64> {
+        let nodes = self.lock();
+        Ok(nodes.capacity.saturating_sub(nodes.used()))
+    }
+
+    fn same_disk(&self, _a: &Path, _b: &Path) -> io::Result<bool> {
+        Ok(true)
+    }
+}
+
+/// Writes at the end of a file of a [`MemoryFs`]
+struct MemoryWriter {
+    nodes: Arc<Mutex<Nodes>>,
+    path: PathBuf,
+}
+
+impl Write for MemoryWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut nodes = self.nodes.lock().unwrap_or_else(|e| e.into_inner());
+        if nodes.used() + buf.len() as u64 > nodes.capacity {
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                "No space left in memory",
+            ));
+        }
+        match nodes.nodes.get_mut(&self.path) {
+            Some(Node::File { contents, modified }) => {
+                contents.extend_from_slice(buf);
+                *modified = SystemTime::now();
+                Ok(buf.len())
+            }
+            _ => Err(not_found(&self.path)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} doesn't exist", path.display()),
+    )
+}
+
+/// Makes `path` absolute from `/` and resolves its `.` and `..`
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Prefix(prefix) => normalized.push(prefix.as_os_str()),
+            Component::RootDir | Component::CurDir => {}
+        }
+    }
+    normalized
+}
+
+/// Reads the whole file at `path`, like [`std::fs::read`]
+#[cfg(feature = "previews")]
+pub(crate) fn read(vfs: &dyn Vfs, path: &Path) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    vfs.open(path)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+pub(crate) fn read_to_string(vfs: &dyn Vfs, path: &Path) -> io::Result<String> {
+    let mut data = String::new();
+    vfs.open(path)?.read_to_string(&mut data)?;
+    Ok(data)
+}
+
+/// Creates or empties the file at `path` with `contents`, like [`std::fs::write`]
+pub(crate) fn write(vfs: &dyn Vfs, path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = vfs.create(path)?;
+    file.write_all(contents)?;
+    file.flush()
+}
+
+/// Walks `root` depth first and sorted by name like [`walkdir::WalkDir`], starting with `root`
+/// itself. The entries `enter` refuses are left out along with everything under them, and the
+/// folders that can't be read are skipped
+pub fn walk<'a, F: FnMut(&Entry) -> bool>(
+    vfs: &'a dyn Vfs,
+    root: &Path,
+    max_depth: usize,
+    enter: F,
+) -> Walk<'a, F> {
+    let root = vfs
+        .metadata(root)
+        .map(|metadata| Entry::new(root, metadata.is_dir, metadata.is_file()));
+    Walk {
+        vfs,
+        max_depth,
+        enter,
+        stack: vec![root.into_iter().collect::<Vec<_>>().into_iter()],
+    }
+}
+
+/// The iterator returned by [`walk`]
+pub struct Walk<'a, F> {
+    vfs: &'a dyn Vfs,
+    max_depth: usize,
+    enter: F,
+    stack: Vec<std::vec::IntoIter<Entry>>,
+}
+
+impl<F: FnMut(&Entry) -> bool> Iterator for Walk<'_, F> {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        loop {
+            let Some(entry) = self.stack.last_mut()?.next() else {
+                self.stack.pop();
+                continue;
+            };
+            if !(self.enter)(&entry) {
+                continue;
+            }
+            if entry.is_dir && entry.depth < self.max_depth {
+                if let Ok(mut children) = self.vfs.read_dir(&entry.path) {
+                    children.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+                    for child in &mut children {
+                        child.depth = entry.depth + 1;
+                    }
+                    self.stack.push(children.into_iter());
+                }
+            }
+            return Some(entry);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+
+    const TARGET: &str = "/memory-target";
+
+    /// Two cards with a `DSC_0001.NEF` each, the first one paired with a jpeg
+    fn cards() -> MemoryFs {
+        let fs = MemoryFs::new();
+        fs.add_file("/a/DCIM/100NIKON/DSC_0001.NEF", [1; 10])
+            .add_file("/a/DCIM/100NIKON/DSC_0001.JPG", [2; 20])
+            .add_file("/a/DCIM/100NIKON/DSC_0002.NEF", [3; 30])
+            .add_file("/b/DCIM/100NIKON/DSC_0001.NEF", [4; 40]);
+        fs
+    }
+
+    async fn ingest(fs: &MemoryFs, structure: Structure<'_>, pairs: PairPolicy) -> Report {
+        let (a, b) = (PathBuf::from("/a"), PathBuf::from("/b"));
+        let mut ingestor = IngestorBuilder::default()
+            .with_source([&a, &b])
+            .with_target(TARGET)
+            .with_structure(structure)
+            .with_filter(Filter::default())
+            .with_pair_policy(pairs)
+            .with_metadata(MetadataPreset {
+                creator: Some("Jane Doe".into()),
+                ..Default::default()
+            })
+            .with_mhl(HashAlgo::Xxh128)
+            .with_checksums(HashAlgo::Md5)
+            .with_vfs(fs.clone())
+            .build()
+            .unwrap();
+        ingestor.ingest().await.unwrap();
+        assert!(
+            !Path::new(TARGET).exists(),
+            "nothing is written to the disk"
+        );
+        ingestor.report
+    }
+
+    /// The files copied to the target along with the checksums, by path relative to it
+    fn copied(fs: &MemoryFs) -> Vec<String> {
+        walk(fs, Path::new(TARGET), usize::MAX, |entry| {
+            entry.file_name() != crate::mhl::MHL_FOLDER
+                && entry.file_name() != crate::names::NAMES_FILE
+        })
+        .filter(Entry::is_file)
+        .map(|entry| {
+            let path = entry.path().strip_prefix(TARGET).unwrap();
+            path.to_string_lossy().into_owned()
+        })
+        .collect()
+    }
+
+    fn checksums(fs: &MemoryFs) -> Vec<String> {
+        let manifest = read_to_string(fs, &Path::new(TARGET).join("checksums.md5")).unwrap();
+        let mut names: Vec<String> = manifest
+            .lines()
+            .filter_map(|line| Some(line.split_once("  ")?.1.to_owned()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn numbers_collisions() {
+        let fs = cards();
+        let report = ingest(&fs, Structure::Preserve, PairPolicy::Both).await;
+        assert!(report.failures.is_empty());
+        let files = [
+            "DSC_0001-1.NEF",
+            "DSC_0001-1.xmp",
+            "DSC_0001.JPG",
+            "DSC_0001.NEF",
+            "DSC_0001.xmp",
+            "DSC_0002.NEF",
+            "DSC_0002.xmp",
+        ];
+        let mut copied = copied(&fs);
+        assert_eq!(copied.pop().as_deref(), Some("checksums.md5"));
+        assert_eq!(copied, files);
+        assert_eq!(checksums(&fs), files);
+        let copy = read_to_string(&fs, &Path::new(TARGET).join("DSC_0001-1.NEF")).unwrap();
+        assert_eq!(copy.as_bytes(), [4; 40]);
+        assert!(fs.is_file(
+            &Path::new(TARGET)
+                .join(crate::mhl::MHL_FOLDER)
+                .join(crate::mhl::MHL_CHAIN)
+        ));
+    }
+
+    #[tokio::test]
+    async fn renames_pairs_together() {
+        let fs = cards();
+        let rename = Rename {
+            name: Some("wedding"),
+            ..Default::default()
+        };
+        ingest(&fs, Structure::Rename(rename), PairPolicy::Both).await;
+        let files = [
+            "0-wedding.jpg",
+            "0-wedding.nef",
+            "0-wedding.xmp",
+            "1-wedding.nef",
+            "1-wedding.xmp",
+            "2-wedding.nef",
+            "2-wedding.xmp",
+        ];
+        let mut copied = copied(&fs);
+        assert_eq!(copied.pop().as_deref(), Some("checksums.md5"));
+        assert_eq!(copied, files);
+        assert_eq!(checksums(&fs), files);
+        let xmp = read_to_string(&fs, &Path::new(TARGET).join("0-wedding.xmp")).unwrap();
+        assert!(xmp.contains("Jane Doe"));
+    }
+
+    #[tokio::test]
+    async fn skips_the_jpeg_of_a_pair() {
+        let fs = cards();
+        fs.add_file("/b/DCIM/100NIKON/DSC_0003.JPG", [5; 50]);
+        let report = ingest(&fs, Structure::Preserve, PairPolicy::PreferRaw).await;
+        assert!(report.failures.is_empty());
+        let copied = copied(&fs);
+        assert!(!copied.iter().any(|file| file == "DSC_0001.JPG"));
+        assert!(copied.iter().any(|file| file == "DSC_0003.JPG"));
+        assert!(copied.iter().any(|file| file == "DSC_0003.xmp"));
+    }
+}
@@ -0,0 +1,394 @@
+use crate::*;
+
+/// Where the depths of [`Ingestor::depth`] and [`Ingestor::min_depth`] are counted from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DepthFrom {
+    /// The root of each source, where its loose files are at depth 1
+    #[default]
+    Source,
+    /// The DCIM folder of the files inside one, whether the source is the card or a folder
+    /// holding several. Other files count from the source
+    Dcim,
+}
+
+/// The depths of an ingest, given to the walks
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Depths {
+    pub min: usize,
+    pub max: usize,
+    pub from: DepthFrom,
+}
+
+impl Depths {
+    /// How deep the walks go under a source, all the way down when the DCIM folders could be
+    /// anywhere
+    pub fn walked(&self) -> usize {
+        match self.from {
+            DepthFrom::Source => self.max,
+            DepthFrom::Dcim => usize::MAX,
+        }
+    }
+
+    /// Whether `entry` is within the depths, the folders always are
+    pub fn keeps(&self, entry: &vfs::Entry) -> bool {
+        if !entry.is_file() {
+            return true;
+        }
+        let depth = match self.from {
+            DepthFrom::Source => entry.depth(),
+            DepthFrom::Dcim => entry
+                .path()
+                .ancestors()
+                .skip(1)
+                .take(entry.depth())
+                .position(|folder| {
+                    folder
+                        .file_name()
+                        .is_some_and(|name| name.eq_ignore_ascii_case("dcim"))
+                })
+                .map_or(entry.depth(), |position| position + 1),
+        };
+        (self.min..=self.max).contains(&depth)
+    }
+}
+
+/// What the walks of the sources go through, given to them
+#[derive(Debug, Clone)]
+pub(crate) struct Scope {
+    pub depths: Depths,
+    /// The [`Ingestor::nested_outputs`]
+    pub outputs: Vec<PathBuf>,
+    /// The [`Ingestor::included_subfolders`]
+    pub included: Vec<PathBuf>,
+    /// The [`Ingestor::excluded_subfolders`]
+    pub excluded: Vec<PathBuf>,
+}
+
+impl Scope {
+    pub fn walked(&self) -> usize {
+        self.depths.walked()
+    }
+
+    /// Whether the walk goes into `entry`, it doesn't into the outputs or the folders out of the
+    /// subfolders. The files are left to [`Scope::keeps`]
+    pub fn enters(&self, entry: &vfs::Entry) -> bool {
+        if !entry.is_dir() {
+            return true;
+        }
+        if self.outputs.iter().any(|output| entry.path() == output) {
+            return false;
+        }
+        let relative = under_source(entry);
+        !self
+            .excluded
+            .iter()
+            .any(|folder| relative.starts_with(folder))
+            && (self.included.is_empty()
+                || self
+                    .included
+                    .iter()
+                    .any(|folder| relative.starts_with(folder) || folder.starts_with(&relative)))
+    }
+
+    /// Whether `entry` is within the depths and the subfolders, the folders always are
+    pub fn keeps(&self, entry: &vfs::Entry) -> bool {
+        if !entry.is_file() {
+            return true;
+        }
+        let relative = under_source(entry);
+        self.depths.keeps(entry)
+            && !self
+                .excluded
+                .iter()
+                .any(|folder| relative.starts_with(folder))
+            && (self.included.is_empty()
+                || self
+                    .included
+                    .iter()
+                    .any(|folder| relative.starts_with(folder)))
+    }
+}
+
+/// The path of `entry` under the source it was walked from
+fn under_source(entry: &vfs::Entry) -> PathBuf {
+    let components = entry.path().components();
+    let skipped = components.clone().count().saturating_sub(entry.depth());
+    components.skip(skipped).collect()
+}
+
+/// `folders` as they're matched against the paths under the sources, eg. `DCIM/105NIKON` for
+/// `./DCIM/105NIKON/`
+pub(crate) fn subfolders<P: AsRef<Path>>(folders: impl IntoIterator<Item = P>) -> Vec<PathBuf> {
+    folders
+        .into_iter()
+        .map(|folder| {
+            folder
+                .as_ref()
+                .components()
+                .filter(|component| matches!(component, std::path::Component::Normal(_)))
+                .collect()
+        })
+        .collect()
+}
+
+/// A walk of the sources along with the size of each file the filter keeps, taken once by an
+/// ingest for its space check, its totals and its passes
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Scan<'ingest> {
+    pub entries: Vec<(&'ingest Path, vfs::Entry)>,
+    pub sizes: HashMap<PathBuf, u64>,
+}
+
+impl<'ingest> Scan<'ingest> {
+    /// Keeps the sizes of the files still in `entries` once they're ordered and sampled
+    pub fn new(
+        entries: Vec<(&'ingest Path, vfs::Entry)>,
+        mut sizes: HashMap<PathBuf, u64>,
+    ) -> Self {
+        let walked: HashSet<&Path> = entries.iter().map(|(_, entry)| entry.path()).collect();
+        sizes.retain(|path, _| walked.contains(path.as_path()));
+        Scan { entries, sizes }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.sizes.values().sum()
+    }
+
+    /// The size of the files of each of `sources`, see [`Ingestor::source_sizes`]
+    pub fn source_sizes(&self, sources: &HashSet<&Path>) -> Vec<(PathBuf, u64)> {
+        sources
+            .iter()
+            .map(|&source| {
+                let size = self
+                    .entries
+                    .iter()
+                    .filter(|(walked, _)| *walked == source)
+                    .filter_map(|(_, entry)| self.sizes.get(entry.path()))
+                    .sum();
+                (source.to_path_buf(), size)
+            })
+            .collect()
+    }
+
+    /// The entries walked from `sources`, for a pipeline copying only some of them
+    pub fn of(&self, sources: &HashSet<&Path>) -> Vec<(&'ingest Path, vfs::Entry)> {
+        self.entries
+            .iter()
+            .filter(|(source, _)| sources.contains(source))
+            .cloned()
+            .collect()
+    }
+}
+
+/// The size of the file of `entry` when the filter keeps it
+pub(crate) fn size_of(vfs: &dyn Vfs, filter: &Filter, entry: &vfs::Entry) -> Option<u64> {
+    if !entry.is_file() || !filter.matches_in(vfs, entry.path()).ok()? {
+        return None;
+    }
+    Some(
+        vfs.metadata(entry.path())
+            .map(|m| m.len)
+            .unwrap_or_default(),
+    )
+}
+
+impl<'ingest> Ingestor<'ingest> {
+    /// Walks all the sources and returns the entries that pass the filters along with their source,
+    /// in [`Ingestor::order`]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "scan",
+            level = "debug",
+            skip_all,
+            fields(sources = self.sources.len())
+        )
+    )]
+    pub(crate) fn entries(&self) -> Vec<(&'ingest Path, vfs::Entry)> {
+        self.blocking_scan().entries
+    }
+
+    /// Same as [`Ingestor::entries`] along with the size of each file the filter keeps
+    pub(crate) fn blocking_scan(&self) -> Scan<'ingest> {
+        let sources: Vec<&'ingest Path> = self.sources.iter().copied().collect();
+        let (mut entries, mut sizes) = (Vec::new(), HashMap::new());
+        walk(
+            self.vfs(),
+            &sources,
+            &self.scope(),
+            &self.filter,
+            |index, entry| {
+                if let Some(size) = size_of(self.vfs(), &self.filter, &entry) {
+                    self.found();
+                    sizes.insert(entry.path().to_path_buf(), size);
+                }
+                entries.push((sources[index], entry));
+                true
+            },
+        );
+        self.order(&mut entries);
+        self.sample(&mut entries);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(entries = entries.len(), "scanned");
+        Scan::new(entries, sizes)
+    }
+
+    /// Leaves out of the walked `entries` the files the filter doesn't sample
+    pub(crate) fn sample(&self, entries: &mut Vec<(&'ingest Path, vfs::Entry)>) {
+        let files = entries
+            .iter()
+            .filter(|(_, entry)| entry.is_file())
+            .map(|(_, entry)| entry.path());
+        if let Some(sampled) = self.filter.sampled(files) {
+            entries.retain(|(_, entry)| !entry.is_file() || sampled.contains(entry.path()));
+        }
+    }
+
+    pub(crate) fn scope(&self) -> Scope {
+        Scope {
+            depths: Depths {
+                min: self.min_depth,
+                max: self.depth,
+                from: self.depth_from,
+            },
+            outputs: self.outputs_in_sources(),
+            included: self.included_subfolders.clone(),
+            excluded: self.excluded_subfolders.clone(),
+        }
+    }
+
+    /// The target and the backup where they are inside a source, as walked from that source.
+    /// They are left out of the walks so an ingest doesn't copy its own copies, and the ingest
+    /// warns about them as it starts
+    pub fn nested_outputs(&self) -> Vec<(&'ingest Path, PathBuf)> {
+        let mut outputs: Vec<&Path> = Vec::new();
+        for output in std::iter::once(&self.target)
+            .chain(&self.backup)
+            .chain(&self.__outputs)
+        {
+            if !outputs.contains(&output.as_path()) {
+                outputs.push(output);
+            }
+        }
+        let mut nested = Vec::new();
+        for &source in &self.sources {
+            for output in &outputs {
+                if let Some(output) = nested_in(self.vfs(), source, output) {
+                    nested.push((source, output));
+                }
+            }
+        }
+        nested
+    }
+
+    /// The paths of [`Ingestor::nested_outputs`]
+    pub(crate) fn outputs_in_sources(&self) -> Vec<PathBuf> {
+        self.nested_outputs()
+            .into_iter()
+            .map(|(_, output)| output)
+            .collect()
+    }
+}
+
+/// `output` under `source` when it's inside it, eg. a target made in a folder of the card
+pub(crate) fn nested_in(vfs: &dyn Vfs, source: &Path, output: &Path) -> Option<PathBuf> {
+    let relative = absolute(vfs, output)?;
+    let relative = relative.strip_prefix(absolute(vfs, source)?).ok()?;
+    (!relative.as_os_str().is_empty()).then(|| source.join(relative))
+}
+
+/// `path` resolved when it exists, only made absolute otherwise, eg. a target not made yet
+fn absolute(vfs: &dyn Vfs, path: &Path) -> Option<PathBuf> {
+    vfs.canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .ok()
+}
+
+/// Leaves out of `sources` the ones inside another source or naming the same folder, which would
+/// be copied twice. Returns what's left and every source left out along with the one it's in
+pub(crate) fn collapse_sources<'a>(
+    vfs: &dyn Vfs,
+    sources: HashSet<&'a Path>,
+) -> (HashSet<&'a Path>, Vec<(PathBuf, PathBuf)>) {
+    let mut sources: Vec<(&Path, PathBuf)> = sources
+        .into_iter()
+        .map(|source| {
+            (
+                source,
+                absolute(vfs, source).unwrap_or_else(|| source.into()),
+            )
+        })
+        .collect();
+    // The outer sources first, and of the names of a folder the first by path
+    sources.sort_by(|(a, x), (b, y)| {
+        let depth = |path: &Path| path.components().count();
+        depth(x).cmp(&depth(y)).then(a.cmp(b))
+    });
+    let mut kept: Vec<(&Path, PathBuf)> = Vec::new();
+    let mut collapsed = Vec::new();
+    for (source, path) in sources {
+        match kept.iter().find(|(_, outer)| path.starts_with(outer)) {
+            Some((outer, _)) => collapsed.push((source.to_path_buf(), outer.to_path_buf())),
+            None => kept.push((source, path)),
+        }
+    }
+    (
+        kept.into_iter().map(|(source, _)| source).collect(),
+        collapsed,
+    )
+}
+
+/// Whether the walk goes into `entry`, which is left out by neither the filter nor the `scope`
+pub(crate) fn enters(vfs: &dyn Vfs, filter: &Filter, scope: &Scope, entry: &vfs::Entry) -> bool {
+    filter.walks(vfs, entry) && scope.enters(entry)
+}
+
+/// Walks `sources` and hands every entry that passes the filter to `send` along with the index
+/// of its source. Stops early once `send` returns false
+pub(crate) fn walk(
+    vfs: &dyn Vfs,
+    sources: &[impl AsRef<Path>],
+    scope: &Scope,
+    filter: &Filter,
+    mut send: impl FnMut(usize, vfs::Entry) -> bool,
+) {
+    for (index, source) in sources.iter().enumerate() {
+        for entry in vfs::walk(vfs, source.as_ref(), scope.walked(), |e| {
+            enters(vfs, filter, scope, e)
+        })
+        .filter(|entry| scope.keeps(entry))
+        {
+            if !send(index, entry) {
+                return;
+            }
+        }
+    }
+}
+
+/// Same as [`walk`] for the sidecars in `sources` whose file is missing
+pub(crate) fn walk_orphans(
+    vfs: &dyn Vfs,
+    sources: &[impl AsRef<Path>],
+    scope: &Scope,
+    ignore_hidden: bool,
+    sidecars: &SidecarPolicy,
+    mut send: impl FnMut(usize, vfs::Entry) -> bool,
+) {
+    for (index, source) in sources.iter().enumerate() {
+        for entry in vfs::walk(vfs, source.as_ref(), scope.walked(), |e| {
+            !(ignore_hidden && e.path().is_hidden()) && scope.enters(e)
+        })
+        .filter(|entry| {
+            entry.is_file()
+                && scope.keeps(entry)
+                && sidecars.is_sidecar(entry.path())
+                && sidecars.owner_in(vfs, entry.path()).is_none()
+        }) {
+            if !send(index, entry) {
+                return;
+            }
+        }
+    }
+}
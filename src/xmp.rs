@@ -0,0 +1,219 @@
+use crate::*;
+
+/// Namespaces of the properties the ingestor writes
+const NAMESPACES: &str = concat!(
+    r#"xmlns:dc="http://purl.org/dc/elements/1.1/" "#,
+    r#"xmlns:xmp="http://ns.adobe.com/xap/1.0/" "#,
+    r#"xmlns:xmpRights="http://ns.adobe.com/xap/1.0/rights/" "#,
+    r#"xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/" "#,
+    r#"xmlns:exif="http://ns.adobe.com/exif/1.0/""#
+);
+
+/// Creator and rights written to the XMP sidecar of every ingested file, like the metadata
+/// presets applied on import by Lightroom or Photo Mechanic
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MetadataPreset {
+    pub creator: Option<String>,
+    /// Eg. "© 2024 Jane Doe"
+    pub copyright: Option<String>,
+    pub usage_terms: Option<String>,
+}
+
+impl MetadataPreset {
+    pub fn is_empty(&self) -> bool {
+        self.creator.is_none() && self.copyright.is_none() && self.usage_terms.is_none()
+    }
+
+    pub(crate) fn properties(&self) -> Vec<Property> {
+        let mut properties = Vec::new();
+        if let Some(ref creator) = self.creator {
+            properties.push(Property::seq("dc:creator", creator));
+        }
+        if let Some(ref copyright) = self.copyright {
+            properties.push(Property::alt("dc:rights", copyright));
+            properties.push(Property::text("xmpRights:Marked", "True"));
+        }
+        if let Some(ref usage_terms) = self.usage_terms {
+            properties.push(Property::alt("xmpRights:UsageTerms", usage_terms));
+        }
+        properties
+    }
+}
+
+/// Only ingests the picks made in another tool, going by the rating and color label in the XMP
+/// sidecars. A file without a sidecar falls back to the one of its RAW/JPEG twin, a file without
+/// either isn't a pick
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Picks {
+    /// Lowest star rating kept, rejects are rated -1
+    pub min_rating: Option<i8>,
+    /// Color labels kept, eg. "Red" or "Green", compared ignoring case
+    pub labels: Vec<String>,
+}
+
+impl Picks {
+    pub fn is_empty(&self) -> bool {
+        self.min_rating.is_none() && self.labels.is_empty()
+    }
+
+    /// Whether `path` is a pick, always when no rating or label is required
+    pub fn keeps(&self, path: impl AsRef<Path>, pairs: &Pairs) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let path = path.as_ref();
+        let Some(xmp) = std::iter::once(path)
+            .chain(pairs.twin(path))
+            .map(|path| sidecar_of(&vfs::RealFs, path))
+            .find_map(|sidecar| std::fs::read_to_string(sidecar).ok())
+        else {
+            return false;
+        };
+        let rated = self.min_rating.is_none_or(|min| {
+            value(&xmp, "xmp:Rating")
+                .and_then(|rating| rating.parse::<f32>().ok())
+                .is_some_and(|rating| rating >= f32::from(min))
+        });
+        let labeled = self.labels.is_empty()
+            || value(&xmp, "xmp:Label").is_some_and(|label| {
+                self.labels.iter().any(|wanted| wanted.eq_ignore_ascii_case(label))
+            });
+        rated && labeled
+    }
+}
+
+/// Returns the value of a simple property, written either as an attribute or an element
+fn value<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+    if let Some(start) = xmp.find(&format!("{}=", name)) {
+        let value = &xmp[start + name.len() + 1..];
+        let quote = value.chars().next()?;
+        let value = &value[1..];
+        return value.find(quote).map(|end| value[..end].trim());
+    }
+    let start = xmp.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = xmp[start..].find(&format!("</{}>", name))?;
+    Some(xmp[start..start + end].trim())
+}
+
+/// What a generated sidecar starts with, the capture date and an empty rating
+pub(crate) fn placeholders(vfs: &dyn Vfs, path: &Path) -> Vec<Property> {
+    let mut properties = vec![Property::text("xmp:Rating", "0")];
+    #[cfg(feature = "exif")]
+    let captured = crate::meta::read(path)
+        .ok()
+        .and_then(|meta| meta.captured)
+        .map(|captured| captured.to_iso8601());
+    #[cfg(not(feature = "exif"))]
+    let captured: Option<String> = None;
+    // Cameras write the file when the photo is taken
+    let captured = captured.or_else(|| Some(times::iso8601(vfs.metadata(path).ok()?.modified?)));
+    if let Some(captured) = captured {
+        properties.push(Property::text("xmp:CreateDate", &captured));
+        properties.push(Property::text("photoshop:DateCreated", &captured));
+    }
+    properties
+}
+
+/// A single XMP property in its element form
+#[derive(Debug, Clone)]
+pub(crate) struct Property {
+    name: &'static str,
+    xml: String,
+}
+
+impl Property {
+    pub fn text(name: &'static str, value: &str) -> Self {
+        Property {
+            name,
+            xml: format!("<{0}>{1}</{0}>", name, mhl::escape(value)),
+        }
+    }
+
+    /// An ordered array, eg. the creators
+    pub fn seq(name: &'static str, value: &str) -> Self {
+        Property {
+            name,
+            xml: format!(
+                "<{0}><rdf:Seq><rdf:li>{1}</rdf:li></rdf:Seq></{0}>",
+                name,
+                mhl::escape(value)
+            ),
+        }
+    }
+
+    /// A text in the default language
+    pub fn alt(name: &'static str, value: &str) -> Self {
+        Property {
+            name,
+            xml: format!(
+                r#"<{0}><rdf:Alt><rdf:li xml:lang="x-default">{1}</rdf:li></rdf:Alt></{0}>"#,
+                name,
+                mhl::escape(value)
+            ),
+        }
+    }
+}
+
+/// Returns the XMP sidecar of `path`, the existing one whatever the case of its name
+pub(crate) fn sidecar_of(vfs: &dyn Vfs, path: &Path) -> PathBuf {
+    companion::with_extension_in(vfs, path, "xmp")
+        .unwrap_or_else(|| path.with_extension("xmp"))
+}
+
+/// Adds the `properties` the sidecar doesn't have yet, creating it if needed. What's already in
+/// the sidecar is kept. Returns whether it was written
+pub(crate) fn update(vfs: &dyn Vfs, sidecar: &Path, properties: &[Property]) -> Result<bool> {
+    let existing = match vfs::read_to_string(vfs, sidecar) {
+        Ok(existing) => Some(existing),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    let missing: Vec<&Property> = properties
+        .iter()
+        .filter(|property| {
+            existing.as_deref().is_none_or(|existing| {
+                !existing.contains(&format!("<{}", property.name))
+                    && !existing.contains(&format!(" {}=", property.name))
+            })
+        })
+        .collect();
+    if missing.is_empty() {
+        return Ok(false);
+    }
+
+    let description = format!(
+        "  <rdf:Description rdf:about=\"\" {}>\n{}  </rdf:Description>\n",
+        NAMESPACES,
+        missing
+            .iter()
+            .map(|property| format!("   {}\n", property.xml))
+            .collect::<String>()
+    );
+    let xmp = match existing {
+        Some(existing) => {
+            let end = existing
+                .rfind("</rdf:RDF>")
+                .ok_or_else(|| Error::custom_error("Not an XMP packet"))?;
+            // Insert on the line of the closing tag to keep the indentation
+            let end = match existing[..end].rfind('\n') {
+                Some(line) if existing[line + 1..end].trim().is_empty() => line + 1,
+                _ => end,
+            };
+            format!("{}{}{}", &existing[..end], description, &existing[end..])
+        }
+        None => format!(
+            concat!(
+                "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+                " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+                "{}",
+                " </rdf:RDF>\n",
+                "</x:xmpmeta>\n"
+            ),
+            description
+        ),
+    };
+    vfs::write(vfs, sidecar, xmp.as_bytes())?;
+    Ok(true)
+}